@@ -0,0 +1,196 @@
+use crate::{
+    util::{
+        checksum::crc32c,
+        codec::{put_slice, Decoder},
+    },
+    Error, Result,
+};
+
+/// The version of the encoded [`WriteBatch`] format.
+const FORMAT_VERSION: u8 = 1;
+/// The length of the version and the number of operations.
+const HEADER_LEN: usize = 1 + 4;
+/// The length of the trailing checksum.
+const CHECKSUM_LEN: usize = 4;
+
+const PUT_TAG: u8 = 0;
+const DELETE_TAG: u8 = 1;
+
+/// A batch of writes to apply to a table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a put of the key-value pair to the batch.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.ops.push((key.to_vec(), Some(value.to_vec())));
+    }
+
+    /// Adds a delete of the key to the batch.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.ops.push((key.to_vec(), None));
+    }
+
+    /// Returns the number of operations in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns true if the batch has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Removes all operations from the batch.
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+
+    /// Returns an iterator over the operations in the batch.
+    ///
+    /// A `None` value represents a delete.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.ops
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_deref()))
+    }
+
+    /// Encodes the batch into bytes.
+    ///
+    /// The format is:
+    ///
+    /// ```text
+    /// | version (u8) | count (u32) | op * count | crc32c (u32) |
+    /// op: | tag (u8) | key_len (u32) | key | [value_len (u32) | value] |
+    /// ```
+    ///
+    /// All integers are little-endian, and the value is only present for puts.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_size());
+        buf.push(FORMAT_VERSION);
+        buf.extend_from_slice(&(self.ops.len() as u32).to_le_bytes());
+        for (key, value) in &self.ops {
+            match value {
+                Some(value) => {
+                    buf.push(PUT_TAG);
+                    put_slice(&mut buf, key);
+                    put_slice(&mut buf, value);
+                }
+                None => {
+                    buf.push(DELETE_TAG);
+                    put_slice(&mut buf, key);
+                }
+            }
+        }
+        let checksum = crc32c(&buf);
+        buf.extend_from_slice(&checksum.to_le_bytes());
+        buf
+    }
+
+    /// Decodes a batch from bytes produced by [`WriteBatch::encode`].
+    ///
+    /// Returns [`Error::Corrupted`] if the bytes are truncated, fail the
+    /// checksum, or have an unknown version.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err(Error::Corrupted);
+        }
+        let (content, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+        if crc32c(content) != checksum {
+            return Err(Error::Corrupted);
+        }
+        let mut decoder = Decoder::new(content);
+        if decoder.get_u8()? != FORMAT_VERSION {
+            return Err(Error::Corrupted);
+        }
+        let count = decoder.get_u32()? as usize;
+        // Each operation takes at least a tag and a key length.
+        if count > decoder.remaining() / 5 {
+            return Err(Error::Corrupted);
+        }
+        let mut ops = Vec::with_capacity(count);
+        for _ in 0..count {
+            let tag = decoder.get_u8()?;
+            let key = decoder.get_slice()?.to_vec();
+            let value = match tag {
+                PUT_TAG => Some(decoder.get_slice()?.to_vec()),
+                DELETE_TAG => None,
+                _ => return Err(Error::Corrupted),
+            };
+            ops.push((key, value));
+        }
+        if decoder.remaining() != 0 {
+            return Err(Error::Corrupted);
+        }
+        Ok(Self { ops })
+    }
+
+    fn encoded_size(&self) -> usize {
+        let ops_size: usize = self
+            .ops
+            .iter()
+            .map(|(key, value)| 1 + 4 + key.len() + value.as_ref().map_or(0, |v| 4 + v.len()))
+            .sum();
+        HEADER_LEN + ops_size + CHECKSUM_LEN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_batch() -> WriteBatch {
+        let mut batch = WriteBatch::new();
+        batch.put(b"k1", b"v1");
+        batch.delete(b"k2");
+        batch.put(b"k3", b"");
+        batch
+    }
+
+    #[test]
+    fn encode_decode() {
+        let batch = test_batch();
+        let bytes = batch.encode();
+        assert_eq!(bytes.len(), batch.encoded_size());
+        let decoded = WriteBatch::decode(&bytes).unwrap();
+        assert_eq!(decoded, batch);
+        let ops: Vec<_> = decoded.iter().collect();
+        assert_eq!(
+            ops,
+            vec![
+                (&b"k1"[..], Some(&b"v1"[..])),
+                (&b"k2"[..], None),
+                (&b"k3"[..], Some(&b""[..])),
+            ]
+        );
+
+        let empty = WriteBatch::new();
+        assert_eq!(WriteBatch::decode(&empty.encode()).unwrap(), empty);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let bytes = test_batch().encode();
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                WriteBatch::decode(&bytes[..len]),
+                Err(Error::Corrupted)
+            ));
+        }
+    }
+
+    #[test]
+    fn decode_corrupted() {
+        let mut bytes = test_batch().encode();
+        bytes[6] ^= 1;
+        assert!(matches!(WriteBatch::decode(&bytes), Err(Error::Corrupted)));
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fs::File, io::Result, os::unix::io::AsRawFd, ptr, slice};
+
+/// A read-only memory mapping of a file.
+///
+/// The mapping covers the file length at the time it is created. It remains
+/// valid after the file is removed, but reads beyond the mapped length must be
+/// served by the file instead.
+pub(crate) struct Mmap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// Safety: the mapping is read-only.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps the whole file for random reads.
+    pub(crate) fn map(file: &File) -> Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(Self {
+                ptr: ptr::null_mut(),
+                len,
+            });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        // The advice is only a hint, so it is fine to ignore the error.
+        unsafe { libc::madvise(ptr, len, libc::MADV_RANDOM) };
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// Returns the length of the mapping.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Copies the mapped bytes at `offset` into the buffer.
+    ///
+    /// Returns the number of bytes copied, which is less than the buffer length
+    /// if the range exceeds the mapping.
+    pub(crate) fn read_at(&self, buf: &mut [u8], offset: u64) -> usize {
+        let data = self.as_slice();
+        let Ok(offset) = usize::try_from(offset) else {
+            return 0;
+        };
+        if offset >= data.len() {
+            return 0;
+        }
+        let n = buf.len().min(data.len() - offset);
+        buf[..n].copy_from_slice(&data[offset..offset + n]);
+        n
+    }
+
+    /// Returns the mapped bytes in the range, or `None` if the range exceeds
+    /// the mapping.
+    pub(crate) fn slice(&self, offset: u64, len: usize) -> Option<&[u8]> {
+        let offset = usize::try_from(offset).ok()?;
+        let end = offset.checked_add(len)?;
+        self.as_slice().get(offset..end)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use std::{future::Future, io::Result, path::Path};
+use std::{future::Future, io::Result, ops::Range, path::Path};
 
 pub use async_trait::async_trait;
 use futures::future::BoxFuture;
 pub use photonio::io::{Read, ReadAt, Write, WriteAt};
 
+mod mmap;
+pub(crate) use mmap::Mmap;
+
 mod stdenv;
 pub use stdenv::Std;
 
@@ -16,6 +19,14 @@ pub struct ReadOptions {
     /// Pass custom flags to the `flags` argument of `open`.
     /// See [`OpenOptionsExt::custome_flags`].
     pub custome_flags: i32,
+
+    /// Serves reads from a memory mapping of the file if the environment
+    /// supports it.
+    ///
+    /// Only the std environment supports it. Tables map their page files
+    /// themselves for [`crate::Options::use_mmap_reads`], so they do not rely
+    /// on this.
+    pub mmap: bool,
 }
 
 ///  Options to configure how the file is written.
@@ -48,6 +59,21 @@ impl Default for WriteOptions {
     }
 }
 
+/// Options to configure how a background task is spawned.
+#[derive(Clone, Debug, Default)]
+pub struct SpawnOptions {
+    /// The name of the thread running the task, if the environment runs it
+    /// on a dedicated thread.
+    pub name: Option<String>,
+
+    /// The CPUs to run the task on.
+    ///
+    /// This is best-effort, it is ignored if the environment or the platform
+    /// does not support it. The std environment supports it on Linux, and the
+    /// PhotonIO environment does not.
+    pub cpu_set: Option<Vec<usize>>,
+}
+
 /// Provides an environment to interact with a specific platform.
 #[async_trait]
 pub trait Env: Clone + Send + Sync {
@@ -74,7 +100,7 @@ pub trait Env: Clone + Send + Sync {
         P: AsRef<Path> + Send;
 
     /// Spawns a task to run in the background.
-    fn spawn_background<F>(&self, f: F) -> BoxFuture<'static, F::Output>
+    fn spawn_background<F>(&self, opt: SpawnOptions, f: F) -> BoxFuture<'static, F::Output>
     where
         F: Future + Send + 'static,
         F::Output: Send;
@@ -108,6 +134,40 @@ pub trait Env: Clone + Send + Sync {
     /// directory, etc.
     /// See alos [`std::fs::metadata`].
     async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::MetedataReader>;
+
+    /// Returns the ranges of a file that contain data.
+    ///
+    /// Holes in sparse files are skipped without reading them.
+    /// See also `SEEK_DATA` and `SEEK_HOLE` in `lseek(2)`.
+    async fn data_ranges<P: AsRef<Path> + Send>(&self, path: P) -> Result<Vec<Range<u64>>>;
+}
+
+/// Returns the ranges of the file that contain data by seeking over holes.
+pub(crate) fn seek_data_ranges(file: &std::fs::File) -> Result<Vec<Range<u64>>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let len = file.metadata()?.len();
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if start < 0 {
+            let err = std::io::Error::last_os_error();
+            // There is no more data after the offset.
+            if err.raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            return Err(err);
+        }
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        ranges.push(start as u64..end as u64);
+        offset = end as u64;
+    }
+    Ok(ranges)
 }
 
 /// Synchronizes modified for the file.
@@ -1,4 +1,4 @@
-use std::{future::Future, io::Result, os::unix::prelude::OpenOptionsExt, path::Path};
+use std::{future::Future, io::Result, ops::Range, os::unix::prelude::OpenOptionsExt, path::Path};
 
 use futures::future::BoxFuture;
 use photonio::{
@@ -6,7 +6,8 @@ use photonio::{
     task,
 };
 
-use super::{async_trait, Env, ReadOptions, Syncer, WriteOptions};
+use super::{async_trait, Env, ReadOptions, SpawnOptions, Syncer, WriteOptions};
+use crate::util::sync::unblock;
 
 /// An implementation of [`Env`] based on PhotonIO.
 #[derive(Clone)]
@@ -27,6 +28,7 @@ impl Env for Photon {
         P: AsRef<Path> + Send,
     {
         let path = path.as_ref();
+        // Reads are not served from mappings here, see `ReadOptions::mmap`.
         OpenOptions::new()
             .read(true)
             .custom_flags(opt.custome_flags)
@@ -52,7 +54,9 @@ impl Env for Photon {
             .await
     }
 
-    fn spawn_background<F>(&self, f: F) -> BoxFuture<'static, F::Output>
+    /// Tasks run on the PhotonIO runtime rather than dedicated threads, so the
+    /// spawn options are ignored.
+    fn spawn_background<F>(&self, _: SpawnOptions, f: F) -> BoxFuture<'static, F::Output>
     where
         F: Future + Send + 'static,
         F::Output: Send,
@@ -97,6 +101,12 @@ impl Env for Photon {
         let metadata = file.metadata().await?;
         Ok(metadata)
     }
+
+    async fn data_ranges<P: AsRef<Path> + Send>(&self, path: P) -> Result<Vec<Range<u64>>> {
+        // There is no asynchronous `lseek`, so seek on another thread.
+        let path = path.as_ref().to_owned();
+        unblock(move || super::seek_data_ranges(&std::fs::File::open(path)?)).await
+    }
 }
 
 impl Syncer for File {
@@ -2,6 +2,7 @@ use std::{
     fs::{File, Metadata, OpenOptions},
     future::Future,
     io::Result,
+    ops::Range,
     os::unix::fs::OpenOptionsExt,
     path::Path,
     thread,
@@ -9,7 +10,9 @@ use std::{
 
 use futures::{executor::block_on, future::BoxFuture};
 
-use super::{async_trait, Env, ReadAt, ReadOptions, Syncer, Write, WriteOptions};
+use super::{
+    async_trait, Env, Mmap, ReadAt, ReadOptions, SpawnOptions, Syncer, Write, WriteOptions,
+};
 
 /// An implementation of [`Env`] based on [`std`] with synchronous I/O.
 #[derive(Clone)]
@@ -33,7 +36,12 @@ impl Env for Std {
             .read(true)
             .custom_flags(opt.custome_flags)
             .open(path.as_ref())?;
-        Ok(PositionalReader(file))
+        let mmap = if opt.mmap {
+            Some(Mmap::map(&file)?)
+        } else {
+            None
+        };
+        Ok(PositionalReader { file, mmap })
     }
 
     async fn open_sequential_writer<P>(
@@ -54,12 +62,23 @@ impl Env for Std {
         Ok(SequentialWriter(file))
     }
 
-    fn spawn_background<F>(&self, f: F) -> BoxFuture<'static, F::Output>
+    fn spawn_background<F>(&self, opt: SpawnOptions, f: F) -> BoxFuture<'static, F::Output>
     where
         F: Future + Send + 'static,
         F::Output: Send,
     {
-        let handle = thread::spawn(move || block_on(f));
+        let mut builder = thread::Builder::new();
+        if let Some(name) = opt.name {
+            builder = builder.name(name);
+        }
+        let handle = builder
+            .spawn(move || {
+                if let Some(cpu_set) = opt.cpu_set {
+                    set_affinity(&cpu_set);
+                }
+                block_on(f)
+            })
+            .expect("spawn background thread fail");
         Box::pin(async { handle.join().unwrap() })
     }
 
@@ -96,9 +115,35 @@ impl Env for Std {
     async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::MetedataReader> {
         std::fs::metadata(path)
     }
+
+    async fn data_ranges<P: AsRef<Path> + Send>(&self, path: P) -> Result<Vec<Range<u64>>> {
+        let file = File::open(path)?;
+        super::seek_data_ranges(&file)
+    }
 }
 
-pub struct PositionalReader(File);
+/// Pins the current thread to the CPUs, ignoring any error.
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu_set: &[usize]) {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in cpu_set {
+            if cpu < libc::CPU_SETSIZE as usize {
+                libc::CPU_SET(cpu, &mut set);
+            }
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_: &[usize]) {}
+
+pub struct PositionalReader {
+    file: File,
+    mmap: Option<Mmap>,
+}
 
 impl ReadAt for PositionalReader {
     type ReadAt<'a> = impl Future<Output = Result<usize>> + 'a;
@@ -106,7 +151,19 @@ impl ReadAt for PositionalReader {
     #[cfg(unix)]
     fn read_at<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> Self::ReadAt<'a> {
         use std::os::unix::fs::FileExt;
-        async move { self.0.read_at(buf, offset) }
+        async move {
+            if let Some(mmap) = &self.mmap {
+                let n = mmap.read_at(buf, offset);
+                if n == buf.len() {
+                    return Ok(n);
+                }
+                // Reads beyond the mapping are served by the file, which may have grown
+                // since it was mapped.
+                let m = self.file.read_at(&mut buf[n..], offset + n as u64)?;
+                return Ok(n + m);
+            }
+            self.file.read_at(buf, offset)
+        }
     }
 }
 
@@ -114,13 +171,13 @@ impl Syncer for PositionalReader {
     type SyncData<'a> = impl Future<Output = Result<()>> + 'a;
 
     fn sync_data(&mut self) -> Self::SyncData<'_> {
-        async move { self.0.sync_data() }
+        async move { self.file.sync_data() }
     }
 
     type SyncAll<'b> = impl Future<Output = Result<()>> + 'b;
 
     fn sync_all(&mut self) -> Self::SyncAll<'_> {
-        async move { self.0.sync_all() }
+        async move { self.file.sync_all() }
     }
 }
 
@@ -166,3 +223,92 @@ impl super::Metadata for std::fs::Metadata {
         std::fs::Metadata::is_symlink(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::{fs::FileExt, io::AsRawFd};
+
+    use super::*;
+
+    #[test]
+    fn data_ranges_skip_holes() {
+        const BLOCK: u64 = 64 << 10;
+
+        let path = std::env::temp_dir().join("test_std_data_ranges");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all_at(&[1].repeat(BLOCK as usize * 3), 0)
+            .unwrap();
+        file.sync_all().unwrap();
+        let ret = unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                BLOCK as libc::off_t,
+                BLOCK as libc::off_t,
+            )
+        };
+        assert_eq!(ret, 0);
+
+        let ranges = block_on(Std.data_ranges(&path)).unwrap();
+        assert_eq!(ranges, vec![0..BLOCK, BLOCK * 2..BLOCK * 3]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn spawn_named_background() {
+        let opt = SpawnOptions {
+            name: Some("photondb-test".into()),
+            cpu_set: Some(vec![0]),
+        };
+        let task = Std.spawn_background(opt, async {
+            thread::current().name().map(|name| name.to_owned())
+        });
+        assert_eq!(block_on(task).as_deref(), Some("photondb-test"));
+    }
+
+    #[test]
+    fn mmap_reads_match_pread() {
+        let path = std::env::temp_dir().join("test_std_mmap_reads");
+        let data: Vec<u8> = (0..8192u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let open = |mmap| {
+            let opt = ReadOptions {
+                mmap,
+                ..Default::default()
+            };
+            block_on(Std.open_positional_reader(&path, opt)).unwrap()
+        };
+        let pread = open(false);
+        let mmap = open(true);
+        for (offset, len) in [(0, 16), (4000, 200), (8000, 192), (8100, 200), (9000, 10)] {
+            let mut a = vec![0; len];
+            let mut b = vec![0; len];
+            let na = block_on(pread.read_at(&mut a, offset)).unwrap();
+            let nb = block_on(mmap.read_at(&mut b, offset)).unwrap();
+            assert_eq!(na, nb);
+            assert_eq!(a[..na], b[..nb]);
+        }
+
+        // Reads beyond the mapping are served after the file grows.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, &[7; 100]).unwrap();
+        let mut buf = vec![0; 200];
+        let n = block_on(mmap.read_at(&mut buf, 8092)).unwrap();
+        assert_eq!(n, 200);
+        assert_eq!(&buf[..100], &data[8092..]);
+        assert_eq!(&buf[100..], &[7; 100]);
+
+        // The mapping remains readable after the file is removed.
+        std::fs::remove_file(&path).unwrap();
+        let mut buf = vec![0; 16];
+        assert_eq!(block_on(mmap.read_at(&mut buf, 0)).unwrap(), 16);
+        assert_eq!(&buf, &data[..16]);
+    }
+}
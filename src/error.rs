@@ -1,3 +1,5 @@
+use std::io::ErrorKind;
+
 use thiserror::Error;
 
 use crate::page_store::Error as PageError;
@@ -6,6 +8,28 @@ use crate::page_store::Error as PageError;
 pub enum Error {
     #[error("Corrupted")]
     Corrupted,
+    /// The storage device is full, writes can be retried after space is
+    /// reclaimed.
+    #[error("Disk full")]
+    DiskFull,
+    #[error("Invalid argument")]
+    InvalidArgument,
+    /// The versions visible at the requested LSN may have been dropped by
+    /// consolidations.
+    ///
+    /// See [`Options::gc_keep_versions_below_lsn`].
+    ///
+    /// [`Options::gc_keep_versions_below_lsn`]: crate::Options::gc_keep_versions_below_lsn
+    #[error("Version expired")]
+    VersionExpired,
+    /// A key read by a transaction has been written since, so the
+    /// transaction can not commit.
+    ///
+    /// See [`Transaction::commit`].
+    ///
+    /// [`Transaction::commit`]: crate::Transaction::commit
+    #[error("Conflict")]
+    Conflict,
     #[error("Unknown")]
     Unknown,
 }
@@ -14,9 +38,24 @@ impl From<PageError> for Error {
     fn from(err: PageError) -> Self {
         match err {
             PageError::Corrupted => Self::Corrupted,
+            PageError::InvalidArgument => Self::InvalidArgument,
+            PageError::Io(err) if err.kind() == ErrorKind::StorageFull => Self::DiskFull,
             _ => Self::Unknown,
         }
     }
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_full_is_disk_full() {
+        let err = PageError::Io(ErrorKind::StorageFull.into());
+        assert!(matches!(Error::from(err), Error::DiskFull));
+        let err = PageError::Io(ErrorKind::PermissionDenied.into());
+        assert!(matches!(Error::from(err), Error::Unknown));
+    }
+}
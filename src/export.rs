@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use crate::{
+    env::{Env, Metadata, ReadOptions, Syncer, WriteOptions},
+    page_store::Error as PageError,
+    util::{
+        checksum::crc32c,
+        codec::{put_slice, Decoder},
+    },
+    Error, Result,
+};
+
+/// The magic bytes at the start of a snapshot file.
+const MAGIC: &[u8; 8] = b"PHDBSNAP";
+/// The version of the snapshot file format.
+const FORMAT_VERSION: u8 = 1;
+/// The length of the magic bytes and the version.
+const HEADER_LEN: usize = MAGIC.len() + 1;
+/// The length of the number of entries and the checksum.
+const FOOTER_LEN: usize = 8 + 4;
+
+/// Encodes the entries of a snapshot file written by [`crate::Table::export`].
+///
+/// The format is:
+///
+/// ```text
+/// | magic (8 bytes) | version (u8) | entry * count | count (u64) | crc32c (u32) |
+/// entry: | key_len (u32) | key | lsn (u64) | value_len (u32) | value |
+/// ```
+///
+/// All integers are little-endian. The format only depends on the entries,
+/// not on how a table lays them out in pages, so it stays readable by later
+/// versions of the crate.
+pub(crate) struct SnapshotEncoder {
+    buf: Vec<u8>,
+    count: u64,
+}
+
+impl SnapshotEncoder {
+    pub(crate) fn new() -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(FORMAT_VERSION);
+        Self { buf, count: 0 }
+    }
+
+    /// Adds the value of the key written at the LSN.
+    pub(crate) fn add(&mut self, key: &[u8], lsn: u64, value: &[u8]) {
+        put_slice(&mut self.buf, key);
+        self.buf.extend_from_slice(&lsn.to_le_bytes());
+        put_slice(&mut self.buf, value);
+        self.count += 1;
+    }
+
+    /// Returns the encoded file.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.buf.extend_from_slice(&self.count.to_le_bytes());
+        let checksum = crc32c(&self.buf);
+        self.buf.extend_from_slice(&checksum.to_le_bytes());
+        self.buf
+    }
+}
+
+/// Decodes the entries of a snapshot file produced by [`SnapshotEncoder`].
+///
+/// Returns [`Error::Corrupted`] if the bytes are truncated, fail the
+/// checksum, or have unknown magic bytes or version.
+pub(crate) fn decode_snapshot(bytes: &[u8]) -> Result<Vec<(&[u8], u64, &[u8])>> {
+    if bytes.len() < HEADER_LEN + FOOTER_LEN {
+        return Err(Error::Corrupted);
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+    if crc32c(content) != checksum {
+        return Err(Error::Corrupted);
+    }
+    let (header, content) = content.split_at(HEADER_LEN);
+    if &header[..MAGIC.len()] != MAGIC || header[MAGIC.len()] != FORMAT_VERSION {
+        return Err(Error::Corrupted);
+    }
+    let (entries, count) = content.split_at(content.len() - 8);
+    let count = u64::from_le_bytes(count.try_into().unwrap());
+    let mut decoder = Decoder::new(entries);
+    let mut result = Vec::new();
+    while decoder.remaining() != 0 {
+        let key = decoder.get_slice()?;
+        let lsn = decoder.get_u64()?;
+        let value = decoder.get_slice()?;
+        result.push((key, lsn, value));
+    }
+    if result.len() as u64 != count {
+        return Err(Error::Corrupted);
+    }
+    Ok(result)
+}
+
+/// Writes the bytes to a new file in the path and syncs it.
+pub(crate) async fn write_file<E: Env>(env: &E, path: &Path, bytes: &[u8]) -> Result<()> {
+    use photonio::io::WriteExt;
+
+    let mut file = env
+        .open_sequential_writer(path, WriteOptions::default())
+        .await
+        .map_err(io_error)?;
+    file.write_all(bytes).await.map_err(io_error)?;
+    file.sync_all().await.map_err(io_error)?;
+    Ok(())
+}
+
+/// Reads the whole file in the path.
+pub(crate) async fn read_file<E: Env>(env: &E, path: &Path) -> Result<Vec<u8>> {
+    use photonio::io::ReadAtExt;
+
+    let len = env.metadata(path).await.map_err(io_error)?.len();
+    let file = env
+        .open_positional_reader(path, ReadOptions::default())
+        .await
+        .map_err(io_error)?;
+    let mut buf = vec![0; len as usize];
+    file.read_exact_at(&mut buf, 0).await.map_err(io_error)?;
+    Ok(buf)
+}
+
+fn io_error(err: std::io::Error) -> Error {
+    PageError::from(err).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_snapshot() -> Vec<u8> {
+        let mut encoder = SnapshotEncoder::new();
+        encoder.add(b"k1", 3, b"v1");
+        encoder.add(b"k2", 1, b"");
+        encoder.add(b"k3", 7, b"v3");
+        encoder.finish()
+    }
+
+    #[test]
+    fn encode_decode() {
+        let bytes = test_snapshot();
+        assert_eq!(
+            decode_snapshot(&bytes).unwrap(),
+            vec![
+                (&b"k1"[..], 3, &b"v1"[..]),
+                (&b"k2"[..], 1, &b""[..]),
+                (&b"k3"[..], 7, &b"v3"[..]),
+            ]
+        );
+        let empty = SnapshotEncoder::new().finish();
+        assert!(decode_snapshot(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let bytes = test_snapshot();
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                decode_snapshot(&bytes[..len]),
+                Err(Error::Corrupted)
+            ));
+        }
+    }
+
+    #[test]
+    fn decode_corrupted() {
+        let mut bytes = test_snapshot();
+        bytes[HEADER_LEN + 5] ^= 1;
+        assert!(matches!(decode_snapshot(&bytes), Err(Error::Corrupted)));
+
+        // A file of another version is rejected even with a valid checksum.
+        let mut bytes = test_snapshot();
+        bytes[MAGIC.len()] = FORMAT_VERSION + 1;
+        let len = bytes.len() - 4;
+        let checksum = crc32c(&bytes[..len]);
+        bytes[len..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(decode_snapshot(&bytes), Err(Error::Corrupted)));
+    }
+}
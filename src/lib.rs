@@ -3,25 +3,38 @@
 // TODO: enable these warnings once the codebase is clean.
 // #![warn(missing_docs, unreachable_pub)]
 
-#![feature(
-    io_error_more,
-    type_alias_impl_trait,
-    hash_drain_filter,
-    pointer_is_aligned
-)]
+#![feature(io_error_more, type_alias_impl_trait)]
 
 mod table;
 pub use table::{RawTable, Table};
 
+mod batch;
+pub use batch::WriteBatch;
+
+mod txn;
+pub use txn::{ReadTxn, Transaction};
+
+mod stream;
+pub use stream::GetStream;
+
+mod export;
+
 mod error;
 pub use error::{Error, Result};
 
 mod options;
-pub use options::Options;
+pub use options::{
+    ChecksumType, Durability, Options, PageAllocHint, ReadRetry, Scheduling, SeekStrategy,
+    StaleWrites,
+};
 
 pub mod env;
 
 mod page;
 mod page_store;
+pub use page_store::{BackgroundHealth, FormatInfo, QuiesceGuard};
 mod tree;
+pub use tree::{AccessHint, Cursor, KeySizes, OwnedScan, VerifyReport};
+#[cfg(feature = "debug")]
+pub use tree::{MappingDump, MappingEntry, PageDump};
 mod util;
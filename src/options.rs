@@ -1,3 +1,13 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{Error, Result};
+
 /// Options to configure a table.
 #[non_exhaustive]
 #[derive(Clone)]
@@ -12,13 +22,334 @@ pub struct Options {
     /// Approximate number of delta pages kept per page chain before it is
     /// consolidated.
     ///
+    /// This can be changed at runtime with [`Table::set_option`].
+    ///
+    /// [`Table::set_option`]: crate::Table::set_option
+    ///
     /// Default: 4
     pub page_chain_length: usize,
 
+    /// Approximate size of delta pages kept per page chain before it is
+    /// consolidated.
+    ///
+    /// A page chain is consolidated when either its length exceeds
+    /// [`Options::page_chain_length`] or the size of its delta pages exceeds
+    /// this, whichever comes first. This prevents a few large deltas from
+    /// slowing down reads.
+    ///
+    /// Default: 64KB
+    pub max_delta_chain_bytes: usize,
+
+    /// The length of page chains above which operations consolidate them
+    /// before going on.
+    ///
+    /// Page chains longer than [`Options::page_chain_length`] are normally
+    /// consolidated after writes, but the consolidation may fail under
+    /// contention and leave long chains behind. Any operation that traverses
+    /// a page chain longer than this consolidates it synchronously, which
+    /// bounds the number of pages a read walks through. Zero disables this.
+    /// Chain lengths are counted up to 255, so larger values disable it too.
+    ///
+    /// Default: 32
+    pub hard_max_delta_chain: usize,
+
+    /// The maximum number of page splits and consolidations in progress at
+    /// once.
+    ///
+    /// Each of them builds new pages in memory, so a flood of them under heavy
+    /// writes can spike memory usage. Operations that would exceed this wait
+    /// for others to finish. Zero means no limit.
+    ///
+    /// Default: 0
+    pub max_concurrent_smos: usize,
+
+    /// The keys at which a new table is split into empty leaf pages.
+    ///
+    /// A new table starts with a single leaf page, which all writers contend
+    /// on until it grows large enough to split. Splitting the table at keys
+    /// that divide the expected writes lets writers to different ranges
+    /// proceed on different pages from the start. The keys need not be
+    /// sorted. They are ignored when an existing table is opened.
+    ///
+    /// Default: empty
+    pub initial_split_keys: Vec<Vec<u8>>,
+
     /// The capacity of [`WriteBuffer`]. It should be power of two.
     ///
     /// Default: 128MB
     pub write_buffer_capacity: u32,
+
+    /// The maximum number of full write buffers waiting to be flushed before
+    /// writes are stalled.
+    ///
+    /// If the flush job can not keep up with writes, writes wait until the
+    /// number of full write buffers drops below this, which bounds the memory
+    /// used by write buffers. Zero disables stalls.
+    ///
+    /// Default: 0
+    pub max_pending_write_buffers: usize,
+
+    /// Whether to reclaim the space of dead pages by punching holes in page
+    /// files instead of rewriting the active pages to new files.
+    ///
+    /// This requires a filesystem that supports `FALLOC_FL_PUNCH_HOLE`.
+    ///
+    /// Default: false
+    pub gc_punch_hole: bool,
+
+    /// The size of writes to page files.
+    ///
+    /// Pages are accumulated in a buffer of this size, rounded up to the
+    /// logical block size of the device, before they are written. With direct
+    /// I/O, only the last write of a page file covers a partial block, which
+    /// is padded, so the device never reads and modifies a block to write it.
+    ///
+    /// Default: 16KB
+    pub write_io_size: usize,
+
+    /// The prefix of the names of the files of the table.
+    ///
+    /// Tables with different prefixes can share a directory, since each table
+    /// only reads, recovers and removes files with its own prefix. If set, the
+    /// prefix must be non-empty and must not contain path separators, or
+    /// [`Error::InvalidArgument`] is returned on open. Files of a table are
+    /// named without a prefix if this is not set, which is compatible with
+    /// tables created before this option existed.
+    ///
+    /// Default: None
+    pub file_prefix: Option<String>,
+
+    /// The algorithm to checksum page files.
+    ///
+    /// Each page and the index of a page file are checksummed, and a page
+    /// that fails its checksum fails the read with [`Error::Corrupted`]. The
+    /// algorithm is recorded in each page file, so files written with a
+    /// different algorithm are still verified correctly.
+    ///
+    /// Default: [`ChecksumType::Crc32c`]
+    pub checksum_type: ChecksumType,
+
+    /// The minimum size of values that are written with their own checksum.
+    ///
+    /// Pages are checksummed when they are written to page files, but a
+    /// value damaged in memory or by a bug before that passes the check. Values
+    /// at least this large are written with a CRC-32C of their own, which
+    /// is verified on every read and fails the read with
+    /// [`Error::Corrupted`] on a mismatch. Small values skip it to save the
+    /// space and CPU. No values are checksummed if this is not set.
+    ///
+    /// [`Error::Corrupted`]: crate::Error::Corrupted
+    ///
+    /// Default: None
+    pub value_checksum_min_size: Option<usize>,
+
+    /// Whether the caller manages the LSNs of reads and writes.
+    ///
+    /// If this is false, the LSNs supplied to [`RawTable`] are ignored. Each
+    /// write is assigned an increasing LSN by the table instead, so the last
+    /// write of a key wins by insertion order, and each read sees the latest
+    /// writes. When the table is opened, the assigned LSNs continue after the
+    /// largest persisted one, which takes a walk through all leaf pages.
+    /// Versions are still stored, but reads can not be pinned to a snapshot
+    /// in this mode.
+    ///
+    /// [`RawTable`]: crate::RawTable
+    ///
+    /// Default: true
+    pub managed_lsn: bool,
+
+    /// Whether writes to [`RawTable`] must have increasing LSNs.
+    ///
+    /// Versions of a key are ordered by their LSNs, and snapshots assume that
+    /// no write with an LSN at or below theirs shows up later. Without this,
+    /// the LSNs supplied by the caller are not checked, and writing an LSN
+    /// smaller than a previous one leads to undefined results: reads may miss
+    /// the write or see it inside an older snapshot. If this is enabled, each
+    /// write must have an LSN larger than every write before it, or it is
+    /// rejected with [`Error::InvalidArgument`]. Writes persisted before the
+    /// table is opened count as well, which takes a walk through all leaf
+    /// pages when it is opened. This is ignored if
+    /// [`Options::managed_lsn`] is false, and by [`Table`], which assigns
+    /// LSNs itself.
+    ///
+    /// [`RawTable`]: crate::RawTable
+    /// [`Table`]: crate::Table
+    ///
+    /// Default: false
+    pub require_monotonic_lsn: bool,
+
+    /// Whether to serve page reads from memory mappings of page files instead
+    /// of direct I/O.
+    ///
+    /// This can be faster for read-heavy workloads on fast storage, at the cost
+    /// of relying on the OS page cache. The page files are mapped by the table
+    /// itself, so this works with any environment, including the default
+    /// PhotonIO one.
+    ///
+    /// Default: false
+    pub use_mmap_reads: bool,
+
+    /// How operations are scheduled when they contend on the same page.
+    ///
+    /// Default: [`Scheduling::Eager`]
+    pub scheduling: Scheduling,
+
+    /// Whether to sync persisted data to the storage device.
+    ///
+    /// [`Durability::None`] is only safe for data that can be thrown away,
+    /// such as tests and caches. Do not use it for durable storage.
+    ///
+    /// Default: [`Durability::Full`]
+    pub durability: Durability,
+
+    /// How keys are searched in leaf pages.
+    ///
+    /// Default: [`SeekStrategy::Binary`]
+    pub seek_strategy: SeekStrategy,
+
+    /// The CPUs to run background jobs on, such as flush and GC.
+    ///
+    /// This allows pinning housekeeping work off the serving cores. It is
+    /// best-effort and ignored where unsupported.
+    ///
+    /// Only environments that run background jobs on dedicated threads, such
+    /// as the std environment on Linux, support it. The default PhotonIO
+    /// environment runs them as tasks on its runtime threads, so the CPU set
+    /// is ignored there, including for tables opened with
+    /// [`crate::Table::open`].
+    ///
+    /// Default: None
+    pub background_cpu_set: Option<Vec<usize>>,
+
+    /// Whether to run background jobs inline instead of spawning them.
+    ///
+    /// If enabled, no background task is spawned. Flushes, consolidations
+    /// triggered by reads, file cleanups and GC only run when the embedder
+    /// calls [`Table::tick`], which suits environments without threads.
+    /// Writes are not stalled in this mode, so
+    /// [`Options::max_pending_write_buffers`] is ignored.
+    ///
+    /// Since nothing runs in the background, this is also the deterministic
+    /// mode for tests. The table reads no clock, and LWW timestamps are always
+    /// given by the caller. The same sequence of writes with the same LSNs and
+    /// ticks, issued from a single task, then produces byte-identical page
+    /// files.
+    ///
+    /// [`Table::tick`]: crate::Table::tick
+    ///
+    /// Default: false
+    pub run_jobs_inline: bool,
+
+    /// The interval between rounds of scrubbing page files.
+    ///
+    /// If set, a background job reads all page files every interval to detect
+    /// damaged files before reads hit them. The index blocks of page files are
+    /// verified against their checksums, and the rest of the files are read to
+    /// surface I/O errors. Files that fail are reported in
+    /// [`BackgroundHealth::corrupted_files`]. Scrubbing is disabled if
+    /// [`Options::run_jobs_inline`] is enabled.
+    ///
+    /// [`BackgroundHealth::corrupted_files`]: crate::BackgroundHealth::corrupted_files
+    ///
+    /// Default: None
+    pub scrub_interval: Option<Duration>,
+
+    /// The maximum rate of reads to scrub page files, in bytes per second.
+    ///
+    /// This keeps scrubbing from competing with foreground I/O. Zero disables
+    /// the limit.
+    ///
+    /// Default: 8MB
+    pub scrub_bytes_per_sec: u64,
+
+    /// Whether to fail on inconsistencies found during recovery instead of
+    /// resolving them.
+    ///
+    /// For example, if two page files claim the same page address, the newer
+    /// file wins unless this is enabled, in which case
+    /// [`Error::Corrupted`] is returned.
+    ///
+    /// Default: false
+    pub paranoid_checks: bool,
+
+    /// The number of page files read at once during recovery.
+    ///
+    /// Recovery reads the page table of every page file, which can take a
+    /// while for large tables on devices that serve concurrent reads well.
+    /// Zero is treated as one.
+    ///
+    /// Default: 1
+    pub recovery_parallelism: usize,
+
+    /// A callback invoked with the number of page files recovered so far and
+    /// the total number of page files, as recovery goes on.
+    ///
+    /// The number of recovered files only grows, and the last call reports
+    /// all files. This allows applications to show the progress of opening
+    /// large tables.
+    ///
+    /// Default: None
+    pub on_recovery_progress: Option<Arc<dyn Fn(usize, usize) + Send + Sync>>,
+
+    /// The LSN below which obsolete versions are dropped when pages are
+    /// consolidated.
+    ///
+    /// Versions visible to reads at or after this LSN are kept, older versions
+    /// are dropped. Applications should advance it to the LSN of the oldest
+    /// snapshot in use, and it must never be advanced past any snapshot that is
+    /// still read from.
+    ///
+    /// This can be changed at runtime with [`Table::set_option`].
+    ///
+    /// [`Table::set_option`]: crate::Table::set_option
+    ///
+    /// Default: `u64::MAX`, which keeps only the latest version of each key.
+    pub gc_keep_versions_below_lsn: u64,
+
+    /// How writes that are not newer than the latest version of their keys are
+    /// handled.
+    ///
+    /// This matters when LSNs are supplied to [`RawTable`], for example, to
+    /// replay a log that may contain duplicates idempotently. Writes with LSNs
+    /// assigned by the table are never stale.
+    ///
+    /// [`RawTable`]: crate::RawTable
+    ///
+    /// Default: [`StaleWrites::Allow`]
+    pub stale_writes: StaleWrites,
+
+    /// How page reads are retried on transient I/O errors.
+    ///
+    /// Default: no retries
+    pub read_retry: ReadRetry,
+
+    /// Whether reads consolidate the leaf pages with long page chains.
+    ///
+    /// If enabled, a read that goes through a leaf page with a chain longer
+    /// than [`Options::page_chain_length`] consolidates the page in the
+    /// background, so that pages read often but rarely written are flattened
+    /// too. The read itself does not wait for the consolidation.
+    ///
+    /// Default: false
+    pub read_triggered_consolidation: bool,
+
+    /// The number of bits per key of the bloom filters of leaf pages, or 0 to
+    /// disable them.
+    ///
+    /// If enabled, a point read that loads a leaf page from a page file builds
+    /// a bloom filter of its keys, which is kept in memory apart from the page.
+    /// Later point reads of keys the filter excludes return without loading
+    /// the page, which saves a disk read for each miss on cold data. A filter
+    /// is dropped once the page changes. About 10 bits per key give a false
+    /// positive rate of 1%.
+    ///
+    /// Default: 0
+    pub leaf_bloom_bits_per_key: usize,
+
+    /// Where the memory of write buffers is allocated.
+    ///
+    /// Default: [`PageAllocHint::Default`]
+    pub page_alloc_hint: PageAllocHint,
 }
 
 impl Default for Options {
@@ -26,11 +357,158 @@ impl Default for Options {
         Self {
             page_size: 8 << 10,
             page_chain_length: 4,
+            max_delta_chain_bytes: 64 << 10,
+            hard_max_delta_chain: 32,
+            max_concurrent_smos: 0,
+            initial_split_keys: Vec::new(),
             write_buffer_capacity: 128 << 20,
+            max_pending_write_buffers: 0,
+            gc_punch_hole: false,
+            write_io_size: 16 << 10,
+            file_prefix: None,
+            checksum_type: ChecksumType::Crc32c,
+            value_checksum_min_size: None,
+            managed_lsn: true,
+            require_monotonic_lsn: false,
+            use_mmap_reads: false,
+            scheduling: Scheduling::Eager,
+            durability: Durability::Full,
+            seek_strategy: SeekStrategy::Binary,
+            background_cpu_set: None,
+            run_jobs_inline: false,
+            scrub_interval: None,
+            scrub_bytes_per_sec: 8 << 20,
+            paranoid_checks: false,
+            recovery_parallelism: 1,
+            on_recovery_progress: None,
+            gc_keep_versions_below_lsn: u64::MAX,
+            stale_writes: StaleWrites::Allow,
+            read_retry: ReadRetry::default(),
+            read_triggered_consolidation: false,
+            leaf_bloom_bits_per_key: 0,
+            page_alloc_hint: PageAllocHint::Default,
         }
     }
 }
 
+/// Options to retry page reads on transient I/O errors.
+///
+/// Errors such as `EIO` or timeouts may be transient on some devices, so the
+/// read is retried a few times before the error is returned. Other errors,
+/// such as unexpected end of files, are returned immediately.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ReadRetry {
+    /// The maximum number of retries of a read.
+    ///
+    /// Default: 0
+    pub max_retries: u32,
+
+    /// The time to wait before the first retry, which is doubled on each
+    /// subsequent retry.
+    ///
+    /// The total wait of a read is capped at 10 seconds, after which the
+    /// error is returned even if retries remain.
+    ///
+    /// Default: 10ms
+    pub backoff: Duration,
+}
+
+impl Default for ReadRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Policies to schedule operations that contend on the same page.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Scheduling {
+    /// Restarts conflicted operations immediately, which gives the best
+    /// throughput but may starve some operations on hot pages.
+    Eager,
+    /// Backs off conflicted operations exponentially with their number of
+    /// restarts, so that no operation is starved on hot pages.
+    Fair,
+}
+
+/// Algorithms to search keys in pages.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SeekStrategy {
+    /// Binary search, which works well for any distribution of keys.
+    Binary,
+    /// Interpolation search, which takes fewer probes for uniformly
+    /// distributed keys, such as sequential integers in big-endian.
+    ///
+    /// It falls back to binary search after a few probes, so skewed keys cost
+    /// a few more probes than binary search.
+    Interpolation,
+}
+
+/// Hints to allocate the memory of write buffers, which hold the recently
+/// written pages.
+///
+/// Hints other than [`PageAllocHint::Default`] are only supported on Linux.
+/// If a hint cannot be satisfied, for example, when no huge pages are
+/// reserved, the memory is allocated as if no hint is given.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PageAllocHint {
+    /// Allocates from the global allocator.
+    Default,
+    /// Maps huge pages to reduce TLB misses.
+    ///
+    /// The write buffer capacity must be a multiple of 2MB, and huge pages must
+    /// be reserved in `/proc/sys/vm/nr_hugepages`.
+    HugePages,
+    /// Binds the memory to the NUMA node with the id, which must be less than
+    /// 64.
+    NumaNode(u32),
+}
+
+/// Policies to handle writes with an LSN not greater than the LSN of the
+/// latest version of the key.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StaleWrites {
+    /// Applies stale writes, which are only visible to reads at their LSNs.
+    Allow,
+    /// Rejects stale writes with [`Error::InvalidArgument`].
+    Reject,
+    /// Drops stale writes silently, so that replaying a write is a no-op.
+    Ignore,
+}
+
+/// Durability guarantees of persisted data.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Durability {
+    /// Syncs page files, manifest files, and directories, so that persisted
+    /// data survives crashes.
+    Full,
+    /// Never syncs files or directories.
+    ///
+    /// This is the fastest mode, but any data may be lost or corrupted after a
+    /// crash, so it must not be used for durable storage.
+    None,
+}
+
+/// Algorithms to checksum persisted data.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChecksumType {
+    /// Disables checksums.
+    None,
+    /// CRC-32C (Castagnoli).
+    Crc32c,
+    /// xxHash64, which has a wider coverage.
+    XxHash64,
+}
+
 /// Options to configure the behavior of reads.
 #[non_exhaustive]
 #[derive(Clone)]
@@ -61,3 +539,114 @@ impl Default for ReadOptions {
 #[non_exhaustive]
 #[derive(Clone)]
 pub struct WriteOptions {}
+
+/// Options that can be changed while a table is open.
+pub(crate) struct RuntimeOptions {
+    page_chain_length: AtomicUsize,
+    gc_keep_versions_below_lsn: AtomicU64,
+}
+
+impl RuntimeOptions {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            page_chain_length: AtomicUsize::new(options.page_chain_length),
+            gc_keep_versions_below_lsn: AtomicU64::new(options.gc_keep_versions_below_lsn),
+        }
+    }
+
+    /// Returns the current [`Options::page_chain_length`].
+    pub(crate) fn page_chain_length(&self) -> usize {
+        self.page_chain_length.load(Ordering::Relaxed)
+    }
+
+    /// Returns the current [`Options::gc_keep_versions_below_lsn`].
+    pub(crate) fn gc_keep_versions_below_lsn(&self) -> u64 {
+        self.gc_keep_versions_below_lsn.load(Ordering::Acquire)
+    }
+
+    /// Returns the options with the current values of runtime options.
+    pub(crate) fn apply(&self, options: &Options) -> Options {
+        Options {
+            page_chain_length: self.page_chain_length(),
+            gc_keep_versions_below_lsn: self.gc_keep_versions_below_lsn(),
+            ..options.clone()
+        }
+    }
+
+    /// Sets the option with the name to the value.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the option is unknown, can not be
+    /// changed at runtime, or the value is invalid.
+    pub(crate) fn set(&self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "page_chain_length" => {
+                let value = value.parse().map_err(|_| Error::InvalidArgument)?;
+                self.page_chain_length.store(value, Ordering::Relaxed);
+                Ok(())
+            }
+            "gc_keep_versions_below_lsn" => {
+                let value = value.parse().map_err(|_| Error::InvalidArgument)?;
+                self.gc_keep_versions_below_lsn
+                    .store(value, Ordering::Release);
+                Ok(())
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_runtime_options() {
+        let options = RuntimeOptions::new(&Options::default());
+        assert_eq!(options.page_chain_length(), 4);
+        options.set("page_chain_length", "2").unwrap();
+        assert_eq!(options.page_chain_length(), 2);
+
+        assert!(matches!(
+            options.set("page_chain_length", "two"),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            options.set("page_size", "4096"),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            options.set("no_such_option", "1"),
+            Err(Error::InvalidArgument)
+        ));
+        assert_eq!(options.page_chain_length(), 2);
+
+        assert_eq!(options.gc_keep_versions_below_lsn(), u64::MAX);
+        options.set("gc_keep_versions_below_lsn", "100").unwrap();
+        assert_eq!(options.gc_keep_versions_below_lsn(), 100);
+    }
+
+    #[test]
+    fn effective_options() {
+        let options = Options {
+            page_size: 4 << 10,
+            ..Default::default()
+        };
+        let runtime = RuntimeOptions::new(&options);
+        runtime.set("page_chain_length", "8").unwrap();
+        let effective = runtime.apply(&options);
+        assert_eq!(effective.page_size, 4 << 10);
+        assert_eq!(effective.page_chain_length, 8);
+        // Defaults that were not set explicitly are present.
+        let defaults = Options::default();
+        assert_eq!(
+            effective.write_buffer_capacity,
+            defaults.write_buffer_capacity
+        );
+        assert_eq!(
+            effective.max_delta_chain_bytes,
+            defaults.max_delta_chain_bytes
+        );
+        assert_eq!(effective.checksum_type, defaults.checksum_type);
+        assert_eq!(effective.durability, defaults.durability);
+    }
+}
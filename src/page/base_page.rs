@@ -28,7 +28,7 @@ pub(crate) struct PagePtr {
 
 impl PagePtr {
     unsafe fn new(ptr: NonNull<u8>, len: usize) -> Self {
-        debug_assert!(ptr.as_ptr().is_aligned_to(8));
+        debug_assert_eq!(ptr.as_ptr() as usize % 8, 0);
         debug_assert!(len >= PAGE_HEADER_LEN);
         Self { ptr, len }
     }
@@ -96,6 +96,11 @@ impl PagePtr {
         unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
     }
 
+    /// Returns a mutable byte slice of the page data.
+    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
     /// Returns the size of the page content.
     pub(super) fn content_size(&self) -> usize {
         self.len - PAGE_HEADER_LEN
@@ -107,7 +112,7 @@ impl PagePtr {
     }
 
     /// Returns a mutable byte slice of the page content.
-    pub(super) fn content_mut<'a>(&mut self) -> &'a mut [u8] {
+    pub(super) fn content_mut(&mut self) -> &mut [u8] {
         unsafe { slice::from_raw_parts_mut(self.content_ptr(), self.content_size()) }
     }
 }
@@ -329,6 +334,27 @@ impl PageFlags {
     fn kind(&self) -> PageKind {
         self.0.into()
     }
+
+    fn is_valid(&self) -> bool {
+        let kind = self.0 & PAGE_KIND_MASK;
+        self.0 & !(PAGE_TIER_MASK | PAGE_KIND_MASK) == 0
+            && (kind == PAGE_KIND_DATA || kind == PAGE_KIND_SPLIT)
+    }
+}
+
+/// Returns true if the bytes start with the header of a data page that has no
+/// pages behind it on the chain.
+///
+/// Unlike [`PageRef::new`], this accepts unaligned bytes from untrusted
+/// sources.
+pub(crate) fn is_base_page_header(data: &[u8]) -> bool {
+    if data.len() < PAGE_HEADER_LEN {
+        return false;
+    }
+    let flags = PageFlags(data[PAGE_EPOCH_LEN]);
+    let chain_len = data[PAGE_EPOCH_LEN + 1];
+    let chain_next = u64::from_le_bytes(data[8..PAGE_HEADER_LEN].try_into().unwrap());
+    flags.is_valid() && flags.kind().is_data() && chain_len == 1 && chain_next == 0
 }
 
 /// Builds a page with basic information.
@@ -342,6 +368,11 @@ impl PageBuilder {
         Self { tier, kind }
     }
 
+    /// Returns the size of a page with the given content size.
+    pub(crate) fn size(&self, content_size: usize) -> usize {
+        PAGE_HEADER_LEN + content_size
+    }
+
     pub(crate) fn build(&self, page: &mut PageBuf<'_>) {
         let flags = PageFlags::new(self.tier, self.kind);
         page.set_flags(flags);
@@ -392,4 +423,28 @@ mod tests {
         assert_eq!(page.data().len(), layout.size());
         assert_eq!(page.content().len(), layout.size() - PAGE_HEADER_LEN);
     }
+
+    #[test]
+    fn base_page_header() {
+        let mut buf = vec![0u64; 2];
+        let buf =
+            unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, PAGE_HEADER_LEN) };
+        let mut page = PageBuf::new(buf);
+        PageBuilder::new(PageTier::Inner, PageKind::Data).build(&mut page);
+        page.set_epoch(7);
+        assert!(is_base_page_header(page.data()));
+        assert!(!is_base_page_header(&page.data()[..PAGE_HEADER_LEN - 1]));
+
+        page.set_chain_next(1);
+        assert!(!is_base_page_header(page.data()));
+        page.set_chain_next(0);
+
+        PageBuilder::new(PageTier::Leaf, PageKind::Split).build(&mut page);
+        assert!(!is_base_page_header(page.data()));
+
+        // Unknown flags.
+        let mut data = page.data().to_vec();
+        data[PAGE_EPOCH_LEN] = 0b1000_0000;
+        assert!(!is_base_page_header(&data));
+    }
 }
@@ -21,6 +21,12 @@ pub(crate) trait DecodeFrom {
     ///
     /// The decoder must have enough data to decode the object.
     unsafe fn decode_from(decoder: &mut Decoder) -> Self;
+
+    /// Returns the size of the object encoded at the start of the buffer, or
+    /// `None` if the buffer does not hold a valid encoding.
+    ///
+    /// This is checked before decoding untrusted data.
+    fn encoded_size(buf: &[u8]) -> Option<usize>;
 }
 
 // An unsafe, little-endian encoder.
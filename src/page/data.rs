@@ -1,4 +1,6 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, fmt};
+
+use crate::util::fmt::{format_range, DEFAULT_MAX_KEY_LEN};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Key<'a> {
@@ -28,6 +30,48 @@ impl PartialOrd for Key<'_> {
     }
 }
 
+/// Compares a search target with the keys in a page.
+///
+/// This allows searching a page with a target of a different type than its
+/// keys.
+pub(crate) trait Comparable<K> {
+    /// Returns the ordering of the target relative to the key.
+    fn compare(&self, key: &K) -> Ordering;
+}
+
+impl<K: Ord> Comparable<K> for K {
+    fn compare(&self, key: &K) -> Ordering {
+        self.cmp(key)
+    }
+}
+
+/// A raw key is ordered before all versions of the same key, so that searching
+/// with it finds the newest version.
+impl Comparable<Key<'_>> for [u8] {
+    fn compare(&self, key: &Key<'_>) -> Ordering {
+        self.cmp(key.raw).then(Ordering::Less)
+    }
+}
+
+/// Keys that pages are split at.
+pub(crate) trait SplitKey {
+    /// Returns the raw key, which starts the range of the right page after a
+    /// split.
+    fn as_raw(&self) -> &[u8];
+}
+
+impl SplitKey for &[u8] {
+    fn as_raw(&self) -> &[u8] {
+        self
+    }
+}
+
+impl SplitKey for Key<'_> {
+    fn as_raw(&self) -> &[u8] {
+        self.raw
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Range<'a> {
     pub(crate) start: &'a [u8],
@@ -41,12 +85,47 @@ impl<'a> Range<'a> {
             end: None,
         }
     }
+
+    /// Returns true if the key is in the range.
+    ///
+    /// The start is inclusive, and an empty start is the smallest key. The
+    /// end is exclusive, and no end means the range is unbounded.
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        self.start <= key && is_before_end(key, self.end)
+    }
+}
+
+/// Returns true if the key is before the exclusive range end.
+///
+/// No end means the range is unbounded, so every key is before it. Use this
+/// instead of comparing with the end directly to treat it consistently.
+pub(crate) fn is_before_end(key: &[u8], end: Option<&[u8]>) -> bool {
+    end.map_or(true, |end| key < end)
+}
+
+impl fmt::Display for Range<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = format_range(self.start, self.end, DEFAULT_MAX_KEY_LEN);
+        f.write_str(&range)
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) enum Value<'a> {
     Put(&'a [u8]),
     Delete,
+    /// A put with an application timestamp.
+    ///
+    /// Of consecutive versions of a key that are all of this kind, the one
+    /// with the highest timestamp wins regardless of their LSNs, and ties are
+    /// broken by LSN. Other kinds of versions override older versions by LSN
+    /// as usual.
+    Lww(u64, &'a [u8]),
+    /// A put with a CRC-32C checksum of the value.
+    ///
+    /// Reads verify the value against the checksum, which detects damage to
+    /// large values that nothing else covers.
+    Checked(u32, &'a [u8]),
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -60,3 +139,45 @@ impl Index {
         Self { id, epoch }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_boundaries() {
+        // The leftmost and rightmost pages have empty bounds.
+        let full = Range::full();
+        assert!(full.contains(b""));
+        assert!(full.contains(b"\xff\xff"));
+
+        let left = Range {
+            start: b"",
+            end: Some(b"m"),
+        };
+        assert!(left.contains(b""));
+        assert!(left.contains(b"l\xff"));
+        assert!(!left.contains(b"m"));
+
+        let right = Range {
+            start: b"m",
+            end: None,
+        };
+        assert!(!right.contains(b"l\xff"));
+        assert!(right.contains(b"m"));
+        assert!(right.contains(b"\xff\xff"));
+
+        // Keys equal to a boundary belong to the page on the right.
+        let middle = Range {
+            start: b"b",
+            end: Some(b"d"),
+        };
+        assert!(middle.contains(b"b"));
+        assert!(middle.contains(b"c"));
+        assert!(!middle.contains(b"d"));
+        assert!(!middle.contains(b"a"));
+
+        assert!(is_before_end(b"\xff", None));
+        assert!(!is_before_end(b"d", Some(b"d")));
+    }
+}
@@ -4,13 +4,15 @@ pub(crate) use iter::{
 };
 
 mod data;
-pub(crate) use data::{Index, Key, Range, Value};
+pub(crate) use data::{is_before_end, Comparable, Index, Key, Range, SplitKey, Value};
 
 mod codec;
 pub(crate) use codec::{DecodeFrom, EncodeTo};
 
 mod base_page;
-pub(crate) use base_page::{PageBuf, PageBuilder, PageKind, PageRef, PageTier};
+pub(crate) use base_page::{
+    is_base_page_header, PageBuf, PageBuilder, PageKind, PageRef, PageTier,
+};
 
 mod sorted_page;
 pub(crate) use sorted_page::{SortedPageBuilder, SortedPageIter, SortedPageRef};
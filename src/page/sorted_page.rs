@@ -1,9 +1,17 @@
-use std::{cmp::Ordering, marker::PhantomData, mem, ops::Deref, slice};
+use std::{cmp::Ordering, fmt::Debug, marker::PhantomData, mem, ops::Deref, slice};
 
 use super::{
     codec::*, data::*, PageBuf, PageBuilder, PageKind, PageRef, PageTier, RewindableIterator,
     SeekableIterator,
 };
+use crate::{
+    page_store::{Error, Result},
+    SeekStrategy,
+};
+
+/// The maximum number of interpolation steps before falling back to binary
+/// search, which bounds the cost on skewed pages.
+const MAX_INTERPOLATION_STEPS: usize = 4;
 
 pub(crate) struct SortedPageBuilder<I> {
     base: PageBuilder,
@@ -15,7 +23,7 @@ pub(crate) struct SortedPageBuilder<I> {
 impl<'a, I, K, V> SortedPageBuilder<I>
 where
     I: RewindableIterator<Item = (K, V)>,
-    K: EncodeTo,
+    K: EncodeTo + Ord + Clone + Debug,
     V: EncodeTo,
 {
     pub(crate) fn new(tier: PageTier, kind: PageKind) -> Self {
@@ -27,12 +35,26 @@ where
         }
     }
 
+    /// Returns the size of the page to build, including the page header.
     pub(crate) fn size(&self) -> usize {
-        self.size
+        self.base.size(self.size)
     }
 
+    /// Sets the items of the page.
+    ///
+    /// The iterator must yield items in key order. This is verified in debug
+    /// builds, since an unsorted page breaks searches silently.
     pub(crate) fn with_iter(mut self, mut iter: I) -> Self {
+        #[cfg(debug_assertions)]
+        let mut last: Option<K> = None;
         for (k, v) in &mut iter {
+            #[cfg(debug_assertions)]
+            {
+                if let Some(last) = &last {
+                    assert!(last <= &k, "unsorted page items: {last:?} before {k:?}");
+                }
+                last = Some(k.clone());
+            }
             self.size += k.encode_size() + v.encode_size();
             self.num_items += 1;
         }
@@ -42,7 +64,7 @@ where
     }
 
     pub(crate) fn build(mut self, page: &'a mut PageBuf<'_>) {
-        assert_eq!(page.size(), self.size);
+        assert_eq!(page.size(), self.size());
         self.base.build(page);
         if let Some(mut iter) = self.iter.take() {
             unsafe {
@@ -98,24 +120,74 @@ where
     K: DecodeFrom + Ord,
     V: DecodeFrom,
 {
+    /// Creates a reference to the sorted page.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page content is inconsistent. Use [`Self::try_new`] to
+    /// handle it as an error.
     pub(crate) fn new(page: PageRef<'a>) -> Self {
+        Self::try_new(page).expect("corrupted sorted page")
+    }
+
+    /// Creates a reference to the sorted page.
+    ///
+    /// Returns [`Error::Corrupted`] if the offsets are inconsistent with the
+    /// content size. The items themselves are not decoded, see
+    /// [`Self::validate`].
+    pub(crate) fn try_new(page: PageRef<'a>) -> Result<Self> {
         let content = page.content();
         let offsets = unsafe {
             let ptr = content.as_ptr() as *const u32;
-            let size = if content.is_empty() {
+            let size = if content.len() < mem::size_of::<u32>() {
                 0
             } else {
                 u32::from_le(ptr.read()) as usize
             };
+            // The first offset is the size of the offsets, so it must fit in the content.
+            if size > content.len() || size % mem::size_of::<u32>() != 0 {
+                return Err(Error::Corrupted);
+            }
             let num_offsets = size / mem::size_of::<u32>();
             slice::from_raw_parts(ptr, num_offsets)
         };
-        Self {
+        let page = Self {
             page,
             content,
             offsets,
             _marker: PhantomData,
+        };
+        page.validate_offsets()?;
+        Ok(page)
+    }
+
+    /// Checks that each item lies between the offsets and the end of the
+    /// content, so that no item is sliced out of bounds.
+    fn validate_offsets(&self) -> Result<()> {
+        let mut last = self.item_offset(0).unwrap_or(0);
+        for index in 0..self.len() {
+            let offset = self.item_offset(index).unwrap();
+            if offset < last || offset > self.content.len() {
+                return Err(Error::Corrupted);
+            }
+            last = offset;
         }
+        Ok(())
+    }
+
+    /// Checks that each item holds a valid key and value.
+    ///
+    /// This decodes every item, so it is left to verification and to pages
+    /// from untrusted sources.
+    pub(crate) fn validate(&self) -> Result<()> {
+        for index in 0..self.len() {
+            let item = self.item(index).unwrap();
+            let key_size = K::encoded_size(item).ok_or(Error::Corrupted)?;
+            if V::encoded_size(&item[key_size..]) != Some(item.len() - key_size) {
+                return Err(Error::Corrupted);
+            }
+        }
+        Ok(())
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -135,27 +207,134 @@ where
         }
     }
 
-    pub(crate) fn rank(&self, target: &K) -> Result<usize, usize> {
+    pub(crate) fn rank<T>(&self, target: &T) -> Result<usize, usize>
+    where
+        T: Comparable<K> + ?Sized,
+    {
         let mut left = 0;
         let mut right = self.len();
         while left < right {
             let mid = (left + right) / 2;
-            let key = unsafe {
-                let item = self.item(mid).unwrap();
-                let mut dec = Decoder::new(item);
-                K::decode_from(&mut dec)
+            let key = self.key(mid);
+            match target.compare(&key) {
+                Ordering::Greater => left = mid + 1,
+                Ordering::Less => right = mid,
+                Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(left)
+    }
+
+    /// Like [`Self::rank`], but searches with the given strategy.
+    pub(crate) fn rank_by(&self, target: &K, strategy: SeekStrategy) -> Result<usize, usize>
+    where
+        K: InterpolationKey,
+    {
+        self.rank_by_impl(target, strategy, &mut 0)
+    }
+
+    fn rank_by_impl(
+        &self,
+        target: &K,
+        strategy: SeekStrategy,
+        probes: &mut usize,
+    ) -> Result<usize, usize>
+    where
+        K: InterpolationKey,
+    {
+        let mut left = 0;
+        let mut right = self.len();
+        let mut steps = match strategy {
+            SeekStrategy::Binary => MAX_INTERPOLATION_STEPS,
+            SeekStrategy::Interpolation => 0,
+        };
+        // The approximate positions of the keys at the bounds.
+        let mut bounds = None;
+        let target_pos = target.position();
+        while left < right {
+            let mid = if steps < MAX_INTERPOLATION_STEPS && right - left > 2 {
+                steps += 1;
+                let (lo, hi) = *bounds.get_or_insert_with(|| {
+                    *probes += 2;
+                    (self.key(left).position(), self.key(right - 1).position())
+                });
+                interpolate(left, right, lo, hi, target_pos)
+            } else {
+                (left + right) / 2
             };
+            *probes += 1;
+            let key = self.key(mid);
             match key.cmp(target) {
-                Ordering::Less => left = mid + 1,
-                Ordering::Greater => right = mid,
+                Ordering::Less => {
+                    left = mid + 1;
+                    if let Some((lo, _)) = bounds.as_mut() {
+                        *lo = key.position();
+                    }
+                }
+                Ordering::Greater => {
+                    right = mid;
+                    if let Some((_, hi)) = bounds.as_mut() {
+                        *hi = key.position();
+                    }
+                }
                 Ordering::Equal => return Ok(mid),
             }
         }
         Err(left)
     }
 
-    pub(crate) fn split(&self) -> Option<(K, SortedPageIter<'a, K, V>)> {
-        todo!()
+    /// Splits the page into two halves at a key in the middle.
+    ///
+    /// Returns the first key of the right half and an iterator over the items
+    /// of it, or `None` if the page can not be split. All versions of a raw key
+    /// stay in the same half, so a page with a single raw key can not be split.
+    pub(crate) fn split(&self) -> Option<(K, SortedPageIter<'a, K, V>)>
+    where
+        K: SplitKey,
+    {
+        let mid = self.len() / 2;
+        let (mid_key, _) = self.get(mid)?;
+        // Move all versions of the middle key to the right, or to the left if
+        // they start the page.
+        let mut index = self.partition_point(|key| key.as_raw() < mid_key.as_raw());
+        if index == 0 {
+            index = self.partition_point(|key| key.as_raw() <= mid_key.as_raw());
+        }
+        let (split_key, _) = self.get(index)?;
+        let page = Self {
+            page: self.page,
+            content: self.content,
+            offsets: self.offsets,
+            _marker: PhantomData,
+        };
+        Some((split_key, SortedPageIter::with_start(page, index)))
+    }
+
+    /// Returns the index of the first key for which the predicate is false,
+    /// given that it is true for all keys before that.
+    fn partition_point<F>(&self, pred: F) -> usize
+    where
+        F: Fn(&K) -> bool,
+    {
+        let mut left = 0;
+        let mut right = self.len();
+        while left < right {
+            let mid = (left + right) / 2;
+            if pred(&self.key(mid)) {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+        left
+    }
+
+    fn key(&self, index: usize) -> K {
+        unsafe {
+            let item = self.item(index).unwrap();
+            let mut dec = Decoder::new(item);
+            K::decode_from(&mut dec)
+        }
     }
 
     fn item(&self, index: usize) -> Option<&[u8]> {
@@ -172,6 +351,41 @@ where
     }
 }
 
+/// Estimates the index of the target position in `[left, right)`, assuming
+/// that positions are uniformly distributed between `lo` and `hi`.
+fn interpolate(left: usize, right: usize, lo: u64, hi: u64, target: u64) -> usize {
+    if target <= lo || hi <= lo {
+        return left;
+    }
+    if target >= hi {
+        return right - 1;
+    }
+    let offset = (target - lo) as u128 * (right - 1 - left) as u128 / (hi - lo) as u128;
+    left + offset as usize
+}
+
+/// Keys that can be mapped to numeric positions for interpolation search.
+pub(crate) trait InterpolationKey {
+    /// Returns a position that is monotonic with the order of keys.
+    fn position(&self) -> u64;
+}
+
+impl InterpolationKey for &[u8] {
+    fn position(&self) -> u64 {
+        // Use the first eight bytes as a big-endian number.
+        let mut buf = [0; 8];
+        let n = self.len().min(buf.len());
+        buf[..n].copy_from_slice(&self[..n]);
+        u64::from_be_bytes(buf)
+    }
+}
+
+impl InterpolationKey for Key<'_> {
+    fn position(&self) -> u64 {
+        self.raw.position()
+    }
+}
+
 impl<'a, K, V> Deref for SortedPageRef<'a, K, V> {
     type Target = PageRef<'a>;
 
@@ -193,12 +407,22 @@ where
 
 pub(crate) struct SortedPageIter<'a, K, V> {
     page: SortedPageRef<'a, K, V>,
+    start: usize,
     next: usize,
 }
 
 impl<'a, K, V> SortedPageIter<'a, K, V> {
     pub(crate) fn new(page: SortedPageRef<'a, K, V>) -> Self {
-        Self { page, next: 0 }
+        Self::with_start(page, 0)
+    }
+
+    /// Creates an iterator over the items from the index to the end.
+    fn with_start(page: SortedPageRef<'a, K, V>, start: usize) -> Self {
+        Self {
+            page,
+            start,
+            next: start,
+        }
     }
 }
 
@@ -236,10 +460,11 @@ where
     V: DecodeFrom,
 {
     fn seek(&mut self, target: &K) {
-        self.next = match self.page.rank(target) {
+        let next = match self.page.rank(target) {
             Ok(i) => i,
             Err(i) => i,
         };
+        self.next = next.max(self.start);
     }
 }
 
@@ -249,7 +474,7 @@ where
     V: DecodeFrom,
 {
     fn rewind(&mut self) {
-        self.next = 0;
+        self.next = self.start;
     }
 }
 
@@ -269,6 +494,13 @@ impl DecodeFrom for &[u8] {
         let len = dec.get_u32() as usize;
         dec.get_slice(len)
     }
+
+    fn encoded_size(buf: &[u8]) -> Option<usize> {
+        let len = buf.get(..mem::size_of::<u32>())?;
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let size = mem::size_of::<u32>().checked_add(len)?;
+        (size <= buf.len()).then_some(size)
+    }
 }
 
 impl EncodeTo for Key<'_> {
@@ -288,16 +520,25 @@ impl DecodeFrom for Key<'_> {
         let lsn = dec.get_u64();
         Self::new(raw, lsn)
     }
+
+    fn encoded_size(buf: &[u8]) -> Option<usize> {
+        let size = <&[u8]>::encoded_size(buf)? + mem::size_of::<u64>();
+        (size <= buf.len()).then_some(size)
+    }
 }
 
 const VALUE_KIND_PUT: u8 = 0;
 const VALUE_KIND_DELETE: u8 = 1;
+const VALUE_KIND_LWW: u8 = 2;
+const VALUE_KIND_CHECKED: u8 = 3;
 
 impl EncodeTo for Value<'_> {
     fn encode_size(&self) -> usize {
         1 + match self {
             Self::Put(v) => v.len(),
             Self::Delete => 0,
+            Self::Lww(_, v) => mem::size_of::<u64>() + v.len(),
+            Self::Checked(_, v) => mem::size_of::<u32>() + v.len(),
         }
     }
 
@@ -308,6 +549,16 @@ impl EncodeTo for Value<'_> {
                 enc.put_slice(v);
             }
             Value::Delete => enc.put_u8(VALUE_KIND_DELETE),
+            Value::Lww(timestamp, v) => {
+                enc.put_u8(VALUE_KIND_LWW);
+                enc.put_u64(*timestamp);
+                enc.put_slice(v);
+            }
+            Value::Checked(checksum, v) => {
+                enc.put_u8(VALUE_KIND_CHECKED);
+                enc.put_u32(*checksum);
+                enc.put_slice(v);
+            }
         }
     }
 }
@@ -318,9 +569,29 @@ impl DecodeFrom for Value<'_> {
         match kind {
             VALUE_KIND_PUT => Self::Put(dec.get_slice(dec.remaining())),
             VALUE_KIND_DELETE => Self::Delete,
+            VALUE_KIND_LWW => {
+                let timestamp = dec.get_u64();
+                Self::Lww(timestamp, dec.get_slice(dec.remaining()))
+            }
+            VALUE_KIND_CHECKED => {
+                let checksum = dec.get_u32();
+                Self::Checked(checksum, dec.get_slice(dec.remaining()))
+            }
             _ => unreachable!(),
         }
     }
+
+    fn encoded_size(buf: &[u8]) -> Option<usize> {
+        // The value takes the rest of the item.
+        let min_size = 1 + match *buf.first()? {
+            VALUE_KIND_PUT => 0,
+            VALUE_KIND_DELETE => return (buf.len() == 1).then_some(1),
+            VALUE_KIND_LWW => mem::size_of::<u64>(),
+            VALUE_KIND_CHECKED => mem::size_of::<u32>(),
+            _ => return None,
+        };
+        (min_size <= buf.len()).then_some(buf.len())
+    }
 }
 
 impl EncodeTo for Index {
@@ -340,4 +611,302 @@ impl DecodeFrom for Index {
         let epoch = dec.get_u64();
         Self::new(id, epoch)
     }
+
+    fn encoded_size(buf: &[u8]) -> Option<usize> {
+        let size = mem::size_of::<u64>() * 2;
+        (size <= buf.len()).then_some(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{alloc, dealloc, Layout};
+
+    use super::*;
+    use crate::page::SliceIter;
+
+    /// An aligned buffer to build pages in tests.
+    pub(crate) struct TestBuf {
+        ptr: *mut u8,
+        layout: Layout,
+    }
+
+    impl TestBuf {
+        pub(crate) fn new(size: usize) -> Self {
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            let ptr = unsafe { alloc(layout) };
+            Self { ptr, layout }
+        }
+
+        pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+            unsafe { slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+        }
+
+        fn page(&self) -> PageRef<'_> {
+            PageRef::new(unsafe { slice::from_raw_parts(self.ptr, self.layout.size()) })
+        }
+    }
+
+    impl Drop for TestBuf {
+        fn drop(&mut self) {
+            unsafe { dealloc(self.ptr, self.layout) }
+        }
+    }
+
+    /// Builds a leaf data page with the items.
+    fn build_page(items: &[(Key<'_>, Value<'_>)]) -> TestBuf {
+        let builder =
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(SliceIter::new(items));
+        let mut buf = TestBuf::new(builder.size());
+        let mut page = PageBuf::new(buf.as_mut_slice());
+        builder.build(&mut page);
+        buf
+    }
+
+    #[test]
+    fn empty_value() {
+        let data = [
+            (Key::new(b"a", 2), Value::Put(&[])),
+            (Key::new(b"b", 1), Value::Delete),
+        ];
+        let buf = build_page(&data);
+        let page = SortedPageRef::<Key, Value>::from(buf.page());
+        assert_eq!(page.len(), 2);
+        // An empty value is present and distinct from a tombstone.
+        assert_eq!(page.get(0), Some(data[0]));
+        assert_eq!(page.get(1), Some(data[1]));
+        assert_ne!(page.get(0).unwrap().1, Value::Delete);
+    }
+
+    #[test]
+    fn rank_raw_key() {
+        let data = [
+            (Key::new(b"a", 3), Value::Put(b"a3")),
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+            (Key::new(b"c", 5), Value::Put(b"c5")),
+            (Key::new(b"c", 4), Value::Delete),
+        ];
+        let buf = build_page(&data);
+        let page = SortedPageRef::<Key, Value>::from(buf.page());
+        // A raw key points to the newest version of the key.
+        assert_eq!(page.rank(b"a".as_slice()), Err(0));
+        assert_eq!(page.rank(b"c".as_slice()), Err(2));
+        assert_eq!(page.rank(b"".as_slice()), Err(0));
+        assert_eq!(page.rank(b"b".as_slice()), Err(2));
+        assert_eq!(page.rank(b"d".as_slice()), Err(4));
+        // It finds the same position as a key with the maximum LSN.
+        for raw in [b"a", b"b", b"c", b"d"] {
+            let key = Key::new(raw, u64::MAX);
+            assert_eq!(page.rank(raw.as_slice()), page.rank(&key));
+        }
+    }
+
+    #[test]
+    fn split_keeps_versions_together() {
+        let data = [
+            (Key::new(b"a", 2), Value::Put(b"a2")),
+            (Key::new(b"b", 3), Value::Put(b"b3")),
+            (Key::new(b"b", 2), Value::Delete),
+            (Key::new(b"b", 1), Value::Put(b"b1")),
+            (Key::new(b"c", 1), Value::Put(b"c1")),
+        ];
+        let buf = build_page(&data);
+        let page = SortedPageRef::<Key, Value>::from(buf.page());
+        // The middle key moves to the right with all its versions.
+        let (split_key, mut iter) = page.split().unwrap();
+        assert_eq!(split_key, data[1].0);
+        assert_eq!(iter.by_ref().collect::<Vec<_>>(), data[1..]);
+        // Rewinding and seeking stay in the right half.
+        iter.rewind();
+        assert_eq!(iter.next(), Some(data[1]));
+        iter.seek(&Key::new(b"a", 2));
+        assert_eq!(iter.next(), Some(data[1]));
+
+        // The versions of the first key stay on the left.
+        let data = [
+            (Key::new(b"a", 3), Value::Put(b"a3")),
+            (Key::new(b"a", 2), Value::Put(b"a2")),
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+            (Key::new(b"b", 1), Value::Put(b"b1")),
+        ];
+        let buf = build_page(&data);
+        let page = SortedPageRef::<Key, Value>::from(buf.page());
+        let (split_key, iter) = page.split().unwrap();
+        assert_eq!(split_key, data[3].0);
+        assert_eq!(iter.collect::<Vec<_>>(), data[3..]);
+
+        // A single key can not be split.
+        let data = [
+            (Key::new(b"a", 2), Value::Put(b"a2")),
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+        ];
+        let buf = build_page(&data);
+        let page = SortedPageRef::<Key, Value>::from(buf.page());
+        assert!(page.split().is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "unsorted page items")]
+    fn unsorted_items() {
+        let data = [
+            (Key::new(b"b", 1), Value::Put(b"b")),
+            (Key::new(b"a", 1), Value::Put(b"a")),
+        ];
+        SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(SliceIter::new(&data));
+    }
+
+    /// A xorshift generator to build reproducible random pages.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self, max_len: u64) -> Vec<u8> {
+            let len = self.next() % (max_len + 1);
+            // Use a small alphabet to produce shared prefixes and duplicated keys.
+            (0..len).map(|_| b'a' + (self.next() % 4) as u8).collect()
+        }
+    }
+
+    #[test]
+    fn random_pages() {
+        use std::{cmp::Reverse, collections::BTreeMap};
+
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..200 {
+            // Keys are ordered by the raw key ascendingly and the LSN descendingly.
+            let mut map = BTreeMap::new();
+            for _ in 0..rng.next() % 64 {
+                let key = (rng.bytes(8), Reverse(rng.next() % 8));
+                let value = if rng.next() % 4 == 0 {
+                    None
+                } else {
+                    Some(rng.bytes(32))
+                };
+                map.insert(key, value);
+            }
+            let data: Vec<_> = map
+                .iter()
+                .map(|((raw, Reverse(lsn)), value)| {
+                    let value = match value {
+                        Some(v) => Value::Put(v.as_slice()),
+                        None => Value::Delete,
+                    };
+                    (Key::new(raw, *lsn), value)
+                })
+                .collect();
+
+            let buf = build_page(&data);
+            let page_ref = buf.page();
+            let page = SortedPageRef::<Key, Value>::try_new(page_ref).unwrap();
+
+            assert_eq!(page.len(), data.len());
+            for (i, item) in data.iter().enumerate() {
+                assert_eq!(page.get(i).as_ref(), Some(item));
+                assert_eq!(page.rank(&item.0), Ok(i));
+            }
+            assert_eq!(page.get(data.len()), None);
+            let items: Vec<_> = SortedPageIter::<Key, Value>::from(page_ref).collect();
+            assert_eq!(items, data);
+
+            for _ in 0..16 {
+                let raw = rng.bytes(8);
+                let probe = Key::new(&raw, rng.next() % 8);
+                let expect = data.binary_search_by(|(k, _)| k.cmp(&probe));
+                assert_eq!(page.rank(&probe), expect);
+                let mut iter = SortedPageIter::<Key, Value>::from(page_ref);
+                iter.seek(&probe);
+                let index = expect.unwrap_or_else(|i| i);
+                assert_eq!(iter.next().as_ref(), data.get(index));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_offsets() {
+        let data = [
+            (Key::new(b"a", 2), Value::Put(b"1")),
+            (Key::new(b"b", 1), Value::Put(b"2")),
+        ];
+        let mut buf = build_page(&data);
+        let page = buf.page();
+        assert!(SortedPageRef::<Key, Value>::try_new(page).is_ok());
+        let header_len = page.size() - page.content().len();
+        let first_offset = buf.as_mut_slice()[header_len..header_len + 4].to_vec();
+
+        // A first offset beyond the page content or not aligned to the offset size.
+        let bad_offsets = [u32::MAX, 5];
+        for offset in bad_offsets {
+            let content = &mut buf.as_mut_slice()[header_len..];
+            content[..4].copy_from_slice(&offset.to_le_bytes());
+            assert!(matches!(
+                SortedPageRef::<Key, Value>::try_new(buf.page()),
+                Err(Error::Corrupted)
+            ));
+        }
+
+        // A later offset beyond the page content.
+        let content = &mut buf.as_mut_slice()[header_len..];
+        content[..4].copy_from_slice(&first_offset);
+        content[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            SortedPageRef::<Key, Value>::try_new(buf.page()),
+            Err(Error::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn interpolation_search() {
+        // Compares the probe counts of both strategies over all keys.
+        fn probes(keys: &[u64]) -> (usize, usize) {
+            let raw = keys.iter().map(|k| k.to_be_bytes()).collect::<Vec<_>>();
+            let data = raw
+                .iter()
+                .map(|k| (Key::new(k, 1), Value::Put(&b"v"[..])))
+                .collect::<Vec<_>>();
+            let buf = build_page(&data);
+            let page = SortedPageRef::<Key, Value>::from(buf.page());
+            let (mut binary, mut interpolation) = (0, 0);
+            for (i, k) in raw.iter().enumerate() {
+                let target = Key::new(k, 1);
+                let expect = Ok(i);
+                let rank = page.rank_by_impl(&target, SeekStrategy::Binary, &mut binary);
+                assert_eq!(rank, expect);
+                let rank =
+                    page.rank_by_impl(&target, SeekStrategy::Interpolation, &mut interpolation);
+                assert_eq!(rank, expect);
+                // Missing keys are ranked the same way by both strategies.
+                let target = Key::new(k, 0);
+                assert_eq!(
+                    page.rank_by(&target, SeekStrategy::Interpolation),
+                    page.rank(&target)
+                );
+            }
+            (binary, interpolation)
+        }
+
+        let sequential = (0..1024).collect::<Vec<u64>>();
+        let (binary, interpolation) = probes(&sequential);
+        assert!(
+            interpolation < binary,
+            "sequential keys: binary {binary} probes, interpolation {interpolation} probes"
+        );
+
+        let mut rng = Rng(42);
+        let mut random = (0..1024).map(|_| rng.next()).collect::<Vec<_>>();
+        random.sort_unstable();
+        random.dedup();
+        let (binary, interpolation) = probes(&random);
+        // Uniformly distributed keys are interpolated well too.
+        assert!(
+            interpolation < binary,
+            "random keys: binary {binary} probes, interpolation {interpolation} probes"
+        );
+    }
 }
@@ -5,11 +5,18 @@ use crate::page_store::{PageFiles, Version};
 pub(crate) struct CleanupCtx {
     // TODO: cancel task
     page_files: Arc<PageFiles>,
+    file_lock: Arc<futures::lock::Mutex<()>>,
 }
 
 impl CleanupCtx {
-    pub(crate) fn new(page_files: Arc<PageFiles>) -> Self {
-        CleanupCtx { page_files }
+    pub(crate) fn new(
+        page_files: Arc<PageFiles>,
+        file_lock: Arc<futures::lock::Mutex<()>>,
+    ) -> Self {
+        CleanupCtx {
+            page_files,
+            file_lock,
+        }
     }
 
     pub(crate) async fn run(self, mut version: Version) {
@@ -25,8 +32,8 @@ impl CleanupCtx {
         }
     }
 
-    #[inline]
-    async fn clean_obsolated_files(&self, files: Vec<u32>) {
+    pub(crate) async fn clean_obsolated_files(&self, files: Vec<u32>) {
+        let _file_lock = self.file_lock.lock().await;
         if let Err(err) = self.page_files.remove_files(files).await {
             todo!("{err}");
         }
@@ -10,6 +10,7 @@ use crate::{
         version::{DeltaVersion, Version},
         *,
     },
+    util::sync::lock,
 };
 
 pub(crate) struct FlushCtx<E: Env> {
@@ -17,6 +18,7 @@ pub(crate) struct FlushCtx<E: Env> {
     global_version: Arc<Mutex<Version>>,
     page_files: Arc<PageFiles>,
     manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+    file_lock: Arc<futures::lock::Mutex<()>>,
 }
 
 impl<E: Env> FlushCtx<E> {
@@ -24,25 +26,19 @@ impl<E: Env> FlushCtx<E> {
         global_version: Arc<Mutex<Version>>,
         page_files: Arc<PageFiles>,
         manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+        file_lock: Arc<futures::lock::Mutex<()>>,
     ) -> Self {
         FlushCtx {
             global_version,
             page_files,
             manifest,
+            file_lock,
         }
     }
 
     pub(crate) async fn run(self) {
         loop {
-            let version = self.version();
-            let write_buffer = {
-                let current = version.buffer_set.current();
-                let file_id = current.min_file_id();
-                current
-                    .write_buffer(file_id)
-                    .expect("WriteBuffer must exists")
-                    .clone()
-            };
+            let (version, write_buffer) = self.oldest_write_buffer();
 
             // If the [`WriteBuffer`] can be flushed, then it should continue because
             // [`Notify`] is single permits. But this may also lead to [`WriteBuffer`]
@@ -51,13 +47,42 @@ impl<E: Env> FlushCtx<E> {
                 version.buffer_set.wait_flushable().await;
             }
 
-            match self.flush(&version, write_buffer.as_ref()).await {
-                Ok(()) => {
-                    self.refresh_version();
-                }
-                Err(err) => {
-                    todo!("flush write buffer: {err}");
-                }
+            self.flush_and_install(&version, &write_buffer).await;
+        }
+    }
+
+    /// Flushes the write buffers that are ready to be flushed without waiting.
+    pub(crate) async fn flush_ready(&self) {
+        loop {
+            let (version, write_buffer) = self.oldest_write_buffer();
+            if !write_buffer.is_flushable() {
+                return;
+            }
+            self.flush_and_install(&version, &write_buffer).await;
+        }
+    }
+
+    fn oldest_write_buffer(&self) -> (Version, Arc<WriteBuffer>) {
+        let version = self.version();
+        let write_buffer = {
+            let current = version.buffer_set.current();
+            let file_id = current.min_file_id();
+            current
+                .write_buffer(file_id)
+                .expect("WriteBuffer must exists")
+                .clone()
+        };
+        (version, write_buffer)
+    }
+
+    async fn flush_and_install(&self, version: &Version, write_buffer: &WriteBuffer) {
+        let _file_lock = self.file_lock.lock().await;
+        match self.flush(version, write_buffer).await {
+            Ok(()) => {
+                self.refresh_version();
+            }
+            Err(err) => {
+                todo!("flush write buffer: {err}");
             }
         }
     }
@@ -69,9 +94,11 @@ impl<E: Env> FlushCtx<E> {
 
         let mut files = files;
         let deleted_files = files
-            .drain_filter(|_, info| info.is_empty())
-            .map(|(file_id, _)| file_id)
+            .iter()
+            .filter(|(_, info)| info.is_empty())
+            .map(|(&file_id, _)| file_id)
             .collect::<HashSet<_>>();
+        files.retain(|file_id, _| !deleted_files.contains(file_id));
         files.insert(file_id, file_info);
 
         self.save_version_edit(version, file_id, &deleted_files)
@@ -90,17 +117,31 @@ impl<E: Env> FlushCtx<E> {
     }
 
     /// Flush [`WriteBuffer`] to page files and returns deleted pages.
+    ///
+    /// The pages deallocated in the same [`WriteBuffer`] are never written to
+    /// the page file, so the returned pages all belong to other files.
     async fn build_page_file(&self, write_buffer: &WriteBuffer) -> Result<(Vec<u64>, FileInfo)> {
         assert!(write_buffer.is_flushable());
         let file_id = write_buffer.file_id();
         let mut deleted_pages = Vec::default();
+        let mut local_deleted_pages = HashSet::new();
+        for (_, _, record_ref) in write_buffer.iter() {
+            if let RecordRef::DeallocPages(pages) = record_ref {
+                for &page_addr in pages.as_slice() {
+                    if PageAddr::from(page_addr).file_id() == file_id {
+                        local_deleted_pages.insert(page_addr);
+                    } else {
+                        deleted_pages.push(page_addr);
+                    }
+                }
+            }
+        }
         let mut builder = self.page_files.new_file_builder(file_id).await?;
+        builder.add_delete_pages(&deleted_pages);
         for (page_addr, header, record_ref) in write_buffer.iter() {
             match record_ref {
-                RecordRef::DeallocPages(pages) => {
-                    builder.add_delete_pages(pages.as_slice());
-                    deleted_pages.extend_from_slice(pages.as_slice());
-                }
+                RecordRef::DeallocPages(_) => {}
+                RecordRef::Page(_) if local_deleted_pages.contains(&page_addr) => {}
                 RecordRef::Page(page) => {
                     let content = page.data();
                     builder
@@ -143,7 +184,7 @@ impl<E: Env> FlushCtx<E> {
     ) -> HashMap<u32, FileInfo> {
         let mut files = version.files().clone();
         for page_addr in deleted_pages {
-            let file_id = (page_addr >> 32) as u32;
+            let file_id = PageAddr::from(page_addr).file_id();
             let file_info = files.get_mut(&file_id).expect("File is missing");
             file_info.deactivate_page(new_file_id, page_addr);
         }
@@ -151,11 +192,11 @@ impl<E: Env> FlushCtx<E> {
     }
 
     fn version(&self) -> Version {
-        self.global_version.lock().expect("Poisoned").clone()
+        lock(&self.global_version).clone()
     }
 
     fn refresh_version(&self) {
-        let mut version = self.global_version.lock().expect("Poisoned");
+        let mut version = lock(&self.global_version);
         if let Some(new) = version.refresh() {
             *version = <Version as Clone>::clone(&new);
         }
@@ -1,8 +1,18 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use async_trait::async_trait;
 
-use crate::page_store::{FileInfo, PageFiles, Result, Version};
+use super::health::JobHealth;
+use crate::{
+    page_store::{FileInfo, PageFiles, Result, Version},
+    util::sync::lock,
+};
 
 /// An abstraction describes how to move pages to the end of page files.
 #[async_trait]
@@ -18,58 +28,233 @@ pub(crate) trait GcPickStrategy: Send + Sync {
 
     /// Compute and return score of the corresponding page file.
     fn score(&self, file_info: &FileInfo) -> f64;
+
+    /// Returns true if the page file should be collected.
+    fn should_collect(&self, file_info: &FileInfo) -> bool {
+        self.score(file_info) >= self.threshold()
+    }
 }
 
 pub(crate) struct GcCtx {
     // TODO: cancel task
     rewriter: Arc<dyn RewritePage>,
-    strategy: Box<dyn GcPickStrategy>,
-    #[allow(unused)]
+    strategy: Arc<dyn GcPickStrategy>,
     page_files: Arc<PageFiles>,
+    /// Whether to punch holes for dead pages. It is cleared if punching fails,
+    /// which falls back to rewriting files.
+    punch_hole: AtomicBool,
+    /// The versions seen by GC rounds that may still be read, in order.
+    ///
+    /// The dead pages of a version may still be read through the versions
+    /// before it, so they are only punched once those versions are released.
+    versions: Mutex<VecDeque<Version>>,
+    health: Arc<JobHealth>,
+    file_lock: Arc<futures::lock::Mutex<()>>,
 }
 
 impl GcCtx {
     pub(crate) fn new(
         rewriter: Arc<dyn RewritePage>,
-        strategy: Box<dyn GcPickStrategy>,
+        strategy: Arc<dyn GcPickStrategy>,
         page_files: Arc<PageFiles>,
+        punch_hole: bool,
+        health: Arc<JobHealth>,
+        file_lock: Arc<futures::lock::Mutex<()>>,
     ) -> Self {
         GcCtx {
             rewriter,
             strategy,
             page_files,
+            punch_hole: AtomicBool::new(punch_hole),
+            versions: Mutex::default(),
+            health,
+            file_lock,
         }
     }
 
     pub(crate) async fn run(self, mut version: Version) {
         loop {
-            self.gc(&version).await;
+            self.gc_round(&version).await;
             version = version.wait_next_version().await;
         }
     }
 
+    /// Runs a GC round on the files of the version.
+    pub(crate) async fn gc_round(&self, version: &Version) {
+        {
+            let _file_lock = self.file_lock.lock().await;
+            if self.punch_hole.load(Ordering::Relaxed) {
+                let version = self.punchable_version(version);
+                self.punch(&version).await;
+            } else {
+                self.gc(version).await;
+            }
+        }
+        self.health.record_gc();
+    }
+
     async fn gc(&self, version: &Version) {
         for (_, file) in version.files() {
-            if !self.is_satisfied(file) {
+            if !self.strategy.should_collect(file) {
                 continue;
             }
-            if let Err(err) = self.forward_active_pages(&file).await {
-                todo!("do_recycle: {err:?}");
+            if let Err(err) = self.forward_active_pages(file).await {
+                self.health
+                    .record_gc_error(format!("rewrite file {}: {err}", file.get_file_id()));
+            }
+        }
+    }
+
+    async fn punch(&self, version: &Version) {
+        for (_, file) in version.files() {
+            if let Err(err) = self.punch_dead_pages(file).await {
+                // The filesystem may not support punching holes, fall back to
+                // rewriting files from the next round.
+                self.health
+                    .record_gc_error(format!("punch file {}: {err}", file.get_file_id()));
+                self.punch_hole.store(false, Ordering::Relaxed);
+                return;
             }
         }
     }
 
-    fn is_satisfied(&self, file: &FileInfo) -> bool {
-        self.strategy.score(file) >= self.strategy.threshold()
+    /// Returns the oldest version that may still be read, after tracking the
+    /// versions up to the current one.
+    ///
+    /// The dead pages of the returned version can not be read through any
+    /// version before it, so it is safe to punch them.
+    fn punchable_version(&self, current: &Version) -> Version {
+        let mut versions = lock(&self.versions);
+        if versions.is_empty() {
+            versions.push_back(current.clone());
+        }
+        // Every version is tracked, since a version is only released after the
+        // versions before it.
+        while let Some(next) = versions.back().unwrap().next_version() {
+            versions.push_back(next);
+        }
+        while versions.len() > 1 && versions.front().unwrap().is_released() {
+            versions.pop_front();
+        }
+        versions.front().unwrap().clone()
+    }
+
+    /// Reclaims the space of dead pages in place, without rewriting the
+    /// active pages of the file.
+    async fn punch_dead_pages(&self, file: &FileInfo) -> Result<()> {
+        let file_id = file.get_file_id();
+        for (page_addr, handle) in file.dead_pages() {
+            // Readers check the holes after they read, so the page is marked
+            // before it is punched.
+            file.mark_hole(page_addr);
+            self.page_files
+                .punch_hole(file_id, handle.offset as u64, handle.size as u64)
+                .await?;
+        }
+        Ok(())
     }
 
     async fn forward_active_pages(&self, file: &FileInfo) -> Result<()> {
-        for _page_addr in file.iter() {
-            // TODO: convert page_addr to page_id.
-            let page_id = 0;
-            self.rewriter.rewrite(page_id).await?;
+        // The page table of the file maps the pages written to it, the active
+        // ones are still mapped to the same addresses.
+        let meta_reader = self.page_files.open_meta_reader(file.get_file_id()).await?;
+        let page_table = meta_reader.read_page_table().await?;
+        for (page_id, page_addr) in page_table {
+            if file.get_page_handle(page_addr).is_some() {
+                self.rewriter.rewrite(page_id).await?;
+            }
         }
         // TODO: rewrite deleted pages.
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        rc::Rc,
+    };
+
+    use super::*;
+    use crate::{
+        page_store::{version::DeltaVersion, PageAddr},
+        PageAllocHint,
+    };
+
+    struct NoRewrite;
+
+    #[async_trait]
+    impl RewritePage for NoRewrite {
+        async fn rewrite(&self, _: u64) -> Result<()> {
+            unreachable!("punching holes does not rewrite pages")
+        }
+    }
+
+    struct NoPick;
+
+    impl GcPickStrategy for NoPick {
+        fn threshold(&self) -> f64 {
+            f64::MAX
+        }
+
+        fn score(&self, _: &FileInfo) -> f64 {
+            0.0
+        }
+    }
+
+    fn page_addr(file_id: u32, index: u32) -> u64 {
+        PageAddr::new(file_id, index).into()
+    }
+
+    #[photonio::test]
+    fn punch_after_versions_released() {
+        let base = std::env::temp_dir().join("test_punch_after_versions_released");
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        let page_files = Arc::new(PageFiles::new(&base, "db"));
+        let info = {
+            let mut b = page_files.new_file_builder(1).await.unwrap();
+            b.add_page(1, page_addr(1, 0), &[1; 8192]).await.unwrap();
+            b.add_page(2, page_addr(1, 1), &[2; 8192]).await.unwrap();
+            b.finish().await.unwrap()
+        };
+        let version = Version::new(
+            1 << 10,
+            PageAllocHint::Default,
+            2,
+            HashMap::from([(1, info.clone())]),
+            HashSet::new(),
+        );
+        let gc = GcCtx::new(
+            Arc::new(NoRewrite),
+            Arc::new(NoPick),
+            page_files,
+            true,
+            Arc::default(),
+            Arc::default(),
+        );
+        gc.gc_round(&version).await;
+
+        // The page dies in the next version, while a reader still holds the
+        // previous one.
+        let mut next_info = info;
+        next_info.deactivate_page(2, page_addr(1, 0));
+        let delta = DeltaVersion {
+            files: HashMap::from([(1, next_info)]),
+            deleted_files: HashSet::new(),
+        };
+        Version::install(Rc::new(version.clone()), delta).unwrap();
+        let next = version.refresh().unwrap();
+        let reader = version;
+        gc.gc_round(&next).await;
+        assert_eq!(next.files()[&1].dead_pages().len(), 1);
+
+        drop(reader);
+        gc.gc_round(&next).await;
+        let file = &next.files()[&1];
+        assert!(file.dead_pages().is_empty());
+        assert!(file.is_hole(page_addr(1, 0)));
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
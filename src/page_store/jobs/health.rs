@@ -0,0 +1,147 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use futures::FutureExt;
+
+use crate::util::sync::lock;
+
+/// The health of the background jobs of a table.
+#[derive(Clone, Debug)]
+pub struct BackgroundHealth {
+    /// Whether the flush job is still running.
+    pub flush_alive: bool,
+    /// Whether the cleanup job is still running.
+    pub cleanup_alive: bool,
+    /// Whether the GC job is still running.
+    pub gc_alive: bool,
+    /// Whether the scrub job is still running, if it is enabled.
+    pub scrub_alive: bool,
+    /// The time the last GC round finished, if any.
+    pub last_gc_at: Option<SystemTime>,
+    /// The last error GC ran into, if any.
+    ///
+    /// GC skips the files it fails on and goes on.
+    pub gc_error: Option<String>,
+    /// The time the last scrub round finished, if any.
+    pub last_scrub_at: Option<SystemTime>,
+    /// The ids of the page files that failed the last scrub round.
+    pub corrupted_files: Vec<u32>,
+    /// The panic message of the first background job that panicked, if any.
+    pub error: Option<String>,
+}
+
+/// The kinds of background jobs to watch.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Job {
+    Flush,
+    Cleanup,
+    Gc,
+    Scrub,
+}
+
+/// Tracks the health of background jobs.
+#[derive(Default)]
+pub(crate) struct JobHealth {
+    flush_exited: AtomicBool,
+    cleanup_exited: AtomicBool,
+    gc_exited: AtomicBool,
+    scrub_exited: AtomicBool,
+    last_gc_at: Mutex<Option<SystemTime>>,
+    gc_error: Mutex<Option<String>>,
+    last_scrub: Mutex<(Option<SystemTime>, Vec<u32>)>,
+    error: Mutex<Option<String>>,
+}
+
+impl JobHealth {
+    /// Runs the job to completion, and records its exit and panic if any.
+    pub(crate) async fn watch<F>(&self, job: Job, f: F)
+    where
+        F: Future<Output = ()>,
+    {
+        let result = AssertUnwindSafe(f).catch_unwind().await;
+        if let Err(payload) = result {
+            let msg = format!("{job:?} job panicked: {}", panic_message(&payload));
+            lock(&self.error).get_or_insert(msg);
+        }
+        let exited = match job {
+            Job::Flush => &self.flush_exited,
+            Job::Cleanup => &self.cleanup_exited,
+            Job::Gc => &self.gc_exited,
+            Job::Scrub => &self.scrub_exited,
+        };
+        exited.store(true, Ordering::Release);
+    }
+
+    /// Records that a GC round has finished.
+    pub(crate) fn record_gc(&self) {
+        *lock(&self.last_gc_at) = Some(SystemTime::now());
+    }
+
+    /// Records an error that GC ran into.
+    pub(crate) fn record_gc_error(&self, msg: String) {
+        *lock(&self.gc_error) = Some(msg);
+    }
+
+    /// Records that a scrub round has finished with the corrupted files.
+    pub(crate) fn record_scrub(&self, corrupted_files: Vec<u32>) {
+        *lock(&self.last_scrub) = (Some(SystemTime::now()), corrupted_files);
+    }
+
+    pub(crate) fn snapshot(&self) -> BackgroundHealth {
+        let (last_scrub_at, corrupted_files) = lock(&self.last_scrub).clone();
+        BackgroundHealth {
+            flush_alive: !self.flush_exited.load(Ordering::Acquire),
+            cleanup_alive: !self.cleanup_exited.load(Ordering::Acquire),
+            gc_alive: !self.gc_exited.load(Ordering::Acquire),
+            scrub_alive: !self.scrub_exited.load(Ordering::Acquire),
+            last_gc_at: *lock(&self.last_gc_at),
+            gc_error: lock(&self.gc_error).clone(),
+            last_scrub_at,
+            corrupted_files,
+            error: lock(&self.error).clone(),
+        }
+    }
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn job_panic_is_unhealthy() {
+        let health = JobHealth::default();
+        let h = health.snapshot();
+        assert!(h.flush_alive && h.cleanup_alive && h.gc_alive);
+        assert!(h.last_gc_at.is_none() && h.error.is_none());
+
+        block_on(health.watch(Job::Gc, async {
+            health.record_gc();
+            panic!("induced");
+        }));
+        let h = health.snapshot();
+        assert!(h.flush_alive);
+        assert!(!h.gc_alive);
+        assert!(h.last_gc_at.is_some());
+        assert_eq!(h.error.as_deref(), Some("Gc job panicked: induced"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::{collections::VecDeque, mem};
+
+use super::{cleanup::CleanupCtx, flush::FlushCtx, gc::GcCtx};
+use crate::{env::Env, page_store::Version};
+
+/// Runs the jobs of a page store on the calling task, for environments
+/// without background threads.
+///
+/// Each [`InlineJobs::tick`] does what the background jobs would do for the
+/// changes since the last tick, but never waits for anything.
+pub(crate) struct InlineJobs<E: Env> {
+    flush: FlushCtx<E>,
+    cleanup: CleanupCtx,
+    gc: GcCtx,
+    /// The latest version seen by cleanup and GC.
+    version: Version,
+    /// The replaced versions and their obsolete files, which are removed once
+    /// the versions are released.
+    replaced: VecDeque<(Version, Vec<u32>)>,
+    gc_pending: bool,
+}
+
+impl<E: Env> InlineJobs<E> {
+    pub(crate) fn new(
+        flush: FlushCtx<E>,
+        cleanup: CleanupCtx,
+        gc: GcCtx,
+        version: Version,
+    ) -> Self {
+        InlineJobs {
+            flush,
+            cleanup,
+            gc,
+            version,
+            replaced: VecDeque::new(),
+            gc_pending: true,
+        }
+    }
+
+    /// Flushes the full write buffers, removes the files that are no longer
+    /// referenced, and runs a GC round if there is a new version.
+    pub(crate) async fn tick(&mut self) {
+        self.flush.flush_ready().await;
+
+        if let Some(next) = self.version.refresh() {
+            let version = mem::replace(&mut self.version, next);
+            let deleted_files = version.deleted_files();
+            self.replaced.push_back((version, deleted_files));
+            self.gc_pending = true;
+        }
+        while let Some((version, _)) = self.replaced.front() {
+            if !version.is_released() {
+                break;
+            }
+            let (_, deleted_files) = self.replaced.pop_front().unwrap();
+            self.cleanup.clean_obsolated_files(deleted_files).await;
+        }
+
+        if mem::take(&mut self.gc_pending) {
+            self.gc.gc_round(&self.version).await;
+        }
+    }
+}
@@ -4,3 +4,7 @@ pub(crate) mod cleanup;
 pub(crate) mod flush;
 pub(crate) mod gc;
 pub(crate) use gc::{GcPickStrategy, RewritePage};
+pub(crate) mod health;
+pub(crate) mod inline;
+pub(crate) mod scrub;
+pub use health::BackgroundHealth;
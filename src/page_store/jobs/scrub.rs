@@ -0,0 +1,135 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::health::JobHealth;
+use crate::{
+    page_store::{PageFiles, Version},
+    util::sync::{lock, unblock},
+};
+
+/// Reads page files periodically to detect damaged files.
+pub(crate) struct ScrubCtx {
+    // TODO: cancel task
+    page_files: Arc<PageFiles>,
+    health: Arc<JobHealth>,
+    interval: Duration,
+    bytes_per_sec: u64,
+}
+
+impl ScrubCtx {
+    pub(crate) fn new(
+        page_files: Arc<PageFiles>,
+        health: Arc<JobHealth>,
+        interval: Duration,
+        bytes_per_sec: u64,
+    ) -> Self {
+        ScrubCtx {
+            page_files,
+            health,
+            interval,
+            bytes_per_sec,
+        }
+    }
+
+    pub(crate) async fn run(self, version: Arc<Mutex<Version>>) {
+        loop {
+            sleep(self.interval).await;
+            // Holding the version keeps its files from being removed.
+            let version = { lock(&version).clone() };
+            let mut file_ids = version.files().keys().copied().collect::<Vec<_>>();
+            file_ids.sort_unstable();
+            self.scrub_round(&file_ids).await;
+        }
+    }
+
+    /// Scrubs the files, and records the ones that fail.
+    pub(crate) async fn scrub_round(&self, file_ids: &[u32]) {
+        let mut corrupted_files = Vec::new();
+        for &file_id in file_ids {
+            let start = Instant::now();
+            match self.page_files.scrub_file(file_id).await {
+                Ok(bytes) => {
+                    let delay = throttle_delay(bytes as u64, self.bytes_per_sec, start.elapsed());
+                    if !delay.is_zero() {
+                        sleep(delay).await;
+                    }
+                }
+                Err(_) => corrupted_files.push(file_id),
+            }
+        }
+        self.health.record_scrub(corrupted_files);
+    }
+}
+
+/// Returns how long to wait after reading `bytes` in `elapsed`, so that the
+/// reads do not exceed `bytes_per_sec` on average.
+fn throttle_delay(bytes: u64, bytes_per_sec: u64, elapsed: Duration) -> Duration {
+    if bytes_per_sec == 0 {
+        return Duration::ZERO;
+    }
+    let expected = Duration::from_secs_f64(bytes as f64 / bytes_per_sec as f64);
+    expected.saturating_sub(elapsed)
+}
+
+/// Waits for the duration without blocking the runtime.
+async fn sleep(duration: Duration) {
+    unblock(move || std::thread::sleep(duration)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::FileExt;
+
+    use super::*;
+    use crate::page_store::{page_file::Footer, PageAddr};
+
+    #[test]
+    fn throttle_scrub_reads() {
+        let rate = 1 << 20;
+        assert_eq!(
+            throttle_delay(1 << 20, rate, Duration::from_millis(200)),
+            Duration::from_millis(800)
+        );
+        assert_eq!(
+            throttle_delay(1 << 20, rate, Duration::from_secs(2)),
+            Duration::ZERO
+        );
+        assert_eq!(throttle_delay(1 << 30, 0, Duration::ZERO), Duration::ZERO);
+    }
+
+    #[photonio::test]
+    async fn scrub_detects_corrupted_file() {
+        let base = std::env::temp_dir();
+        let page_files = Arc::new(PageFiles::new(&base, "test_scrub"));
+        for file_id in [1, 2] {
+            let mut b = page_files.new_file_builder(file_id).await.unwrap();
+            let addr = PageAddr::new(file_id, 0).into();
+            b.add_page(1, addr, &[1].repeat(100)).await.unwrap();
+            b.finish().await.unwrap();
+        }
+
+        let health = Arc::new(JobHealth::default());
+        let ctx = ScrubCtx::new(page_files, health.clone(), Duration::ZERO, 0);
+        ctx.scrub_round(&[1, 2]).await;
+        let h = health.snapshot();
+        assert!(h.last_scrub_at.is_some());
+        assert!(h.corrupted_files.is_empty());
+
+        // Flip a byte of the index block, which precedes the footer.
+        let path = base.join("test_scrub_2");
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let offset = file.metadata().unwrap().len() - Footer::size() as u64 - 1;
+        let mut byte = [0u8];
+        file.read_exact_at(&mut byte, offset).unwrap();
+        file.write_all_at(&[!byte[0]], offset).unwrap();
+
+        ctx.scrub_round(&[1, 2]).await;
+        assert_eq!(health.snapshot().corrupted_files, vec![2]);
+    }
+}
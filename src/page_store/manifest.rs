@@ -17,23 +17,35 @@ const MAX_MANIFEST_SIZE: u64 = 128 << 20; // 128 MiB
 pub(crate) struct Manifest<E: Env> {
     env: E,
     base: PathBuf,
+    /// The prefix of the names of manifest files, which is empty or ends
+    /// with `_`.
+    prefix: String,
 
     max_file_size: u64,
     current_file_size: u64,
 
     current_file_num: Option<u32>,
+    /// Whether the current file ends with a partially written record, which
+    /// must not be followed by new records.
+    torn_tail: bool,
+
+    sync: bool,
 }
 
 impl<E: Env> Manifest<E> {
     // Open manifest in specified folder.
     // it will reopen manifest by find CURRENT and do some cleanup.
-    pub(crate) async fn open(env: E, base: impl Into<PathBuf>) -> Result<Self> {
+    // Files without the prefix belong to other tables and are left untouched.
+    pub(crate) async fn open(env: E, base: impl Into<PathBuf>, prefix: &str) -> Result<Self> {
         let mut manifest = Self {
             env,
             base: base.into(),
+            prefix: prefix.to_owned(),
             max_file_size: MAX_MANIFEST_SIZE,
             current_file_size: Default::default(),
             current_file_num: None,
+            torn_tail: false,
+            sync: true,
         };
         manifest.create_base_dir_if_not_exist().await?;
         manifest.current_file_num = manifest.load_current().await?;
@@ -42,6 +54,13 @@ impl<E: Env> Manifest<E> {
         Ok(manifest)
     }
 
+    /// Sets whether to sync manifest files and the base directory after
+    /// updates.
+    pub(crate) fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
     async fn create_base_dir_if_not_exist(&self) -> Result<()> {
         match self.env.create_dir_all(&self.base).await {
             Ok(_) => {}
@@ -70,24 +89,24 @@ impl<E: Env> Manifest<E> {
         ve: VersionEdit,
         version_snapshot: impl FnOnce() -> VersionEdit,
     ) -> Result<()> {
-        let (rolled, current_file_num) =
-            if self.current_file_num.is_none() || self.current_file_size > self.max_file_size {
-                (
-                    true,
-                    if let Some(current) = self.current_file_num {
-                        current + 1
-                    } else {
-                        0
-                    },
-                )
-            } else {
-                (false, self.current_file_num.as_ref().unwrap().to_owned())
-            };
+        let (rolled, current_file_num) = if self.current_file_num.is_none()
+            || self.current_file_size > self.max_file_size
+            || self.torn_tail
+        {
+            (
+                true,
+                if let Some(current) = self.current_file_num {
+                    current + 1
+                } else {
+                    0
+                },
+            )
+        } else {
+            (false, self.current_file_num.as_ref().unwrap().to_owned())
+        };
 
         let (mut writer, path) = {
-            let path = self
-                .base
-                .join(format!("{}_{}", MANIFEST_FILE_NAME, current_file_num));
+            let path = self.manifest_path(current_file_num);
 
             (
                 self.env
@@ -126,7 +145,8 @@ impl<E: Env> Manifest<E> {
             self.set_current(current_file_num).await?;
             // TODO: notify cleaner previous manifest + size, so it can be delete when need.
             self.current_file_num = Some(current_file_num);
-        } else {
+            self.torn_tail = false;
+        } else if self.sync {
             use crate::env::Syncer;
             writer.sync_data().await.expect("sync manifest data fail");
         }
@@ -142,21 +162,24 @@ impl<E: Env> Manifest<E> {
 
     // List current versions.
     // the caller can recovery Versions by apply each version_edits.
-    pub(crate) async fn list_versions(&self) -> Result<Vec<VersionEdit>> {
+    //
+    // A partially written record at the end of the file is left by a crash
+    // while recording a version edit, so it is ignored and the manifest will be
+    // rolled to a new file on the next edit.
+    pub(crate) async fn list_versions(&mut self) -> Result<Vec<VersionEdit>> {
         Ok(if let Some(current_file) = self.current_file_num {
-            let path = self
-                .base
-                .join(format!("{}_{}", MANIFEST_FILE_NAME, current_file));
+            let path = self.manifest_path(current_file);
             let reader = self
                 .env
                 .open_positional_reader(path, ReadOptions::default())
                 .await
                 .expect("open manifest fail");
-            let mut decoder = VersionEditDecoder::new(reader);
+            let mut decoder = VersionEditDecoder::new(reader, self.current_file_size);
             let mut ves = Vec::new();
             while let Some(ve) = decoder.next_record().await.expect("manifest decode error") {
                 ves.push(ve)
             }
+            self.torn_tail = decoder.offset < self.current_file_size;
             ves
         } else {
             vec![]
@@ -166,7 +189,7 @@ impl<E: Env> Manifest<E> {
     async fn load_current(&self) -> Result<Option<u32 /* file_num */>> {
         let curr_file_reader = match self
             .env
-            .open_positional_reader(self.base.join(CURRENT_FILE_NAME), ReadOptions::default())
+            .open_positional_reader(self.current_path(), ReadOptions::default())
             .await
         {
             Ok(f) => f,
@@ -190,7 +213,7 @@ impl<E: Env> Manifest<E> {
         Ok(if let Some(curr) = self.current_file_num {
             let metadata = self
                 .env
-                .metadata(self.base.join(format!("{}_{}", MANIFEST_FILE_NAME, curr)))
+                .metadata(self.manifest_path(curr))
                 .await
                 .expect("read manifest fail");
             use crate::env::Metadata;
@@ -202,9 +225,10 @@ impl<E: Env> Manifest<E> {
 
     async fn set_current(&self, file_num: u32) -> Result<()> {
         {
-            let tmp_path = self
-                .base
-                .join(format!("curr.{}.{}", file_num, TEMPFILE_SUFFIX));
+            let tmp_path = self.base.join(format!(
+                "{}curr.{}.{}",
+                self.prefix, file_num, TEMPFILE_SUFFIX
+            ));
 
             {
                 let mut tmp_file = self
@@ -218,11 +242,7 @@ impl<E: Env> Manifest<E> {
                     .expect("write file_num to tmp fail");
             }
 
-            match self
-                .env
-                .rename(&tmp_path, self.base.join(CURRENT_FILE_NAME))
-                .await
-            {
+            match self.env.rename(&tmp_path, self.current_path()).await {
                 Ok(_) => Ok(()),
                 Err(_err) => {
                     let _ = self.env.remove_file(&tmp_path).await;
@@ -231,7 +251,7 @@ impl<E: Env> Manifest<E> {
                 }
             }?;
         }
-        {
+        if self.sync {
             use crate::env::Syncer;
             let mut base_dir = self
                 .env
@@ -243,9 +263,25 @@ impl<E: Env> Manifest<E> {
         Ok(())
     }
 
+    fn current_path(&self) -> PathBuf {
+        self.base
+            .join(format!("{}{}", self.prefix, CURRENT_FILE_NAME))
+    }
+
+    fn manifest_path(&self, file_num: u32) -> PathBuf {
+        self.base.join(format!(
+            "{}{}_{}",
+            self.prefix, MANIFEST_FILE_NAME, file_num
+        ))
+    }
+
     async fn cleanup_obsolete_files(&self) -> Result<()> {
-        fn is_obsolete_manifest(file_name: &str, curr_file_num: Option<u32>) -> bool {
-            let file_num_str = file_name.trim_start_matches(&format!("{}_", MANIFEST_FILE_NAME));
+        let manifest_prefix = format!("{}{}_", self.prefix, MANIFEST_FILE_NAME);
+        let tempfile_prefix = format!("{}curr.", self.prefix);
+        let is_obsolete_manifest = |file_name: &str, curr_file_num: Option<u32>| -> bool {
+            let Some(file_num_str) = file_name.strip_prefix(&manifest_prefix) else {
+                return false;
+            };
             if let Ok(file_num) = file_num_str.parse::<u32>() {
                 if let Some(curr_file_num) = curr_file_num {
                     if file_num < curr_file_num {
@@ -256,21 +292,22 @@ impl<E: Env> Manifest<E> {
                 }
             }
             false
-        }
+        };
 
         let mut wait_remove_paths = Vec::new();
         for path in self.env.read_dir(&self.base).expect("open base dir fail") {
             let file_path = path.unwrap().path();
+            let Some(file_name) = file_path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
             if let Some(ext) = file_path.extension() {
-                if ext.to_str().unwrap() == TEMPFILE_SUFFIX {
+                if ext.to_str() == Some(TEMPFILE_SUFFIX) && file_name.starts_with(&tempfile_prefix)
+                {
                     wait_remove_paths.push(file_path.to_owned());
                     continue;
                 }
             }
-            if is_obsolete_manifest(
-                file_path.file_name().unwrap().to_str().unwrap(),
-                self.current_file_num,
-            ) {
+            if is_obsolete_manifest(file_name, self.current_file_num) {
                 wait_remove_paths.push(file_path.to_owned());
             }
         }
@@ -302,11 +339,16 @@ impl VersionEditEncoder {
 struct VersionEditDecoder<R: ReadAt> {
     reader: R,
     offset: u64,
+    file_size: u64,
 }
 
 impl<R: ReadAt> VersionEditDecoder<R> {
-    fn new(reader: R) -> Self {
-        Self { reader, offset: 0 }
+    fn new(reader: R, file_size: u64) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            file_size,
+        }
     }
     async fn next_record(&mut self) -> Result<Option<VersionEdit>> {
         let mut offset = self.offset;
@@ -328,12 +370,22 @@ impl<R: ReadAt> VersionEditDecoder<R> {
             )
         };
         offset += core::mem::size_of::<u64>() as u64;
+        // The length of a torn record may be garbage, so it is not trusted
+        // beyond the end of the file.
+        if len > self.file_size.saturating_sub(offset) {
+            return Ok(None);
+        }
         let ve = {
             let mut ve_bytes = vec![0u8; len as usize];
-            self.reader
+            match self
+                .reader
                 .read_exact_at(&mut ve_bytes, offset as u64)
                 .await
-                .expect("read version edit record payload fail");
+            {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                e @ Err(_) => e.expect("read version edit record payload fail"),
+            };
             VersionEdit::decode(ve_bytes.as_slice()).expect("decode version edit fail")
         };
         self.offset = offset + len;
@@ -343,8 +395,24 @@ impl<R: ReadAt> VersionEditDecoder<R> {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        future::Future,
+        io,
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use futures::future::BoxFuture;
+    use photonio::fs::File;
+
     use super::*;
-    use crate::page_store::NewFile;
+    use crate::{
+        env::{async_trait, Photon, SpawnOptions, Syncer},
+        page_store::NewFile,
+    };
 
     #[inline]
     fn new_files(ids: Vec<u32>) -> Vec<NewFile> {
@@ -367,7 +435,7 @@ mod tests {
             }
         }
         {
-            let mut manifest = Manifest::open(env.to_owned(), base.to_owned())
+            let mut manifest = Manifest::open(env.to_owned(), base.to_owned(), "")
                 .await
                 .unwrap();
             manifest.max_file_size = 1;
@@ -418,7 +486,9 @@ mod tests {
             assert_eq!(files, 5); // 3 data + 1 current + 1 tmp
         }
         {
-            let _ = Manifest::open(env.clone(), base.to_owned()).await.unwrap();
+            let _ = Manifest::open(env.clone(), base.to_owned(), "")
+                .await
+                .unwrap();
 
             let files = env
                 .read_dir(&base)
@@ -456,7 +526,7 @@ mod tests {
         };
 
         {
-            let mut manifest = Manifest::open(env.to_owned(), base.to_owned())
+            let mut manifest = Manifest::open(env.to_owned(), base.to_owned(), "")
                 .await
                 .unwrap();
             manifest.max_file_size = 100; // set a small threshold value to trigger roll
@@ -475,7 +545,7 @@ mod tests {
         }
 
         {
-            let manifest2 = Manifest::open(env, base.to_owned()).await.unwrap();
+            let mut manifest2 = Manifest::open(env, base.to_owned(), "").await.unwrap();
             let versions = manifest2.list_versions().await.unwrap();
 
             let mut recover_ver = VersionEdit::default();
@@ -510,7 +580,7 @@ mod tests {
         }
 
         {
-            let mut manifest = Manifest::open(env.to_owned(), base.to_owned())
+            let mut manifest = Manifest::open(env.to_owned(), base.to_owned(), "")
                 .await
                 .unwrap();
             manifest
@@ -546,9 +616,256 @@ mod tests {
         }
 
         {
-            let manifest2 = Manifest::open(env, base.to_owned()).await.unwrap();
+            let mut manifest2 = Manifest::open(env, base.to_owned(), "").await.unwrap();
             let versions = manifest2.list_versions().await.unwrap();
             assert_eq!(versions.len(), 4);
         }
     }
+
+    #[photonio::test]
+    fn test_skip_sync() {
+        fn version_snapshot() -> VersionEdit {
+            VersionEdit {
+                new_files: vec![],
+                deleted_files: vec![],
+            }
+        }
+
+        let base = std::env::temp_dir().join("curr_test_skip_sync");
+        for sync in [false, true] {
+            let env = SyncCountingEnv::default();
+            if base.try_exists().unwrap_or(false) {
+                env.remove_dir_all(base.to_owned()).await.unwrap();
+            }
+            let mut manifest = Manifest::open(env.clone(), base.to_owned(), "")
+                .await
+                .unwrap()
+                .with_sync(sync);
+            for id in 1..4 {
+                // Roll the file on every other edit to update the CURRENT file.
+                manifest.max_file_size = if id % 2 == 0 { 1 } else { u64::MAX };
+                manifest
+                    .record_version_edit(
+                        VersionEdit {
+                            new_files: new_files(vec![id]),
+                            deleted_files: vec![],
+                        },
+                        version_snapshot,
+                    )
+                    .await
+                    .unwrap();
+            }
+            let syncs = env.syncs.load(Ordering::Relaxed);
+            if sync {
+                assert!(syncs > 0);
+            } else {
+                assert_eq!(syncs, 0);
+            }
+        }
+    }
+
+    /// An [`Env`] that counts the syncs of files and directories.
+    #[derive(Clone, Default)]
+    struct SyncCountingEnv {
+        syncs: Arc<AtomicUsize>,
+    }
+
+    impl SyncCountingEnv {
+        fn wrap(&self, file: File) -> SyncCountingFile {
+            SyncCountingFile {
+                file,
+                syncs: self.syncs.clone(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Env for SyncCountingEnv {
+        type PositionalReader = SyncCountingFile;
+        type SequentialWriter = SyncCountingFile;
+        type MetedataReader = <Photon as Env>::MetedataReader;
+
+        async fn open_positional_reader<P>(
+            &self,
+            path: P,
+            opt: ReadOptions,
+        ) -> io::Result<Self::PositionalReader>
+        where
+            P: AsRef<Path> + Send,
+        {
+            let file = Photon.open_positional_reader(path, opt).await?;
+            Ok(self.wrap(file))
+        }
+
+        async fn open_sequential_writer<P>(
+            &self,
+            path: P,
+            opt: WriteOptions,
+        ) -> io::Result<Self::SequentialWriter>
+        where
+            P: AsRef<Path> + Send,
+        {
+            let file = Photon.open_sequential_writer(path, opt).await?;
+            Ok(self.wrap(file))
+        }
+
+        fn spawn_background<F>(&self, opt: SpawnOptions, f: F) -> BoxFuture<'static, F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send,
+        {
+            Photon.spawn_background(opt, f)
+        }
+
+        async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
+            &self,
+            from: P,
+            to: Q,
+        ) -> io::Result<()> {
+            Photon.rename(from, to).await
+        }
+
+        async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<()> {
+            Photon.remove_file(path).await
+        }
+
+        async fn create_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<()> {
+            Photon.create_dir_all(path).await
+        }
+
+        async fn remove_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<()> {
+            Photon.remove_dir_all(path).await
+        }
+
+        fn read_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<std::fs::ReadDir> {
+            Photon.read_dir(path)
+        }
+
+        async fn metadata<P: AsRef<Path> + Send>(
+            &self,
+            path: P,
+        ) -> io::Result<Self::MetedataReader> {
+            Photon.metadata(path).await
+        }
+
+        async fn data_ranges<P: AsRef<Path> + Send>(
+            &self,
+            path: P,
+        ) -> io::Result<Vec<std::ops::Range<u64>>> {
+            Photon.data_ranges(path).await
+        }
+    }
+
+    struct SyncCountingFile {
+        file: File,
+        syncs: Arc<AtomicUsize>,
+    }
+
+    impl ReadAt for SyncCountingFile {
+        type ReadAt<'a> = impl Future<Output = io::Result<usize>> + 'a;
+
+        fn read_at<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> Self::ReadAt<'a> {
+            self.file.read_at(buf, offset)
+        }
+    }
+
+    impl Write for SyncCountingFile {
+        type Write<'a> = impl Future<Output = io::Result<usize>> + 'a;
+
+        fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::Write<'a> {
+            self.file.write(buf)
+        }
+    }
+
+    impl Syncer for SyncCountingFile {
+        type SyncData<'a> = impl Future<Output = io::Result<()>> + 'a;
+
+        fn sync_data(&mut self) -> Self::SyncData<'_> {
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            self.file.sync_data()
+        }
+
+        type SyncAll<'b> = impl Future<Output = io::Result<()>> + 'b;
+
+        fn sync_all(&mut self) -> Self::SyncAll<'_> {
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            self.file.sync_all()
+        }
+    }
+
+    #[photonio::test]
+    fn test_ignore_torn_record() {
+        fn version_snapshot() -> VersionEdit {
+            VersionEdit {
+                new_files: new_files(vec![2]),
+                deleted_files: vec![],
+            }
+        }
+
+        let env = crate::env::Photon;
+
+        let base = std::env::temp_dir().join("curr_test_torn");
+        if base.try_exists().unwrap_or(false) {
+            env.remove_dir_all(base.to_owned()).await.unwrap();
+        }
+
+        {
+            let mut manifest = Manifest::open(env.to_owned(), base.to_owned(), "")
+                .await
+                .unwrap();
+            manifest
+                .record_version_edit(
+                    VersionEdit {
+                        new_files: new_files(vec![2]),
+                        deleted_files: vec![],
+                    },
+                    version_snapshot,
+                )
+                .await
+                .unwrap();
+        }
+
+        // Simulate a crash while recording a version edit.
+        {
+            let path = base.join(format!("{}_{}", MANIFEST_FILE_NAME, 0));
+            let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+            let bytes = VersionEdit {
+                new_files: new_files(vec![3]),
+                deleted_files: vec![],
+            }
+            .encode_to_vec();
+            std::io::Write::write_all(&mut file, &bytes.len().to_le_bytes()).unwrap();
+            std::io::Write::write_all(&mut file, &bytes[..bytes.len() / 2]).unwrap();
+        }
+
+        {
+            let mut manifest = Manifest::open(env.to_owned(), base.to_owned(), "")
+                .await
+                .unwrap();
+            // The torn record is ignored.
+            let versions = manifest.list_versions().await.unwrap();
+            assert_eq!(versions.len(), 2);
+            manifest
+                .record_version_edit(
+                    VersionEdit {
+                        new_files: new_files(vec![4]),
+                        deleted_files: vec![],
+                    },
+                    version_snapshot,
+                )
+                .await
+                .unwrap();
+        }
+
+        {
+            // New records are not written after the torn record.
+            let mut manifest = Manifest::open(env, base.to_owned(), "").await.unwrap();
+            let versions = manifest.list_versions().await.unwrap();
+            let files = versions
+                .iter()
+                .flat_map(|ve| ve.new_files.iter().map(|f| f.id))
+                .collect::<Vec<_>>();
+            assert_eq!(files, vec![2, 4]);
+        }
+    }
 }
@@ -1,17 +1,25 @@
 use std::{
     collections::HashSet,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use crate::{env::Env, Options};
+use crate::{
+    env::{Env, SpawnOptions},
+    util::sync::{lock, unblock},
+    Options,
+};
 
 mod error;
 pub(crate) use error::{Error, Result};
 
+mod page_addr;
+pub(crate) use page_addr::PageAddr;
+
 mod page_txn;
 use futures::future::BoxFuture;
-pub(crate) use page_txn::Guard;
+pub(crate) use page_txn::{Guard, PageTxn};
 
 mod page_table;
 use page_table::PageTable;
@@ -21,30 +29,33 @@ mod meta;
 pub(crate) use meta::{NewFile, VersionEdit};
 
 mod version;
-use version::Version;
+pub(crate) use version::Version;
 
 mod jobs;
+pub use jobs::BackgroundHealth;
+use jobs::{health::JobHealth, inline::InlineJobs};
 pub(crate) use jobs::{GcPickStrategy, RewritePage};
 
 mod write_buffer;
+use write_buffer::ReleaseState;
 pub(crate) use write_buffer::{RecordRef, WriteBuffer};
 
 mod manifest;
 pub(crate) use manifest::Manifest;
 
 mod page_file;
+pub use page_file::FormatInfo;
 pub(crate) use page_file::{FileInfo, PageFiles};
 
 mod recover;
 mod strategy;
+pub(crate) use strategy::DeadRatioStrategy;
 
 pub(crate) struct PageStore<E: Env>
 where
     Self: Send + Sync,
 {
-    #[allow(unused)]
     options: Options,
-    #[allow(unused)]
     env: E,
     table: PageTable,
 
@@ -54,17 +65,37 @@ where
     version: Arc<Mutex<Version>>,
 
     page_files: Arc<PageFiles>,
+    /// The page files recovered on open and the next file id at that time.
+    /// Files with larger ids are created by this store.
+    recovered_files: HashSet<u32>,
+    first_file_id: u32,
     #[allow(unused)]
     manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+    /// Held while page files or manifest files are created, changed, or
+    /// removed.
+    file_lock: Arc<futures::lock::Mutex<()>>,
+}
+
+/// The longest time [`PageStore::punch_dead_pages`] waits for the readers of
+/// older versions.
+const PUNCH_WAIT_LIMIT: Duration = Duration::from_secs(1);
+
+/// Stops the table from changing its files while held.
+///
+/// See [`crate::Table::quiesce`].
+pub struct QuiesceGuard<'a> {
+    _file_lock: futures::lock::MutexGuard<'a, ()>,
 }
 
 impl<E: Env> PageStore<E> {
     pub(crate) async fn open<P: AsRef<Path>>(env: E, path: P, options: Options) -> Result<Self> {
         let (next_file_id, manifest, table, page_files, file_infos) =
-            Self::recover(env.to_owned(), path).await?;
+            Self::recover(env.to_owned(), path, &options).await?;
 
+        let recovered_files = file_infos.keys().cloned().collect();
         let version = Version::new(
             options.write_buffer_capacity,
+            options.page_alloc_hint,
             next_file_id,
             file_infos,
             HashSet::default(),
@@ -80,14 +111,152 @@ impl<E: Env> PageStore<E> {
             table,
             version,
             page_files,
+            recovered_files,
+            first_file_id: next_file_id,
             manifest,
+            file_lock: Arc::default(),
         })
     }
 
+    /// Removes page files that are neither recovered nor created by this
+    /// store, and returns their paths.
+    ///
+    /// Orphan files left by crashes are already removed on open, so this only
+    /// finds files that appear in the directory afterwards.
+    pub(crate) async fn cleanup_orphans(&self) -> Result<Vec<PathBuf>> {
+        let _file_lock = self.file_lock.lock().await;
+        self.page_files
+            .remove_orphan_files(|file_id| {
+                file_id >= self.first_file_id || self.recovered_files.contains(&file_id)
+            })
+            .await
+    }
+
+    pub(crate) fn env(&self) -> &E {
+        &self.env
+    }
+
     pub(crate) fn guard(&self) -> Guard {
         Guard::new(self.current_version(), &self.table, &self.page_files)
     }
 
+    /// Returns the format of the persisted page files.
+    pub(crate) fn format_info(&self) -> FormatInfo {
+        let version = self.current_version();
+        let mut files = version.files().values().collect::<Vec<_>>();
+        files.sort_unstable_by_key(|file| file.get_file_id());
+        FormatInfo::from_files(files.into_iter().map(|file| file.meta()))
+    }
+
+    /// Returns the number of page files picked by the GC strategy.
+    pub(crate) fn num_files_needing_gc(&self, strategy: &dyn GcPickStrategy) -> usize {
+        self.current_version()
+            .files()
+            .values()
+            .filter(|file| strategy.should_collect(file))
+            .count()
+    }
+
+    /// Returns the number of bytes used in the active write buffer and its
+    /// capacity.
+    pub(crate) fn write_buffer_usage(&self) -> (u32, u32) {
+        self.current_version().buffer_set.usage()
+    }
+
+    /// Returns the number of write buffers waiting to be flushed.
+    pub(crate) fn num_pending_flush(&self) -> usize {
+        self.current_version().buffer_set.num_pending_flush()
+    }
+
+    /// Waits until fewer than `limit` write buffers are waiting to be flushed.
+    pub(crate) async fn wait_pending_flush_below(&self, limit: usize) {
+        let buffer_set = self.current_version().buffer_set.clone();
+        buffer_set.wait_pending_flush_below(limit).await;
+    }
+
+    /// Reclaims the space of the dead pages in page files whose content is
+    /// selected by `f`.
+    ///
+    /// The selected pages are marked as holes first, so that readers that
+    /// load them from now on retry with the latest page table, and readers
+    /// that are reading them find the holes once they are done. They are
+    /// punched once `since` and the versions before it are released, since
+    /// only their readers may have loaded the pages before they were
+    /// deallocated. The wait is bounded by [`PUNCH_WAIT_LIMIT`], since an idle
+    /// thread may keep an old version cached without reading anything.
+    pub(crate) async fn punch_dead_pages<F>(&self, since: Version, f: F) -> Result<()>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let _file_lock = self.file_lock.lock().await;
+        let version = self.global_version();
+        let mut pages = Vec::new();
+        for file in version.files().values() {
+            let file_id = file.get_file_id();
+            let block_size = file.meta().block_size();
+            for (page_addr, handle) in file.dead_pages() {
+                let (offset, size) = (handle.offset as u64, handle.size as usize);
+                let page = self
+                    .page_files
+                    .read_page(file_id, block_size, offset, size)
+                    .await?;
+                if f(&page) {
+                    file.mark_hole(page_addr);
+                    pages.push((file_id, handle));
+                }
+            }
+        }
+        let start = Instant::now();
+        while !since.is_released() && start.elapsed() < PUNCH_WAIT_LIMIT {
+            // Release the version cached by this thread.
+            drop(self.current_version());
+            unblock(|| std::thread::sleep(Duration::from_millis(1))).await;
+        }
+        for (file_id, handle) in pages {
+            self.page_files
+                .punch_hole(file_id, handle.offset as u64, handle.size as u64)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the active write buffer and stops changing files until the
+    /// guard is dropped.
+    pub(crate) async fn quiesce(&self) -> QuiesceGuard<'_> {
+        self.seal_active_buffer();
+        self.wait_pending_flush_below(1).await;
+        QuiesceGuard {
+            _file_lock: self.file_lock.lock().await,
+        }
+    }
+
+    /// Seals the active write buffer if it is not empty, so that it will be
+    /// flushed.
+    pub(crate) fn seal_active_buffer(&self) {
+        let version = self.current_version();
+        let buffer_set = &version.buffer_set;
+        let current = buffer_set.current();
+        let write_buffer = current.last_writer_buffer();
+        if write_buffer.allocated_size() == 0 {
+            return;
+        }
+        // Safety: no writer is acquired by this call.
+        let Ok(release_state) = (unsafe { write_buffer.seal(false) }) else {
+            // The buffer has been sealed by a writer, which installs the next one.
+            return;
+        };
+        let next_buffer = buffer_set.new_write_buffer(write_buffer.file_id() + 1);
+        buffer_set.install(Arc::new(next_buffer));
+        if matches!(release_state, ReleaseState::Flush) {
+            buffer_set.notify_flush_job();
+        }
+    }
+
+    /// Returns true if some write buffers are full and waiting to be flushed.
+    pub(crate) fn flush_scheduled(&self) -> bool {
+        self.current_version().buffer_set.has_pending_flush()
+    }
+
     #[inline]
     fn current_version(&self) -> Arc<Version> {
         Version::from_local().unwrap_or_else(|| {
@@ -97,58 +266,160 @@ impl<E: Env> PageStore<E> {
         })
     }
 
+    /// Returns the latest version.
     #[inline]
-    fn global_version(&self) -> Version {
-        self.version.lock().expect("Poisoned").clone()
+    pub(crate) fn global_version(&self) -> Version {
+        lock(&self.version).clone()
     }
 }
 
-pub(crate) struct JobHandle {
+pub(crate) struct JobHandle<E: Env> {
     flush_task: Option<BoxFuture<'static, ()>>,
     cleanup_task: Option<BoxFuture<'static, ()>>,
     gc_task: Option<BoxFuture<'static, ()>>,
+    scrub_task: Option<BoxFuture<'static, ()>>,
+    /// The jobs run by [`JobHandle::tick`] if [`Options::run_jobs_inline`] is
+    /// enabled.
+    inline: Option<futures::lock::Mutex<InlineJobs<E>>>,
+    health: Arc<JobHealth>,
+    pick_strategy: Arc<dyn GcPickStrategy>,
 }
 
-impl JobHandle {
-    pub(crate) fn new<E: Env>(
-        env: &'static E,
+impl<E: Env> JobHandle<E> {
+    pub(crate) fn new(
+        env: &E,
         page_store: &PageStore<E>,
         rewriter: Arc<dyn RewritePage>,
         pick_strategy: Box<dyn GcPickStrategy>,
-    ) -> JobHandle {
-        use self::jobs::{cleanup::CleanupCtx, flush::FlushCtx, gc::GcCtx};
+    ) -> JobHandle<E> {
+        use self::jobs::{
+            cleanup::CleanupCtx, flush::FlushCtx, gc::GcCtx, health::Job, scrub::ScrubCtx,
+        };
 
+        let pick_strategy: Arc<dyn GcPickStrategy> = pick_strategy.into();
         let page_files = page_store.page_files.clone();
         let version = page_store.version.clone();
         let manifest = page_store.manifest.clone();
+        let health = Arc::new(JobHealth::default());
+        let spawn_options = |name: &str| SpawnOptions {
+            name: Some(format!("photondb-{name}")),
+            cpu_set: page_store.options.background_cpu_set.clone(),
+        };
+
+        let file_lock = page_store.file_lock.clone();
+        let cleanup_ctx = CleanupCtx::new(page_files.clone(), file_lock.clone());
+        let global_version = { lock(&version).clone() };
+        if page_store.options.run_jobs_inline {
+            let flush_ctx = FlushCtx::new(version, page_files.clone(), manifest, file_lock.clone());
+            let gc_ctx = GcCtx::new(
+                rewriter,
+                pick_strategy.clone(),
+                page_files,
+                page_store.options.gc_punch_hole,
+                health.clone(),
+                file_lock,
+            );
+            let jobs = InlineJobs::new(flush_ctx, cleanup_ctx, gc_ctx, global_version);
+            return JobHandle {
+                flush_task: None,
+                cleanup_task: None,
+                gc_task: None,
+                scrub_task: None,
+                inline: Some(futures::lock::Mutex::new(jobs)),
+                health,
+                pick_strategy,
+            };
+        }
 
-        let cleanup_ctx = CleanupCtx::new(page_files.clone());
-        let global_version = { version.lock().expect("Poisoned").clone() };
         let cloned_global_version = global_version.clone();
-        let cleanup_task = env.spawn_background(async {
-            cleanup_ctx.run(cloned_global_version).await;
+        let cloned_health = health.clone();
+        let cleanup_task = env.spawn_background(spawn_options("cleanup"), async move {
+            let job = cleanup_ctx.run(cloned_global_version);
+            cloned_health.watch(Job::Cleanup, job).await;
         });
 
-        let flush_ctx = FlushCtx::new(version, page_files.clone(), manifest);
-        let flush_task = env.spawn_background(async {
-            flush_ctx.run().await;
+        let scrub_task = page_store.options.scrub_interval.map(|interval| {
+            let scrub_ctx = ScrubCtx::new(
+                page_files.clone(),
+                health.clone(),
+                interval,
+                page_store.options.scrub_bytes_per_sec,
+            );
+            let cloned_version = version.clone();
+            let cloned_health = health.clone();
+            env.spawn_background(spawn_options("scrub"), async move {
+                cloned_health
+                    .watch(Job::Scrub, scrub_ctx.run(cloned_version))
+                    .await;
+            })
         });
 
-        let gc_ctx = GcCtx::new(rewriter, pick_strategy, page_files);
-        let gc_task = env.spawn_background(async {
-            gc_ctx.run(global_version).await;
+        let flush_ctx = FlushCtx::new(version, page_files.clone(), manifest, file_lock.clone());
+        let cloned_health = health.clone();
+        let flush_task = env.spawn_background(spawn_options("flush"), async move {
+            cloned_health.watch(Job::Flush, flush_ctx.run()).await;
+        });
+
+        let punch_hole = page_store.options.gc_punch_hole;
+        let gc_ctx = GcCtx::new(
+            rewriter,
+            pick_strategy.clone(),
+            page_files,
+            punch_hole,
+            health.clone(),
+            file_lock,
+        );
+        let cloned_health = health.clone();
+        let gc_task = env.spawn_background(spawn_options("gc"), async move {
+            cloned_health
+                .watch(Job::Gc, gc_ctx.run(global_version))
+                .await;
         });
 
         JobHandle {
             flush_task: Some(flush_task),
             cleanup_task: Some(cleanup_task),
             gc_task: Some(gc_task),
+            scrub_task,
+            inline: None,
+            health,
+            pick_strategy,
+        }
+    }
+
+    /// Runs the jobs inline if [`Options::run_jobs_inline`] is enabled.
+    ///
+    /// Does nothing if the jobs run in the background.
+    pub(crate) async fn tick(&self) {
+        if let Some(jobs) = &self.inline {
+            jobs.lock().await.tick().await;
         }
     }
+
+    /// Returns true if the jobs run inline.
+    pub(crate) fn is_inline(&self) -> bool {
+        self.inline.is_some()
+    }
+
+    /// Returns the health of the background jobs.
+    pub(crate) fn health(&self) -> BackgroundHealth {
+        self.health.snapshot()
+    }
+
+    /// Returns the number of page files of the store picked by the GC
+    /// strategy.
+    pub(crate) fn num_files_needing_gc(&self, page_store: &PageStore<E>) -> usize {
+        page_store.num_files_needing_gc(self.pick_strategy.as_ref())
+    }
 }
 
-impl Drop for JobHandle {
+impl<E: Env> Drop for JobHandle<E> {
     fn drop(&mut self) {
+        // Waiting for background tasks while unwinding may panic again and abort
+        // the process, so the tasks are detached instead.
+        if std::thread::panicking() {
+            return;
+        }
         futures::executor::block_on(async {
             if let Some(task) = self.flush_task.take() {
                 task.await;
@@ -159,6 +430,9 @@ impl Drop for JobHandle {
             if let Some(task) = self.gc_task.take() {
                 task.await;
             }
+            if let Some(task) = self.scrub_task.take() {
+                task.await;
+            }
         });
     }
 }
@@ -0,0 +1,63 @@
+/// The address of a page.
+///
+/// A page address packs the id of the file that holds the page in the high 32
+/// bits, and the index of the page in the file in the low 32 bits. For pages in
+/// a write buffer, the index is the offset of the page in the buffer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) struct PageAddr(u64);
+
+impl PageAddr {
+    pub(crate) const fn new(file_id: u32, index: u32) -> Self {
+        Self(((file_id as u64) << 32) | index as u64)
+    }
+
+    /// Returns the id of the file that holds the page.
+    pub(crate) const fn file_id(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Returns the index of the page in the file.
+    pub(crate) const fn index(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+impl From<u64> for PageAddr {
+    fn from(addr: u64) -> Self {
+        Self(addr)
+    }
+}
+
+impl From<PageAddr> for u64 {
+    fn from(addr: PageAddr) -> Self {
+        addr.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_addr_round_trip() {
+        let cases = [
+            (0, 0),
+            (1, 0),
+            (0, 1),
+            (3, 7),
+            (u32::MAX, 0),
+            (0, u32::MAX),
+            (u32::MAX, u32::MAX),
+        ];
+        for (file_id, index) in cases {
+            let addr = PageAddr::new(file_id, index);
+            assert_eq!(addr.file_id(), file_id);
+            assert_eq!(addr.index(), index);
+            let raw = u64::from(addr);
+            assert_eq!(PageAddr::from(raw), addr);
+        }
+        assert_eq!(u64::from(PageAddr::new(1, 2)), (1 << 32) | 2);
+        // Addresses are ordered by file id first.
+        assert!(PageAddr::new(1, u32::MAX) < PageAddr::new(2, 0));
+    }
+}
@@ -9,10 +9,22 @@ use photonio::{
     io::WriteExt,
 };
 
-use super::{types::split_page_addr, FileInfo, FileMeta};
-use crate::page_store::{Error, Result};
+use super::{FileInfo, FileMeta};
+use crate::{
+    page_store::{Error, PageAddr, Result},
+    ChecksumType,
+};
+
+/// The default size of writes to page files.
+pub(crate) const IO_BUFFER_SIZE: usize = 4096 * 4;
 
-const IO_BUFFER_SIZE: usize = 4096 * 4;
+/// The magic number at the start of the footer of files without checksums,
+/// written before the footer has a version.
+const LEGACY_MAGIC: u64 = 142857;
+/// The magic number at the end of the footer of versioned files.
+const MAGIC: u64 = 0x5044_4250_4147_4546;
+/// The version of the file format, recorded in the footer.
+const FORMAT_VERSION: u64 = 1;
 
 /// Builder for a page file.
 ///
@@ -24,9 +36,16 @@ const IO_BUFFER_SIZE: usize = 4096 * 4;
 /// page table block = [(page_id, page_addr[low 32bit])]
 /// delete pages block = [delete-page-addr]
 /// index_blocks = {data block index} {meta block index}
-/// data block index = {page_addr[low 32bit], file_offset}
+/// data block index = [{page_addr, file_offset, page checksum}]
 /// meta block index = {file_offset}
-/// footer = {magic_number} {data block index} {meta block index}
+/// footer = {data block index} {meta block index} {checksum type}
+///          {index blocks checksum} {format version} {magic_number}
+///
+/// Files written before the footer has a version end with a legacy footer
+/// instead, which has no checksums:
+///
+/// data block index = [{page_addr, file_offset}]
+/// legacy footer = {legacy magic_number} {data block index} {meta block index}
 ///
 /// `page_addr`'s high 32 bit always be `file-id`(`PageAddr = {file id} {write
 /// buffer index}`), so it only store lower 32 bit.
@@ -39,12 +58,21 @@ pub(crate) struct FileBuilder {
 
     last_add_page_id: u64,
     block_size: usize,
+    checksum_type: ChecksumType,
 }
 
 impl FileBuilder {
     /// Create new file builder for given writer.
-    pub(crate) fn new(file_id: u32, file: File, use_direct: bool, block_size: usize) -> Self {
-        let writer = BufferedWriter::new(file, IO_BUFFER_SIZE, use_direct, block_size);
+    pub(crate) fn new(
+        file_id: u32,
+        file: File,
+        use_direct: bool,
+        use_sync: bool,
+        block_size: usize,
+        io_size: usize,
+        checksum_type: ChecksumType,
+    ) -> Self {
+        let writer = BufferedWriter::new(file, io_size, use_direct, block_size).with_sync(use_sync);
         Self {
             file_id,
             writer,
@@ -52,6 +80,7 @@ impl FileBuilder {
             meta: Default::default(),
             last_add_page_id: 0,
             block_size,
+            checksum_type,
         }
     }
 
@@ -68,7 +97,8 @@ impl FileBuilder {
         self.last_add_page_id = page_id;
 
         let file_offset = self.writer.write(page_content).await?;
-        self.index.add_data_block(page_addr, file_offset);
+        let checksum = self.checksum_type.checksum(page_content);
+        self.index.add_data_block(page_addr, file_offset, checksum);
         self.meta.add_page(page_id, page_addr);
         Ok(())
     }
@@ -105,8 +135,9 @@ impl FileBuilder {
     async fn finish_file_footer(&mut self) -> Result<FileInfo> {
         let footer = {
             let (data_index, meta_index) = self.index.finish_index_block();
+            let checksum = index_blocks_checksum(self.checksum_type, &data_index, &meta_index);
             Footer {
-                magic: 142857,
+                format_version: FORMAT_VERSION,
                 data_handle: BlockHandler {
                     offset: { self.writer.write(&data_index).await? },
                     length: data_index.len() as u64,
@@ -115,10 +146,13 @@ impl FileBuilder {
                     offset: { self.writer.write(&meta_index).await? },
                     length: meta_index.len() as u64,
                 },
+                checksum_type: self.checksum_type,
+                checksum,
             }
         };
 
         let footer_dat = footer.encode();
+        debug_assert_eq!(footer_dat.len(), Footer::size() as usize);
         let foot_offset = self.writer.write(&footer_dat).await?;
         let file_size = foot_offset as usize + footer_dat.len();
 
@@ -127,20 +161,26 @@ impl FileBuilder {
 
     fn as_file_info(&self, file_size: usize, footer: &Footer) -> FileInfo {
         let meta = {
-            let (indexes, offsets) = self.index.index_block.as_meta_file_cached(footer);
-            Arc::new(FileMeta::new(
+            let index_block = &self.index.index_block;
+            let (indexes, offsets) = index_block.as_meta_file_cached(footer);
+            let meta = FileMeta::new(
                 self.file_id,
                 file_size as usize,
                 indexes,
                 offsets,
                 self.block_size,
-            ))
+                footer.checksum_type,
+            );
+            Arc::new(
+                meta.with_format_version(footer.format_version)
+                    .with_page_checksums(index_block.page_checksums.clone()),
+            )
         };
 
         let active_pages = {
             let mut active_pages = roaring::RoaringBitmap::new();
             for (_page_id, page_addr) in &self.meta.page_table.0 {
-                let (_, index) = split_page_addr(*page_addr);
+                let index = PageAddr::from(*page_addr).index();
                 active_pages.insert(index);
             }
             active_pages
@@ -184,45 +224,109 @@ impl BlockHandler {
 }
 
 pub(crate) struct Footer {
-    magic: u64,
+    /// The version of the file format, 0 for files with a legacy footer.
+    pub(crate) format_version: u64,
     pub(crate) data_handle: BlockHandler,
     pub(crate) meta_handle: BlockHandler,
+    pub(crate) checksum_type: ChecksumType,
+    pub(crate) checksum: u64,
 }
 
 impl Footer {
+    /// Returns the size of the footer of the current format.
     #[inline]
     pub(crate) fn size() -> u32 {
+        (core::mem::size_of::<u64>() * 8) as u32
+    }
+
+    /// Returns the size of the legacy footer.
+    #[inline]
+    pub(crate) fn legacy_size() -> u32 {
         (core::mem::size_of::<u64>() * 5) as u32
     }
 
     fn encode(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(core::mem::size_of::<u64>() * 5);
-        bytes.extend_from_slice(&self.magic.to_le_bytes());
+        let mut bytes = Vec::with_capacity(Self::size() as usize);
         self.data_handle.encode(&mut bytes);
         self.meta_handle.encode(&mut bytes);
+        bytes.extend_from_slice(&self.checksum_type.code().to_le_bytes());
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        bytes.extend_from_slice(&self.format_version.to_le_bytes());
+        bytes.extend_from_slice(&MAGIC.to_le_bytes());
         bytes
     }
 
+    /// Decodes the footer at the end of the bytes, which are the last
+    /// [`Self::size`] bytes of the file, or all of them if the file is smaller.
     pub(crate) fn decode(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() != Self::size() as usize {
+        let size = Self::size() as usize;
+        if bytes.len() >= size && read_u64(&bytes[bytes.len() - 8..])? == MAGIC {
+            return Self::decode_versioned(&bytes[bytes.len() - size..]);
+        }
+        let legacy_size = Self::legacy_size() as usize;
+        if bytes.len() < legacy_size {
+            return Err(Error::Corrupted);
+        }
+        Self::decode_legacy(&bytes[bytes.len() - legacy_size..])
+    }
+
+    fn decode_versioned(bytes: &[u8]) -> Result<Self> {
+        let u64_size = core::mem::size_of::<u64>();
+        let data_handle = BlockHandler::decode(&bytes[..u64_size * 2])?;
+        let meta_handle = BlockHandler::decode(&bytes[u64_size * 2..u64_size * 4])?;
+        let checksum_type = read_u64(&bytes[u64_size * 4..])?;
+        let checksum_type = ChecksumType::from_code(checksum_type).ok_or(Error::Corrupted)?;
+        let checksum = read_u64(&bytes[u64_size * 5..])?;
+        let format_version = read_u64(&bytes[u64_size * 6..])?;
+        if format_version != FORMAT_VERSION {
             return Err(Error::Corrupted);
         }
-        let mut idx = 0;
-        let magic = u64::from_le_bytes(
-            bytes[idx..idx + core::mem::size_of::<u64>()]
-                .try_into()
-                .map_err(|_| Error::Corrupted)?,
-        );
-        idx += core::mem::size_of::<u64>();
-        let data_handle = BlockHandler::decode(&bytes[idx..idx + core::mem::size_of::<u64>() * 2])?;
-        idx += core::mem::size_of::<u64>() * 2;
-        let meta_handle = BlockHandler::decode(&bytes[idx..idx + core::mem::size_of::<u64>() * 2])?;
         Ok(Self {
-            magic,
+            format_version,
             data_handle,
             meta_handle,
+            checksum_type,
+            checksum,
         })
     }
+
+    fn decode_legacy(bytes: &[u8]) -> Result<Self> {
+        let u64_size = core::mem::size_of::<u64>();
+        if read_u64(bytes)? != LEGACY_MAGIC {
+            return Err(Error::Corrupted);
+        }
+        let data_handle = BlockHandler::decode(&bytes[u64_size..u64_size * 3])?;
+        let meta_handle = BlockHandler::decode(&bytes[u64_size * 3..u64_size * 5])?;
+        Ok(Self {
+            format_version: 0,
+            data_handle,
+            meta_handle,
+            checksum_type: ChecksumType::None,
+            checksum: 0,
+        })
+    }
+
+    /// Returns the size of an entry of the data block index.
+    fn data_index_entry_size(&self) -> usize {
+        let num_fields = if self.format_version == 0 { 2 } else { 3 };
+        core::mem::size_of::<u64>() * num_fields
+    }
+}
+
+/// Reads a little-endian u64 at the start of the bytes.
+fn read_u64(bytes: &[u8]) -> Result<u64> {
+    let bytes = bytes.get(..core::mem::size_of::<u64>());
+    let bytes = bytes.ok_or(Error::Corrupted)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Returns the checksum of the index blocks recorded in the footer.
+pub(crate) fn index_blocks_checksum(
+    checksum_type: ChecksumType,
+    data_index: &[u8],
+    meta_index: &[u8],
+) -> u64 {
+    checksum_type.checksum(&[data_index, meta_index].concat())
 }
 
 #[derive(Default)]
@@ -264,8 +368,21 @@ impl PageTable {
         bytes
     }
 
+    /// The size of an encoded entry.
+    pub(crate) const ENTRY_SIZE: usize = core::mem::size_of::<u64>() * 2;
+
     pub(crate) fn decode(bytes: &[u8]) -> Result<Self> {
-        let mut table = BTreeMap::new();
+        let mut table = PageTable::default();
+        table.decode_from(bytes)?;
+        Ok(table)
+    }
+
+    /// Decodes the entries in the bytes into the table.
+    ///
+    /// The bytes must contain whole entries, so a large table can be decoded
+    /// in chunks.
+    pub(crate) fn decode_from(&mut self, bytes: &[u8]) -> Result<()> {
+        let table = &mut self.0;
         let mut idx = 0;
         while idx < bytes.len() {
             let end = idx + core::mem::size_of::<u64>() * 2;
@@ -285,7 +402,7 @@ impl PageTable {
             table.insert(page_addr, page_id);
             idx = end;
         }
-        Ok(PageTable(table))
+        Ok(())
     }
 }
 
@@ -307,8 +424,21 @@ impl DeletePages {
         bytes
     }
 
+    /// The size of an encoded entry.
+    pub(crate) const ENTRY_SIZE: usize = core::mem::size_of::<u64>();
+
     pub(crate) fn decode(bytes: &[u8]) -> Result<Self> {
-        let mut pages = BTreeSet::new();
+        let mut pages = DeletePages::default();
+        pages.decode_from(bytes)?;
+        Ok(pages)
+    }
+
+    /// Decodes the entries in the bytes into the pages.
+    ///
+    /// The bytes must contain whole entries, so a large block can be decoded
+    /// in chunks.
+    pub(crate) fn decode_from(&mut self, bytes: &[u8]) -> Result<()> {
+        let pages = &mut self.0;
         let mut idx = 0;
         while idx < bytes.len() {
             let end = idx + core::mem::size_of::<u64>();
@@ -320,7 +450,7 @@ impl DeletePages {
             pages.insert(del_page);
             idx = end;
         }
-        Ok(DeletePages(pages))
+        Ok(())
     }
 }
 
@@ -337,8 +467,9 @@ struct IndexBlockBuilder {
 
 impl IndexBlockBuilder {
     #[inline]
-    pub(crate) fn add_data_block(&mut self, page_addr: u64, file_offset: u64) {
+    pub(crate) fn add_data_block(&mut self, page_addr: u64, file_offset: u64, checksum: u64) {
         self.index_block.page_offsets.insert(page_addr, file_offset);
+        self.index_block.page_checksums.insert(page_addr, checksum);
     }
 
     #[inline]
@@ -364,6 +495,8 @@ impl IndexBlockBuilder {
 #[derive(Default)]
 pub(crate) struct IndexBlock {
     pub(crate) page_offsets: BTreeMap<u64, u64>,
+    /// The checksums of the pages, empty for files with a legacy footer.
+    pub(crate) page_checksums: BTreeMap<u64, u64>,
     pub(crate) meta_page_table: Option<u64>,
     pub(crate) meta_delete_pages: Option<u64>,
 }
@@ -371,10 +504,12 @@ pub(crate) struct IndexBlock {
 impl IndexBlock {
     fn encode_data_block_index(&self) -> Vec<u8> {
         let mut bytes =
-            Vec::with_capacity(self.page_offsets.len() * core::mem::size_of::<u64>() * 2);
+            Vec::with_capacity(self.page_offsets.len() * core::mem::size_of::<u64>() * 3);
         for (page_addr, offset) in &self.page_offsets {
+            let checksum = self.page_checksums[page_addr];
             bytes.extend_from_slice(&page_addr.to_le_bytes());
-            bytes.extend_from_slice(&offset.to_le_bytes())
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&checksum.to_le_bytes());
         }
         bytes
     }
@@ -386,27 +521,25 @@ impl IndexBlock {
         bytes
     }
 
-    pub(crate) fn decode(data_index_bytes: &[u8], meta_index_bytes: &[u8]) -> Result<Self> {
+    pub(crate) fn decode(
+        data_index_bytes: &[u8],
+        meta_index_bytes: &[u8],
+        footer: &Footer,
+    ) -> Result<Self> {
         let mut page_offsets = BTreeMap::new();
-        let mut idx = 0;
-        while idx < data_index_bytes.len() {
-            let end = idx + core::mem::size_of::<u64>() * 2;
-            if end > data_index_bytes.len() {
-                return Err(Error::Corrupted);
+        let mut page_checksums = BTreeMap::new();
+        let entry_size = footer.data_index_entry_size();
+        if data_index_bytes.len() % entry_size != 0 {
+            return Err(Error::Corrupted);
+        }
+        for entry in data_index_bytes.chunks(entry_size) {
+            let page_addr = read_u64(entry)?;
+            let offset = read_u64(&entry[core::mem::size_of::<u64>()..])?;
+            page_offsets.insert(page_addr, offset);
+            if footer.format_version != 0 {
+                let checksum = read_u64(&entry[core::mem::size_of::<u64>() * 2..])?;
+                page_checksums.insert(page_addr, checksum);
             }
-            let page_addr = u64::from_le_bytes(
-                data_index_bytes[idx..idx + core::mem::size_of::<u64>()]
-                    .try_into()
-                    .map_err(|_| Error::Corrupted)?,
-            );
-            let page_id = u64::from_le_bytes(
-                data_index_bytes
-                    [idx + core::mem::size_of::<u64>()..idx + core::mem::size_of::<u64>() * 2]
-                    .try_into()
-                    .map_err(|_| Error::Corrupted)?,
-            );
-            page_offsets.insert(page_addr, page_id);
-            idx = end;
         }
 
         if meta_index_bytes.len() != core::mem::size_of::<u64>() * 2 {
@@ -424,6 +557,7 @@ impl IndexBlock {
         ));
         Ok(Self {
             page_offsets,
+            page_checksums,
             meta_page_table,
             meta_delete_pages,
         })
@@ -443,6 +577,7 @@ struct BufferedWriter {
     file: File,
 
     use_direct: bool,
+    use_sync: bool,
 
     next_page_offset: u64,
     actual_data_size: usize,
@@ -450,6 +585,10 @@ struct BufferedWriter {
     align_size: usize,
     buffer: AlignBuffer,
     buf_pos: usize,
+
+    /// The offsets and lengths of the writes issued to the file.
+    #[cfg(test)]
+    io_log: Vec<(usize, usize)>,
 }
 
 impl BufferedWriter {
@@ -458,14 +597,28 @@ impl BufferedWriter {
         Self {
             file,
             use_direct,
+            use_sync: true,
             next_page_offset: 0,
             actual_data_size: 0,
             align_size,
             buffer,
             buf_pos: 0,
+            #[cfg(test)]
+            io_log: Vec::new(),
         }
     }
 
+    fn with_sync(mut self, use_sync: bool) -> Self {
+        self.use_sync = use_sync;
+        self
+    }
+
+    /// Appends the page to the buffer, and writes the buffer to the file
+    /// whenever it is full.
+    ///
+    /// The buffer size is a multiple of the block size, so with direct I/O
+    /// every write but the last one in [`Self::flush_and_sync`] covers whole
+    /// blocks, and the device never needs to read-modify-write a block.
     async fn write(&mut self, page: &[u8]) -> Result<u64> {
         let mut page_consumed = 0;
         let buf_cap = self.buffer.len();
@@ -478,7 +631,7 @@ impl BufferedWriter {
                 self.buf_pos += fill_end - page_consumed;
                 page_consumed = fill_end;
             } else {
-                self.flush().await?;
+                self.write_buffer(buf_cap).await?;
             }
         }
         let page_offset = self.next_page_offset;
@@ -486,19 +639,29 @@ impl BufferedWriter {
         Ok(page_offset)
     }
 
+    /// Writes the buffered data to the file, padding the last block with
+    /// direct I/O.
+    ///
+    /// The padding is truncated by [`Self::flush_and_sync`], so no more data
+    /// can be written after a partial block is flushed.
     async fn flush(&mut self) -> Result<()> {
         if self.buf_pos == 0 {
             return Ok(());
         }
-        if self.use_direct {
-            self.actual_data_size += self.buf_pos;
-            let align_len = ceil_to_block_hi_pos(self.buf_pos, self.align_size);
-            self.buf_pos = align_len;
-        }
-        self.file
-            .write_all(&self.buffer.as_bytes()[..self.buf_pos])
-            .await
-            .expect("flush page file error");
+        let len = if self.use_direct {
+            ceil_to_block_hi_pos(self.buf_pos, self.align_size)
+        } else {
+            self.buf_pos
+        };
+        self.write_buffer(len).await
+    }
+
+    async fn write_buffer(&mut self, len: usize) -> Result<()> {
+        #[cfg(test)]
+        self.io_log
+            .push((self.io_log.iter().map(|(_, len)| len).sum(), len));
+        self.actual_data_size += self.buf_pos;
+        self.file.write_all(&self.buffer.as_bytes()[..len]).await?;
         self.buf_pos = 0;
         Ok(())
     }
@@ -506,13 +669,12 @@ impl BufferedWriter {
     async fn flush_and_sync(&mut self) -> Result<()> {
         self.flush().await?;
         if self.use_direct {
-            self.file
-                .set_len(self.actual_data_size as u64)
-                .await
-                .expect("set set file len fail");
+            self.file.set_len(self.actual_data_size as u64).await?;
+        }
+        if self.use_sync {
+            // panic when sync fail, https://wiki.postgresql.org/wiki/Fsync_Errors
+            self.file.sync_all().await.expect("sync file fail");
         }
-        // panic when sync fail, https://wiki.postgresql.org/wiki/Fsync_Errors
-        self.file.sync_all().await.expect("sync file fail");
         Ok(())
     }
 }
@@ -557,7 +719,7 @@ impl AlignBuffer {
         self.size
     }
 
-    fn as_bytes(&self) -> &[u8] {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
         unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.size) }
     }
 
@@ -586,11 +748,6 @@ unsafe impl Send for AlignBuffer {}
 /// guaranteed that the aliases do not overlap.
 unsafe impl Sync for AlignBuffer {}
 
-#[inline]
-pub(crate) fn floor_to_block_lo_pos(pos: usize, align: usize) -> usize {
-    pos - (pos & (align - 1))
-}
-
 #[inline]
 pub(crate) fn ceil_to_block_hi_pos(pos: usize, align: usize) -> usize {
     ((pos + align - 1) / align) * align
@@ -603,7 +760,7 @@ pub(crate) fn is_block_aligned_pos(pos: usize, align: usize) -> bool {
 
 #[inline]
 pub(crate) fn is_block_aligned_ptr(p: *const u8, align: usize) -> bool {
-    p.is_aligned_to(align)
+    is_block_aligned_pos(p as usize, align)
 }
 
 #[cfg(test)]
@@ -647,4 +804,47 @@ mod tests {
             assert_eq!(length, 10 + (4096 * 2 + 1) * 2)
         }
     }
+
+    #[photonio::test]
+    async fn test_buffered_writer_coalesces_direct_writes() {
+        use std::os::unix::prelude::OpenOptionsExt;
+
+        use photonio::io::ReadAtExt;
+
+        const ALIGN: usize = 512;
+        let path = std::env::temp_dir().join("buf_test_coalesce");
+        let file = photonio::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(0x4000)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .await
+            .unwrap();
+        let mut writer = BufferedWriter::new(file, ALIGN * 3, true, ALIGN);
+        let mut expect = Vec::new();
+        for i in 0..1000usize {
+            let page = [i as u8].repeat(i % 37 + 1);
+            let offset = writer.write(&page).await.unwrap();
+            assert_eq!(offset as usize, expect.len());
+            expect.extend_from_slice(&page);
+        }
+        writer.flush_and_sync().await.unwrap();
+
+        assert!(writer.io_log.len() > 1);
+        for &(offset, len) in &writer.io_log {
+            assert!(is_block_aligned_pos(offset, ALIGN));
+            assert!(is_block_aligned_pos(len, ALIGN));
+        }
+
+        let file = photonio::fs::OpenOptions::new()
+            .read(true)
+            .open(&path)
+            .await
+            .unwrap();
+        assert_eq!(file.metadata().await.unwrap().len() as usize, expect.len());
+        let mut buf = vec![0u8; expect.len()];
+        file.read_exact_at(&mut buf, 0).await.unwrap();
+        assert_eq!(buf, expect);
+    }
 }
@@ -1,14 +1,24 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, io, sync::Arc, time::Duration};
 
 use photonio::io::{ReadAt, ReadAtExt};
 
 use super::{file_builder::*, types::FileMeta};
-use crate::page_store::{Error, Result};
+use crate::{
+    env::Mmap,
+    page_store::{Error, Result},
+    util::{align::aligned_range, sync::unblock},
+    ReadRetry,
+};
+
+/// The maximum total time that a read backs off before it gives up.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
 
 pub(crate) struct PageFileReader<R: ReadAt> {
     reader: R,
+    mmap: Option<Arc<Mmap>>,
     use_direct: bool,
     align_size: usize,
+    retry: ReadRetry,
 }
 
 impl<R: ReadAt> PageFileReader<R> {
@@ -16,35 +26,119 @@ impl<R: ReadAt> PageFileReader<R> {
     pub(super) fn from(reader: R, use_direct: bool, align_size: usize) -> Self {
         Self {
             reader,
+            mmap: None,
             use_direct,
             align_size,
+            retry: ReadRetry::default(),
         }
     }
 
+    /// Retries reads on transient errors.
+    pub(super) fn with_retry(mut self, retry: ReadRetry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Serves reads from the memory mapping of the file if possible.
+    pub(super) fn with_mmap(mut self, mmap: Arc<Mmap>) -> Self {
+        self.mmap = Some(mmap);
+        self
+    }
+
     /// Reads the exact number of bytes from the page specified by `offset`.
+    ///
+    /// I/O errors are returned to the caller, so that reads can fail without
+    /// bringing down the process.
     pub(crate) async fn read_exact_at(&self, buf: &mut [u8], req_offset: u64) -> Result<()> {
+        if let Some(mmap) = &self.mmap {
+            if mmap.read_at(buf, req_offset) == buf.len() {
+                return Ok(());
+            }
+        }
         if !self.use_direct {
-            self.reader
-                .read_exact_at(buf, req_offset)
-                .await
-                .expect("read page data fail");
-            return Ok(());
+            let mut backoff = Backoff::default();
+            loop {
+                match self.reader.read_exact_at(buf, req_offset).await {
+                    Ok(()) => return Ok(()),
+                    Err(err) => self.back_off(err, &mut backoff).await?,
+                }
+            }
+        }
+
+        let (align_buf, offset_ahead) = self.read_aligned(buf.len(), req_offset).await?;
+        buf.copy_from_slice(&align_buf.as_bytes()[offset_ahead..offset_ahead + buf.len()]);
+
+        Ok(())
+    }
+
+    /// Reads `len` bytes at `offset` into a new buffer.
+    ///
+    /// Unlike [`Self::read_exact_at`], the buffer is not zeroed before it is
+    /// filled, which saves a pass over large pages. A short read returns an
+    /// error, so no uninitialized bytes are exposed.
+    pub(crate) async fn read_to_vec(&self, len: usize, req_offset: u64) -> Result<Vec<u8>> {
+        if let Some(mmap) = &self.mmap {
+            if let Some(data) = mmap.slice(req_offset, len) {
+                return Ok(data.to_vec());
+            }
+        }
+        if !self.use_direct || len == 0 {
+            // The buffered reader needs an initialized buffer to read into.
+            let mut buf = vec![0u8; len];
+            self.read_exact_at(&mut buf, req_offset).await?;
+            return Ok(buf);
         }
 
-        let align_offset = floor_to_block_lo_pos(req_offset as usize, self.align_size);
-        let offset_ahead = (req_offset as usize) - align_offset;
-        let align_buf_size =
-            ceil_to_block_hi_pos(req_offset as usize + buf.len(), self.align_size) - align_offset;
+        let (align_buf, offset_ahead) = self.read_aligned(len, req_offset).await?;
+        Ok(align_buf.as_bytes()[offset_ahead..offset_ahead + len].to_vec())
+    }
+
+    /// Reads the aligned blocks covering `len` bytes at `req_offset`.
+    ///
+    /// Returns the blocks and the offset of the requested bytes in them.
+    async fn read_aligned(&self, len: usize, req_offset: u64) -> Result<(AlignBuffer, usize)> {
+        let (align_offset, align_buf_size, offset_ahead) =
+            aligned_range(req_offset, len, self.align_size);
 
         let mut align_buf = AlignBuffer::new(align_buf_size, self.align_size); // TODO: pool this buf?
         let read_buf = align_buf.as_bytes_mut();
 
-        self.inner_read_exact_at(&self.reader, read_buf, align_offset as u64)
-            .await
-            .expect("read page data fail");
+        let mut backoff = Backoff::default();
+        let read = loop {
+            match self
+                .inner_read_exact_at(&self.reader, read_buf, align_offset)
+                .await
+            {
+                Ok(read) => break read,
+                Err(err) => self.back_off(err, &mut backoff).await?,
+            }
+        };
+        if read < offset_ahead + len {
+            // The rest of the buffer is uninitialized.
+            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
 
-        buf.copy_from_slice(&read_buf[offset_ahead..offset_ahead + buf.len()]);
+        Ok((align_buf, offset_ahead))
+    }
 
+    /// Backs off before the read is retried after the error, or returns the
+    /// error if the read should not be retried.
+    ///
+    /// The wait does not block the runtime, and the total wait of a read is
+    /// bounded by [`MAX_RETRY_DELAY`].
+    async fn back_off(&self, err: io::Error, backoff: &mut Backoff) -> Result<()> {
+        let remaining = MAX_RETRY_DELAY.saturating_sub(backoff.waited);
+        if backoff.retries >= self.retry.max_retries || remaining.is_zero() || !is_transient(&err) {
+            return Err(err.into());
+        }
+        let delay = self
+            .retry
+            .backoff
+            .saturating_mul(1 << backoff.retries.min(16))
+            .min(remaining);
+        unblock(move || std::thread::sleep(delay)).await;
+        backoff.retries += 1;
+        backoff.waited += delay;
         Ok(())
     }
 
@@ -53,9 +147,10 @@ impl<R: ReadAt> PageFileReader<R> {
         r: &R,
         mut buf: &mut [u8],
         mut pos: u64,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<usize> {
         assert!(is_block_aligned_ptr(buf.as_ptr(), self.align_size));
         assert!(is_block_aligned_pos(pos as usize, self.align_size));
+        let len = buf.len();
         while !buf.is_empty() {
             match r.read_at(buf, pos).await {
                 Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
@@ -71,13 +166,33 @@ impl<R: ReadAt> PageFileReader<R> {
                 Err(e) => return Err(e),
             }
         }
-        Ok(())
+        Ok(len - buf.len())
     }
 }
 
+/// The retries of a read so far.
+#[derive(Default)]
+struct Backoff {
+    retries: u32,
+    waited: Duration,
+}
+
+/// Returns true if the error may go away if the read is retried.
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ) || err.raw_os_error() == Some(libc::EIO)
+}
+
+/// The maximum size of the buffer to read meta pages, so that recovering files
+/// with large meta pages does not allocate huge buffers.
+const META_CHUNK_SIZE: usize = 64 << 10;
+
 pub(crate) struct MetaReader<R: ReadAt> {
     reader: PageFileReader<R>,
     file_meta: Arc<FileMeta>,
+    chunk_size: usize,
 }
 
 impl<R: ReadAt> MetaReader<R> {
@@ -91,13 +206,18 @@ impl<R: ReadAt> MetaReader<R> {
         let index_block = Self::read_index_block(reader, &footer).await?;
         Ok({
             let (indexes, offsets) = index_block.as_meta_file_cached(&footer);
-            Arc::new(FileMeta::new(
+            let meta = FileMeta::new(
                 file_id,
                 file_size as usize,
                 indexes,
                 offsets,
                 reader.align_size,
-            ))
+                footer.checksum_type,
+            );
+            Arc::new(
+                meta.with_format_version(footer.format_version)
+                    .with_page_checksums(index_block.page_checksums),
+            )
         })
     }
 
@@ -108,33 +228,73 @@ impl<R: ReadAt> MetaReader<R> {
         file_id: u32,
     ) -> Result<Self> {
         let file_meta = Self::read_file_meta(&reader, file_size, file_id).await?;
-        Ok(Self { reader, file_meta })
+        Ok(Self {
+            reader,
+            file_meta,
+            chunk_size: META_CHUNK_SIZE,
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
     }
 
     /// Returns the page table in the file.
     pub(crate) async fn read_page_table(&self) -> Result<BTreeMap<u64, u64>> {
         let (page_table_offset, page_table_len) = self.file_meta.get_page_table_meta_page()?;
-        let mut buf = vec![0u8; page_table_len];
-        self.reader
-            .read_exact_at(&mut buf, page_table_offset)
-            .await
-            .expect("read page table meta page fail");
-        let table = PageTable::decode(&buf)?;
+        let mut table = PageTable::default();
+        self.read_chunks(
+            page_table_offset,
+            page_table_len,
+            PageTable::ENTRY_SIZE,
+            |chunk| table.decode_from(chunk),
+        )
+        .await?;
         Ok(table.into())
     }
 
     /// Returns the delete page addrs in the file.
     pub(crate) async fn read_delete_pages(&self) -> Result<Vec<u64> /* delete page addrs */> {
         let (del_offset, del_len) = self.file_meta.get_delete_pages_meta_page()?;
-        let mut buf = vec![0u8; del_len];
-        self.reader
-            .read_exact_at(&mut buf, del_offset)
-            .await
-            .expect("read delete pages meta page fail");
-        let dels = DeletePages::decode(&buf)?;
+        let mut dels = DeletePages::default();
+        self.read_chunks(del_offset, del_len, DeletePages::ENTRY_SIZE, |chunk| {
+            dels.decode_from(chunk)
+        })
+        .await?;
         Ok(dels.into())
     }
 
+    /// Reads `len` bytes at `offset` in chunks and applies the function to each
+    /// chunk.
+    ///
+    /// Chunks are no larger than the chunk size of the reader, unless a single
+    /// entry is larger, and always contain whole entries of `entry_size`.
+    pub(crate) async fn read_chunks<F>(
+        &self,
+        offset: u64,
+        len: usize,
+        entry_size: usize,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> Result<()>,
+    {
+        let chunk_size = (self.chunk_size / entry_size).max(1) * entry_size;
+        let mut buf = vec![0u8; chunk_size.min(len)];
+        let mut pos = 0;
+        while pos < len {
+            let chunk = &mut buf[..chunk_size.min(len - pos)];
+            self.reader
+                .read_exact_at(chunk, offset + pos as u64)
+                .await?;
+            f(chunk)?;
+            pos += chunk.len();
+        }
+        Ok(())
+    }
+
     //// Returns the file metadata for current reader.
     pub(crate) fn file_metadata(&self) -> Arc<FileMeta> {
         self.file_meta.clone()
@@ -143,11 +303,13 @@ impl<R: ReadAt> MetaReader<R> {
 
 impl<R: ReadAt> MetaReader<R> {
     async fn read_footer(read: &PageFileReader<R>, file_size: u32) -> Result<Footer> {
-        if file_size <= Footer::size() {
+        if file_size <= Footer::legacy_size() {
             return Err(Error::Corrupted);
         }
-        let footer_offset = (file_size - Footer::size()) as u64;
-        let mut buf = vec![0u8; Footer::size() as usize];
+        // Files with a legacy footer may be smaller than the current footer.
+        let footer_size = Footer::size().min(file_size);
+        let footer_offset = (file_size - footer_size) as u64;
+        let mut buf = vec![0u8; footer_size as usize];
         read.read_exact_at(&mut buf, footer_offset)
             .await
             .expect("read file footer error");
@@ -166,6 +328,81 @@ impl<R: ReadAt> MetaReader<R> {
             .await
             .expect("read meta page index fail");
 
-        IndexBlock::decode(&data_idx_bytes, &meta_idx_bytes)
+        let checksum =
+            index_blocks_checksum(footer.checksum_type, &data_idx_bytes, &meta_idx_bytes);
+        if checksum != footer.checksum {
+            return Err(Error::Corrupted);
+        }
+
+        IndexBlock::decode(&data_idx_bytes, &meta_idx_bytes, footer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// A reader that fails with the error a number of times before it succeeds.
+    struct FlakyReader {
+        data: Vec<u8>,
+        failures: AtomicU32,
+        error: fn() -> io::Error,
+    }
+
+    impl ReadAt for FlakyReader {
+        type ReadAt<'a> = impl Future<Output = io::Result<usize>> + 'a;
+
+        fn read_at<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> Self::ReadAt<'a> {
+            async move {
+                let failures = self.failures.load(Ordering::Relaxed);
+                if failures > 0 {
+                    self.failures.store(failures - 1, Ordering::Relaxed);
+                    return Err((self.error)());
+                }
+                let data = self.data.get(offset as usize..).unwrap_or_default();
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok(n)
+            }
+        }
+    }
+
+    fn flaky_reader(failures: u32, error: fn() -> io::Error) -> PageFileReader<FlakyReader> {
+        let reader = FlakyReader {
+            data: (0..64).collect(),
+            failures: AtomicU32::new(failures),
+            error,
+        };
+        PageFileReader::from(reader, false, 4096).with_retry(ReadRetry {
+            max_retries: 3,
+            backoff: Duration::from_millis(1),
+        })
+    }
+
+    #[photonio::test]
+    fn read_retry() {
+        let eio = || io::Error::from_raw_os_error(libc::EIO);
+        let mut buf = [0u8; 4];
+
+        // Transient errors within the retry budget are not surfaced.
+        let reader = flaky_reader(3, eio);
+        reader.read_exact_at(&mut buf, 8).await.unwrap();
+        assert_eq!(buf, [8, 9, 10, 11]);
+
+        // The error is returned once the retries are exhausted.
+        let reader = flaky_reader(4, eio);
+        assert!(reader.read_exact_at(&mut buf, 8).await.is_err());
+
+        // Permanent errors are not retried.
+        let reader = flaky_reader(1, || io::ErrorKind::InvalidData.into());
+        assert!(reader.read_exact_at(&mut buf, 8).await.is_err());
+        assert_eq!(reader.reader.failures.load(Ordering::Relaxed), 0);
+        reader.read_exact_at(&mut buf, 8).await.unwrap();
     }
 }
@@ -2,11 +2,8 @@ use std::{collections::HashMap, path::PathBuf};
 
 use photonio::fs::File;
 
-use super::{
-    file_builder::logical_block_size, file_reader::MetaReader, types::split_page_addr, FileInfo,
-    PageFileReader,
-};
-use crate::page_store::{NewFile, Result};
+use super::{file_builder::logical_block_size, file_reader::MetaReader, FileInfo, PageFileReader};
+use crate::page_store::{NewFile, PageAddr, Result};
 
 pub(crate) struct FileInfoBuilder {
     base: PathBuf,
@@ -26,19 +23,21 @@ impl FileInfoBuilder {
     /// Recovery file infos for specified file ids.
     /// It could be used to recovery in-memory `Version`'s `active_files` after
     /// it be recoveried from manifest files.
+    ///
+    /// The files must be sorted by id. Pages are only deleted by files written
+    /// after them, so the delete pages of each file are applied as soon as the
+    /// file is read, and only one file's delete pages are held in memory at a
+    /// time.
     pub(crate) async fn recovery_base_file_infos(
         &self,
         files: &[NewFile],
     ) -> Result<HashMap<u32, FileInfo>> {
+        debug_assert!(files.windows(2).all(|w| w[0].id < w[1].id));
         let mut file_infos = HashMap::with_capacity(files.len());
-        let mut delete_pages_map = HashMap::new();
         for file in files {
             let (info, delete_pages) = self.recovery_one_file(file).await?;
             file_infos.insert(file.id, info);
-            delete_pages_map.insert(file.id, delete_pages);
-        }
-        for (file_id, delete_pages) in delete_pages_map {
-            Self::mantain_file_active_pages(&mut file_infos, file_id, &delete_pages);
+            Self::mantain_file_active_pages(&mut file_infos, file.id, &delete_pages);
         }
         Ok(file_infos)
     }
@@ -72,7 +71,7 @@ impl FileInfoBuilder {
                 .expect("read page table error");
             let mut active_pages = roaring::RoaringBitmap::new();
             for (_page_id, page_addr) in page_table {
-                let (_, index) = split_page_addr(page_addr);
+                let index = PageAddr::from(page_addr).index();
                 active_pages.insert(index);
             }
             active_pages
@@ -93,7 +92,7 @@ impl FileInfoBuilder {
         delete_pages: &[u64],
     ) {
         for page_addr in delete_pages {
-            let (file_id, _) = split_page_addr(*page_addr);
+            let file_id = PageAddr::from(*page_addr).file_id();
             if let Some(info) = files.get_mut(&file_id) {
                 info.deactivate_page(update_at, *page_addr)
             }
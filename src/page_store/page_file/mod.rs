@@ -1,5 +1,7 @@
 mod file_builder;
 pub(crate) use file_builder::FileBuilder;
+#[cfg(test)]
+pub(crate) use file_builder::Footer;
 
 mod file_reader;
 pub(crate) use file_reader::PageFileReader;
@@ -9,15 +11,31 @@ pub(crate) use info_builder::FileInfoBuilder;
 
 mod types;
 pub(crate) use facade::PageFiles;
+pub use types::FormatInfo;
 pub(crate) use types::{FileInfo, FileMeta};
 
 pub(crate) mod facade {
-    use std::{os::unix::prelude::OpenOptionsExt, path::PathBuf};
+    use std::{
+        collections::HashMap,
+        ops::Range,
+        os::unix::prelude::OpenOptionsExt,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
 
     use photonio::fs::{File, OpenOptions};
 
-    use super::{file_builder::logical_block_size, file_reader::MetaReader, *};
-    use crate::page_store::Result;
+    use super::{
+        file_builder::{logical_block_size, IO_BUFFER_SIZE},
+        file_reader::MetaReader,
+        *,
+    };
+    use crate::{
+        env::{seek_data_ranges, Mmap},
+        page_store::{Error, Result},
+        util::sync::{lock, unblock},
+        ChecksumType, ReadRetry,
+    };
 
     /// The facade for page_file module.
     /// it hides the detail about disk location for caller(after it be created).
@@ -26,6 +44,14 @@ pub(crate) mod facade {
 
         file_prefix: String,
         use_direct: bool,
+        use_mmap: bool,
+        use_sync: bool,
+        io_size: usize,
+        checksum_type: ChecksumType,
+        read_retry: ReadRetry,
+        /// The memory mappings of the page files read so far, if mmap reads
+        /// are enabled.
+        mmaps: Mutex<HashMap<u32, Arc<Mmap>>>,
     }
 
     impl PageFiles {
@@ -36,9 +62,45 @@ pub(crate) mod facade {
                 base: base.into(),
                 file_prefix: file_prefile.into(),
                 use_direct: true,
+                use_mmap: false,
+                use_sync: true,
+                io_size: IO_BUFFER_SIZE,
+                checksum_type: ChecksumType::Crc32c,
+                read_retry: ReadRetry::default(),
+                mmaps: Mutex::default(),
             }
         }
 
+        /// Sets the checksum algorithm of new page files.
+        pub(crate) fn with_checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+            self.checksum_type = checksum_type;
+            self
+        }
+
+        /// Sets whether to serve page reads from memory mappings.
+        pub(crate) fn with_mmap_reads(mut self, use_mmap: bool) -> Self {
+            self.use_mmap = use_mmap;
+            self
+        }
+
+        /// Sets how page reads are retried on transient errors.
+        pub(crate) fn with_read_retry(mut self, read_retry: ReadRetry) -> Self {
+            self.read_retry = read_retry;
+            self
+        }
+
+        /// Sets the size of writes to new page files.
+        pub(crate) fn with_io_size(mut self, io_size: usize) -> Self {
+            self.io_size = io_size;
+            self
+        }
+
+        /// Sets whether to sync page files after they are written.
+        pub(crate) fn with_sync(mut self, use_sync: bool) -> Self {
+            self.use_sync = use_sync;
+            self
+        }
+
         /// Create file_builder to write a new page_file.
         pub(crate) async fn new_file_builder(&self, file_id: u32) -> Result<FileBuilder> {
             // TODO: switch to env in suitable time.
@@ -58,7 +120,10 @@ pub(crate) mod facade {
                 file_id,
                 writer,
                 self.use_direct,
+                self.use_sync,
                 block_size,
+                self.io_size,
+                self.checksum_type,
             ))
         }
 
@@ -88,13 +153,60 @@ pub(crate) mod facade {
         ) -> Result<PageFileReader<File>> {
             let path = self.base.join(format!("{}_{}", self.file_prefix, file_id));
             let flags = self.direct_flags();
+            let mmap = if self.use_mmap {
+                Some(self.mmap(file_id)?)
+            } else {
+                None
+            };
             let file = OpenOptions::new()
                 .read(true)
                 .custom_flags(flags)
                 .open(path)
-                .await
-                .expect("open reader for file_id: {file_id} fail");
-            Ok(PageFileReader::from(file, self.use_direct, block_size))
+                .await?;
+            let reader =
+                PageFileReader::from(file, self.use_direct, block_size).with_retry(self.read_retry);
+            Ok(match mmap {
+                Some(mmap) => reader.with_mmap(mmap),
+                None => reader,
+            })
+        }
+
+        /// Reads `size` bytes of the page at `offset` in the page file.
+        ///
+        /// If mmap reads are enabled, the page is copied from the mapping of
+        /// the file without opening it.
+        pub(crate) async fn read_page(
+            &self,
+            file_id: u32,
+            block_size: usize,
+            offset: u64,
+            size: usize,
+        ) -> Result<Vec<u8>> {
+            if self.use_mmap {
+                if let Some(data) = self.mmap(file_id)?.slice(offset, size) {
+                    return Ok(data.to_vec());
+                }
+            }
+            // TODO: cache page file reader for speed up.
+            let reader = self.open_page_reader(file_id, block_size).await?;
+            reader.read_to_vec(size, offset).await
+        }
+
+        /// Returns the memory mapping of the page file.
+        ///
+        /// The file is mapped on the first read, and the mapping is kept until
+        /// the file is removed. Page files are not changed after they are
+        /// written, except for punched holes, which the mapping observes.
+        fn mmap(&self, file_id: u32) -> Result<Arc<Mmap>> {
+            let mut mmaps = lock(&self.mmaps);
+            if let Some(mmap) = mmaps.get(&file_id) {
+                return Ok(mmap.clone());
+            }
+            // TODO: switch to env in suitable time.
+            let file = std::fs::File::open(self.file_path(file_id))?;
+            let mmap = Arc::new(Mmap::map(&file)?);
+            mmaps.insert(file_id, mmap.clone());
+            Ok(mmap)
         }
 
         // Create info_builder to help recovery & mantains version's file_info.
@@ -113,6 +225,53 @@ pub(crate) mod facade {
             MetaReader::open(page_file_reader, raw_metadata.len() as u32, file_id).await
         }
 
+        /// Reads the whole page file and verifies its checksums.
+        ///
+        /// The holes punched for dead pages are skipped without reading them.
+        ///
+        /// Returns the number of bytes read.
+        pub(crate) async fn scrub_file(&self, file_id: u32) -> Result<usize> {
+            let meta_reader = self.open_meta_reader(file_id).await?;
+            meta_reader.read_page_table().await?;
+            meta_reader.read_delete_pages().await?;
+            let file_meta = meta_reader.file_metadata();
+            let (data_len, _) = file_meta.get_page_table_meta_page()?;
+            let mut bytes = file_meta.file_size() - data_len as usize;
+            let data_ranges = self.data_ranges(file_id).await?;
+            let page_reader = self
+                .open_page_reader(file_id, file_meta.block_size())
+                .await?;
+            for (page_addr, (offset, size)) in file_meta.pages() {
+                let end = offset + size as u64;
+                let is_data = data_ranges
+                    .iter()
+                    .any(|range| range.start <= offset && end <= range.end);
+                if !is_data {
+                    continue;
+                }
+                let page = page_reader.read_to_vec(size, offset).await?;
+                if !file_meta.verify_page(page_addr, &page) {
+                    return Err(Error::Corrupted);
+                }
+                bytes += size;
+            }
+            Ok(bytes)
+        }
+
+        /// Returns the ranges of the page file that contain data, which
+        /// exclude the holes punched for dead pages.
+        pub(crate) async fn data_ranges(&self, file_id: u32) -> Result<Vec<Range<u64>>> {
+            let path = self.file_path(file_id);
+            // TODO: switch to env in suitable time.
+            let ranges = unblock(move || seek_data_ranges(&std::fs::File::open(path)?)).await?;
+            Ok(ranges)
+        }
+
+        /// Returns the path of the page file.
+        pub(crate) fn file_path(&self, file_id: u32) -> PathBuf {
+            self.base.join(format!("{}_{}", self.file_prefix, file_id))
+        }
+
         pub(crate) async fn remove_files(&self, files: Vec<u32>) -> Result<()> {
             for file_id in files {
                 // FIXME: handle error.
@@ -121,7 +280,67 @@ pub(crate) mod facade {
             Ok(())
         }
 
+        /// Removes the page files that are not live, and returns their paths.
+        ///
+        /// Files that do not look like page files are left untouched.
+        pub(crate) async fn remove_orphan_files<F>(&self, is_live: F) -> Result<Vec<PathBuf>>
+        where
+            F: Fn(u32) -> bool,
+        {
+            let prefix = format!("{}_", self.file_prefix);
+            let mut removed = Vec::new();
+            // TODO: switch to env in suitable time.
+            for entry in std::fs::read_dir(&self.base)? {
+                let path = entry?.path();
+                let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let file_id = name.strip_prefix(&prefix).map(|id| id.parse::<u32>());
+                let Some(Ok(file_id)) = file_id else {
+                    continue;
+                };
+                if !is_live(file_id) {
+                    self.remove_file(file_id).await?;
+                    removed.push(path);
+                }
+            }
+            Ok(removed)
+        }
+
+        /// Deallocates the space of the range in the page file by punching a
+        /// hole. Reads of the range will return zeros afterwards.
+        ///
+        /// The hole is synced before this returns if syncs are enabled.
+        pub(crate) async fn punch_hole(&self, file_id: u32, offset: u64, len: u64) -> Result<()> {
+            use std::os::unix::io::AsRawFd;
+
+            let path = self.file_path(file_id);
+            let use_sync = self.use_sync;
+            // TODO: switch to env in suitable time.
+            unblock(move || {
+                let file = std::fs::OpenOptions::new().write(true).open(path)?;
+                let ret = unsafe {
+                    libc::fallocate(
+                        file.as_raw_fd(),
+                        libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                        offset as libc::off_t,
+                        len as libc::off_t,
+                    )
+                };
+                if ret != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if use_sync {
+                    file.sync_data()?;
+                }
+                Ok(())
+            })
+            .await?;
+            Ok(())
+        }
+
         async fn remove_file(&self, file_id: u32) -> Result<()> {
+            lock(&self.mmaps).remove(&file_id);
             let path = self.base.join(format!("{}_{}", self.file_prefix, file_id));
             photonio::fs::remove_file(&path)
                 .await
@@ -135,6 +354,7 @@ pub(crate) mod facade {
         use std::collections::HashMap;
 
         use super::*;
+        use crate::page_store::PageAddr;
 
         #[photonio::test]
         fn test_file_builder() {
@@ -146,6 +366,80 @@ pub(crate) mod facade {
             builder.finish().await.unwrap();
         }
 
+        #[photonio::test]
+        fn test_remove_orphan_files() {
+            let base = std::env::temp_dir().join("test_orphan");
+            std::fs::create_dir_all(&base).unwrap();
+            let files = PageFiles::new(&base, "db");
+            for file_id in [1, 2] {
+                let mut builder = files.new_file_builder(file_id).await.unwrap();
+                builder
+                    .add_page(3, page_addr(file_id, 0), &[1; 16])
+                    .await
+                    .unwrap();
+                builder.finish().await.unwrap();
+            }
+            // A partially written file left by a crash.
+            std::fs::write(base.join("db_3"), [0; 16]).unwrap();
+            std::fs::write(base.join("CURRENT"), [0; 4]).unwrap();
+
+            let removed = files
+                .remove_orphan_files(|file_id| file_id == 1 || file_id == 2)
+                .await
+                .unwrap();
+            assert_eq!(removed, vec![base.join("db_3")]);
+            assert!(base.join("db_1").exists());
+            assert!(base.join("db_2").exists());
+            assert!(base.join("CURRENT").exists());
+            std::fs::remove_dir_all(&base).unwrap();
+        }
+
+        #[photonio::test]
+        fn test_mmap_reads() {
+            let base = std::env::temp_dir().join("test_mmap_reads");
+            let _ = std::fs::remove_dir_all(&base);
+            std::fs::create_dir_all(&base).unwrap();
+            let files = PageFiles::new(&base, "db").with_mmap_reads(true);
+            let pages = [(page_addr(1, 0), [7; 100]), (page_addr(1, 1), [8; 100])];
+            let info = {
+                let mut b = files.new_file_builder(1).await.unwrap();
+                for (i, (addr, page)) in pages.iter().enumerate() {
+                    b.add_page(i as u64, *addr, page).await.unwrap();
+                }
+                b.finish().await.unwrap()
+            };
+
+            for _ in 0..2 {
+                for (addr, page) in &pages {
+                    let hd = info.get_page_handle(*addr).unwrap();
+                    let buf = files
+                        .read_page(1, 4096, hd.offset as u64, hd.size as usize)
+                        .await
+                        .unwrap();
+                    assert_eq!(buf, page);
+                }
+            }
+            // The file is mapped once for all reads.
+            assert_eq!(lock(&files.mmaps).len(), 1);
+            let mmap = files.mmap(1).unwrap();
+            assert!(Arc::ptr_eq(&mmap, &files.mmap(1).unwrap()));
+            // Readers share the mapping too.
+            let reader = files.open_page_reader(1, 4096).await.unwrap();
+            let hd = info.get_page_handle(pages[1].0).unwrap();
+            let buf = reader
+                .read_to_vec(hd.size as usize, hd.offset as u64)
+                .await
+                .unwrap();
+            assert_eq!(buf, pages[1].1);
+            assert_eq!(Arc::strong_count(&mmap), 3);
+            drop(reader);
+
+            // The mapping is dropped with the file.
+            files.remove_files(vec![1]).await.unwrap();
+            assert!(lock(&files.mmaps).is_empty());
+            std::fs::remove_dir_all(&base).unwrap();
+        }
+
         #[photonio::test]
         fn test_read_page() {
             let files = {
@@ -206,6 +500,61 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_read_to_vec() {
+            let files = {
+                let base = std::env::temp_dir();
+                PageFiles::new(&base, "test_read_to_vec")
+            };
+            let file_id = 3;
+            let info = {
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                b.add_page(1, page_addr(3, 0), &[7].repeat(8192 / 3))
+                    .await
+                    .unwrap();
+                b.finish().await.unwrap()
+            };
+            let page_reader = files
+                .open_page_reader(info.meta().get_file_id(), 4096)
+                .await
+                .unwrap();
+
+            let hd = info.get_page_handle(page_addr(3, 0)).unwrap();
+            let buf = page_reader
+                .read_to_vec(hd.size as usize, hd.offset as u64)
+                .await
+                .unwrap();
+            assert_eq!(buf, [7].repeat(8192 / 3));
+
+            // A short read must fail instead of returning uninitialized bytes.
+            let file_size = info.meta().file_size() as u64;
+            assert!(page_reader.read_to_vec(4096, file_size - 16).await.is_err());
+        }
+
+        #[photonio::test]
+        fn test_read_errors() {
+            let files = {
+                let base = std::env::temp_dir();
+                PageFiles::new(&base, "test_read_errors")
+            };
+            let file_id = 5;
+            let info = {
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                b.add_page(1, page_addr(5, 0), &[7; 64]).await.unwrap();
+                b.finish().await.unwrap()
+            };
+            let page_reader = files.open_page_reader(file_id, 4096).await.unwrap();
+
+            // Failed reads are returned as errors instead of panicking.
+            let file_size = info.meta().file_size() as u64;
+            let mut buf = vec![0u8; 4096];
+            assert!(page_reader
+                .read_exact_at(&mut buf, file_size + 4096)
+                .await
+                .is_err());
+            assert!(files.open_page_reader(file_id + 1, 4096).await.is_err());
+        }
+
         #[photonio::test]
         fn test_test_simple_write_reader() {
             let files = {
@@ -265,6 +614,54 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_read_meta_in_chunks() {
+            let files = {
+                let base = std::env::temp_dir();
+                PageFiles::new(&base, "test_meta_chunks")
+            };
+            let file_id = 4;
+            let delete_pages = (0..500).map(|i| page_addr(1, i)).collect::<Vec<_>>();
+            {
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                b.add_delete_pages(&delete_pages);
+                for i in 1..=1000 {
+                    b.add_page(i as u64, page_addr(file_id, i), &[i as u8; 8])
+                        .await
+                        .unwrap();
+                }
+                b.finish().await.unwrap();
+            }
+
+            let meta_reader = files.open_meta_reader(file_id).await.unwrap();
+            let page_table = meta_reader.read_page_table().await.unwrap();
+            let dels = meta_reader.read_delete_pages().await.unwrap();
+            assert_eq!(page_table.len(), 1000);
+            assert_eq!(dels, delete_pages);
+
+            // Reads with a small buffer yield the same result.
+            let chunk_size = 100;
+            let meta_reader = meta_reader.with_chunk_size(chunk_size);
+            assert_eq!(meta_reader.read_page_table().await.unwrap(), page_table);
+            assert_eq!(meta_reader.read_delete_pages().await.unwrap(), dels);
+
+            let (offset, len) = meta_reader
+                .file_metadata()
+                .get_page_table_meta_page()
+                .unwrap();
+            let mut total = 0;
+            meta_reader
+                .read_chunks(offset, len, 16, |chunk| {
+                    assert!(chunk.len() <= chunk_size);
+                    assert_eq!(chunk.len() % 16, 0);
+                    total += chunk.len();
+                    Ok(())
+                })
+                .await
+                .unwrap();
+            assert_eq!(total, len);
+        }
+
         #[photonio::test]
         fn test_file_info_recovery_and_add_new_file() {
             let files = {
@@ -346,8 +743,223 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_file_info_recovery_applies_deletes_in_order() {
+            let files = {
+                let base = std::env::temp_dir();
+                PageFiles::new(&base, "test_recovery_deletes")
+            };
+            // Each file deletes one more page of every older file.
+            for file_id in 1..=4 {
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                for index in 0..3 {
+                    let page_id = (file_id * 3 + index) as u64;
+                    b.add_page(page_id, page_addr(file_id, index), &[1].repeat(10))
+                        .await
+                        .unwrap();
+                }
+                let delete_pages = (1..file_id)
+                    .map(|older| page_addr(older, file_id - older - 1))
+                    .collect::<Vec<_>>();
+                b.add_delete_pages(&delete_pages);
+                b.finish().await.unwrap();
+            }
+
+            let known_files = (1..=4).map(Into::into).collect::<Vec<_>>();
+            let infos = files
+                .new_info_builder()
+                .recovery_base_file_infos(&known_files)
+                .await
+                .unwrap();
+            for file_id in 1..=4 {
+                let info = infos.get(&file_id).unwrap();
+                let deleted = 4 - file_id as usize;
+                assert_eq!(info.effective_size(), (3 - deleted) * 10);
+                for index in 0..3 {
+                    let active = index as usize >= deleted;
+                    let handle = info.get_page_handle(page_addr(file_id, index));
+                    assert_eq!(handle.is_some(), active);
+                }
+            }
+            // The files are updated in order, so the last two updates are kept.
+            let file1 = infos.get(&1).unwrap();
+            assert_eq!((file1.up1(), file1.up2()), (4, 3));
+        }
+
+        #[photonio::test]
+        fn test_punch_dead_pages() {
+            use std::os::unix::fs::MetadataExt;
+
+            let files = {
+                let base = std::env::temp_dir();
+                PageFiles::new(&base, "test_punch_hole")
+            };
+            let file_id = 3;
+            let page_size = 64 << 10;
+            let mut info = {
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                for i in 0..3 {
+                    b.add_page(i + 1, page_addr(file_id, i as u32), &[7].repeat(page_size))
+                        .await
+                        .unwrap();
+                }
+                b.finish().await.unwrap()
+            };
+            let path = std::env::temp_dir().join(format!("test_punch_hole_{file_id}"));
+            let blocks = std::fs::metadata(&path).unwrap().blocks();
+
+            info.deactivate_page(file_id + 1, page_addr(file_id, 1));
+            let dead_pages = info.dead_pages();
+            assert_eq!(dead_pages.len(), 1);
+            for (addr, handle) in dead_pages {
+                files
+                    .punch_hole(file_id, handle.offset as u64, handle.size as u64)
+                    .await
+                    .unwrap();
+                info.mark_hole(addr);
+            }
+            assert!(info.dead_pages().is_empty());
+            assert!(std::fs::metadata(&path).unwrap().blocks() < blocks);
+
+            // The active pages are still readable.
+            let page_reader = files.open_page_reader(file_id, 4096).await.unwrap();
+            let hd = info.get_page_handle(page_addr(file_id, 2)).unwrap();
+            let mut buf = vec![0u8; hd.size as usize];
+            page_reader
+                .read_exact_at(&mut buf, hd.offset as u64)
+                .await
+                .unwrap();
+            assert_eq!(buf, [7].repeat(page_size));
+        }
+
+        #[photonio::test]
+        fn test_checksum_types() {
+            let base = std::env::temp_dir().join("test_checksum_types");
+            let _ = std::fs::remove_dir_all(&base);
+            std::fs::create_dir_all(&base).unwrap();
+            let types = [
+                ChecksumType::None,
+                ChecksumType::Crc32c,
+                ChecksumType::XxHash64,
+            ];
+            for (i, checksum_type) in types.into_iter().enumerate() {
+                let file_id = 10 + i as u32;
+                let files = PageFiles::new(&base, "db").with_checksum_type(checksum_type);
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                b.add_delete_pages(&[page_addr(1, 0)]);
+                b.add_page(1, page_addr(file_id, 0), &[1].repeat(100))
+                    .await
+                    .unwrap();
+                b.finish().await.unwrap();
+
+                // Read with the same and a different configured algorithm, both use the
+                // recorded one.
+                for other in types {
+                    let files = PageFiles::new(&base, "db").with_checksum_type(other);
+                    let meta_reader = files.open_meta_reader(file_id).await.unwrap();
+                    assert_eq!(meta_reader.read_page_table().await.unwrap().len(), 1);
+                    let meta = meta_reader.file_metadata();
+                    assert_eq!(meta.checksum_type(), checksum_type);
+                    assert!(meta.verify_page(page_addr(file_id, 0), &[1].repeat(100)));
+                }
+            }
+            std::fs::remove_dir_all(&base).unwrap();
+        }
+
+        #[photonio::test]
+        fn test_page_checksums() {
+            use std::os::unix::fs::FileExt;
+
+            let base = std::env::temp_dir().join("test_page_checksums");
+            let _ = std::fs::remove_dir_all(&base);
+            std::fs::create_dir_all(&base).unwrap();
+            let files = PageFiles::new(&base, "db");
+            let file_id = 1;
+            let info = {
+                let mut b = files.new_file_builder(file_id).await.unwrap();
+                for i in 0..2 {
+                    b.add_page(i as u64 + 1, page_addr(file_id, i), &[7].repeat(100))
+                        .await
+                        .unwrap();
+                }
+                b.finish().await.unwrap()
+            };
+            let meta = files
+                .open_meta_reader(file_id)
+                .await
+                .unwrap()
+                .file_metadata();
+            for i in 0..2 {
+                let addr = page_addr(file_id, i);
+                assert!(info.meta().verify_page(addr, &[7].repeat(100)));
+                assert!(meta.verify_page(addr, &[7].repeat(100)));
+                assert!(!meta.verify_page(addr, &[8].repeat(100)));
+            }
+            assert!(files.scrub_file(file_id).await.is_ok());
+
+            // Damage the second page, which only fails its own checksum.
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(files.file_path(file_id))
+                .unwrap();
+            file.write_all_at(&[8], 150).unwrap();
+            let page_reader = files.open_page_reader(file_id, 4096).await.unwrap();
+            for (i, valid) in [(0, true), (1, false)] {
+                let addr = page_addr(file_id, i);
+                let (offset, size) = meta.get_page_handle(addr).unwrap();
+                let page = page_reader.read_to_vec(size, offset).await.unwrap();
+                assert_eq!(meta.verify_page(addr, &page), valid);
+            }
+            assert!(files.scrub_file(file_id).await.is_err());
+            std::fs::remove_dir_all(&base).unwrap();
+        }
+
+        #[photonio::test]
+        fn test_read_legacy_footer() {
+            let base = std::env::temp_dir().join("test_read_legacy_footer");
+            let _ = std::fs::remove_dir_all(&base);
+            std::fs::create_dir_all(&base).unwrap();
+            let files = PageFiles::new(&base, "db");
+            let file_id = 1;
+            let addr = page_addr(file_id, 0);
+            // A page, the page table, an empty delete pages block, the index
+            // blocks and the legacy footer without checksums.
+            let mut bytes = [5].repeat(100);
+            for value in [1, addr, addr, 0, 100, 116, 142857, 116, 16, 132, 16] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            std::fs::write(files.file_path(file_id), &bytes).unwrap();
+
+            let meta_reader = files.open_meta_reader(file_id).await.unwrap();
+            let page_table = meta_reader.read_page_table().await.unwrap();
+            assert_eq!(page_table.into_iter().collect::<Vec<_>>(), [(1, addr)]);
+            assert!(meta_reader.read_delete_pages().await.unwrap().is_empty());
+            let meta = meta_reader.file_metadata();
+            assert_eq!(meta.checksum_type(), ChecksumType::None);
+            assert_eq!(meta.format_version(), 0);
+            assert_eq!(meta.get_page_handle(addr), Some((0, 100)));
+            assert!(meta.verify_page(addr, &[6].repeat(100)));
+
+            // A legacy file alongside a file in the current format.
+            let info = {
+                let mut b = files.new_file_builder(file_id + 1).await.unwrap();
+                b.add_page(1, page_addr(file_id + 1, 0), &[7].repeat(100))
+                    .await
+                    .unwrap();
+                b.finish().await.unwrap()
+            };
+            assert_eq!(info.meta().format_version(), 1);
+            let format = FormatInfo::from_files([meta, info.meta()]);
+            assert_eq!(format.format_versions, vec![0, 1]);
+            assert_eq!(
+                format.checksum_types,
+                vec![ChecksumType::None, info.meta().checksum_type()]
+            );
+            std::fs::remove_dir_all(&base).unwrap();
+        }
+
         fn page_addr(file_id: u32, index: u32) -> u64 {
-            ((file_id as u64) << 32) | (index as u64)
+            PageAddr::new(file_id, index).into()
         }
     }
 }
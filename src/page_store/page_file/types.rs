@@ -1,6 +1,57 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    page_store::{Error, PageAddr, Result},
+    util::sync::lock,
+    ChecksumType,
+};
+
+/// The format of the persisted files of a table.
+#[derive(Clone, Debug)]
+pub struct FormatInfo {
+    /// The name of the order of keys, keys are compared bytewise.
+    pub comparator_name: &'static str,
+    /// The checksum algorithms used by the page files, in ascending order of
+    /// file ids and without duplicates.
+    ///
+    /// Files written before the table was reopened with a different
+    /// [`ChecksumType`] keep their original algorithm.
+    pub checksum_types: Vec<ChecksumType>,
+    /// The format versions of the page files, in ascending order of file ids
+    /// and without duplicates.
+    ///
+    /// Files written before the format was versioned have version 0, and are
+    /// still read as they are.
+    pub format_versions: Vec<u64>,
+}
 
-use crate::page_store::{Error, Result};
+impl FormatInfo {
+    /// Collects the format of the page files, given in ascending order of
+    /// file ids.
+    pub(crate) fn from_files(files: impl IntoIterator<Item = impl Borrow<FileMeta>>) -> Self {
+        let mut checksum_types = Vec::new();
+        let mut format_versions = Vec::new();
+        for file in files {
+            let file = file.borrow();
+            if !checksum_types.contains(&file.checksum_type()) {
+                checksum_types.push(file.checksum_type());
+            }
+            if !format_versions.contains(&file.format_version()) {
+                format_versions.push(file.format_version());
+            }
+        }
+        FormatInfo {
+            comparator_name: "bytewise",
+            checksum_types,
+            format_versions,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub(crate) struct PageHandle {
@@ -48,11 +99,13 @@ impl FileInfo {
     }
 
     pub(crate) fn deactivate_page(&mut self, now: u32, page_addr: u64) {
-        let (_, index) = split_page_addr(page_addr);
+        let index = PageAddr::from(page_addr).index();
         if self.active_pages.remove(index) {
             if let Some((_, page_size)) = self.meta.get_page_handle(page_addr) {
-                self.active_size -= page_size;
+                debug_assert!(self.active_size >= page_size);
+                self.active_size = self.active_size.saturating_sub(page_size);
             }
+            debug_assert_eq!(self.active_size, self.recompute_active_size());
         }
         if self.up1 <= now {
             self.up2 = self.up1;
@@ -104,10 +157,78 @@ impl FileInfo {
         self.active_size as usize
     }
 
+    /// Computes the size of active pages from scratch.
+    fn recompute_active_size(&self) -> usize {
+        let file_id = self.get_file_id();
+        self.active_pages
+            .iter()
+            .filter_map(|index| {
+                self.meta
+                    .get_page_handle(PageAddr::new(file_id, index).into())
+            })
+            .map(|(_, size)| size)
+            .sum()
+    }
+
     #[inline]
     pub(crate) fn iter(&self) -> FileInfoIterator {
         FileInfoIterator::new(self)
     }
+
+    /// Returns the addrs and handles of the dead pages which have not been
+    /// punched yet.
+    pub(crate) fn dead_pages(&self) -> Vec<(u64, PageHandle)> {
+        let holes = lock(&self.meta.holes);
+        self.meta
+            .data_offsets
+            .keys()
+            .filter(|addr| {
+                let index = PageAddr::from(**addr).index();
+                !self.active_pages.contains(index) && !holes.contains(index)
+            })
+            .filter_map(|addr| {
+                let (offset, size) = self.meta.get_page_handle(*addr)?;
+                let handle = PageHandle {
+                    offset: offset as u32,
+                    size: size as u32,
+                };
+                Some((*addr, handle))
+            })
+            .collect()
+    }
+
+    /// Returns true if the space of the page has been reclaimed.
+    pub(crate) fn is_hole(&self, page_addr: u64) -> bool {
+        let index = PageAddr::from(page_addr).index();
+        lock(&self.meta.holes).contains(index)
+    }
+
+    /// Marks the page as a hole, the space of it has been reclaimed.
+    pub(crate) fn mark_hole(&self, page_addr: u64) {
+        debug_assert!(!self.is_page_active(page_addr));
+        let index = PageAddr::from(page_addr).index();
+        lock(&self.meta.holes).insert(index);
+    }
+
+    /// Marks the dead pages in the holes of the file as holes, given the
+    /// ranges of the file that contain data.
+    ///
+    /// Holes are only kept in memory, so they are recovered from the file
+    /// itself on open. A punched page may keep some data at its ends if it is
+    /// not aligned to blocks, so any dead page that is not entirely data is
+    /// taken as punched.
+    pub(crate) fn recover_holes(&self, data_ranges: &[Range<u64>]) {
+        for (page_addr, handle) in self.dead_pages() {
+            let start = handle.offset as u64;
+            let end = start + handle.size as u64;
+            let is_data = data_ranges
+                .iter()
+                .any(|range| range.start <= start && end <= range.end);
+            if !is_data {
+                self.mark_hole(page_addr);
+            }
+        }
+    }
 }
 
 pub(crate) struct FileMeta {
@@ -118,6 +239,16 @@ pub(crate) struct FileMeta {
     meta_indexes: Vec<u64>,           // [0] -> page_table, [1] ->  delete page, [2], meta_bloc_end
 
     block_size: usize,
+
+    /// The version of the file format, 0 for files with a legacy footer.
+    format_version: u64,
+    /// The checksum algorithm recorded in the file footer.
+    checksum_type: ChecksumType,
+    /// The checksums of the pages, empty if the file has no checksums.
+    page_checksums: BTreeMap<u64, u64>,
+
+    /// The pages whose space has been reclaimed by punching holes.
+    holes: Mutex<roaring::RoaringBitmap>,
 }
 
 impl FileMeta {
@@ -127,6 +258,7 @@ impl FileMeta {
         indexes: Vec<u64>,
         offsets: BTreeMap<u64, u64>,
         block_size: usize,
+        checksum_type: ChecksumType,
     ) -> Self {
         Self {
             file_id,
@@ -134,9 +266,44 @@ impl FileMeta {
             meta_indexes: indexes,
             data_offsets: offsets,
             block_size,
+            format_version: 0,
+            checksum_type,
+            page_checksums: BTreeMap::default(),
+            holes: Mutex::default(),
         }
     }
 
+    /// Sets the version of the file format.
+    pub(crate) fn with_format_version(mut self, format_version: u64) -> Self {
+        self.format_version = format_version;
+        self
+    }
+
+    /// Sets the checksums of the pages in the file.
+    pub(crate) fn with_page_checksums(mut self, page_checksums: BTreeMap<u64, u64>) -> Self {
+        self.page_checksums = page_checksums;
+        self
+    }
+
+    /// Returns false if the data read from the page does not match the
+    /// checksum of the page.
+    ///
+    /// Pages without a checksum, such as those in files with a legacy footer,
+    /// always pass.
+    pub(crate) fn verify_page(&self, page_addr: u64, data: &[u8]) -> bool {
+        match self.page_checksums.get(&page_addr) {
+            Some(&checksum) => self.checksum_type.checksum(data) == checksum,
+            None => true,
+        }
+    }
+
+    /// Returns the addresses and handles of all pages in the file.
+    pub(crate) fn pages(&self) -> impl Iterator<Item = (u64, (u64, usize))> + '_ {
+        self.data_offsets
+            .keys()
+            .filter_map(|&addr| Some((addr, self.get_page_handle(addr)?)))
+    }
+
     #[inline]
     pub(crate) fn get_file_id(&self) -> u32 {
         self.file_id
@@ -171,6 +338,18 @@ impl FileMeta {
         (**self.meta_indexes.first().as_ref().unwrap()) as usize
     }
 
+    /// Returns the version of the file format.
+    #[inline]
+    pub(crate) fn format_version(&self) -> u64 {
+        self.format_version
+    }
+
+    /// Returns the checksum algorithm of the file.
+    #[inline]
+    pub(crate) fn checksum_type(&self) -> ChecksumType {
+        self.checksum_type
+    }
+
     // Return the block_size for the file's device.
     #[inline]
     pub(crate) fn block_size(&self) -> usize {
@@ -219,12 +398,70 @@ impl<'a> Iterator for FileInfoIterator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let file_id = self.info.get_file_id();
-        self.iter.next().map(|v| (file_id as u64) | (v as u64))
+        self.iter.next().map(|v| PageAddr::new(file_id, v).into())
     }
 }
 
-// TODO: switch some common util method?
-#[inline]
-pub(crate) fn split_page_addr(page_addr: u64) -> (u32 /* file_id */, u32 /* index */) {
-    ((page_addr >> 32) as u32, page_addr as u32)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_size_accounting() {
+        let file_id = 3;
+        let addr = |index: u32| u64::from(PageAddr::new(file_id, index));
+        let sizes = [100, 200, 50, 300];
+        let mut offsets = BTreeMap::new();
+        let mut offset = 0;
+        for (index, size) in sizes.iter().enumerate() {
+            offsets.insert(addr(index as u64), offset);
+            offset += size;
+        }
+        let meta = FileMeta::new(
+            file_id as u32,
+            4096,
+            vec![offset, offset, offset],
+            offsets,
+            4096,
+            ChecksumType::Crc32c,
+        );
+        let active_pages = (0..sizes.len() as u32).collect();
+        let total = sizes.iter().sum::<u64>() as usize;
+        let mut info = FileInfo::new(active_pages, total, 1, 1, Arc::new(meta));
+        assert_eq!(info.effective_size(), info.recompute_active_size());
+
+        // Deactivate pages repeatedly and out of order, including unknown pages.
+        for (now, index) in [2, 0, 2, 7, 3, 0].into_iter().enumerate() {
+            info.deactivate_page(now as u32 + 2, addr(index));
+            assert_eq!(info.effective_size(), info.recompute_active_size());
+        }
+        assert_eq!(info.effective_size(), 200);
+        assert_eq!(info.num_active_pages(), 1);
+    }
+
+    #[test]
+    fn recover_holes_from_data_ranges() {
+        let file_id = 3;
+        let addr = |index: u32| u64::from(PageAddr::new(file_id, index));
+        let mut offsets = BTreeMap::new();
+        for index in 0..4 {
+            offsets.insert(addr(index), index as u64 * 4096);
+        }
+        let meta = FileMeta::new(
+            file_id,
+            4 * 4096 + 100,
+            vec![4 * 4096, 4 * 4096, 4 * 4096],
+            offsets,
+            4096,
+            ChecksumType::Crc32c,
+        );
+        let active_pages = [3].into_iter().collect();
+        let info = FileInfo::new(active_pages, 4096, 1, 1, Arc::new(meta));
+
+        // Page 1 is punched, and page 2 is dead but still has its data.
+        info.recover_holes(&[0..4096, 2 * 4096..4 * 4096 + 100]);
+        let dead_pages = info.dead_pages();
+        let dead_addrs = dead_pages.iter().map(|(addr, _)| *addr).collect::<Vec<_>>();
+        assert_eq!(dead_addrs, vec![addr(0), addr(2)]);
+    }
 }
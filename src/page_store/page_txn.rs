@@ -6,9 +6,12 @@ use std::{
 use super::{
     version::Version,
     write_buffer::{RecordHeader, ReleaseState},
-    Error, PageFiles, PageTable, Result, WriteBuffer, NAN_ID,
+    Error, PageAddr, PageFiles, PageTable, Result, WriteBuffer, NAN_ID,
+};
+use crate::{
+    page::{PageBuf, PageRef},
+    util::sync::lock,
 };
-use crate::page::{PageBuf, PageRef};
 
 pub(crate) struct Guard<'a>
 where
@@ -64,8 +67,26 @@ impl<'a> Guard<'a> {
         page_addr
     }
 
+    /// Returns the address of the page, or `None` if the id is not mapped.
+    pub(crate) fn try_page_addr(&self, id: u64) -> Option<u64> {
+        Some(self.page_table.get(id)).filter(|&addr| addr != 0)
+    }
+
+    /// Returns true if the page at the address is in a write buffer rather
+    /// than a page file.
+    pub(crate) fn is_in_memory(&self, addr: u64) -> bool {
+        let file_id = PageAddr::from(addr).file_id();
+        self.version.contains_write_buffer(file_id)
+    }
+
+    /// Returns the number of pages read from page files by this guard.
+    #[cfg(test)]
+    pub(crate) fn num_file_reads(&self) -> usize {
+        lock(&self.owned_pages).len()
+    }
+
     pub(crate) async fn read_page(&self, addr: u64) -> Result<PageRef> {
-        let file_id = (addr >> 32) as u32;
+        let file_id = PageAddr::from(addr).file_id();
         if self.version.contains_write_buffer(file_id) {
             let page_ref = self
                 .version
@@ -76,21 +97,31 @@ impl<'a> Guard<'a> {
             Ok(page_ref)
         } else {
             let Some(file_info) = self.version.files().get(&file_id) else {
-                panic!("File {file_id} is not exists");
+                return Err(self.missing_file_error(file_id));
+            };
+            // The page file is immutable, so it holds every page written to it.
+            let Some(handle) = file_info.get_page_handle(addr) else {
+                return Err(Error::Corrupted);
             };
-            let handle = file_info
-                .get_page_handle(addr)
-                .expect("The addr is not belongs to the target page file");
+            if file_info.is_hole(addr) {
+                return Err(self.punched_page_error(file_id, addr));
+            }
 
-            // TODO: cache page file reader for speed up.
-            let reader = self
+            let block_size = file_info.meta().block_size();
+            let (offset, size) = (handle.offset as u64, handle.size as usize);
+            let buf = self
                 .page_files
-                .open_page_reader(file_id, file_info.meta().block_size())
+                .read_page(file_id, block_size, offset, size)
                 .await?;
-            let mut buf = vec![0u8; handle.size as usize];
-            reader.read_exact_at(&mut buf, handle.offset as u64).await?;
+            // The page may have been punched during the read.
+            if file_info.is_hole(addr) {
+                return Err(self.punched_page_error(file_id, addr));
+            }
+            if !file_info.meta().verify_page(addr, &buf) {
+                return Err(Error::Corrupted);
+            }
 
-            let mut owned_pages = self.owned_pages.lock().expect("Poisoned");
+            let mut owned_pages = lock(&self.owned_pages);
             owned_pages.push(buf);
             let page = owned_pages.last().expect("Verified");
             let page = page.as_slice();
@@ -101,6 +132,34 @@ impl<'a> Guard<'a> {
             }))
         }
     }
+
+    /// Returns the error of reading a page from a file that is not in the
+    /// version of the guard.
+    ///
+    /// The page table may point to a file installed after the version, which
+    /// the reader can see after a restart. Files are only removed after all
+    /// versions holding them are released, so a file that is not in the
+    /// latest version either is lost.
+    fn missing_file_error(&self, file_id: u32) -> Error {
+        match self.version.refresh() {
+            Some(latest) if latest.files().contains_key(&file_id) => Error::Again,
+            _ => Error::Corrupted,
+        }
+    }
+
+    /// Returns the error of reading a page that has been punched.
+    ///
+    /// Pages are only punched after they are dead, so the reader loaded the
+    /// address before the page was replaced, and can retry with the latest
+    /// page table. A punched page that is still active is corrupted.
+    fn punched_page_error(&self, file_id: u32, addr: u64) -> Error {
+        let latest = self.version.refresh();
+        let latest = latest.as_ref().unwrap_or(&*self.version);
+        match latest.files().get(&file_id) {
+            Some(file_info) if file_info.get_page_handle(addr).is_some() => Error::Corrupted,
+            _ => Error::Again,
+        }
+    }
 }
 
 /// A transaction to manipulate pages in a page store.
@@ -125,8 +184,11 @@ impl<'a> PageTxn<'a> {
     ///
     /// If the transaction aborts, all pages allocated by this transaction will
     /// be deallocated.
+    ///
+    /// Pages can be larger than the configured page size, but not larger than
+    /// the write buffer. Returns [`Error::InvalidArgument`] for such pages.
     pub(crate) fn alloc_page(&mut self, size: usize) -> Result<(u64, PageBuf<'a>)> {
-        let page_size = size as u32;
+        let page_size = u32::try_from(size).map_err(|_| Error::InvalidArgument)?;
         let (addr, header, buf) = self.alloc_page_impl(page_size)?;
         self.records.insert(addr, header);
         Ok((addr, buf))
@@ -228,8 +290,8 @@ impl<'a> PageTxn<'a> {
             }
         };
 
-        let capacity = self.guard.version.buffer_set.write_buffer_capacity();
-        let write_buffer = Arc::new(WriteBuffer::with_capacity(self.file_id + 1, capacity));
+        let buffer_set = &self.guard.version.buffer_set;
+        let write_buffer = Arc::new(buffer_set.new_write_buffer(self.file_id + 1));
         self.guard.version.buffer_set.install(write_buffer);
         if matches!(release_state, ReleaseState::Flush) {
             self.guard.version.buffer_set.notify_flush_job();
@@ -271,8 +333,11 @@ impl<'a> PageTxn<'a> {
             .version
             .with_write_buffer(self.file_id, f)
             .map_err(|err| {
-                assert!(matches!(err, Error::Again));
-                self.seal_write_buffer();
+                // Only seal the buffer if it is full, a page that never fits is
+                // rejected without affecting other writes.
+                if matches!(err, Error::Again) {
+                    self.seal_write_buffer();
+                }
                 err
             })
     }
@@ -291,7 +356,8 @@ impl<'a> PageTxn<'a> {
         }
     }
 
-    fn commit(mut self) {
+    /// Commits all operations in the transaction.
+    pub(crate) fn commit(mut self) {
         if !self.records.is_empty() {
             self.drop_writer_guard();
             self.records.clear();
@@ -328,10 +394,19 @@ mod tests {
     use std::collections::HashSet;
 
     use super::*;
-    use crate::page_store::{page_table::PageTable, version::Version};
+    use crate::{
+        page_store::{page_table::PageTable, version::Version},
+        PageAllocHint,
+    };
 
     fn new_version(size: u32) -> Arc<Version> {
-        Arc::new(Version::new(size, 1, HashMap::default(), HashSet::new()))
+        Arc::new(Version::new(
+            size,
+            PageAllocHint::Default,
+            1,
+            HashMap::default(),
+            HashSet::new(),
+        ))
     }
 
     #[test]
@@ -423,6 +498,20 @@ mod tests {
         page_txn.seal_write_buffer();
     }
 
+    #[photonio::test]
+    fn read_page_from_missing_file() {
+        let files = {
+            let base = std::env::temp_dir();
+            Arc::new(PageFiles::new(&base, "test_read_page_from_missing_file"))
+        };
+
+        let version = new_version(512);
+        let page_table = PageTable::default();
+        let guard = Guard::new(version, &page_table, &files);
+        let addr = PageAddr::new(7, 0).into();
+        assert!(matches!(guard.read_page(addr).await, Err(Error::Corrupted)));
+    }
+
     #[test]
     fn page_txn_insert_page() {
         let files = {
@@ -3,8 +3,12 @@ use std::{
     path::Path,
 };
 
-use super::{page_table::PageTable, FileInfo, NewFile, PageFiles, PageStore, Result, VersionEdit};
-use crate::{env::Env, page_store::Manifest};
+use futures::future::try_join_all;
+
+use super::{
+    page_table::PageTable, Error, FileInfo, NewFile, PageFiles, PageStore, Result, VersionEdit,
+};
+use crate::{env::Env, page_store::Manifest, Durability, Options};
 
 struct FilesSummary {
     active_files: HashMap<u32, NewFile>,
@@ -15,6 +19,7 @@ impl<E: Env> PageStore<E> {
     pub(super) async fn recover<P: AsRef<Path>>(
         env: E,
         path: P,
+        options: &Options,
     ) -> Result<(
         u32, /* next file id */
         Manifest<E>,
@@ -22,16 +27,31 @@ impl<E: Env> PageStore<E> {
         PageFiles,
         HashMap<u32, FileInfo>,
     )> {
-        let manifest = Manifest::open(env, path.as_ref()).await?;
+        let sync = options.durability != Durability::None;
+        let prefix = file_prefix(options)?;
+        let mut manifest = Manifest::open(env.clone(), path.as_ref(), &prefix)
+            .await?
+            .with_sync(sync);
         let versions = manifest.list_versions().await?;
         let summary = Self::apply_version_edits(versions);
 
-        let page_files = PageFiles::new(path.as_ref(), "db");
+        let page_files = PageFiles::new(path.as_ref(), &format!("{prefix}db"))
+            .with_checksum_type(options.checksum_type)
+            .with_mmap_reads(options.use_mmap_reads)
+            .with_read_retry(options.read_retry)
+            .with_io_size(options.write_io_size)
+            .with_sync(sync);
         let file_infos = Self::recover_file_infos(&page_files, &summary.active_files).await?;
-        let page_table = Self::recover_page_table(&page_files, &summary.active_files).await?;
+        Self::recover_holes(&env, &page_files, &file_infos).await?;
+        let page_table =
+            Self::recover_page_table(&page_files, &summary.active_files, options).await?;
 
         let deleted_files = summary.obsolated_files.into_iter().collect::<Vec<_>>();
         page_files.remove_files(deleted_files).await?;
+        // Remove files left by crashes, such as partially written page files.
+        page_files
+            .remove_orphan_files(|file_id| summary.active_files.contains_key(&file_id))
+            .await?;
 
         let next_file_id = summary.active_files.keys().cloned().max().unwrap_or(0) + 1;
         Ok((next_file_id, manifest, page_table, page_files, file_infos))
@@ -67,21 +87,278 @@ impl<E: Env> PageStore<E> {
         builder.recovery_base_file_infos(&files).await
     }
 
+    /// Recovers the holes punched for dead pages from the data ranges of the
+    /// page files, so that they are not read or punched again.
+    async fn recover_holes(
+        env: &E,
+        page_files: &PageFiles,
+        file_infos: &HashMap<u32, FileInfo>,
+    ) -> Result<()> {
+        for (&file_id, info) in file_infos {
+            let data_ranges = env.data_ranges(page_files.file_path(file_id)).await?;
+            info.recover_holes(&data_ranges);
+        }
+        Ok(())
+    }
+
+    /// Recovers the page table from the page files.
+    ///
+    /// Newer files override the page addresses of older files. If two files
+    /// claim the same page address for different pages, the claim of the newer
+    /// file wins, or [`Error::Corrupted`] is returned if `paranoid_checks` is
+    /// enabled.
+    ///
+    /// Up to `recovery_parallelism` files are read at once, but they are
+    /// applied in order, and the progress is reported after each of them.
     async fn recover_page_table(
         page_files: &PageFiles,
         active_files: &HashMap<u32, NewFile>,
+        options: &Options,
     ) -> Result<PageTable> {
         // ensure recover files in order.
         let mut files = active_files.keys().cloned().collect::<Vec<_>>();
         files.sort_unstable();
 
-        let table = PageTable::default();
-        for file_id in files {
-            let meta_reader = page_files.open_meta_reader(file_id).await?;
-            for (page_id, page_addr) in meta_reader.read_page_table().await? {
-                table.set(page_id, page_addr);
+        let mut page_addrs = HashMap::new();
+        let mut page_owners = HashMap::new();
+        let mut files_done = 0;
+        for batch in files.chunks(options.recovery_parallelism.max(1)) {
+            let tables = try_join_all(batch.iter().map(|&file_id| async move {
+                let meta_reader = page_files.open_meta_reader(file_id).await?;
+                meta_reader.read_page_table().await
+            }))
+            .await?;
+            for table in tables {
+                for (page_id, page_addr) in table {
+                    if let Some(owner) = page_owners.insert(page_addr, page_id) {
+                        if owner != page_id {
+                            if options.paranoid_checks {
+                                return Err(Error::Corrupted);
+                            }
+                            // Drop the stale claim unless the page has moved since.
+                            if page_addrs.get(&owner) == Some(&page_addr) {
+                                page_addrs.remove(&owner);
+                            }
+                        }
+                    }
+                    page_addrs.insert(page_id, page_addr);
+                }
+                files_done += 1;
+                if let Some(progress) = &options.on_recovery_progress {
+                    progress(files_done, files.len());
+                }
             }
         }
+
+        let table = PageTable::default();
+        for (page_id, page_addr) in page_addrs {
+            table.set(page_id, page_addr);
+        }
         Ok(table)
     }
 }
+
+/// Returns the prefix of the names of the files of the table, which is empty
+/// or ends with `_`.
+fn file_prefix(options: &Options) -> Result<String> {
+    match options.file_prefix.as_deref() {
+        None => Ok(String::new()),
+        Some(prefix) if prefix.is_empty() || prefix.contains(std::path::is_separator) => {
+            Err(Error::InvalidArgument)
+        }
+        Some(prefix) => Ok(format!("{prefix}_")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{env::Photon, page_store::PageAddr};
+
+    fn page_addr(file_id: u32, index: u32) -> u64 {
+        PageAddr::new(file_id, index).into()
+    }
+
+    #[photonio::test]
+    fn duplicate_page_addrs() {
+        let base = std::env::temp_dir().join("test_duplicate_page_addrs");
+        std::fs::create_dir_all(&base).unwrap();
+        let files = PageFiles::new(&base, "db");
+        {
+            let mut b = files.new_file_builder(1).await.unwrap();
+            b.add_page(1, page_addr(1, 0), &[1; 16]).await.unwrap();
+            b.add_page(2, page_addr(1, 1), &[2; 16]).await.unwrap();
+            b.finish().await.unwrap();
+        }
+        {
+            // A crafted file that claims an address of page 1 for page 3.
+            let mut b = files.new_file_builder(2).await.unwrap();
+            b.add_page(3, page_addr(1, 0), &[3; 16]).await.unwrap();
+            b.finish().await.unwrap();
+        }
+        let active_files = [1, 2]
+            .into_iter()
+            .map(|id| (id, NewFile::from(id)))
+            .collect::<HashMap<_, _>>();
+
+        // The newer file wins.
+        let options = Options::default();
+        let table = PageStore::<Photon>::recover_page_table(&files, &active_files, &options)
+            .await
+            .unwrap();
+        assert_eq!(table.get(1), 0);
+        assert_eq!(table.get(2), page_addr(1, 1));
+        assert_eq!(table.get(3), page_addr(1, 0));
+
+        let options = Options {
+            paranoid_checks: true,
+            ..Default::default()
+        };
+        let result = PageStore::<Photon>::recover_page_table(&files, &active_files, &options).await;
+        assert!(matches!(result, Err(Error::Corrupted)));
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn prefixed_tables_share_directory() {
+        let base = std::env::temp_dir().join("test_prefixed_tables_share_directory");
+        let _ = std::fs::remove_dir_all(&base);
+        let options = |prefix: &str| Options {
+            file_prefix: Some(prefix.to_owned()),
+            ..Default::default()
+        };
+        let tables = [("a", 1), ("b", 2)];
+
+        // Both tables write a file with the same id.
+        for (prefix, page_id) in tables {
+            let (_, mut manifest, _, files, _) =
+                PageStore::recover(Photon, &base, &options(prefix))
+                    .await
+                    .unwrap();
+            let mut b = files.new_file_builder(1).await.unwrap();
+            b.add_page(page_id, page_addr(1, 0), &[page_id as u8; 16])
+                .await
+                .unwrap();
+            b.finish().await.unwrap();
+            let edit = VersionEdit {
+                new_files: vec![NewFile::from(1)],
+                deleted_files: vec![],
+            };
+            manifest
+                .record_version_edit(edit.clone(), || edit)
+                .await
+                .unwrap();
+        }
+
+        // Recovering a table neither sees nor removes the files of the other.
+        for _ in 0..2 {
+            for (prefix, page_id) in tables {
+                let (next_file_id, _, table, _, file_infos) =
+                    PageStore::recover(Photon, &base, &options(prefix))
+                        .await
+                        .unwrap();
+                assert_eq!(next_file_id, 2);
+                assert_eq!(file_infos.len(), 1);
+                assert_eq!(table.get(page_id), page_addr(1, 0));
+                assert_eq!(table.get(3 - page_id), 0);
+            }
+        }
+
+        let result = PageStore::recover(Photon, &base, &options("")).await;
+        assert!(matches!(result, Err(Error::InvalidArgument)));
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn recovery_progress() {
+        let base = std::env::temp_dir().join("test_recovery_progress");
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        let files = PageFiles::new(&base, "db");
+        // Each file moves page 1, so the newest file must win.
+        for file_id in 1..=5 {
+            let mut b = files.new_file_builder(file_id).await.unwrap();
+            b.add_page(1, page_addr(file_id, 0), &[1; 16])
+                .await
+                .unwrap();
+            b.add_page(file_id as u64 + 1, page_addr(file_id, 1), &[2; 16])
+                .await
+                .unwrap();
+            b.finish().await.unwrap();
+        }
+        let active_files = (1..=5)
+            .map(|id| (id, NewFile::from(id)))
+            .collect::<HashMap<_, _>>();
+
+        for parallelism in [0, 1, 2, 8] {
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let options = Options {
+                recovery_parallelism: parallelism,
+                on_recovery_progress: Some(Arc::new({
+                    let calls = calls.clone();
+                    move |done, total| calls.lock().unwrap().push((done, total))
+                })),
+                ..Default::default()
+            };
+            let table = PageStore::<Photon>::recover_page_table(&files, &active_files, &options)
+                .await
+                .unwrap();
+            assert_eq!(table.get(1), page_addr(5, 0));
+            for file_id in 1..=5 {
+                assert_eq!(table.get(file_id as u64 + 1), page_addr(file_id, 1));
+            }
+            let calls = calls.lock().unwrap();
+            assert_eq!(*calls, (1..=5).map(|done| (done, 5)).collect::<Vec<_>>());
+        }
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn recover_punched_holes() {
+        let base = std::env::temp_dir().join("test_recover_punched_holes");
+        let _ = std::fs::remove_dir_all(&base);
+        let options = Options::default();
+        let (_, mut manifest, _, files, _) =
+            PageStore::recover(Photon, &base, &options).await.unwrap();
+        {
+            let mut b = files.new_file_builder(1).await.unwrap();
+            b.add_page(1, page_addr(1, 0), &[1; 8192]).await.unwrap();
+            b.add_page(2, page_addr(1, 1), &[2; 8192]).await.unwrap();
+            b.finish().await.unwrap();
+        }
+        {
+            // Page 1 moves to the new file, which leaves a dead page behind.
+            let mut b = files.new_file_builder(2).await.unwrap();
+            b.add_page(1, page_addr(2, 0), &[3; 8192]).await.unwrap();
+            b.add_delete_pages(&[page_addr(1, 0)]);
+            b.finish().await.unwrap();
+        }
+        let edit = VersionEdit {
+            new_files: vec![NewFile::from(1), NewFile::from(2)],
+            deleted_files: vec![],
+        };
+        manifest
+            .record_version_edit(edit.clone(), || edit)
+            .await
+            .unwrap();
+        drop(manifest);
+
+        let (_, _, _, files, file_infos) =
+            PageStore::recover(Photon, &base, &options).await.unwrap();
+        let dead_pages = file_infos[&1].dead_pages();
+        assert_eq!(dead_pages.len(), 1);
+        let (addr, handle) = &dead_pages[0];
+        assert_eq!(*addr, page_addr(1, 0));
+        files
+            .punch_hole(1, handle.offset as u64, handle.size as u64)
+            .await
+            .unwrap();
+
+        // The punched page is not taken as a dead page to punch again.
+        let (_, _, _, _, file_infos) = PageStore::recover(Photon, &base, &options).await.unwrap();
+        assert!(file_infos[&1].dead_pages().is_empty());
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,4 +1,38 @@
-use super::FileInfo;
+use super::{FileInfo, GcPickStrategy};
+
+/// Picks the page files in which dead pages take up at least the threshold
+/// ratio of the page size.
+pub(crate) struct DeadRatioStrategy {
+    threshold: f64,
+}
+
+impl DeadRatioStrategy {
+    pub(crate) fn new(threshold: f64) -> Self {
+        Self { threshold }
+    }
+}
+
+impl Default for DeadRatioStrategy {
+    /// Picks the page files with at least as much dead data as live data.
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl GcPickStrategy for DeadRatioStrategy {
+    fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    fn score(&self, file_info: &FileInfo) -> f64 {
+        let total_size = file_info.meta().total_page_size();
+        if total_size == 0 {
+            return 0.0;
+        }
+        let dead_size = total_size.saturating_sub(file_info.effective_size());
+        dead_size as f64 / total_size as f64
+    }
+}
 
 #[allow(unused)]
 pub(crate) fn decline_rate(file_info: &FileInfo, now: u32) -> f64 {
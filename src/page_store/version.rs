@@ -13,7 +13,10 @@ use crossbeam_epoch::Guard;
 use futures::channel::oneshot;
 
 use super::{FileInfo, Result, WriteBuffer};
-use crate::util::notify::Notify;
+use crate::{
+    util::{notify::Notify, sync::lock},
+    PageAllocHint,
+};
 
 thread_local! {
     static VERSION: RefCell<Option<Arc<Version>>> = RefCell::new(None);
@@ -59,10 +62,12 @@ pub(crate) struct NextVersion {
 
 pub(crate) struct BufferSet {
     write_buffer_capacity: u32,
+    alloc_hint: PageAllocHint,
 
     current: AtomicPtr<BufferSetVersion>,
 
     flush_notify: Notify,
+    flushed_notify: Notify,
 }
 
 pub(crate) struct BufferSetVersion {
@@ -85,11 +90,16 @@ pub(crate) struct BufferSetRef<'a> {
 impl Version {
     pub(crate) fn new(
         write_buffer_capacity: u32,
+        alloc_hint: PageAllocHint,
         next_file_id: u32,
         files: HashMap<u32, FileInfo>,
         deleted_files: HashSet<u32>,
     ) -> Self {
-        let buffer_set = Arc::new(BufferSet::new(next_file_id, write_buffer_capacity));
+        let buffer_set = Arc::new(BufferSet::with_alloc_hint(
+            next_file_id,
+            write_buffer_capacity,
+            alloc_hint,
+        ));
         let (buffers_range, write_buffers) = {
             let current = buffer_set.current();
             (current.buffers_range.clone(), current.snapshot())
@@ -167,6 +177,16 @@ impl Version {
         self.next.refresh()
     }
 
+    /// Returns the [`Version`] installed right after this one, if any.
+    ///
+    /// Unlike [`Self::refresh`], this does not skip the versions in between.
+    pub(crate) fn next_version(&self) -> Option<Version> {
+        let raw = self.next.raw_version.load(Ordering::Acquire);
+        // Safety: it is obtained from [`Box::into_raw`] and only released with
+        // this version.
+        unsafe { raw.as_ref() }.cloned()
+    }
+
     /// Wait and construct next [`Version`].
     pub(crate) async fn wait_next_version(&self) -> Self {
         self.next.new_version_notify.notified().await;
@@ -178,16 +198,22 @@ impl Version {
     /// There can only be one waiter per [`Version`].
     pub(crate) async fn wait_version_released(self) {
         let handle = {
-            self.next
-                .cleanup_handle
-                .lock()
-                .expect("Poisoned")
+            lock(&self.next.cleanup_handle)
                 .take()
                 .expect("There can only be one waiter per version")
         };
         handle.await.unwrap_or_default();
     }
 
+    /// Returns true if no one else references the [`Version`], without
+    /// waiting.
+    ///
+    /// A version is also referenced by the versions before it, so it is only
+    /// released after them.
+    pub(crate) fn is_released(&self) -> bool {
+        Arc::strong_count(&self.next) == 1
+    }
+
     /// Fetch the files which obsolated but referenced by the [`Version`].
     #[inline]
     pub(crate) fn deleted_files(&self) -> Vec<u32> {
@@ -296,8 +322,17 @@ impl Drop for NextVersion {
 }
 
 impl BufferSet {
+    #[cfg(test)]
     pub(crate) fn new(next_file_id: u32, write_buffer_capacity: u32) -> BufferSet {
-        let buf = WriteBuffer::with_capacity(next_file_id, write_buffer_capacity);
+        Self::with_alloc_hint(next_file_id, write_buffer_capacity, PageAllocHint::Default)
+    }
+
+    pub(crate) fn with_alloc_hint(
+        next_file_id: u32,
+        write_buffer_capacity: u32,
+        alloc_hint: PageAllocHint,
+    ) -> BufferSet {
+        let buf = WriteBuffer::with_alloc_hint(next_file_id, write_buffer_capacity, alloc_hint);
         let version = Box::new(BufferSetVersion {
             buffers_range: next_file_id..(next_file_id + 1),
             sealed_buffers: Vec::default(),
@@ -306,8 +341,10 @@ impl BufferSet {
         let raw = Box::leak(version);
         BufferSet {
             write_buffer_capacity,
+            alloc_hint,
             current: AtomicPtr::new(raw),
             flush_notify: Notify::new(),
+            flushed_notify: Notify::new(),
         }
     }
 
@@ -316,6 +353,42 @@ impl BufferSet {
         self.write_buffer_capacity
     }
 
+    /// Creates a [`WriteBuffer`] with the capacity and the allocation hint of
+    /// the set.
+    pub(crate) fn new_write_buffer(&self, file_id: u32) -> WriteBuffer {
+        WriteBuffer::with_alloc_hint(file_id, self.write_buffer_capacity, self.alloc_hint)
+    }
+
+    /// Returns the number of bytes used in the current [`WriteBuffer`] and its
+    /// capacity.
+    pub(crate) fn usage(&self) -> (u32, u32) {
+        let current = self.current();
+        let used = current.current_buffer.allocated_size();
+        (used, self.write_buffer_capacity)
+    }
+
+    /// Returns true if there are sealed [`WriteBuffer`]s waiting to be
+    /// flushed.
+    pub(crate) fn has_pending_flush(&self) -> bool {
+        self.num_pending_flush() > 0
+    }
+
+    /// Returns the number of sealed [`WriteBuffer`]s waiting to be flushed.
+    pub(crate) fn num_pending_flush(&self) -> usize {
+        let current = self.current();
+        current.sealed_buffers.len() + current.current_buffer.is_sealed() as usize
+    }
+
+    /// Waits until the number of sealed [`WriteBuffer`]s waiting to be
+    /// flushed drops below `limit`.
+    pub(crate) async fn wait_pending_flush_below(&self, limit: usize) {
+        while self.num_pending_flush() >= limit {
+            self.flushed_notify.notified().await;
+        }
+        // Pass the notification on to other waiting tasks.
+        self.flushed_notify.notify_one();
+    }
+
     /// Obtains a reference of current [`BufferSetVersion`].
     pub(crate) fn current<'a>(&self) -> BufferSetRef<'a> {
         let guard = buffer_set_guard::pin();
@@ -376,6 +449,7 @@ impl BufferSet {
         });
 
         self.switch_version(new, guard);
+        self.flushed_notify.notify_one();
     }
 
     #[inline]
@@ -528,6 +602,81 @@ mod tests {
         buffer_set.install(Arc::new(buf));
     }
 
+    #[test]
+    fn buffer_set_usage_and_pending_flush() {
+        let buffer_set = BufferSet::new(1, 1 << 10);
+        assert_eq!(buffer_set.usage(), (0, 1 << 10));
+        assert!(!buffer_set.has_pending_flush());
+
+        let current = buffer_set.current();
+        current.current_buffer.batch(&[(1, 64)], &[]).unwrap();
+        let (used, _) = buffer_set.usage();
+        assert!(used > 64);
+
+        // A sealed buffer is pending until it is flushed.
+        unsafe { current.current_buffer.seal(true).unwrap() };
+        assert!(buffer_set.has_pending_flush());
+        let file_id = current.next_file_id();
+        drop(current);
+        let buf = WriteBuffer::with_capacity(file_id, buffer_set.write_buffer_capacity());
+        buffer_set.install(Arc::new(buf));
+        assert!(buffer_set.has_pending_flush());
+        assert_eq!(buffer_set.usage(), (0, 1 << 10));
+
+        buffer_set.on_flushed(1);
+        assert!(!buffer_set.has_pending_flush());
+    }
+
+    #[test]
+    fn version_released_after_predecessors() {
+        let version = Version::new(
+            1 << 10,
+            PageAllocHint::Default,
+            1,
+            HashMap::new(),
+            HashSet::new(),
+        );
+        assert!(version.is_released());
+
+        let delta = DeltaVersion {
+            files: HashMap::new(),
+            deleted_files: HashSet::new(),
+        };
+        Version::install(Rc::new(version.clone()), delta).unwrap();
+        let next = version.refresh().unwrap();
+        // The new version is referenced by the old one.
+        assert!(!next.is_released());
+
+        let reader = version.clone();
+        assert!(!version.is_released());
+        drop(version);
+        assert!(reader.is_released());
+        assert!(!next.is_released());
+        drop(reader);
+        assert!(next.is_released());
+    }
+
+    #[photonio::test]
+    async fn buffer_set_wait_pending_flush() {
+        let buffer_set = Arc::new(BufferSet::new(1, 1 << 10));
+        let current = buffer_set.current();
+        unsafe { current.current_buffer.seal(false).unwrap() };
+        let file_id = current.next_file_id();
+        drop(current);
+        let buf = WriteBuffer::with_capacity(file_id, buffer_set.write_buffer_capacity());
+        buffer_set.install(Arc::new(buf));
+        assert_eq!(buffer_set.num_pending_flush(), 1);
+        buffer_set.wait_pending_flush_below(2).await;
+
+        let cloned_buffer_set = buffer_set.clone();
+        let handle = photonio::task::spawn(async move {
+            cloned_buffer_set.wait_pending_flush_below(1).await;
+            cloned_buffer_set.num_pending_flush()
+        });
+        buffer_set.on_flushed(1);
+        assert_eq!(handle.await.unwrap(), 0);
+    }
+
     #[photonio::test]
     async fn buffer_set_write_buffer_flush_wait_and_notify() {
         let buffer_set = Arc::new(BufferSet::new(1, 1 << 10));
@@ -9,7 +9,8 @@ use bitflags::bitflags;
 use super::Result;
 use crate::{
     page::{PageBuf, PageRef},
-    page_store::Error,
+    page_store::{Error, PageAddr},
+    PageAllocHint,
 };
 
 pub(crate) struct WriteBuffer
@@ -20,6 +21,8 @@ where
 
     buf: NonNull<u8>,
     buf_size: usize,
+    // Whether `buf` is mapped rather than allocated.
+    mapped: bool,
 
     // The state of current buffer, see [`BufferState`] for details.
     buffer_state: AtomicU64,
@@ -69,9 +72,13 @@ pub(crate) enum ReleaseState {
 }
 
 impl WriteBuffer {
+    #[cfg(test)]
     pub(crate) fn with_capacity(file_id: u32, size: u32) -> Self {
-        use std::alloc::{alloc, Layout};
+        Self::with_alloc_hint(file_id, size, PageAllocHint::Default)
+    }
 
+    /// Creates a [`WriteBuffer`] with memory allocated according to the hint.
+    pub(crate) fn with_alloc_hint(file_id: u32, size: u32, hint: PageAllocHint) -> Self {
         let buf_size = size as usize;
         if buf_size <= core::mem::size_of::<usize>() {
             panic!("The capacity of WriteBuffer is too small");
@@ -81,17 +88,13 @@ impl WriteBuffer {
             panic!("The capacity of WriteBuffer is not pow of two");
         }
 
-        let layout = Layout::from_size_align(buf_size, core::mem::size_of::<usize>())
-            .expect("Invalid layout");
-        let buf = unsafe {
-            // Safety: it is guaranteed that layout has non-zero size.
-            NonNull::new(alloc(layout)).expect("The memory is exhausted")
-        };
+        let (buf, mapped) = alloc_buf(buf_size, hint);
         let default_state = BufferState::default();
         WriteBuffer {
             file_id,
             buf,
             buf_size,
+            mapped,
             buffer_state: AtomicU64::new(default_state.apply()),
         }
     }
@@ -111,6 +114,12 @@ impl WriteBuffer {
         self.buffer_state().sealed
     }
 
+    /// Returns the number of bytes allocated from the buffer.
+    #[inline]
+    pub(crate) fn allocated_size(&self) -> u32 {
+        self.buffer_state().allocated
+    }
+
     /// Allocate pages and record dealloc pages in one batch. This operation
     /// will acquire a writer guard.
     #[cfg(test)]
@@ -157,6 +166,9 @@ impl WriteBuffer {
 
     /// Allocate new page from the buffer.
     ///
+    /// Returns `Err(Error::InvalidArgument)` if the page is larger than the
+    /// capacity of the buffer, since it will never fit in any buffer.
+    ///
     /// # Safety
     ///
     /// The user needs to ensure that the [`WriteBuffer`] is valid for as long
@@ -168,6 +180,9 @@ impl WriteBuffer {
         acquire_writer: bool,
     ) -> Result<(u64, &'a mut RecordHeader, PageBuf<'a>)> {
         let acquire_size = record_size(page_size);
+        if acquire_size as usize > self.buf_size {
+            return Err(Error::InvalidArgument);
+        }
         let offset = self.alloc_size(acquire_size, acquire_writer)?;
         // Safety: here is the only one reference to the record.
         Ok(unsafe { self.new_page_at(offset, page_id, page_size) })
@@ -301,8 +316,8 @@ impl WriteBuffer {
     pub(crate) unsafe fn page<'a>(&self, page_addr: u64) -> PageRef<'a> {
         const ALIGN: u32 = core::mem::size_of::<usize>() as u32;
 
-        let file_id = (page_addr >> 32) as u32;
-        let offset = (page_addr & ((1 << 32) - 1)) as u32;
+        let page_addr = PageAddr::from(page_addr);
+        let (file_id, offset) = (page_addr.file_id(), page_addr.index());
 
         if file_id != self.file_id {
             panic!("The specified addr is not belongs to the buffer");
@@ -437,7 +452,7 @@ impl WriteBuffer {
 
         // Compute page addr.
         let page_offset = offset + core::mem::size_of::<RecordHeader>() as u32;
-        let page_addr = ((self.file_id as u64) << 32) | (page_offset as u64);
+        let page_addr: u64 = PageAddr::new(self.file_id, page_offset).into();
 
         // Construct `PageBuf`.
         let buf = unsafe {
@@ -479,6 +494,83 @@ impl WriteBuffer {
     }
 }
 
+/// Allocates `size` bytes for a [`WriteBuffer`] according to the hint.
+///
+/// Returns the memory and whether it is mapped. If the hint cannot be
+/// satisfied, the memory is allocated from the global allocator instead.
+fn alloc_buf(size: usize, hint: PageAllocHint) -> (NonNull<u8>, bool) {
+    use std::alloc::{alloc, Layout};
+
+    if let Some(buf) = map_buf(size, hint) {
+        return (buf, true);
+    }
+    let layout =
+        Layout::from_size_align(size, core::mem::size_of::<usize>()).expect("Invalid layout");
+    let buf = unsafe {
+        // Safety: it is guaranteed that layout has non-zero size.
+        NonNull::new(alloc(layout)).expect("The memory is exhausted")
+    };
+    (buf, false)
+}
+
+#[cfg(target_os = "linux")]
+fn map_buf(size: usize, hint: PageAllocHint) -> Option<NonNull<u8>> {
+    const HUGE_PAGE_SIZE: usize = 2 << 20;
+    const MPOL_BIND: libc::c_int = 2;
+
+    let mut flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    match hint {
+        PageAllocHint::Default => return None,
+        PageAllocHint::HugePages => {
+            // Huge page mappings can only be unmapped in whole huge pages.
+            if size % HUGE_PAGE_SIZE != 0 {
+                return None;
+            }
+            flags |= libc::MAP_HUGETLB;
+        }
+        PageAllocHint::NumaNode(node) => {
+            if node >= libc::c_ulong::BITS {
+                return None;
+            }
+        }
+    }
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            flags,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return None;
+    }
+    if let PageAllocHint::NumaNode(node) = hint {
+        // The memory is still usable if it can not be bound, for example, when
+        // the node does not exist.
+        let mask: libc::c_ulong = 1 << node;
+        unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                ptr,
+                size,
+                MPOL_BIND,
+                &mask as *const libc::c_ulong,
+                libc::c_ulong::BITS as libc::c_ulong,
+                0,
+            )
+        };
+    }
+    NonNull::new(ptr as *mut u8)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn map_buf(_: usize, _: PageAllocHint) -> Option<NonNull<u8>> {
+    None
+}
+
 impl Drop for WriteBuffer {
     fn drop(&mut self) {
         use std::alloc::{dealloc, Layout};
@@ -488,6 +580,13 @@ impl Drop for WriteBuffer {
             panic!("Try drop a write buffer that is still in use");
         }
 
+        if self.mapped {
+            unsafe {
+                // Safety: this memory is mapped in [`map_buf`] with the same size.
+                libc::munmap(self.buf.as_ptr() as *mut libc::c_void, self.buf_size);
+            }
+            return;
+        }
         let layout = Layout::from_size_align(self.buf_size, core::mem::size_of::<usize>())
             .expect("Invalid layout");
         unsafe {
@@ -658,7 +757,7 @@ impl<'a> Iterator for RecordIterator<'a> {
 
             self.offset += record_header.record_size();
             if let Some(record_ref) = record_header.record_ref() {
-                let page_addr = ((self.write_buffer.file_id as u64) << 32) | (record_offset as u64);
+                let page_addr: u64 = PageAddr::new(self.write_buffer.file_id, record_offset).into();
                 return Some((page_addr, record_header, record_ref));
             }
         }
@@ -801,6 +900,43 @@ mod tests {
         ));
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn write_buffer_alloc_hint() {
+        // Huge pages may not be reserved on the host, in which case the buffer
+        // falls back to the global allocator.
+        for hint in [
+            PageAllocHint::Default,
+            PageAllocHint::HugePages,
+            PageAllocHint::NumaNode(0),
+            PageAllocHint::NumaNode(64),
+        ] {
+            let buf = WriteBuffer::with_alloc_hint(1, 2 << 20, hint);
+            let (addr, _, mut page) = unsafe { buf.alloc_page(1, 64, true) }.unwrap();
+            page.set_epoch(42);
+            page.set_chain_next(7);
+            let page = unsafe { buf.page(addr) };
+            assert_eq!(page.epoch(), 42);
+            assert_eq!(page.chain_next(), 7);
+            unsafe { buf.release_writer() };
+        }
+    }
+
+    #[test]
+    fn write_buffer_alloc_oversized_page() {
+        let buf = WriteBuffer::with_capacity(1, 1024);
+        assert!(matches!(
+            unsafe { buf.alloc_page(1, 1024, true) },
+            Err(Error::InvalidArgument)
+        ));
+        // The buffer is still usable for pages that fit.
+        unsafe { buf.alloc_page(1, 512, true) }.unwrap();
+        assert!(matches!(
+            unsafe { buf.alloc_page(1, 512, false) },
+            Err(Error::Again)
+        ));
+    }
+
     #[test]
     fn write_buffer_sealed_seal() {
         let buf = WriteBuffer::with_capacity(1, 512);
@@ -0,0 +1,201 @@
+use futures::{
+    future::BoxFuture,
+    stream::{FuturesOrdered, StreamExt},
+};
+
+use crate::{Error, Result};
+
+/// The number of reads a [`GetStream`] keeps in flight.
+const GET_STREAM_DEPTH: usize = 16;
+
+/// A stream of point reads over ascending keys.
+///
+/// Up to a fixed number of reads are in flight at once, so the leaf pages of
+/// upcoming keys are loaded while the caller handles the current results.
+/// Results are yielded in the order of the keys, each with its key.
+///
+/// Keys must be in ascending order, which keeps the reads in flight on
+/// neighbouring leaf pages. A key smaller than the one before it yields
+/// [`Error::InvalidArgument`] after the results of the keys before it, and
+/// ends the stream.
+pub struct GetStream<'a, I> {
+    pipeline: Pipeline<'a, I, Option<Vec<u8>>>,
+}
+
+impl<'a, I> GetStream<'a, I>
+where
+    I: Iterator<Item = Vec<u8>>,
+{
+    pub(crate) fn new(
+        keys: I,
+        get: impl Fn(Vec<u8>) -> BoxFuture<'a, Result<Option<Vec<u8>>>> + Send + Sync + 'a,
+    ) -> Self {
+        Self {
+            pipeline: Pipeline::new(keys, GET_STREAM_DEPTH, get),
+        }
+    }
+
+    /// Returns the next key and its value, or `None` if all keys are read.
+    ///
+    /// The value is `None` if the key does not exist.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, Option<Vec<u8>>)>> {
+        self.pipeline.next().await
+    }
+}
+
+type Fetch<'a, T> = Box<dyn Fn(Vec<u8>) -> BoxFuture<'a, Result<T>> + Send + Sync + 'a>;
+
+/// Runs a fetch for each key with up to `depth` fetches in flight, and
+/// yields the results in the order of the keys.
+struct Pipeline<'a, I, T> {
+    keys: I,
+    depth: usize,
+    fetch: Fetch<'a, T>,
+    last: Option<Vec<u8>>,
+    pending: FuturesOrdered<BoxFuture<'a, Result<(Vec<u8>, T)>>>,
+    /// Set once a key is out of order, after which no more keys are read.
+    stopped: bool,
+    /// The error to yield after the pending results.
+    error: Option<Error>,
+}
+
+impl<'a, I, T> Pipeline<'a, I, T>
+where
+    I: Iterator<Item = Vec<u8>>,
+    T: Send + 'a,
+{
+    fn new(
+        keys: I,
+        depth: usize,
+        fetch: impl Fn(Vec<u8>) -> BoxFuture<'a, Result<T>> + Send + Sync + 'a,
+    ) -> Self {
+        Self {
+            keys,
+            depth: depth.max(1),
+            fetch: Box::new(fetch),
+            last: None,
+            pending: FuturesOrdered::new(),
+            stopped: false,
+            error: None,
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<(Vec<u8>, T)>> {
+        self.fill();
+        match self.pending.next().await {
+            Some(result) => result.map(Some),
+            None => self.error.take().map_or(Ok(None), Err),
+        }
+    }
+
+    /// Starts fetches for the upcoming keys until `depth` are in flight.
+    fn fill(&mut self) {
+        while !self.stopped && self.pending.len() < self.depth {
+            let Some(key) = self.keys.next() else {
+                break;
+            };
+            if self.last.as_ref().map_or(false, |last| last > &key) {
+                self.stopped = true;
+                self.error = Some(Error::InvalidArgument);
+                break;
+            }
+            self.last = Some(key.clone());
+            let fetch = (self.fetch)(key.clone());
+            self.pending.push_back(Box::pin(
+                async move { fetch.await.map(|value| (key, value)) },
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use futures::{channel::oneshot, executor::block_on, FutureExt};
+
+    use super::*;
+
+    /// Returns the key after a delay, with the wait done by another thread.
+    fn delayed(key: Vec<u8>, delay: Duration) -> BoxFuture<'static, Result<Vec<u8>>> {
+        let (tx, rx) = oneshot::channel();
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let _ = tx.send(key);
+        });
+        rx.map(|key| Ok(key.unwrap())).boxed()
+    }
+
+    fn keys(n: u8) -> impl Iterator<Item = Vec<u8>> {
+        (0..n).map(|i| vec![i])
+    }
+
+    #[test]
+    fn pipeline_overlaps_fetches() {
+        for depth in [1, 8] {
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            let max_in_flight = Arc::new(AtomicUsize::new(0));
+            let fetch = {
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                move |key: Vec<u8>| {
+                    let n = in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+                    max_in_flight.fetch_max(n, Ordering::Relaxed);
+                    let in_flight = in_flight.clone();
+                    async move {
+                        in_flight.fetch_sub(1, Ordering::Relaxed);
+                        Ok(key)
+                    }
+                    .boxed()
+                }
+            };
+            let mut pipeline = Pipeline::new(keys(16), depth, fetch);
+            let mut results = Vec::new();
+            while let Some((key, _)) = block_on(pipeline.next()).unwrap() {
+                results.push(key);
+            }
+            assert_eq!(results, keys(16).collect::<Vec<_>>());
+            assert_eq!(max_in_flight.load(Ordering::Relaxed), depth);
+        }
+    }
+
+    #[test]
+    fn pipeline_keeps_key_order() {
+        // Earlier keys take longer, so they finish last.
+        let mut pipeline = Pipeline::new(keys(10), 4, |key| {
+            let delay = Duration::from_millis(10 * (10 - key[0] as u64));
+            delayed(key, delay)
+        });
+        let mut results = Vec::new();
+        while let Some((key, value)) = block_on(pipeline.next()).unwrap() {
+            assert_eq!(key, value);
+            results.push(key);
+        }
+        assert_eq!(results, keys(10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pipeline_rejects_unsorted_keys() {
+        // The results before the unsorted key are yielded first, even if they
+        // are pending when it is read.
+        for depth in [1, 4] {
+            let keys = vec![vec![1], vec![1], vec![0], vec![2]];
+            let mut pipeline = Pipeline::new(keys.into_iter(), depth, |key| {
+                async move { Ok(key) }.boxed()
+            });
+            assert_eq!(block_on(pipeline.next()).unwrap(), Some((vec![1], vec![1])));
+            assert_eq!(block_on(pipeline.next()).unwrap(), Some((vec![1], vec![1])));
+            assert!(matches!(
+                block_on(pipeline.next()),
+                Err(Error::InvalidArgument)
+            ));
+            assert_eq!(block_on(pipeline.next()).unwrap(), None);
+        }
+    }
+}
@@ -1,92 +1,2025 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use futures::{channel::oneshot, FutureExt};
 
 use crate::{
     env::{Env, Photon},
+    export::{self, SnapshotEncoder},
     page::{Key, Value},
-    page_store::JobHandle,
-    tree::{PageRewriter, Stats, Tree},
-    util::atomic::Sequencer,
-    Options, Result,
+    page_store::{BackgroundHealth, DeadRatioStrategy, FormatInfo, JobHandle, QuiesceGuard},
+    stream::GetStream,
+    tree::{
+        AccessHint, Cursor, KeySizes, OwnedScan, PageRewriter, Pressure, Stats, Tree, VerifyReport,
+    },
+    txn::{self, ReadTxn, Transaction},
+    util::{atomic::Sequencer, checksum::crc32c, sync::lock},
+    Error, Options, Result, WriteBatch,
 };
 
 pub struct Table {
     raw: RawTable<Photon>,
-    lsn: Sequencer,
+    lsn: LsnSequence,
+    /// Serializes the validation and writes of transaction commits.
+    commit_lock: futures::lock::Mutex<()>,
 }
 
 impl Table {
     /// Opens a table in the path.
+    ///
+    /// The LSNs assigned to writes continue after the largest persisted one,
+    /// which takes a walk through all leaf pages.
+    /// [`Options::require_monotonic_lsn`] is ignored, since the table assigns
+    /// LSNs itself.
     pub async fn open<P: AsRef<Path>>(path: P, options: Options) -> Result<Self> {
+        // Concurrent writes may reach the raw table out of order, and
+        // transactions write several keys at the same LSN.
+        let options = Options {
+            require_monotonic_lsn: false,
+            ..options
+        };
         let raw = RawTable::open(Photon, path, options).await?;
+        let lsn = LsnSequence::new(raw.tree.max_lsn().await?);
+        raw.tree.publish_lsn(lsn.visible());
         Ok(Self {
             raw,
-            lsn: Sequencer::new(0),
+            lsn,
+            commit_lock: futures::lock::Mutex::new(()),
         })
     }
 
+    /// Assigns `n` consecutive LSNs to a write in progress.
+    ///
+    /// The LSNs are not visible to reads until the returned write is finished
+    /// or dropped, along with all writes at smaller LSNs.
+    fn begin_write(&self, n: u64) -> LsnWrite<'_> {
+        let lsn = self.lsn.assign(n);
+        LsnWrite { table: self, lsn }
+    }
+
+    /// Reserves `n` consecutive LSNs from `lsn` for a write in progress, like
+    /// [`Table::begin_write`].
+    ///
+    /// Returns [`Error::InvalidArgument`] if `lsn` is smaller than the next
+    /// LSN, since smaller LSNs may be visible to reads or assigned to other
+    /// writes already.
+    fn begin_write_at(&self, lsn: u64, n: u64) -> Result<LsnWrite<'_>> {
+        if self.lsn.reserve(lsn, n) {
+            Ok(LsnWrite { table: self, lsn })
+        } else {
+            Err(Error::InvalidArgument)
+        }
+    }
+
+    /// Sets the LSN assigned to the next write.
+    ///
+    /// This is meant for administrative use only, such as reconciling LSNs with
+    /// an external source when importing data. The LSN can not go backwards,
+    /// so values smaller than the next LSN are rejected with
+    /// [`Error::InvalidArgument`].
+    ///
+    /// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+    pub fn set_next_lsn(&self, lsn: u64) -> Result<()> {
+        if self.lsn.advance_to(lsn) {
+            self.raw.tree.publish_lsn(self.lsn.visible());
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument)
+        }
+    }
+
     /// Gets the value corresponding to the key.
+    ///
+    /// Returns `None` if the key does not exist or has been deleted. An empty
+    /// value is a valid value, which is returned as `Some` with an empty
+    /// vector.
     pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let lsn = self.lsn.get();
+        let lsn = self.lsn.visible();
         self.raw
             .get(key, lsn, |value| value.map(|value| value.to_vec()))
             .await
     }
 
+    /// Gets the value corresponding to the key as of `lsn`.
+    ///
+    /// This reads the state of the table after the write at `lsn`, as long as
+    /// the versions visible at `lsn` are kept. Versions are kept at and above
+    /// the smallest of [`Options::gc_keep_versions_below_lsn`], the LSN that
+    /// reads are served at, and the snapshots of open [`ReadTxn`]s. Returns
+    /// [`Error::VersionExpired`] if `lsn` is below that, the versions visible
+    /// at `lsn` are kept until the read is done otherwise.
+    ///
+    /// [`Error::VersionExpired`]: crate::Error::VersionExpired
+    pub async fn get_at(&self, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>> {
+        let _pin = self.pin_version(lsn)?;
+        self.raw
+            .get(key, lsn, |value| value.map(|value| value.to_vec()))
+            .await
+    }
+
+    /// Returns a cursor over the key-value pairs at or after `start` as of
+    /// `lsn`.
+    ///
+    /// See [`Table::get_at`] for the versions that can be read. The versions
+    /// visible at `lsn` are kept until the cursor is dropped.
+    pub fn scan_at(&self, start: &[u8], lsn: u64) -> Result<Cursor<'_, Photon>> {
+        self.raw
+            .tree
+            .scan_snapshot(start, lsn)
+            .ok_or(Error::VersionExpired)
+    }
+
+    /// Pins the visible LSN and returns it.
+    ///
+    /// The versions visible at it are kept until it is released, so a read
+    /// that spans several pages observes a single snapshot even if later
+    /// writes consolidate the pages in between.
+    fn pin_visible(&self) -> u64 {
+        self.raw
+            .tree
+            .pin_visible_snapshot()
+            .expect("the table publishes its visible LSN when opened")
+    }
+
+    /// Keeps the versions visible at `lsn` until the returned pin is dropped.
+    ///
+    /// Returns [`Error::VersionExpired`] if some of them may have been
+    /// dropped already.
+    fn pin_version(&self, lsn: u64) -> Result<VersionPin<'_>> {
+        if self.raw.tree.pin_snapshot(lsn) {
+            Ok(VersionPin { table: self, lsn })
+        } else {
+            Err(Error::VersionExpired)
+        }
+    }
+
+    /// Returns the LSN of the newest version of the key visible at `lsn`.
+    ///
+    /// Returns `None` if the key does not exist or has been deleted at `lsn`.
+    /// This is cheaper than reading the value, and can be used to detect
+    /// conflicts with later writes.
+    pub async fn version_of(&self, key: &[u8], lsn: u64) -> Result<Option<u64>> {
+        self.raw.version_of(key, lsn).await
+    }
+
+    /// Gets the newest value corresponding to the key, regardless of the
+    /// visible LSN of the table.
+    ///
+    /// Tombstones are still respected, so this returns `None` if the newest
+    /// version of the key is a delete.
+    pub async fn get_latest(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.raw
+            .get_latest(key, |value| value.map(|value| value.to_vec()))
+            .await
+    }
+
+    /// Gets the value corresponding to the key with an access hint.
+    ///
+    /// Reusing the same hint for a sequence of ascending keys avoids
+    /// traversing the tree from the root for keys in the same leaf page.
+    pub async fn get_with_hint(
+        &self,
+        key: &[u8],
+        hint: &mut AccessHint,
+    ) -> Result<Option<Vec<u8>>> {
+        let lsn = self.lsn.visible();
+        self.raw
+            .get_with_hint(key, lsn, hint, |value| value.map(|value| value.to_vec()))
+            .await
+    }
+
+    /// Gets the values of a stream of ascending keys.
+    ///
+    /// Unlike a sequence of [`Table::get`], the reads are pipelined: the leaf
+    /// pages of upcoming keys are loaded while the caller handles the current
+    /// results, which overlaps I/O with the work of the caller. Results are
+    /// yielded in the order of the keys. All reads observe the table as of
+    /// when this is called.
+    pub fn get_stream<I>(&self, keys: I) -> GetStream<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        let pin = VersionPin {
+            table: self,
+            lsn: self.pin_visible(),
+        };
+        GetStream::new(keys.into_iter(), move |key| {
+            let lsn = pin.lsn;
+            async move {
+                self.raw
+                    .get(&key, lsn, |value| value.map(|value| value.to_vec()))
+                    .await
+            }
+            .boxed()
+        })
+    }
+
+    /// Returns a cursor over the key-value pairs at or after `start`.
+    ///
+    /// The cursor tolerates concurrent structure modifications of the table.
+    /// It keeps the versions it reads until it is dropped.
+    pub fn scan(&self, start: &[u8]) -> Cursor<'_, Photon> {
+        let lsn = self.pin_visible();
+        self.raw.scan(start, lsn).with_pin()
+    }
+
+    /// Returns a cursor over the key-value pairs between the bounds.
+    ///
+    /// An inverted range, or an empty range such as `[a, a)`, yields nothing.
+    /// The cursor keeps the versions it reads until it is dropped.
+    pub fn scan_range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Cursor<'_, Photon> {
+        let lsn = self.pin_visible();
+        self.raw.scan_range(start, end, lsn).with_pin()
+    }
+
+    /// Returns a cursor over the keys at or after `start` and the lengths of
+    /// their values.
+    ///
+    /// The values are not copied, so this is cheaper than [`Table::scan`] to
+    /// collect value sizes.
+    pub fn scan_key_sizes(&self, start: &[u8]) -> KeySizes<'_, Photon> {
+        let lsn = self.pin_visible();
+        self.raw.scan_key_sizes(start, lsn).with_pin()
+    }
+
+    /// Returns an owned cursor over the key-value pairs at or after `start`.
+    ///
+    /// Unlike [`Table::scan`], the cursor does not borrow the table, so it can
+    /// be stored or returned across function boundaries. It keeps the
+    /// versions it reads until it is dropped.
+    pub fn owned_scan(&self, start: &[u8]) -> OwnedScan<Photon> {
+        self.raw
+            .tree
+            .owned_scan_visible(start)
+            .expect("the table publishes its visible LSN when opened")
+    }
+
     /// Inserts the key-value pair into the table.
+    ///
+    /// Putting an empty value is different from deleting the key, the key is
+    /// still present with the empty value.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the key is empty. The empty key is
+    /// reserved as the lower bound of the leftmost page, so it can never be
+    /// stored, and all writes reject it alike.
     pub async fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        let lsn = self.lsn.inc();
-        self.raw.put(key, lsn, value).await
+        let write = self.begin_write(1);
+        self.raw.put(key, write.lsn, value).await?;
+        write.finish().await;
+        Ok(())
+    }
+
+    /// Inserts the key-value pair as a last-write-wins register.
+    ///
+    /// If the latest versions of the key are all written by this, the value
+    /// with the highest timestamp is visible, regardless of the order of the
+    /// writes. Ties are broken by the order of the writes. A [`Table::put`] or
+    /// [`Table::delete`] overrides all older writes as usual.
+    pub async fn put_lww(&self, key: &[u8], value: &[u8], timestamp: u64) -> Result<()> {
+        let write = self.begin_write(1);
+        self.raw.put_lww(key, write.lsn, value, timestamp).await?;
+        write.finish().await;
+        Ok(())
     }
 
     /// Deletes the key from the table.
     pub async fn delete(&self, key: &[u8]) -> Result<()> {
-        let lsn = self.lsn.inc();
-        self.raw.delete(key, lsn).await
+        let write = self.begin_write(1);
+        self.raw.delete(key, write.lsn).await?;
+        write.finish().await;
+        Ok(())
+    }
+
+    /// Applies the operations in the batch to the table in order.
+    ///
+    /// The batch is not applied atomically, a failure may leave a prefix of
+    /// the operations applied. Batches with empty keys are rejected before
+    /// anything is applied.
+    pub async fn write(&self, batch: &WriteBatch) -> Result<()> {
+        check_batch(batch)?;
+        for (key, value) in batch.iter() {
+            match value {
+                Some(value) => self.put(key, value).await?,
+                None => self.delete(key).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Begins an optimistic transaction that reads the current state of the
+    /// table.
+    ///
+    /// Only other transactions are serialized against it, writes made with
+    /// [`Table::put`] and the like are detected as conflicts if they finish
+    /// before the commit, but are not ordered with the commit otherwise.
+    pub fn begin(&self) -> Transaction<'_> {
+        // Pinned like a read transaction, so reads keep their snapshot.
+        Transaction::new(self, self.pin_visible())
+    }
+
+    /// Begins a read-only transaction on the current state of the table.
+    ///
+    /// The transaction observes the writes that have finished when this is
+    /// called, and never observes writes in progress or later writes.
+    pub fn read_txn(&self) -> ReadTxn<'_> {
+        // The visible LSN is pinned atomically, so a consolidation can not
+        // drop the versions visible at it in between.
+        ReadTxn::new(self, self.pin_visible())
+    }
+
+    pub(crate) fn release_snapshot(&self, lsn: u64) {
+        self.raw.tree.unpin_snapshot(lsn);
+    }
+
+    /// Validates the reads of a transaction and applies its writes at a new
+    /// LSN.
+    ///
+    /// If a write fails, the writes applied before it are undone at the next
+    /// LSN, and both LSNs become visible together, so reads at the visible
+    /// LSN never observe part of the commit.
+    pub(crate) async fn commit(
+        &self,
+        reads: &BTreeMap<Vec<u8>, Option<u64>>,
+        writes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    ) -> Result<()> {
+        let _commit = self.commit_lock.lock().await;
+        let mut current = Vec::with_capacity(reads.len());
+        for key in reads.keys() {
+            current.push(self.raw.version_of(key, u64::MAX).await?);
+        }
+        txn::validate_reads(reads, &current)?;
+        writes.keys().try_for_each(|key| check_key(key))?;
+        if writes.is_empty() {
+            return Ok(());
+        }
+        // The commit becomes visible after all its writes. The second LSN is
+        // reserved to undo them.
+        let write = self.begin_write(2);
+        for (i, (key, value)) in writes.iter().enumerate() {
+            let result = match value {
+                Some(value) => self.raw.put(key, write.lsn, value).await,
+                None => self.raw.delete(key, write.lsn).await,
+            };
+            if let Err(err) = result {
+                let applied = writes.keys().take(i);
+                // The error of the commit matters more than that of the undo.
+                let _ = self.undo_writes(applied, write.lsn).await;
+                return Err(err);
+            }
+        }
+        write.finish().await;
+        Ok(())
+    }
+
+    /// Restores the keys written at `lsn` to their values before it, at the
+    /// next LSN.
+    async fn undo_writes<'k>(
+        &self,
+        keys: impl Iterator<Item = &'k Vec<u8>>,
+        lsn: u64,
+    ) -> Result<()> {
+        for key in keys {
+            let value = self
+                .raw
+                .get(key, lsn - 1, |value| value.map(|value| value.to_vec()))
+                .await?;
+            match value {
+                Some(value) => self.raw.put(key, lsn + 1, &value).await?,
+                None => self.raw.delete(key, lsn + 1).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Ingests the live entries of `other` into the table.
+    ///
+    /// Each entry is written at its LSN in `other` plus `lsn_offset`, so
+    /// entries keep their relative order. The LSNs of `other` up to its
+    /// visible LSN are reserved in the table like a single write, so the
+    /// entries become visible together once all of them are written, and are
+    /// newer than all writes before. Writes to `other` after this starts are
+    /// not ingested.
+    ///
+    /// Returns [`Error::InvalidArgument`] if an adjusted LSN overflows, or
+    /// `lsn_offset + 1` is smaller than the next LSN of the table.
+    pub async fn ingest_table(&self, other: &Table, lsn_offset: u64) -> Result<()> {
+        let pin = VersionPin {
+            table: other,
+            lsn: other.pin_visible(),
+        };
+        let snapshot = pin.lsn;
+        // LSNs of a table start from 1, so nothing is written below 1.
+        if snapshot == 0 {
+            return Ok(());
+        }
+        // The LSN after the reserved ones must not overflow either.
+        if lsn_offset.checked_add(snapshot + 1).is_none() {
+            return Err(Error::InvalidArgument);
+        }
+        let write = self.begin_write_at(lsn_offset + 1, snapshot)?;
+        let mut cursor = other.raw.scan(&[], snapshot);
+        while let Some((key, value)) = cursor.next().await? {
+            // The key may have been purged since the cursor read it.
+            let Some(lsn) = other.raw.version_of(&key, snapshot).await? else {
+                continue;
+            };
+            // Entries imported from a snapshot file may be below 1.
+            if lsn == 0 {
+                return Err(Error::InvalidArgument);
+            }
+            self.raw.put(&key, lsn + lsn_offset, &value).await?;
+        }
+        write.finish().await;
+        Ok(())
+    }
+
+    /// Rewrites the live entries of the table into a new table in `path`.
+    ///
+    /// Options such as [`Options::page_size`] only affect pages written after
+    /// they are changed, so reopening a table with different options does not
+    /// change the layout of existing pages. This streams all entries into a
+    /// freshly laid out table with `options` instead, and returns it. Writes
+    /// to this table after the rewrite starts are not included.
+    pub async fn rewrite_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: Options,
+    ) -> Result<Table> {
+        let table = Table::open(path, options).await?;
+        table.ingest_table(self, 0).await?;
+        Ok(table)
+    }
+
+    /// Writes the live entries of the table to a snapshot file in `path`.
+    ///
+    /// The file holds the latest version of each live key with the LSN it was
+    /// written at, followed by a checksum. Unlike the page files of the table,
+    /// the format does not depend on how entries are laid out in pages, so the
+    /// file can be loaded by [`Table::import`] in later versions of the crate
+    /// or read by other tools. Writes to the table after this starts are not
+    /// included.
+    ///
+    /// The imported table reads the same values as this table at the time of
+    /// the export, and [`Table::version_of`] returns the same LSNs at and
+    /// after the largest exported LSN. Older versions of keys and tombstones
+    /// are not exported, so reads at earlier LSNs are not preserved.
+    pub async fn export<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let pin = VersionPin {
+            table: self,
+            lsn: self.pin_visible(),
+        };
+        let snapshot = pin.lsn;
+        let mut cursor = self.raw.scan(&[], snapshot);
+        let mut encoder = SnapshotEncoder::new();
+        while let Some((key, value)) = cursor.next().await? {
+            // The key may have been purged since the cursor read it.
+            let Some(lsn) = self.raw.version_of(&key, snapshot).await? else {
+                continue;
+            };
+            encoder.add(&key, lsn, &value);
+        }
+        export::write_file(&Photon, path.as_ref(), &encoder.finish()).await
+    }
+
+    /// Opens a table in `path` and loads a snapshot file written by
+    /// [`Table::export`] into it.
+    ///
+    /// Each entry is written at the LSN it has in the file, and the next LSN
+    /// of the table is set after the largest one. The whole file is read and
+    /// verified before anything is written. Returns [`Error::Corrupted`] if
+    /// the file fails the checksum or has an unknown version.
+    pub async fn import<P: AsRef<Path>, Q: AsRef<Path>>(
+        snapshot: P,
+        path: Q,
+        options: Options,
+    ) -> Result<Table> {
+        let bytes = export::read_file(&Photon, snapshot.as_ref()).await?;
+        let entries = export::decode_snapshot(&bytes)?;
+        entries.iter().try_for_each(|(key, ..)| check_key(key))?;
+        let table = Table::open(path, options).await?;
+        let mut next_lsn = None;
+        for (key, lsn, value) in entries {
+            table.raw.put(key, lsn, value).await?;
+            next_lsn = next_lsn.max(Some(lsn.saturating_add(1)));
+        }
+        if let Some(lsn) = next_lsn {
+            table.lsn.advance_to(lsn);
+            table.raw.tree.publish_lsn(table.lsn.visible());
+        }
+        Ok(table)
+    }
+
+    /// Purges all versions of the key from the table.
+    ///
+    /// Unlike [`Table::delete`], this removes the key from the page that
+    /// contains it immediately, instead of leaving a tombstone that is only
+    /// dropped by later consolidations. The pages that a leaf page was split
+    /// from may still hold the key, so their chains are rebuilt without it as
+    /// well. The table is then flushed, and the dead pages in page files that
+    /// hold the key are punched out, so the purged values no longer stay on
+    /// disk.
+    ///
+    /// This walks through the leaf pages before the key and reads the dead
+    /// pages in page files, so it is meant for occasional use. It fails if the
+    /// file system does not support punching holes.
+    pub async fn purge(&self, key: &[u8]) -> Result<()> {
+        self.raw.purge(key).await
     }
 
     /// Returns the statistics of the table.
     pub fn stats(&self) -> Stats {
         self.raw.stats()
     }
+
+    /// Removes files in the table directory that are not referenced by the
+    /// table, and returns their paths.
+    ///
+    /// This also runs automatically when the table is opened, which removes
+    /// files left by crashes, such as partially written page files.
+    pub async fn cleanup_orphans(&self) -> Result<Vec<PathBuf>> {
+        self.raw.cleanup_orphans().await
+    }
+
+    /// Flushes the table and stops it from creating or removing files until
+    /// the returned guard is dropped.
+    ///
+    /// This allows backing up the table directory with external tools while
+    /// the table is open. Reads and writes go on while the guard is held, but
+    /// writes are only buffered in memory and stall once
+    /// [`Options::max_pending_write_buffers`] buffers are waiting to be
+    /// flushed, so the guard should be dropped as soon as the backup is done.
+    pub async fn quiesce(&self) -> QuiesceGuard<'_> {
+        self.raw.quiesce().await
+    }
+
+    /// Runs the background jobs of the table inline.
+    ///
+    /// This flushes full write buffers, consolidates the pages marked by reads,
+    /// removes obsolete files and runs a GC round on the calling task. It is
+    /// meant to be called periodically by embedders that enable
+    /// [`Options::run_jobs_inline`], and does nothing otherwise.
+    pub async fn tick(&self) -> Result<()> {
+        self.raw.tick().await
+    }
+
+    /// Consolidates the page chain of every leaf page into a single base page.
+    ///
+    /// Reads then look up each key in one page, without walking through delta
+    /// pages. Leaf pages without delta pages are left alone, so this writes
+    /// much less than [`RawTable::compact_and_verify`], and reclaims no space
+    /// in page files. Returns the number of page chains consolidated.
+    pub async fn consolidate_all(&self) -> Result<usize> {
+        self.raw.consolidate_all().await
+    }
+
+    /// Changes an option of the table while it is open.
+    ///
+    /// Only [`Options::page_chain_length`] and
+    /// [`Options::gc_keep_versions_below_lsn`] can be changed at runtime.
+    /// Returns [`crate::Error::InvalidArgument`] for other options or invalid
+    /// values.
+    pub fn set_option(&self, name: &str, value: &str) -> Result<()> {
+        self.raw.set_option(name, value)
+    }
+
+    /// Returns the format of the persisted files of the table.
+    pub fn format_info(&self) -> FormatInfo {
+        self.raw.format_info()
+    }
+
+    /// Returns the health of the background jobs of the table.
+    ///
+    /// A background job that is not alive has exited unexpectedly, the panic
+    /// message is recorded in the health if it panicked.
+    pub fn background_health(&self) -> BackgroundHealth {
+        self.raw.background_health()
+    }
+
+    /// Returns the number of bytes used in the active write buffer and its
+    /// capacity.
+    ///
+    /// Writers can use this to slow down before the buffer fills up.
+    pub fn write_buffer_usage(&self) -> (usize, usize) {
+        self.raw.write_buffer_usage()
+    }
+
+    /// Returns true if some write buffers are full and waiting to be flushed.
+    ///
+    /// Writes may stall while this is true, until the flush completes.
+    pub fn flush_scheduled(&self) -> bool {
+        self.raw.flush_scheduled()
+    }
 }
 
 pub struct RawTable<E: Env> {
     tree: Arc<Tree<E>>,
-    _job_guard: JobHandle,
+    lsn: LsnMode,
+    value_checksum_min_size: Option<usize>,
+    job_handle: JobHandle<E>,
 }
 
 impl<E: Env> RawTable<E> {
     pub async fn open<P: AsRef<Path>>(env: E, path: P, options: Options) -> Result<Self> {
+        let managed_lsn = options.managed_lsn;
+        let require_monotonic_lsn = options.require_monotonic_lsn;
+        let value_checksum_min_size = options.value_checksum_min_size;
         let tree = Arc::new(Tree::open(env.clone(), path, options).await?);
+        // Assigned or checked LSNs continue after the persisted ones.
+        let max_lsn = if managed_lsn && !require_monotonic_lsn {
+            None
+        } else {
+            tree.max_lsn().await?
+        };
+        let lsn = LsnMode::new(managed_lsn, require_monotonic_lsn, max_lsn);
         let rewriter = Arc::new(PageRewriter::new(tree.clone()));
-        let _job_guard = JobHandle::new(&env, tree.store(), rewriter, todo!());
-        Ok(Self { tree, _job_guard })
+        let strategy = Box::new(DeadRatioStrategy::default());
+        let job_handle = JobHandle::new(&env, tree.store(), rewriter, strategy);
+        Ok(Self {
+            tree,
+            lsn,
+            value_checksum_min_size,
+            job_handle,
+        })
     }
 
     pub async fn get<F, R>(&self, key: &[u8], lsn: u64, f: F) -> Result<R>
     where
+        E: 'static,
         F: FnOnce(Option<&[u8]>) -> R,
     {
-        let key = Key::new(key, lsn);
+        let key = Key::new(key, self.lsn.read_lsn(lsn));
         let result = self.tree.get(key, f).await?;
         Ok(result)
     }
 
+    pub async fn version_of(&self, key: &[u8], lsn: u64) -> Result<Option<u64>> {
+        let key = Key::new(key, self.lsn.read_lsn(lsn));
+        let lsn = self.tree.version_of(key).await?;
+        Ok(lsn)
+    }
+
+    /// Gets the newest value corresponding to the key, ignoring LSNs.
+    pub async fn get_latest<F, R>(&self, key: &[u8], f: F) -> Result<R>
+    where
+        E: 'static,
+        F: FnOnce(Option<&[u8]>) -> R,
+    {
+        let key = Key::new(key, u64::MAX);
+        let result = self.tree.get(key, f).await?;
+        Ok(result)
+    }
+
+    pub async fn get_with_hint<F, R>(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        hint: &mut AccessHint,
+        f: F,
+    ) -> Result<R>
+    where
+        F: FnOnce(Option<&[u8]>) -> R,
+    {
+        let key = Key::new(key, self.lsn.read_lsn(lsn));
+        let result = self.tree.get_with_hint(key, hint, f).await?;
+        Ok(result)
+    }
+
+    pub fn scan(&self, start: &[u8], lsn: u64) -> Cursor<'_, E> {
+        self.tree.scan(start, self.lsn.read_lsn(lsn))
+    }
+
+    pub fn scan_range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, lsn: u64) -> Cursor<'_, E> {
+        self.tree.scan_range(start, end, self.lsn.read_lsn(lsn))
+    }
+
+    pub fn scan_key_sizes(&self, start: &[u8], lsn: u64) -> KeySizes<'_, E> {
+        self.tree.scan_key_sizes(start, self.lsn.read_lsn(lsn))
+    }
+
+    pub fn owned_scan(&self, start: &[u8], lsn: u64) -> OwnedScan<E>
+    where
+        E: 'static,
+    {
+        self.tree.owned_scan(start, self.lsn.read_lsn(lsn))
+    }
+
+    /// Inserts the key-value pair at the LSN.
+    ///
+    /// See [`Options::stale_writes`] for writes that are not newer than the
+    /// latest version of the key.
     pub async fn put(&self, key: &[u8], lsn: u64, value: &[u8]) -> Result<()> {
-        let key = Key::new(key, lsn);
-        let value = Value::Put(value);
+        check_key(key)?;
+        let key = Key::new(key, self.lsn.write_lsn(lsn)?);
+        let value = put_value(value, self.value_checksum_min_size);
+        self.tree.write(key, value).await?;
+        Ok(())
+    }
+
+    /// Inserts the key-value pair as a last-write-wins register at the LSN.
+    ///
+    /// See [`Table::put_lww`] for how the value is resolved.
+    pub async fn put_lww(&self, key: &[u8], lsn: u64, value: &[u8], timestamp: u64) -> Result<()> {
+        check_key(key)?;
+        let key = Key::new(key, self.lsn.write_lsn(lsn)?);
+        let value = Value::Lww(timestamp, value);
         self.tree.write(key, value).await?;
         Ok(())
     }
 
     pub async fn delete(&self, key: &[u8], lsn: u64) -> Result<()> {
-        let key = Key::new(key, lsn);
+        check_key(key)?;
+        let key = Key::new(key, self.lsn.write_lsn(lsn)?);
         let value = Value::Delete;
         self.tree.write(key, value).await?;
         Ok(())
     }
 
+    /// Applies the operations in the batch to the table in order.
+    ///
+    /// The operations are written with consecutive LSNs starting from `lsn`.
+    pub async fn write(&self, batch: &WriteBatch, lsn: u64) -> Result<()> {
+        check_batch(batch)?;
+        for (i, (key, value)) in batch.iter().enumerate() {
+            let lsn = lsn + i as u64;
+            match value {
+                Some(value) => self.put(key, lsn, value).await?,
+                None => self.delete(key, lsn).await?,
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn purge(&self, key: &[u8]) -> Result<()> {
+        self.tree.purge(key).await?;
+        // Readers that loaded the old pages hold this version or older ones.
+        let version = self.tree.store().global_version();
+        // Flush the deallocation of the old pages, and punch them out of the
+        // page files.
+        drop(self.quiesce().await);
+        self.tree.punch_key(key, version).await?;
+        Ok(())
+    }
+
+    /// Returns the encoded bytes of the page with the id for physical
+    /// replication.
+    ///
+    /// The bytes are the page as stored in page files, followed by a CRC-32C
+    /// checksum. Page chains are consolidated before they are read, since
+    /// delta pages refer to each other by addresses local to this table.
+    /// Returns [`Error::InvalidArgument`] if the id is not mapped.
+    pub async fn read_raw_page(&self, id: u64) -> Result<Vec<u8>> {
+        let raw = self.tree.read_raw_page(id).await?;
+        Ok(raw)
+    }
+
+    /// Installs a page read by [`Self::read_raw_page`] on another table.
+    ///
+    /// Page ids and epochs are the same on every node: the page replaces the
+    /// page with the same id here, and keeps the epoch it has on the primary.
+    /// The replica must start from the same pages as the primary and apply
+    /// every page the primary changes, so that index pages keep referring to
+    /// the right children. Since ids can not be allocated on demand, the id
+    /// must already be mapped here.
+    ///
+    /// Returns [`Error::Corrupted`] if the bytes fail the checksum, or do not
+    /// hold a well-formed base page of the same tier as the page it replaces.
+    pub async fn apply_raw_page(&self, id: u64, raw: &[u8]) -> Result<()> {
+        self.tree.apply_raw_page(id, raw).await?;
+        Ok(())
+    }
+
     pub fn stats(&self) -> Stats {
         self.tree.stats()
     }
+
+    /// Returns the options the table is running with.
+    ///
+    /// Options changed at runtime are reflected with their current values.
+    pub fn options(&self) -> Options {
+        self.tree.options()
+    }
+
+    pub fn background_health(&self) -> BackgroundHealth {
+        self.job_handle.health()
+    }
+
+    pub fn write_buffer_usage(&self) -> (usize, usize) {
+        let (used, capacity) = self.tree.store().write_buffer_usage();
+        (used as usize, capacity as usize)
+    }
+
+    pub fn flush_scheduled(&self) -> bool {
+        self.tree.store().flush_scheduled()
+    }
+
+    pub fn set_option(&self, name: &str, value: &str) -> Result<()> {
+        self.tree.set_option(name, value)
+    }
+
+    pub fn format_info(&self) -> FormatInfo {
+        self.tree.store().format_info()
+    }
+
+    pub async fn cleanup_orphans(&self) -> Result<Vec<PathBuf>> {
+        let removed = self.tree.store().cleanup_orphans().await?;
+        Ok(removed)
+    }
+
+    pub async fn quiesce(&self) -> QuiesceGuard<'_> {
+        if self.job_handle.is_inline() {
+            // Flush here, since nothing else would.
+            self.tree.store().seal_active_buffer();
+            self.job_handle.tick().await;
+        }
+        self.tree.store().quiesce().await
+    }
+
+    pub async fn tick(&self) -> Result<()> {
+        self.tree.consolidate_pending().await?;
+        self.job_handle.tick().await;
+        Ok(())
+    }
+
+    /// Reads and decodes the page with the id for debugging.
+    ///
+    /// The page is loaded from disk if it is not in memory.
+    #[cfg(feature = "debug")]
+    pub async fn read_page(&self, id: u64) -> Result<crate::PageDump> {
+        let page = self.tree.dump_page(id).await?;
+        Ok(page)
+    }
+
+    /// Dumps the page table entries of the pages reachable from the root.
+    ///
+    /// The dump also lists the ids referenced by index pages that are not
+    /// mapped, which indicate corruption. Like [`Self::live_page_ids`], it
+    /// walks through all pages in the table.
+    #[cfg(feature = "debug")]
+    pub async fn export_mapping(&self) -> Result<crate::MappingDump> {
+        let dump = self.tree.export_mapping().await?;
+        Ok(dump)
+    }
+
+    /// Splits the leaf page that contains the key immediately.
+    ///
+    /// This allows tests to exercise page splits without loading enough data
+    /// to exceed the page size.
+    #[cfg(feature = "debug")]
+    pub async fn force_split(&self, key: &[u8]) -> Result<()> {
+        self.tree.force_split(key).await?;
+        Ok(())
+    }
+
+    /// Returns the structure maintenance pressure of the table.
+    ///
+    /// The pressure is tracked as pages change, so this is cheap enough to
+    /// poll.
+    pub fn pressure(&self) -> Pressure {
+        let mut pressure = self.tree.pressure();
+        let files = self.job_handle.num_files_needing_gc(self.tree.store());
+        pressure.files_needing_gc = files as u64;
+        pressure
+    }
+
+    /// Returns the ids of all pages reachable from the root in order.
+    ///
+    /// This allows maintenance tools to cross-check the pages in page files
+    /// for leaked or dangling pages. It walks through all pages in the table,
+    /// so it should not be called frequently.
+    pub async fn live_page_ids(&self) -> Result<Vec<u64>> {
+        let ids = self.tree.live_page_ids().await?;
+        Ok(ids)
+    }
+
+    /// Returns up to `n - 1` keys that divide the table into `n` ranges of
+    /// roughly equal size.
+    ///
+    /// The keys are leaf page boundaries weighted by the size of the pages,
+    /// so the ranges are only as balanced as the page sizes allow. Like
+    /// [`Self::live_page_ids`], it walks through all pages in the table.
+    pub async fn suggest_split_points(&self, n: usize) -> Result<Vec<Vec<u8>>> {
+        let points = self.tree.suggest_split_points(n).await?;
+        Ok(points)
+    }
+
+    /// Verifies all pages in the table and consolidates their page chains.
+    ///
+    /// Every page is checked against its checksum in the page file, for
+    /// consistent offsets, and for sorted keys within the range given by its
+    /// parent. If any page is malformed, its id is reported and nothing is
+    /// consolidated. Pages in files written without checksums are only
+    /// checked for their structure. This walks through all pages twice at
+    /// most and is meant for maintenance windows.
+    pub async fn compact_and_verify(&self) -> Result<VerifyReport> {
+        let report = self.tree.compact_and_verify().await?;
+        Ok(report)
+    }
+
+    /// Consolidates the page chain of every leaf page into a single base page.
+    ///
+    /// See [`Table::consolidate_all`].
+    pub async fn consolidate_all(&self) -> Result<usize> {
+        let consolidated = self.tree.consolidate_all().await?;
+        Ok(consolidated)
+    }
+}
+
+/// A pinned LSN of a [`Table`], which is unpinned when dropped.
+struct VersionPin<'a> {
+    table: &'a Table,
+    lsn: u64,
+}
+
+impl Drop for VersionPin<'_> {
+    fn drop(&mut self) {
+        self.table.release_snapshot(self.lsn);
+    }
+}
+
+/// Assigns the LSNs of the writes to a [`Table`] and decides which of them
+/// reads observe.
+///
+/// Writes finish out of order, so an LSN only becomes visible once the write
+/// at it and the writes at all smaller LSNs have finished. Reads at the
+/// visible LSN never observe a write in progress.
+struct LsnSequence {
+    state: Mutex<LsnState>,
+}
+
+struct LsnState {
+    /// The LSN assigned to the next write.
+    next: u64,
+    /// The largest LSN at and below which all writes have finished.
+    visible: u64,
+    /// The first LSN of each write in progress.
+    in_flight: BTreeSet<u64>,
+    /// The tasks waiting for LSNs to become visible.
+    waiters: Vec<(u64, oneshot::Sender<()>)>,
+}
+
+impl LsnSequence {
+    /// Creates a sequence that continues after the largest persisted LSN.
+    ///
+    /// LSNs start from 1 in an empty table, so that reads at 0 observe no
+    /// writes.
+    fn new(max_lsn: Option<u64>) -> Self {
+        let visible = max_lsn.unwrap_or(0);
+        let state = LsnState {
+            next: visible.saturating_add(1),
+            visible,
+            in_flight: BTreeSet::new(),
+            waiters: Vec::new(),
+        };
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    fn visible(&self) -> u64 {
+        lock(&self.state).visible
+    }
+
+    /// Assigns `n` consecutive LSNs to a write and returns the first one.
+    fn assign(&self, n: u64) -> u64 {
+        let mut state = lock(&self.state);
+        let lsn = state.next;
+        state.next += n;
+        state.in_flight.insert(lsn);
+        lsn
+    }
+
+    /// Marks the write at the LSNs assigned from `lsn` as finished.
+    fn finish(&self, lsn: u64) {
+        let mut state = lock(&self.state);
+        state.in_flight.remove(&lsn);
+        state.update_visible();
+    }
+
+    /// Assigns `n` consecutive LSNs from `lsn` to a write, like
+    /// [`Self::assign`].
+    ///
+    /// Returns false and leaves the sequence unchanged if `lsn` is smaller
+    /// than the next LSN, so that LSNs are never assigned twice.
+    fn reserve(&self, lsn: u64, n: u64) -> bool {
+        let mut state = lock(&self.state);
+        if lsn < state.next {
+            return false;
+        }
+        state.next = lsn + n;
+        state.in_flight.insert(lsn);
+        // The LSNs skipped before `lsn` become visible, as with `advance_to`.
+        state.update_visible();
+        true
+    }
+
+    /// Sets the LSN assigned to the next write.
+    ///
+    /// Returns false and leaves the sequence unchanged if `lsn` is smaller
+    /// than the next LSN, so that LSNs never go backwards.
+    fn advance_to(&self, lsn: u64) -> bool {
+        let mut state = lock(&self.state);
+        if lsn < state.next {
+            return false;
+        }
+        state.next = lsn;
+        state.update_visible();
+        true
+    }
+
+    /// Waits until `lsn` is visible.
+    async fn wait_visible(&self, lsn: u64) {
+        let receiver = {
+            let mut state = lock(&self.state);
+            if lsn <= state.visible {
+                return;
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push((lsn, sender));
+            receiver
+        };
+        let _ = receiver.await;
+    }
+}
+
+impl LsnState {
+    fn update_visible(&mut self) {
+        self.visible = match self.in_flight.iter().next() {
+            Some(&lsn) => lsn - 1,
+            None => self.next - 1,
+        };
+        let visible = self.visible;
+        let (ready, waiters) = std::mem::take(&mut self.waiters)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(lsn, _)| *lsn <= visible);
+        self.waiters = waiters;
+        for (_, sender) in ready {
+            let _ = sender.send(());
+        }
+    }
+}
+
+/// A write to a [`Table`] in progress.
+///
+/// The LSNs of the write become visible when it is finished, or when it is
+/// dropped if it fails or is cancelled.
+struct LsnWrite<'a> {
+    table: &'a Table,
+    lsn: u64,
+}
+
+impl LsnWrite<'_> {
+    /// Finishes the write and waits until it is visible, so that later reads
+    /// observe it.
+    async fn finish(self) {
+        let (table, lsn) = (self.table, self.lsn);
+        drop(self);
+        table.lsn.wait_visible(lsn).await;
+    }
+}
+
+impl Drop for LsnWrite<'_> {
+    fn drop(&mut self) {
+        self.table.lsn.finish(self.lsn);
+        self.table.raw.tree.publish_lsn(self.table.lsn.visible());
+    }
+}
+
+/// Decides the LSNs used by a [`RawTable`].
+enum LsnMode {
+    /// Uses the LSNs supplied by the caller.
+    Managed,
+    /// Uses the LSNs supplied by the caller, which must increase with each
+    /// write. Holds the LSN of the last accepted write.
+    Monotonic(Mutex<Option<u64>>),
+    /// Ignores the LSNs supplied by the caller and orders writes by insertion.
+    Unmanaged(Sequencer),
+}
+
+impl LsnMode {
+    /// Creates the mode for a table whose largest persisted LSN is
+    /// `max_lsn`.
+    fn new(managed: bool, require_monotonic: bool, max_lsn: Option<u64>) -> Self {
+        match (managed, require_monotonic) {
+            (true, false) => Self::Managed,
+            (true, true) => Self::Monotonic(Mutex::new(max_lsn)),
+            (false, _) => {
+                let next = max_lsn.map_or(0, |lsn| lsn.saturating_add(1));
+                Self::Unmanaged(Sequencer::new(next))
+            }
+        }
+    }
+
+    fn read_lsn(&self, lsn: u64) -> u64 {
+        match self {
+            Self::Managed | Self::Monotonic(_) => lsn,
+            Self::Unmanaged(_) => u64::MAX,
+        }
+    }
+
+    /// Returns the LSN to write with.
+    ///
+    /// Returns [`Error::InvalidArgument`] if LSNs must increase and `lsn` is
+    /// not larger than the LSN of a previous write.
+    fn write_lsn(&self, lsn: u64) -> Result<u64> {
+        match self {
+            Self::Managed => Ok(lsn),
+            Self::Monotonic(last) => {
+                let mut last = lock(last);
+                if last.map_or(false, |last| lsn <= last) {
+                    return Err(Error::InvalidArgument);
+                }
+                *last = Some(lsn);
+                Ok(lsn)
+            }
+            Self::Unmanaged(seq) => Ok(seq.inc()),
+        }
+    }
+}
+
+/// Rejects the empty key, which is reserved as the lower bound of the
+/// leftmost page.
+///
+/// Index pages start their leftmost child at the empty key, so a split at a
+/// stored empty key would give two children the same start.
+fn check_key(key: &[u8]) -> Result<()> {
+    if key.is_empty() {
+        Err(Error::InvalidArgument)
+    } else {
+        Ok(())
+    }
+}
+
+/// Rejects batches with any empty key.
+fn check_batch(batch: &WriteBatch) -> Result<()> {
+    batch.iter().try_for_each(|(key, _)| check_key(key))
+}
+
+/// Returns the value to write for a put, which has a checksum if the value is
+/// at least `min_size` bytes.
+///
+/// See [`Options::value_checksum_min_size`].
+fn put_value(value: &[u8], min_size: Option<usize>) -> Value<'_> {
+    match min_size {
+        Some(min_size) if value.len() >= min_size => Value::Checked(crc32c(value), value),
+        _ => Value::Put(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChecksumType, StaleWrites};
+
+    #[test]
+    fn empty_keys_are_reserved() {
+        assert!(matches!(check_key(b""), Err(Error::InvalidArgument)));
+        assert!(check_key(b"\x00").is_ok());
+
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"1");
+        assert!(check_batch(&batch).is_ok());
+        batch.delete(b"");
+        assert!(matches!(check_batch(&batch), Err(Error::InvalidArgument)));
+    }
+
+    #[test]
+    fn value_checksum_for_large_values() {
+        let small = [1u8; 15];
+        let large = [2u8; 16];
+        assert_eq!(put_value(&large, None), Value::Put(&large));
+        assert_eq!(put_value(&small, Some(16)), Value::Put(&small));
+        let Value::Checked(checksum, value) = put_value(&large, Some(16)) else {
+            panic!("large values must be checked");
+        };
+        assert_eq!(value, large);
+        assert_eq!(checksum, crc32c(&large));
+    }
+
+    #[test]
+    fn managed_lsn_uses_caller_lsn() {
+        let lsn = LsnMode::new(true, false, None);
+        assert_eq!(lsn.write_lsn(7).unwrap(), 7);
+        assert_eq!(lsn.read_lsn(3), 3);
+        // Without enforcement, LSNs may go backwards.
+        assert_eq!(lsn.write_lsn(5).unwrap(), 5);
+    }
+
+    #[test]
+    fn monotonic_lsn_rejects_reordered_writes() {
+        let lsn = LsnMode::new(true, true, None);
+        assert_eq!(lsn.write_lsn(10).unwrap(), 10);
+        assert!(matches!(lsn.write_lsn(5), Err(Error::InvalidArgument)));
+        assert!(matches!(lsn.write_lsn(10), Err(Error::InvalidArgument)));
+        assert_eq!(lsn.write_lsn(11).unwrap(), 11);
+        assert_eq!(lsn.read_lsn(3), 3);
+        assert_eq!(lsn.write_lsn(u64::MAX).unwrap(), u64::MAX);
+        assert!(lsn.write_lsn(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn lsn_sequence_visibility() {
+        let lsn = LsnSequence::new(None);
+        assert_eq!(lsn.visible(), 0);
+        let (first, second, third) = (lsn.assign(1), lsn.assign(2), lsn.assign(1));
+        assert_eq!((first, second, third), (1, 2, 4));
+        // Later writes may finish first, but are not visible before earlier
+        // ones.
+        lsn.finish(second);
+        assert_eq!(lsn.visible(), 0);
+        lsn.finish(first);
+        assert_eq!(lsn.visible(), 3);
+        lsn.finish(third);
+        assert_eq!(lsn.visible(), 4);
+
+        assert!(!lsn.advance_to(3));
+        assert!(lsn.advance_to(10));
+        assert_eq!(lsn.visible(), 9);
+        assert_eq!(lsn.assign(1), 10);
+
+        // Reserved LSNs are never assigned to other writes.
+        let lsn = LsnSequence::new(None);
+        assert!(lsn.reserve(5, 3));
+        assert!(!lsn.reserve(7, 1));
+        assert_eq!(lsn.visible(), 4);
+        let next = lsn.assign(1);
+        assert_eq!(next, 8);
+        lsn.finish(next);
+        assert_eq!(lsn.visible(), 4);
+        lsn.finish(5);
+        assert_eq!(lsn.visible(), 8);
+
+        // Recovered sequences continue after the persisted LSNs.
+        let lsn = LsnSequence::new(Some(7));
+        assert_eq!(lsn.visible(), 7);
+        assert_eq!(lsn.assign(1), 8);
+    }
+
+    #[photonio::test]
+    fn reads_skip_writes_in_progress() {
+        let path = std::env::temp_dir().join("test_reads_skip_writes_in_progress");
+        let _ = std::fs::remove_dir_all(&path);
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        // A fresh table has no visible writes.
+        let empty = table.read_txn();
+        assert_eq!(empty.lsn(), 0);
+        table.put(b"a", b"1").await.unwrap();
+        assert_eq!(empty.get(b"a").await.unwrap(), None);
+        drop(empty);
+
+        // The write is in the tree, but has not finished yet.
+        let write = table.begin_write(1);
+        table.raw.put(b"a", write.lsn, b"2").await.unwrap();
+        let txn = table.read_txn();
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(txn.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+
+        // A later write is not visible before the earlier one finishes.
+        let put = table.put(b"b", b"3");
+        futures::pin_mut!(put);
+        assert!(futures::poll!(&mut put).is_pending());
+        assert_eq!(table.get(b"b").await.unwrap(), None);
+        write.finish().await;
+        put.await.unwrap();
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"2".to_vec()));
+        assert_eq!(table.get(b"b").await.unwrap(), Some(b"3".to_vec()));
+
+        // The snapshot still observes the state before both writes.
+        assert_eq!(txn.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(txn.get(b"b").await.unwrap(), None);
+        drop(txn);
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn get_latest_ignores_visibility() {
+        let path = std::env::temp_dir().join("test_get_latest_ignores_visibility");
+        let _ = std::fs::remove_dir_all(&path);
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        for value in [b"1", b"2", b"3"] {
+            table.put(b"k", value).await.unwrap();
+        }
+        assert_eq!(table.get_latest(b"k").await.unwrap(), Some(b"3".to_vec()));
+        assert_eq!(table.get_latest(b"x").await.unwrap(), None);
+
+        // Writes in progress are read too.
+        let write = table.begin_write(1);
+        table.raw.put(b"k", write.lsn, b"4").await.unwrap();
+        assert_eq!(table.get(b"k").await.unwrap(), Some(b"3".to_vec()));
+        assert_eq!(table.get_latest(b"k").await.unwrap(), Some(b"4".to_vec()));
+        write.finish().await;
+
+        // Tombstones are respected.
+        table.delete(b"k").await.unwrap();
+        assert_eq!(table.get_latest(b"k").await.unwrap(), None);
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn version_of_returns_put_lsn() {
+        let path = std::env::temp_dir().join("test_version_of_returns_put_lsn");
+        let _ = std::fs::remove_dir_all(&path);
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        table.set_next_lsn(5).unwrap();
+        table.put(b"k", b"1").await.unwrap();
+        table.set_next_lsn(10).unwrap();
+        table.put(b"k", b"2").await.unwrap();
+        assert_eq!(table.version_of(b"k", 3).await.unwrap(), None);
+        assert_eq!(table.version_of(b"k", 7).await.unwrap(), Some(5));
+        assert_eq!(table.version_of(b"k", 10).await.unwrap(), Some(10));
+        assert_eq!(table.version_of(b"x", 10).await.unwrap(), None);
+
+        // A deleted key has no version after the delete.
+        table.delete(b"k").await.unwrap();
+        assert_eq!(table.version_of(b"k", 10).await.unwrap(), Some(10));
+        assert_eq!(table.version_of(b"k", 11).await.unwrap(), None);
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn ingest_disjoint_tables() {
+        let base = std::env::temp_dir().join("test_ingest_disjoint_tables");
+        let _ = std::fs::remove_dir_all(&base);
+        let table = Table::open(base.join("table"), Options::default())
+            .await
+            .unwrap();
+        let other = Table::open(base.join("other"), Options::default())
+            .await
+            .unwrap();
+        table.put(b"a", b"1").await.unwrap();
+        table.put(b"c", b"3").await.unwrap();
+        other.put(b"b", b"2").await.unwrap();
+        other.put(b"d", b"4").await.unwrap();
+        // The LSNs of the other table are placed after those of the table.
+        table.ingest_table(&other, 2).await.unwrap();
+        for (key, value) in [(b"a", b"1"), (b"b", b"2"), (b"c", b"3"), (b"d", b"4")] {
+            assert_eq!(table.get(key).await.unwrap(), Some(value.to_vec()));
+        }
+        // The other table is left as is.
+        assert_eq!(other.get(b"a").await.unwrap(), None);
+        drop(table);
+        drop(other);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn ingest_overlapping_tables() {
+        let base = std::env::temp_dir().join("test_ingest_overlapping_tables");
+        let _ = std::fs::remove_dir_all(&base);
+        let table = Table::open(base.join("table"), Options::default())
+            .await
+            .unwrap();
+        let other = Table::open(base.join("other"), Options::default())
+            .await
+            .unwrap();
+        table.set_next_lsn(10).unwrap();
+        table.put(b"k", b"table").await.unwrap();
+        other.put(b"k", b"other").await.unwrap();
+        assert_eq!(other.version_of(b"k", 1).await.unwrap(), Some(1));
+
+        // The ingested versions can not be placed at visible LSNs.
+        assert!(matches!(
+            table.ingest_table(&other, 0).await,
+            Err(Error::InvalidArgument)
+        ));
+        assert_eq!(table.get(b"k").await.unwrap(), Some(b"table".to_vec()));
+        // The ingested version is newer with a larger offset, but not for
+        // snapshots pinned before.
+        let txn = table.read_txn();
+        table.ingest_table(&other, 100).await.unwrap();
+        assert_eq!(table.get(b"k").await.unwrap(), Some(b"other".to_vec()));
+        assert_eq!(table.version_of(b"k", 101).await.unwrap(), Some(101));
+        assert_eq!(txn.get(b"k").await.unwrap(), Some(b"table".to_vec()));
+        drop(txn);
+        // Later writes are ordered after the ingested versions.
+        table.put(b"k", b"later").await.unwrap();
+        assert_eq!(table.get(b"k").await.unwrap(), Some(b"later".to_vec()));
+        drop(table);
+        drop(other);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn rewrite_with_smaller_pages() {
+        let base = std::env::temp_dir().join("test_rewrite_with_smaller_pages");
+        let _ = std::fs::remove_dir_all(&base);
+        let table = Table::open(base.join("table"), Options::default())
+            .await
+            .unwrap();
+        let entry = |i: u32| (format!("key{i:03}"), format!("value{i:03}"));
+        for i in 0..100 {
+            let (key, value) = entry(i);
+            table.put(key.as_bytes(), value.as_bytes()).await.unwrap();
+        }
+        table.delete(b"key050").await.unwrap();
+        table.consolidate_all().await.unwrap();
+
+        let options = Options {
+            page_size: 512,
+            ..Default::default()
+        };
+        let rewritten = table
+            .rewrite_with_options(base.join("rewritten"), options)
+            .await
+            .unwrap();
+        rewritten.consolidate_all().await.unwrap();
+        // The entries are laid out in more, smaller pages.
+        let pages = table.raw.live_page_ids().await.unwrap();
+        let rewritten_pages = rewritten.raw.live_page_ids().await.unwrap();
+        assert!(rewritten_pages.len() > pages.len());
+        for i in 0..100 {
+            let (key, _) = entry(i);
+            assert_eq!(
+                rewritten.get(key.as_bytes()).await.unwrap(),
+                table.get(key.as_bytes()).await.unwrap()
+            );
+        }
+        assert_eq!(rewritten.get(b"key050").await.unwrap(), None);
+        drop(table);
+        drop(rewritten);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn reopen_after_crash_mid_write() {
+        let path = std::env::temp_dir().join("test_reopen_after_crash_mid_write");
+        let _ = std::fs::remove_dir_all(&path);
+        {
+            let table = Table::open(&path, Options::default()).await.unwrap();
+            table.put(b"a", b"1").await.unwrap();
+            drop(table.quiesce().await);
+        }
+
+        // Returns the number of the last file with the prefix.
+        let last_file = |prefix: &str| {
+            std::fs::read_dir(&path)
+                .unwrap()
+                .filter_map(|entry| {
+                    let name = entry.unwrap().file_name().into_string().unwrap();
+                    name.strip_prefix(prefix)?.parse::<u32>().ok()
+                })
+                .max()
+                .unwrap()
+        };
+
+        // Simulate a crash while writing the next page file, before it is
+        // recorded in the manifest.
+        let last = last_file("db_");
+        let data = std::fs::read(path.join(format!("db_{last}"))).unwrap();
+        let partial = path.join(format!("db_{}", last + 1));
+        std::fs::write(&partial, &data[..data.len() / 2]).unwrap();
+        // The crash also leaves a garbage length at the end of the manifest.
+        let manifest = path.join(format!("MANIFEST_{}", last_file("MANIFEST_")));
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(manifest)
+            .unwrap();
+        std::io::Write::write_all(&mut file, &u64::MAX.to_le_bytes()).unwrap();
+        drop(file);
+
+        // The table opens at the prior state and discards the partial file.
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert!(!partial.exists());
+        table.put(b"b", b"2").await.unwrap();
+        drop(table.quiesce().await);
+        drop(table);
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(table.get(b"b").await.unwrap(), Some(b"2".to_vec()));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn compact_and_verify_reports_checksum_failures() {
+        let path = std::env::temp_dir().join("test_compact_and_verify_reports_checksum_failures");
+        let _ = std::fs::remove_dir_all(&path);
+        let value = b"the value to be damaged";
+        {
+            let table = Table::open(&path, Options::default()).await.unwrap();
+            table.put(b"a", value).await.unwrap();
+            drop(table.quiesce().await);
+            let report = table.raw.compact_and_verify().await.unwrap();
+            assert!(report.corrupted_pages.is_empty());
+        }
+
+        // Flip a bit of the value in the page file.
+        for entry in std::fs::read_dir(&path).unwrap() {
+            let file = entry.unwrap().path();
+            let mut data = std::fs::read(&file).unwrap();
+            if let Some(pos) = data.windows(value.len()).position(|w| w == value) {
+                data[pos] ^= 1;
+                std::fs::write(&file, data).unwrap();
+            }
+        }
+
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        let report = table.raw.compact_and_verify().await.unwrap();
+        assert_eq!(report.corrupted_pages.len(), 1);
+        assert_eq!(report.pages_compacted, 0);
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn export_and_import() {
+        let base = std::env::temp_dir().join("test_export_and_import");
+        let _ = std::fs::remove_dir_all(&base);
+        let snapshot = base.join("snapshot");
+        let table = Table::open(base.join("source"), Options::default())
+            .await
+            .unwrap();
+        table.put(b"a", b"1").await.unwrap();
+        table.put(b"b", b"1").await.unwrap();
+        table.put(b"a", b"2").await.unwrap();
+        table.put(b"c", b"1").await.unwrap();
+        table.delete(b"c").await.unwrap();
+        table.export(&snapshot).await.unwrap();
+        // Writes after the export are not included.
+        table.put(b"d", b"1").await.unwrap();
+
+        let imported = Table::import(&snapshot, base.join("imported"), Options::default())
+            .await
+            .unwrap();
+        let lsn = imported.read_txn().lsn();
+        assert_eq!(
+            lsn,
+            table.version_of(b"a", u64::MAX).await.unwrap().unwrap()
+        );
+        for key in [b"a", b"b", b"c"] {
+            assert_eq!(
+                imported.get(key).await.unwrap(),
+                table.get(key).await.unwrap()
+            );
+            assert_eq!(
+                imported.version_of(key, lsn).await.unwrap(),
+                table.version_of(key, lsn).await.unwrap()
+            );
+        }
+        assert_eq!(imported.get(b"d").await.unwrap(), None);
+        // New writes are ordered after the imported ones.
+        imported.put(b"b", b"2").await.unwrap();
+        assert!(imported.version_of(b"b", u64::MAX).await.unwrap() > Some(lsn));
+
+        // A damaged file is rejected before a table is created.
+        let mut bytes = std::fs::read(&snapshot).unwrap();
+        bytes[12] ^= 1;
+        std::fs::write(&snapshot, bytes).unwrap();
+        let result = Table::import(&snapshot, base.join("damaged"), Options::default()).await;
+        assert!(matches!(result, Err(Error::Corrupted)));
+        assert!(!base.join("damaged").exists());
+        drop(table);
+        drop(imported);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn mmap_reads_on_default_env() {
+        let path = std::env::temp_dir().join("test_mmap_reads_on_default_env");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            use_mmap_reads: true,
+            ..Default::default()
+        };
+        {
+            let table = Table::open(&path, options.clone()).await.unwrap();
+            table.put(b"a", b"1").await.unwrap();
+            drop(table.quiesce().await);
+        }
+        // Pages are read from the mapped page files after reopening.
+        let table = Table::open(&path, options).await.unwrap();
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        table.put(b"a", b"2").await.unwrap();
+        drop(table.quiesce().await);
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"2".to_vec()));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn owned_scans_outlive_table() {
+        let path = std::env::temp_dir().join("test_owned_scans_outlive_table");
+        let _ = std::fs::remove_dir_all(&path);
+        let mut scans = Vec::new();
+        {
+            let table = Table::open(&path, Options::default()).await.unwrap();
+            for key in [b"a", b"b", b"c"] {
+                table.put(key, b"1").await.unwrap();
+            }
+            scans.push(table.owned_scan(b""));
+            scans.push(table.owned_scan(b"b"));
+            // The scans keep their versions through later writes.
+            table
+                .set_option("gc_keep_versions_below_lsn", &u64::MAX.to_string())
+                .unwrap();
+            table.put(b"a", b"2").await.unwrap();
+            table.put(b"d", b"2").await.unwrap();
+            table.consolidate_all().await.unwrap();
+        }
+
+        let mut results = Vec::new();
+        for mut scan in scans {
+            let mut items = Vec::new();
+            while let Some((key, value)) = scan.next().await.unwrap() {
+                items.push((key, value));
+            }
+            results.push(items);
+        }
+        let item = |key: &[u8]| (key.to_vec(), b"1".to_vec());
+        assert_eq!(results[0], vec![item(b"a"), item(b"b"), item(b"c")]);
+        assert_eq!(results[1], vec![item(b"b"), item(b"c")]);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn scans_keep_their_snapshot() {
+        let path = std::env::temp_dir().join("test_scans_keep_their_snapshot");
+        let _ = std::fs::remove_dir_all(&path);
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        for key in [b"a", b"b", b"c"] {
+            table.put(key, b"1").await.unwrap();
+        }
+        let mut scan = table.scan(b"");
+        let mut range = table.scan_range(Bound::Excluded(b"a"), Bound::Unbounded);
+        let mut sizes = table.scan_key_sizes(b"");
+        let mut stream = table.get_stream([b"a".to_vec(), b"c".to_vec()]);
+        // Later writes are consolidated while the reads are open.
+        table
+            .set_option("gc_keep_versions_below_lsn", &u64::MAX.to_string())
+            .unwrap();
+        for key in [b"a", b"b", b"c"] {
+            table.put(key, b"22").await.unwrap();
+        }
+        table.consolidate_all().await.unwrap();
+
+        let item = |key: &[u8]| (key.to_vec(), b"1".to_vec());
+        let mut items = Vec::new();
+        while let Some(item) = scan.next().await.unwrap() {
+            items.push(item);
+        }
+        assert_eq!(items, vec![item(b"a"), item(b"b"), item(b"c")]);
+        let mut items = Vec::new();
+        while let Some(item) = range.next().await.unwrap() {
+            items.push(item);
+        }
+        assert_eq!(items, vec![item(b"b"), item(b"c")]);
+        while let Some((_, size)) = sizes.next().await.unwrap() {
+            assert_eq!(size, 1);
+        }
+        while let Some((key, value)) = stream.next().await.unwrap() {
+            assert_eq!(value, Some(b"1".to_vec()), "{key:?}");
+        }
+        drop((scan, range, sizes, stream));
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"22".to_vec()));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn read_at_older_versions() {
+        let path = std::env::temp_dir().join("test_read_at_older_versions");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            gc_keep_versions_below_lsn: 6,
+            ..Default::default()
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        table.set_next_lsn(5).unwrap();
+        table.put(b"k", b"v5").await.unwrap();
+        table.set_next_lsn(10).unwrap();
+        table.put(b"k", b"v10").await.unwrap();
+        table.consolidate_all().await.unwrap();
+
+        let scan_at = |lsn: u64| {
+            let table = &table;
+            async move {
+                let mut cursor = table.scan_at(b"", lsn)?;
+                let mut items = Vec::new();
+                while let Some((key, value)) = cursor.next().await? {
+                    items.push((key, value));
+                }
+                Ok::<_, Error>(items)
+            }
+        };
+        let item = |value: &[u8]| vec![(b"k".to_vec(), value.to_vec())];
+        assert_eq!(table.get_at(b"k", 7).await.unwrap(), Some(b"v5".to_vec()));
+        assert_eq!(table.get_at(b"k", 12).await.unwrap(), Some(b"v10".to_vec()));
+        assert_eq!(scan_at(7).await.unwrap(), item(b"v5"));
+        assert_eq!(scan_at(12).await.unwrap(), item(b"v10"));
+        // Versions below the watermark may have been dropped.
+        assert!(matches!(
+            table.get_at(b"k", 4).await,
+            Err(Error::VersionExpired)
+        ));
+        assert!(matches!(scan_at(4).await, Err(Error::VersionExpired)));
+
+        // An open cursor keeps its versions while the watermark is raised.
+        let mut cursor = table.scan_at(b"", 7).unwrap();
+        table
+            .set_option("gc_keep_versions_below_lsn", &u64::MAX.to_string())
+            .unwrap();
+        table.put(b"k", b"v11").await.unwrap();
+        table.consolidate_all().await.unwrap();
+        assert_eq!(
+            cursor.next().await.unwrap(),
+            Some((b"k".to_vec(), b"v5".to_vec()))
+        );
+        drop(cursor);
+        // The versions are dropped once the cursor is gone.
+        table.put(b"k", b"v12").await.unwrap();
+        table.consolidate_all().await.unwrap();
+        assert!(matches!(
+            table.get_at(b"k", 7).await,
+            Err(Error::VersionExpired)
+        ));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn transaction_snapshots_and_conflicts() {
+        let path = std::env::temp_dir().join("test_transaction_snapshots_and_conflicts");
+        let _ = std::fs::remove_dir_all(&path);
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        table.put(b"x", b"0").await.unwrap();
+        table.put(b"y", b"0").await.unwrap();
+
+        // Both transactions read `x`, and then write it.
+        let mut first = table.begin();
+        let mut second = table.begin();
+        assert_eq!(first.get(b"x").await.unwrap(), Some(b"0".to_vec()));
+        assert_eq!(second.get(b"x").await.unwrap(), Some(b"0".to_vec()));
+        first.put(b"x", b"1");
+        first.put(b"y", b"1");
+        second.put(b"x", b"2");
+        first.commit().await.unwrap();
+
+        // The second one still reads its snapshot, and fails to commit.
+        table.consolidate_all().await.unwrap();
+        assert_eq!(second.get(b"y").await.unwrap(), Some(b"0".to_vec()));
+        assert!(matches!(second.commit().await, Err(Error::Conflict)));
+        assert_eq!(table.get(b"x").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(table.get(b"y").await.unwrap(), Some(b"1".to_vec()));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn failed_commit_is_undone() {
+        let path = std::env::temp_dir().join("test_failed_commit_is_undone");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            stale_writes: StaleWrites::Reject,
+            ..Default::default()
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        table.put(b"a", b"0").await.unwrap();
+        // A newer version of `b` makes the commit fail after writing `a`.
+        table.raw.put(b"b", 100, b"0").await.unwrap();
+
+        let mut txn = table.begin();
+        txn.put(b"a", b"1");
+        txn.put(b"b", b"1");
+        assert!(matches!(txn.commit().await, Err(Error::InvalidArgument)));
+        let snapshot = table.read_txn();
+        assert_eq!(snapshot.get(b"a").await.unwrap(), Some(b"0".to_vec()));
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"0".to_vec()));
+        drop(snapshot);
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Returns true if any file in the directory contains the bytes.
+    fn dir_contains(path: &Path, bytes: &[u8]) -> bool {
+        std::fs::read_dir(path).unwrap().any(|entry| {
+            let data = std::fs::read(entry.unwrap().path()).unwrap();
+            data.windows(bytes.len()).any(|window| window == bytes)
+        })
+    }
+
+    #[photonio::test]
+    fn purge_removes_values_from_disk() {
+        let path = std::env::temp_dir().join("test_purge_removes_values_from_disk");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        let secret = b"the value of the purged key";
+        {
+            let table = Table::open(&path, options.clone()).await.unwrap();
+            // One copy is flushed and then consolidated away, another is only
+            // in memory when the key is purged.
+            table.put(b"a", secret).await.unwrap();
+            table.put(b"b", b"v").await.unwrap();
+            drop(table.quiesce().await);
+            assert!(dir_contains(&path, secret));
+            table.consolidate_all().await.unwrap();
+            table.put(b"a", secret).await.unwrap();
+            table.purge(b"a").await.unwrap();
+            assert!(!dir_contains(&path, secret));
+            assert_eq!(table.get(b"a").await.unwrap(), None);
+            assert_eq!(table.get(b"b").await.unwrap(), Some(b"v".to_vec()));
+        }
+
+        // The holes are skipped after reopening.
+        let table = Table::open(&path, options).await.unwrap();
+        assert_eq!(table.get(b"a").await.unwrap(), None);
+        assert_eq!(table.get(b"b").await.unwrap(), Some(b"v".to_vec()));
+        assert!(!dir_contains(&path, secret));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(feature = "debug")]
+    #[photonio::test]
+    fn purge_removes_values_from_split_pages() {
+        let path = std::env::temp_dir().join("test_purge_removes_values_from_split_pages");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        let secret = b"the value of the purged key";
+        let table = Table::open(&path, options).await.unwrap();
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.put(key, b"v").await.unwrap();
+        }
+        table.put(b"z", secret).await.unwrap();
+        drop(table.quiesce().await);
+        // The base page of the left page still holds the key after the split.
+        table.raw.force_split(b"a").await.unwrap();
+        drop(table.quiesce().await);
+        assert!(table.raw.live_page_ids().await.unwrap().len() > 2);
+        assert!(dir_contains(&path, secret));
+
+        table.purge(b"z").await.unwrap();
+        assert!(!dir_contains(&path, secret));
+        assert_eq!(table.get(b"z").await.unwrap(), None);
+        for key in [b"a", b"b", b"c", b"d"] {
+            assert_eq!(table.get(key).await.unwrap(), Some(b"v".to_vec()));
+        }
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn format_info_reports_checksum_types() {
+        let path = std::env::temp_dir().join("test_format_info_reports_checksum_types");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = |checksum_type| Options {
+            checksum_type,
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        {
+            let table = Table::open(&path, options(ChecksumType::XxHash64))
+                .await
+                .unwrap();
+            // Nothing is persisted yet.
+            assert!(table.format_info().checksum_types.is_empty());
+            table.put(b"a", b"1").await.unwrap();
+            drop(table.quiesce().await);
+            let info = table.format_info();
+            assert_eq!(info.comparator_name, "bytewise");
+            assert_eq!(info.checksum_types, vec![ChecksumType::XxHash64]);
+            assert_eq!(info.format_versions, vec![1]);
+        }
+
+        // Files written before reopening keep their checksum type.
+        let table = Table::open(&path, options(ChecksumType::Crc32c))
+            .await
+            .unwrap();
+        assert_eq!(
+            table.format_info().checksum_types,
+            vec![ChecksumType::XxHash64]
+        );
+        table.put(b"b", b"2").await.unwrap();
+        drop(table.quiesce().await);
+        assert_eq!(
+            table.format_info().checksum_types,
+            vec![ChecksumType::XxHash64, ChecksumType::Crc32c]
+        );
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn quiesce_freezes_files() {
+        use std::time::Duration;
+
+        use crate::util::sync::unblock;
+
+        let path = std::env::temp_dir().join("test_quiesce_freezes_files");
+        let _ = std::fs::remove_dir_all(&path);
+        let list_files = || {
+            let mut names = std::fs::read_dir(&path)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let sleep = || unblock(|| std::thread::sleep(Duration::from_millis(10)));
+        let table = Table::open(&path, Options::default()).await.unwrap();
+        table.put(b"a", b"1").await.unwrap();
+
+        let guard = table.quiesce().await;
+        let files = list_files();
+        table.put(b"b", b"2").await.unwrap();
+        table.consolidate_all().await.unwrap();
+        // The background flush waits for the guard.
+        table.raw.tree.store().seal_active_buffer();
+        sleep().await;
+        assert_eq!(list_files(), files);
+        assert_eq!(table.get(b"a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(table.get(b"b").await.unwrap(), Some(b"2".to_vec()));
+        assert_eq!(list_files(), files);
+
+        // The flush goes on once the guard is dropped.
+        drop(guard);
+        let mut waits = 0;
+        while list_files() == files {
+            waits += 1;
+            assert!(waits < 1000, "the buffer is never flushed");
+            sleep().await;
+        }
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    async fn get_raw(table: &RawTable<Photon>, key: &[u8], lsn: u64) -> Option<Vec<u8>> {
+        table
+            .get(key, lsn, |value| value.map(|value| value.to_vec()))
+            .await
+            .unwrap()
+    }
+
+    #[photonio::test]
+    fn unmanaged_lsn_orders_by_insertion() {
+        let path = std::env::temp_dir().join("test_unmanaged_lsn_orders_by_insertion");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            managed_lsn: false,
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        {
+            let table = RawTable::open(Photon, &path, options.clone())
+                .await
+                .unwrap();
+            // Back-to-back writes with the same caller LSN.
+            table.put(b"k", 7, b"a").await.unwrap();
+            table.put(b"k", 7, b"b").await.unwrap();
+            assert_eq!(get_raw(&table, b"k", 0).await, Some(b"b".to_vec()));
+            drop(table.quiesce().await);
+        }
+
+        // Writes after reopening are ordered after the persisted ones.
+        let table = RawTable::open(Photon, &path, options).await.unwrap();
+        assert_eq!(get_raw(&table, b"k", 0).await, Some(b"b".to_vec()));
+        table.put(b"k", 0, b"c").await.unwrap();
+        assert_eq!(get_raw(&table, b"k", 0).await, Some(b"c".to_vec()));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn monotonic_lsn_after_reopen() {
+        let path = std::env::temp_dir().join("test_monotonic_lsn_after_reopen");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            require_monotonic_lsn: true,
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        {
+            let table = RawTable::open(Photon, &path, options.clone())
+                .await
+                .unwrap();
+            table.put(b"a", 10, b"1").await.unwrap();
+            table.put(b"b", 20, b"2").await.unwrap();
+            drop(table.quiesce().await);
+        }
+
+        // The LSNs of persisted writes are not accepted again.
+        let table = RawTable::open(Photon, &path, options).await.unwrap();
+        for lsn in [5, 10, 20] {
+            assert!(matches!(
+                table.put(b"c", lsn, b"3").await,
+                Err(Error::InvalidArgument)
+            ));
+        }
+        table.put(b"c", 21, b"3").await.unwrap();
+        assert_eq!(get_raw(&table, b"c", 21).await, Some(b"3".to_vec()));
+        drop(table);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
 }
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::util::{checksum::xxhash64, sync::lock};
+
+/// A bloom filter over the keys of a page.
+pub(super) struct BloomFilter {
+    bits: Vec<u64>,
+    num_probes: u32,
+}
+
+impl BloomFilter {
+    /// Builds a filter over the keys with about `bits_per_key` bits per key.
+    pub(super) fn build(keys: &[&[u8]], bits_per_key: usize) -> Self {
+        let num_bits = (keys.len() * bits_per_key).max(64);
+        // The false positive rate is the lowest with ln(2) probes per bit.
+        let num_probes = (bits_per_key as f64 * 0.69).round().clamp(1.0, 30.0) as u32;
+        let mut filter = Self {
+            bits: vec![0; (num_bits + 63) / 64],
+            num_probes,
+        };
+        for key in keys {
+            for bit in filter.bit_positions(key) {
+                filter.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        filter
+    }
+
+    /// Returns false if the key is definitely not in the filter.
+    pub(super) fn may_contain(&self, key: &[u8]) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the bits of the key, derived from a single hash with double
+    /// hashing.
+    fn bit_positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = xxhash64(key, 0);
+        let delta = (hash >> 32) | 1;
+        let num_bits = self.bits.len() as u64 * 64;
+        (0..self.num_probes as u64)
+            .map(move |i| (hash.wrapping_add(i.wrapping_mul(delta)) % num_bits) as usize)
+    }
+}
+
+/// The bloom filters of leaf pages loaded from page files, kept in memory
+/// apart from the pages.
+///
+/// A filter is keyed by the page id, and only applies while the page has the
+/// address and epoch the filter was built from. Any change to the page
+/// installs a new address, so a stale filter is never used.
+#[derive(Default)]
+pub(super) struct LeafBlooms {
+    filters: Mutex<HashMap<u64, LeafBloom>>,
+}
+
+struct LeafBloom {
+    addr: u64,
+    epoch: u64,
+    filter: BloomFilter,
+}
+
+impl LeafBlooms {
+    /// Returns true if the leaf page at the address definitely does not
+    /// contain the key.
+    pub(super) fn excludes(&self, id: u64, addr: u64, epoch: u64, key: &[u8]) -> bool {
+        let filters = lock(&self.filters);
+        filters.get(&id).map_or(false, |bloom| {
+            bloom.addr == addr && bloom.epoch == epoch && !bloom.filter.may_contain(key)
+        })
+    }
+
+    /// Returns true if there is a filter for the leaf page at the address.
+    pub(super) fn contains(&self, id: u64, addr: u64) -> bool {
+        let filters = lock(&self.filters);
+        filters.get(&id).map_or(false, |bloom| bloom.addr == addr)
+    }
+
+    /// Installs the filter of the leaf page at the address, which replaces
+    /// the filter of an older address.
+    pub(super) fn insert(&self, id: u64, addr: u64, epoch: u64, filter: BloomFilter) {
+        let bloom = LeafBloom {
+            addr,
+            epoch,
+            filter,
+        };
+        lock(&self.filters).insert(id, bloom);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter() {
+        let keys = (0..1000u32)
+            .map(|i| format!("key{i}").into_bytes())
+            .collect::<Vec<_>>();
+        let refs = keys.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let filter = BloomFilter::build(&refs, 10);
+        assert!(refs.iter().all(|key| filter.may_contain(key)));
+        let false_positives = (1000..11000u32)
+            .filter(|i| filter.may_contain(format!("key{i}").as_bytes()))
+            .count();
+        // About 1% with 10 bits per key.
+        assert!(false_positives < 300, "{false_positives} false positives");
+
+        let empty = BloomFilter::build(&[], 10);
+        assert!(!empty.may_contain(b"key"));
+    }
+
+    #[test]
+    fn stale_leaf_blooms() {
+        let blooms = LeafBlooms::default();
+        blooms.insert(1, 100, 1, BloomFilter::build(&[b"a"], 10));
+        assert!(blooms.contains(1, 100));
+        assert!(!blooms.excludes(1, 100, 1, b"a"));
+        assert!(blooms.excludes(1, 100, 1, b"b"));
+        // Not for another address, epoch or page.
+        assert!(!blooms.excludes(1, 200, 1, b"b"));
+        assert!(!blooms.excludes(1, 100, 2, b"b"));
+        assert!(!blooms.excludes(2, 100, 1, b"b"));
+        blooms.insert(1, 200, 1, BloomFilter::build(&[b"b"], 10));
+        assert!(!blooms.contains(1, 100));
+        assert!(blooms.excludes(1, 200, 1, b"a"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::VecDeque,
+    ops::{Bound, Deref},
+    sync::Arc,
+};
+
+use super::Tree;
+use crate::{env::Env, Result};
+
+/// A cursor over the key-value pairs of a tree in key order.
+///
+/// The cursor buffers one leaf page at a time. Before it moves on to the next
+/// leaf page, it checks whether the epoch of the current leaf page has changed.
+/// If so, the page range may have changed by a structure modification, and the
+/// cursor seeks again from the last returned key instead of the stale range
+/// end. This ensures that keys are neither missed nor returned twice.
+///
+/// The cursor yields each key with `T` derived from its value, which is a copy
+/// of the value by default.
+pub struct Cursor<'a, E: Env, T = Vec<u8>> {
+    tree: TreeRef<'a, E>,
+    lsn: u64,
+    value_fn: fn(&[u8]) -> T,
+    start: Vec<u8>,
+    last: Option<Vec<u8>>,
+    leaf: Option<LeafPosition>,
+    buffer: VecDeque<(Vec<u8>, T)>,
+    skip: Option<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    done: bool,
+    /// Whether the cursor unpins its LSN when dropped.
+    pinned: bool,
+}
+
+/// An owned cursor that holds a reference to the tree.
+///
+/// It does not borrow the table, so it can be stored in structs or returned
+/// from functions.
+pub type OwnedScan<E> = Cursor<'static, E>;
+
+/// A cursor over the keys and the lengths of their values.
+///
+/// The values are not copied, which makes it cheap to find large values or
+/// build histograms of value sizes.
+pub type KeySizes<'a, E> = Cursor<'a, E, usize>;
+
+enum TreeRef<'a, E: Env> {
+    Borrowed(&'a Tree<E>),
+    Owned(Arc<Tree<E>>),
+}
+
+impl<'a, E: Env> Deref for TreeRef<'a, E> {
+    type Target = Tree<E>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Borrowed(tree) => tree,
+            Self::Owned(tree) => tree,
+        }
+    }
+}
+
+/// The position of a scanned leaf page.
+pub(super) struct LeafPosition {
+    pub(super) id: u64,
+    pub(super) epoch: u64,
+    pub(super) end: Option<Vec<u8>>,
+}
+
+impl<E: Env + 'static> OwnedScan<E> {
+    pub(super) fn owned(tree: Arc<Tree<E>>, start: &[u8], lsn: u64) -> Self {
+        Self::with_tree(TreeRef::Owned(tree), start, lsn, <[u8]>::to_vec)
+    }
+}
+
+impl<'a, E: Env> Cursor<'a, E> {
+    pub(super) fn new(tree: &'a Tree<E>, start: &[u8], lsn: u64) -> Self {
+        Self::with_tree(TreeRef::Borrowed(tree), start, lsn, <[u8]>::to_vec)
+    }
+}
+
+impl<'a, E: Env> KeySizes<'a, E> {
+    pub(super) fn key_sizes(tree: &'a Tree<E>, start: &[u8], lsn: u64) -> Self {
+        Self::with_tree(TreeRef::Borrowed(tree), start, lsn, <[u8]>::len)
+    }
+}
+
+impl<'a, E: Env, T> Cursor<'a, E, T> {
+    fn with_tree(tree: TreeRef<'a, E>, start: &[u8], lsn: u64, value_fn: fn(&[u8]) -> T) -> Self {
+        Self {
+            tree,
+            lsn,
+            value_fn,
+            start: start.to_vec(),
+            last: None,
+            leaf: None,
+            buffer: VecDeque::new(),
+            skip: None,
+            end: Bound::Unbounded,
+            done: false,
+            pinned: false,
+        }
+    }
+
+    /// Makes the cursor unpin its LSN when dropped, which the caller has
+    /// pinned.
+    pub(crate) fn with_pin(mut self) -> Self {
+        self.pinned = true;
+        self
+    }
+
+    /// Restricts the cursor to the keys between the bounds.
+    ///
+    /// The cursor must start from the start bound. An inverted range, or an
+    /// empty range such as `[a, a)`, yields nothing.
+    pub(super) fn with_bounds(mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Self {
+        if let Bound::Excluded(start) = start {
+            self.skip = Some(start.to_vec());
+        }
+        self.end = match end {
+            Bound::Included(end) => Bound::Included(end.to_vec()),
+            Bound::Excluded(end) => Bound::Excluded(end.to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.done = is_empty_range(start, end);
+        self
+    }
+
+    /// Returns the next key-value pair, or `None` if the cursor is exhausted.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, T)>> {
+        if self.done {
+            return Ok(None);
+        }
+        loop {
+            if let Some((key, value)) = self.buffer.pop_front() {
+                if !is_within_end(&key, &self.end) {
+                    self.done = true;
+                    self.buffer.clear();
+                    return Ok(None);
+                }
+                self.last = Some(key.clone());
+                if self.skip.as_ref() == Some(&key) {
+                    self.skip = None;
+                    continue;
+                }
+                return Ok(Some((key, value)));
+            }
+            if !self.fill().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Fills the buffer with the next leaf page.
+    ///
+    /// Returns false if there are no more leaf pages.
+    async fn fill(&mut self) -> Result<bool> {
+        let mut seek_last = false;
+        if let Some(leaf) = &self.leaf {
+            if self.tree.leaf_epoch(leaf.id).await? == Some(leaf.epoch) {
+                // The range of the leaf page is unchanged, continue from its end.
+                match &leaf.end {
+                    Some(end) => self.start = end.clone(),
+                    None => return Ok(false),
+                }
+            } else {
+                // The leaf page has been split or merged, seek again from the last key.
+                if let Some(last) = &self.last {
+                    self.start = last.clone();
+                    seek_last = true;
+                }
+            }
+        }
+        let after = if seek_last {
+            self.last.as_deref()
+        } else {
+            None
+        };
+        let mut items = Vec::new();
+        let leaf = self
+            .tree
+            .scan_leaf(&self.start, after, self.lsn, self.value_fn, &mut items)
+            .await?;
+        self.buffer.extend(items);
+        // Stop if the last leaf page has nothing left.
+        let done = self.buffer.is_empty() && leaf.end.is_none();
+        self.leaf = Some(leaf);
+        Ok(!done)
+    }
+}
+
+impl<'a, E: Env, T> Drop for Cursor<'a, E, T> {
+    fn drop(&mut self) {
+        if self.pinned {
+            self.tree.unpin_snapshot(self.lsn);
+        }
+    }
+}
+
+/// Returns true if no key is between the bounds.
+fn is_empty_range(start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end))
+        | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
+    }
+}
+
+/// Returns true if the key is not after the end bound.
+fn is_within_end(key: &[u8], end: &Bound<Vec<u8>>) -> bool {
+    match end {
+        Bound::Included(end) => key <= end.as_slice(),
+        Bound::Excluded(end) => key < end.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degenerate_ranges() {
+        use Bound::*;
+        let (a, b): (&[u8], &[u8]) = (b"a", b"b");
+
+        // Inverted ranges are empty with any bounds.
+        for start in [Included(b), Excluded(b)] {
+            for end in [Included(a), Excluded(a)] {
+                assert!(is_empty_range(start, end));
+            }
+        }
+        // A single-point range is empty unless both bounds are inclusive.
+        assert!(!is_empty_range(Included(a), Included(a)));
+        assert!(is_empty_range(Included(a), Excluded(a)));
+        assert!(is_empty_range(Excluded(a), Included(a)));
+        assert!(is_empty_range(Excluded(a), Excluded(a)));
+        // Unbounded ranges are never empty.
+        assert!(!is_empty_range(Unbounded, Excluded(b"")));
+        assert!(!is_empty_range(Included(b), Unbounded));
+        assert!(!is_empty_range(Unbounded, Unbounded));
+        assert!(!is_empty_range(Excluded(a), Excluded(b)));
+    }
+
+    #[test]
+    fn range_end() {
+        let end = b"b".to_vec();
+        assert!(is_within_end(b"b", &Bound::Included(end.clone())));
+        assert!(!is_within_end(b"b\0", &Bound::Included(end.clone())));
+        assert!(!is_within_end(b"b", &Bound::Excluded(end.clone())));
+        assert!(is_within_end(b"a\xff", &Bound::Excluded(end)));
+        assert!(is_within_end(b"\xff", &Bound::Unbounded));
+    }
+}
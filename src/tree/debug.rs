@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+
+use super::{page::*, tree_txn::split_delta_from_page, Tree, TreeTxn};
+use crate::{
+    env::Env,
+    page::*,
+    page_store::{Error, Result, MIN_ID},
+};
+
+/// A decoded view of a page for debugging.
+#[derive(Clone, Debug)]
+pub struct PageDump {
+    /// The id of the page.
+    pub id: u64,
+    /// The address of the head of the page chain.
+    pub addr: u64,
+    /// Whether the page is a leaf page.
+    pub is_leaf: bool,
+    /// Whether the head of the page chain is a split delta.
+    pub is_split: bool,
+    /// The epoch of the page.
+    pub epoch: u64,
+    /// The length of the page chain.
+    pub chain_len: u8,
+    /// The keys in the page chain, excluding overwritten versions.
+    ///
+    /// Inner pages contain the start keys of their children.
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl PageDump {
+    /// Returns the number of keys in the page.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the page has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the smallest and largest keys in the page.
+    pub fn key_range(&self) -> Option<(&[u8], &[u8])> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        Some((first, last))
+    }
+}
+
+/// An entry of the page table for debugging.
+#[derive(Clone, Debug)]
+pub struct MappingEntry {
+    /// The id of the page.
+    pub id: u64,
+    /// The address of the head of the page chain.
+    pub addr: u64,
+    /// Whether the page is a leaf page.
+    pub is_leaf: bool,
+    /// Whether the head of the page chain is a split delta.
+    pub is_split: bool,
+    /// The epoch of the page.
+    pub epoch: u64,
+    /// The length of the page chain.
+    pub chain_len: u8,
+    /// Whether the head of the page chain is in a write buffer rather than a
+    /// page file.
+    pub in_memory: bool,
+}
+
+/// A dump of the page table entries of the pages reachable from the root.
+#[derive(Clone, Debug, Default)]
+pub struct MappingDump {
+    /// The entries ordered by page id.
+    pub entries: Vec<MappingEntry>,
+    /// The ids referenced by index pages or split deltas but not mapped to any
+    /// address, ordered by page id.
+    pub dangling_ids: Vec<u64>,
+}
+
+impl MappingDump {
+    /// Returns the ids of the mapped pages.
+    pub fn ids(&self) -> Vec<u64> {
+        self.entries.iter().map(|entry| entry.id).collect()
+    }
+
+    /// Returns true if every referenced page is mapped.
+    pub fn is_consistent(&self) -> bool {
+        self.dangling_ids.is_empty()
+    }
+}
+
+impl<E: Env> Tree<E> {
+    /// Dumps the page table entries of the pages reachable from the root.
+    pub(crate) async fn export_mapping(&self) -> Result<MappingDump> {
+        loop {
+            let txn = self.begin();
+            match txn.export_mapping().await {
+                Ok(dump) => return Ok(dump),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads and decodes the page with the id.
+    pub(crate) async fn dump_page(&self, id: u64) -> Result<PageDump> {
+        let txn = self.begin();
+        txn.dump_page(id).await
+    }
+
+    /// Splits the leaf page that contains the key immediately, regardless of
+    /// its size.
+    ///
+    /// This does nothing if the page has too few keys to split.
+    pub(crate) async fn force_split(&self, key: &[u8]) -> Result<()> {
+        loop {
+            let txn = self.begin();
+            match txn.force_split(key).await {
+                Ok(_) => return Ok(()),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<'a, E: Env> TreeTxn<'a, E> {
+    async fn dump_page(&self, id: u64) -> Result<PageDump> {
+        let view = self.page_view(id, Range::full()).await?;
+        let keys = match view.page.tier() {
+            PageTier::Leaf => {
+                let iter = self.iter_page::<Key, Value>(&view).await?;
+                MergingLeafPageIter::new(iter, u64::MAX, false)
+                    .map(|(k, _)| k.raw.to_vec())
+                    .collect()
+            }
+            PageTier::Inner => {
+                let iter = self.iter_page::<&[u8], Index>(&view).await?;
+                MergingInnerPageIter::new(iter)
+                    .map(|(k, _)| k.to_vec())
+                    .collect()
+            }
+        };
+        Ok(PageDump {
+            id,
+            addr: view.addr,
+            is_leaf: view.page.tier().is_leaf(),
+            is_split: view.page.kind().is_split(),
+            epoch: view.page.epoch(),
+            chain_len: view.page.chain_len(),
+            keys,
+        })
+    }
+
+    async fn export_mapping(&self) -> Result<MappingDump> {
+        let mut dump = MappingDump::default();
+        let mut visited = HashSet::new();
+        let mut pending = vec![MIN_ID];
+        while let Some(id) = pending.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let Some(addr) = self.guard.try_page_addr(id) else {
+                dump.dangling_ids.push(id);
+                continue;
+            };
+            let page = self.guard.read_page(addr).await?;
+            self.walk_page(page, |page| {
+                if page.kind().is_split() {
+                    let (_, index) = split_delta_from_page(page);
+                    pending.push(index.id);
+                }
+                false
+            })
+            .await?;
+            if page.tier().is_inner() {
+                let view = PageView {
+                    id,
+                    addr,
+                    page,
+                    range: Range::full(),
+                };
+                let iter = self.iter_page::<&[u8], Index>(&view).await?;
+                for (_, index) in MergingInnerPageIter::new(iter) {
+                    pending.push(index.id);
+                }
+            }
+            dump.entries.push(MappingEntry {
+                id,
+                addr,
+                is_leaf: page.tier().is_leaf(),
+                is_split: page.kind().is_split(),
+                epoch: page.epoch(),
+                chain_len: page.chain_len(),
+                in_memory: self.guard.is_in_memory(addr),
+            });
+        }
+        dump.entries.sort_unstable_by_key(|entry| entry.id);
+        dump.dangling_ids.sort_unstable();
+        Ok(dump)
+    }
+
+    async fn force_split(&self, key: &[u8]) -> Result<()> {
+        let key = Key::new(key, u64::MAX);
+        let (view, parent) = self.find_leaf(&key).await?;
+        if view.page.kind().is_split() {
+            // Finish the pending split before starting a new one.
+            let _ = self.reconcile_page(view, parent).await;
+            return Err(Error::Again);
+        }
+        if view.page.chain_next() != 0 {
+            // Only base pages can be split.
+            self.consolidate_page(view, parent).await?;
+            return Err(Error::Again);
+        }
+        self.split_page(view, parent).await
+    }
+}
@@ -0,0 +1,83 @@
+use crate::page::Range;
+
+/// A hint to speed up sequential accesses to a table.
+///
+/// The hint caches the last leaf page visited by a read, so that the next
+/// read with a key in the same leaf page can skip the traversal from the root.
+/// A stale hint is detected by the page epoch and falls back to a normal
+/// traversal.
+#[derive(Clone, Debug, Default)]
+pub struct AccessHint {
+    leaf: Option<LeafHint>,
+}
+
+#[derive(Clone, Debug)]
+struct LeafHint {
+    id: u64,
+    epoch: u64,
+    start: Vec<u8>,
+    end: Option<Vec<u8>>,
+}
+
+impl AccessHint {
+    /// Creates an empty hint.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id and epoch of the cached leaf page if it may contain the
+    /// key.
+    pub(super) fn lookup(&self, key: &[u8]) -> Option<(u64, u64)> {
+        let leaf = self.leaf.as_ref()?;
+        let range = Range {
+            start: &leaf.start,
+            end: leaf.end.as_deref(),
+        };
+        if !range.contains(key) {
+            return None;
+        }
+        Some((leaf.id, leaf.epoch))
+    }
+
+    /// Caches the leaf page with the given id, epoch, and range.
+    pub(super) fn update(&mut self, id: u64, epoch: u64, range: Range<'_>) {
+        self.leaf = Some(LeafHint {
+            id,
+            epoch,
+            start: range.start.to_vec(),
+            end: range.end.map(|end| end.to_vec()),
+        });
+    }
+
+    /// Clears the cached leaf page.
+    pub(super) fn clear(&mut self) {
+        self.leaf = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_hint() {
+        let mut hint = AccessHint::new();
+        assert_eq!(hint.lookup(b"a"), None);
+        hint.update(
+            2,
+            1,
+            Range {
+                start: b"b",
+                end: Some(b"d"),
+            },
+        );
+        assert_eq!(hint.lookup(b"a"), None);
+        assert_eq!(hint.lookup(b"b"), Some((2, 1)));
+        assert_eq!(hint.lookup(b"c"), Some((2, 1)));
+        assert_eq!(hint.lookup(b"d"), None);
+        hint.update(3, 0, Range::full());
+        assert_eq!(hint.lookup(b"z"), Some((3, 0)));
+        hint.clear();
+        assert_eq!(hint.lookup(b"z"), None);
+    }
+}
@@ -1,10 +1,34 @@
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    ops::Bound,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 mod page;
 
+mod bloom;
+use bloom::LeafBlooms;
+
+mod hint;
+pub use hint::AccessHint;
+
+mod cursor;
+pub use cursor::{Cursor, KeySizes, OwnedScan};
+
+#[cfg(feature = "debug")]
+mod debug;
+#[cfg(feature = "debug")]
+pub use debug::{MappingDump, MappingEntry, PageDump};
+
+mod snapshot;
+use snapshot::Snapshots;
+
 mod stats;
-use stats::AtomicStats;
-pub(crate) use stats::Stats;
+pub use stats::VerifyReport;
+use stats::{AtomicStats, PressureTracker};
+pub(crate) use stats::{Pressure, Stats};
 
 mod tree_txn;
 use tree_txn::TreeTxn;
@@ -13,17 +37,29 @@ mod rewrite;
 pub(crate) use rewrite::PageRewriter;
 
 use crate::{
-    env::Env,
-    page::{Key, Value},
-    page_store::{Error, PageStore, Result},
-    Options,
+    env::{Env, SpawnOptions},
+    options::RuntimeOptions,
+    page::{Key, PageRef, Range, Value},
+    page_store::{Error, PageStore, Result, Version, MIN_ID},
+    util::sync::{lock, Semaphore},
+    Options, Scheduling,
 };
 
 /// A latch-free, log-structured tree.
 pub(crate) struct Tree<E: Env> {
     options: Options,
+    runtime_options: RuntimeOptions,
     stats: AtomicStats,
     store: PageStore<E>,
+    /// The pages being consolidated in the background, or waiting to be
+    /// consolidated by [`Tree::consolidate_pending`] if jobs run inline.
+    consolidating: Mutex<HashSet<u64>>,
+    snapshots: Snapshots,
+    /// Bounds the number of splits and consolidations in progress.
+    smo_permits: Semaphore,
+    leaf_blooms: LeafBlooms,
+    /// The pages that should be split or consolidated.
+    pressure: PressureTracker,
 }
 
 impl<E: Env> Tree<E> {
@@ -31,11 +67,25 @@ impl<E: Env> Tree<E> {
     pub(crate) async fn open<P: AsRef<Path>>(env: E, path: P, options: Options) -> Result<Self> {
         let stats = AtomicStats::default();
         let store = PageStore::open(env, path, options.clone()).await?;
-        Ok(Self {
+        let runtime_options = RuntimeOptions::new(&options);
+        let smo_permits = Semaphore::new(options.max_concurrent_smos);
+        let tree = Self {
             options,
+            runtime_options,
             stats,
             store,
-        })
+            consolidating: Mutex::default(),
+            snapshots: Snapshots::default(),
+            smo_permits,
+            leaf_blooms: LeafBlooms::default(),
+            pressure: PressureTracker::default(),
+        };
+        let txn = tree.begin();
+        if txn.guard.try_page_addr(MIN_ID).is_none() {
+            txn.init_root(&tree.options.initial_split_keys)?;
+        }
+        drop(txn);
+        Ok(tree)
     }
 
     fn begin(&self) -> TreeTxn<E> {
@@ -43,19 +93,76 @@ impl<E: Env> Tree<E> {
     }
 
     /// Gets the value corresponding to the key and applies the function to it.
-    pub(crate) async fn get<F, R>(&self, key: Key<'_>, f: F) -> Result<R>
+    pub(crate) async fn get<F, R>(self: &Arc<Self>, key: Key<'_>, f: F) -> Result<R>
     where
+        E: 'static,
         F: FnOnce(Option<&[u8]>) -> R,
     {
+        let mut restarts = 0;
         loop {
             let txn = self.begin();
             match txn.get(key).await {
+                Ok((value, hot_leaf)) => {
+                    self.stats.success.get.inc();
+                    let result = f(value);
+                    if let Some(id) = hot_leaf {
+                        self.consolidate_in_background(id);
+                    }
+                    return Ok(result);
+                }
+                Err(Error::Again) => {
+                    self.stats.restart.get.inc();
+                    self.on_restart(&mut restarts);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Gets the LSN of the newest version of the key visible at the key LSN.
+    pub(crate) async fn version_of(&self, key: Key<'_>) -> Result<Option<u64>> {
+        let mut restarts = 0;
+        loop {
+            let txn = self.begin();
+            match txn.version_of(key).await {
+                Ok(lsn) => {
+                    self.stats.success.get.inc();
+                    return Ok(lsn);
+                }
+                Err(Error::Again) => {
+                    self.stats.restart.get.inc();
+                    self.on_restart(&mut restarts);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Gets the value corresponding to the key with an access hint.
+    ///
+    /// The hint is updated with the leaf page that contains the key.
+    pub(crate) async fn get_with_hint<F, R>(
+        &self,
+        key: Key<'_>,
+        hint: &mut AccessHint,
+        f: F,
+    ) -> Result<R>
+    where
+        F: FnOnce(Option<&[u8]>) -> R,
+    {
+        let mut restarts = 0;
+        loop {
+            let txn = self.begin();
+            match txn.get_with_hint(key, hint).await {
                 Ok(value) => {
                     self.stats.success.get.inc();
                     return Ok(f(value));
                 }
                 Err(Error::Again) => {
                     self.stats.restart.get.inc();
+                    self.on_restart(&mut restarts);
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -63,8 +170,104 @@ impl<E: Env> Tree<E> {
         }
     }
 
+    /// Creates a cursor over the key-value pairs at or after `start`.
+    pub(crate) fn scan(&self, start: &[u8], lsn: u64) -> Cursor<'_, E> {
+        Cursor::new(self, start, lsn)
+    }
+
+    /// Creates a cursor like [`Tree::scan`] that keeps the versions visible
+    /// at `lsn` until it is dropped.
+    ///
+    /// Returns `None` if `lsn` can not be pinned, see [`Tree::pin_snapshot`].
+    pub(crate) fn scan_snapshot(&self, start: &[u8], lsn: u64) -> Option<Cursor<'_, E>> {
+        self.pin_snapshot(lsn)
+            .then(|| Cursor::new(self, start, lsn).with_pin())
+    }
+
+    /// Creates a cursor over the key-value pairs between the bounds.
+    pub(crate) fn scan_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        lsn: u64,
+    ) -> Cursor<'_, E> {
+        let first = match start {
+            Bound::Included(start) | Bound::Excluded(start) => start,
+            Bound::Unbounded => &[],
+        };
+        Cursor::new(self, first, lsn).with_bounds(start, end)
+    }
+
+    /// Creates a cursor over the keys at or after `start` and the lengths of
+    /// their values.
+    pub(crate) fn scan_key_sizes(&self, start: &[u8], lsn: u64) -> KeySizes<'_, E> {
+        KeySizes::key_sizes(self, start, lsn)
+    }
+
+    /// Creates an owned cursor over the key-value pairs at or after `start`.
+    pub(crate) fn owned_scan(self: &Arc<Self>, start: &[u8], lsn: u64) -> OwnedScan<E>
+    where
+        E: 'static,
+    {
+        OwnedScan::owned(self.clone(), start, lsn)
+    }
+
+    /// Creates an owned cursor at the published LSN, which keeps the versions
+    /// visible at the LSN until it is dropped.
+    ///
+    /// Returns `None` if no LSN has been published.
+    pub(crate) fn owned_scan_visible(self: &Arc<Self>, start: &[u8]) -> Option<OwnedScan<E>>
+    where
+        E: 'static,
+    {
+        let lsn = self.pin_visible_snapshot()?;
+        Some(OwnedScan::owned(self.clone(), start, lsn).with_pin())
+    }
+
+    /// Collects the visible keys from the leaf page that contains `start`,
+    /// with the values mapped by `f`.
+    async fn scan_leaf<T>(
+        &self,
+        start: &[u8],
+        after: Option<&[u8]>,
+        lsn: u64,
+        f: fn(&[u8]) -> T,
+        out: &mut Vec<(Vec<u8>, T)>,
+    ) -> Result<cursor::LeafPosition> {
+        let mut restarts = 0;
+        loop {
+            let txn = self.begin();
+            match txn.scan_leaf(start, after, lsn, f, out).await {
+                Ok(position) => {
+                    self.stats.success.scan.inc();
+                    return Ok(position);
+                }
+                Err(Error::Again) => {
+                    self.stats.restart.scan.inc();
+                    self.on_restart(&mut restarts);
+                    out.clear();
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the epoch of the page if it is still a leaf page.
+    async fn leaf_epoch(&self, id: u64) -> Result<Option<u64>> {
+        let txn = self.begin();
+        let view = txn.page_view(id, Range::full()).await?;
+        if view.page.tier().is_leaf() {
+            Ok(Some(view.page.epoch()))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Writes the key-value pair to the tree.
     pub(crate) async fn write(&self, key: Key<'_>, value: Value<'_>) -> Result<()> {
+        self.stall_write().await;
+        let mut restarts = 0;
         loop {
             let txn = self.begin();
             match txn.write(key, value).await {
@@ -74,6 +277,7 @@ impl<E: Env> Tree<E> {
                 }
                 Err(Error::Again) => {
                     self.stats.restart.write.inc();
+                    self.on_restart(&mut restarts);
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -81,6 +285,73 @@ impl<E: Env> Tree<E> {
         }
     }
 
+    /// Waits for pending flushes if there are too many full write buffers.
+    async fn stall_write(&self) {
+        // Nothing would flush the write buffers while the write waits.
+        if self.options.run_jobs_inline {
+            return;
+        }
+        let limit = self.options.max_pending_write_buffers;
+        if limit == 0 || self.store.num_pending_flush() < limit {
+            return;
+        }
+        let start = Instant::now();
+        self.store.wait_pending_flush_below(limit).await;
+        self.stats.write_stalls.inc();
+        self.stats
+            .write_stall_micros
+            .add(start.elapsed().as_micros() as u64);
+    }
+
+    /// Purges all versions of the key from the tree.
+    ///
+    /// Every leaf page up to the one that contains the key is checked, since
+    /// the key may stay on the chains of the pages it was split from.
+    pub(crate) async fn purge(&self, key: &[u8]) -> Result<()> {
+        let mut start = Vec::new();
+        loop {
+            let txn = self.begin();
+            match txn.purge_leaf(&start, key).await {
+                Ok(Some(next)) => start = next,
+                Ok(None) => return Ok(()),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Punches the dead pages that hold some version of the key out of page
+    /// files, once the readers of `since` and older versions are gone.
+    pub(crate) async fn punch_key(&self, key: &[u8], since: Version) -> Result<()> {
+        self.store
+            .punch_dead_pages(since, |page| page::leaf_holds_key(PageRef::new(page), key))
+            .await
+    }
+
+    /// Returns the raw page with the id for shipping to another tree.
+    pub(crate) async fn read_raw_page(&self, id: u64) -> Result<Vec<u8>> {
+        loop {
+            let txn = self.begin();
+            match txn.read_raw_page(id).await {
+                Ok(raw) => return Ok(raw),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Installs a raw page read from another tree.
+    pub(crate) async fn apply_raw_page(&self, id: u64, raw: &[u8]) -> Result<()> {
+        loop {
+            let txn = self.begin();
+            match txn.apply_raw_page(id, raw).await {
+                Ok(_) => return Ok(()),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     /// Rewrites the corresponding page.
     pub(crate) async fn rewrite(&self, page_id: u64) -> Result<()> {
         loop {
@@ -93,12 +364,1068 @@ impl<E: Env> Tree<E> {
         }
     }
 
+    /// Returns the structure maintenance pressure of the pages of the tree.
+    ///
+    /// The page files are left to the GC strategy of the caller.
+    pub(crate) fn pressure(&self) -> Pressure {
+        self.pressure.snapshot()
+    }
+
+    /// Verifies all pages and consolidates the page chains if none of them is
+    /// malformed.
+    pub(crate) async fn compact_and_verify(&self) -> Result<VerifyReport> {
+        let (mut report, chains) = loop {
+            let txn = self.begin();
+            match txn.verify_pages().await {
+                Ok(result) => break result,
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if !report.corrupted_pages.is_empty() {
+            return Ok(report);
+        }
+        for id in chains {
+            loop {
+                let txn = self.begin();
+                match txn.rewrite(id).await {
+                    Ok(()) => break,
+                    Err(Error::Again) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            report.pages_compacted += 1;
+        }
+        Ok(report)
+    }
+
+    /// Returns up to `n - 1` keys that divide the tree into `n` ranges of
+    /// roughly equal size.
+    pub(crate) async fn suggest_split_points(&self, n: usize) -> Result<Vec<Vec<u8>>> {
+        loop {
+            let txn = self.begin();
+            match txn.leaf_sizes().await {
+                Ok(leaves) => return Ok(tree_txn::pick_split_points(&leaves, n)),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the largest LSN written to the tree, or `None` if it is empty.
+    ///
+    /// This walks through all leaf pages, so it is only meant to recover LSN
+    /// sequences when a table is opened. Tombstones dropped by consolidations
+    /// are not counted.
+    pub(crate) async fn max_lsn(&self) -> Result<Option<u64>> {
+        loop {
+            let txn = self.begin();
+            match txn.max_lsn().await {
+                Ok(lsn) => return Ok(lsn),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Consolidates the page chain of every leaf page into a single base page.
+    ///
+    /// Returns the number of page chains consolidated.
+    pub(crate) async fn consolidate_all(&self) -> Result<usize> {
+        let mut consolidated = 0;
+        for id in self.live_page_ids().await? {
+            // A consolidation may leave some delta pages, so it is repeated
+            // until the chain is a single base page.
+            let mut flattened = false;
+            loop {
+                let txn = self.begin();
+                match txn.flatten_leaf(id).await {
+                    Ok(true) => flattened = true,
+                    Ok(false) => break,
+                    Err(Error::Again) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            if flattened {
+                consolidated += 1;
+            }
+        }
+        Ok(consolidated)
+    }
+
+    /// Returns the ids of all pages reachable from the root in order.
+    pub(crate) async fn live_page_ids(&self) -> Result<Vec<u64>> {
+        loop {
+            let txn = self.begin();
+            match txn.live_page_ids().await {
+                Ok(ids) => return Ok(ids),
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Consolidates the page in the background unless it is already being
+    /// consolidated.
+    fn consolidate_in_background(self: &Arc<Self>, id: u64)
+    where
+        E: 'static,
+    {
+        if !lock(&self.consolidating).insert(id) || self.options.run_jobs_inline {
+            return;
+        }
+        let tree = self.clone();
+        let options = SpawnOptions {
+            name: Some("photondb-consolidate".to_owned()),
+            cpu_set: self.options.background_cpu_set.clone(),
+        };
+        // The task is detached, so reads never wait for it.
+        let _ = self.store.env().spawn_background(options, async move {
+            loop {
+                let txn = tree.begin();
+                match txn.rewrite(id).await {
+                    Err(Error::Again) => continue,
+                    _ => break,
+                }
+            }
+            lock(&tree.consolidating).remove(&id);
+        });
+    }
+
+    /// Consolidates the pages recorded by reads since the last call, if jobs
+    /// run inline.
+    pub(crate) async fn consolidate_pending(&self) -> Result<()> {
+        let ids = std::mem::take(&mut *lock(&self.consolidating));
+        for id in ids {
+            self.rewrite(id).await?;
+        }
+        Ok(())
+    }
+
+    /// Records a restart of an operation and backs off if fair scheduling is
+    /// enabled.
+    fn on_restart(&self, restarts: &mut u32) {
+        *restarts += 1;
+        self.stats.max_restarts.max(*restarts as u64);
+        if self.options.scheduling == Scheduling::Fair {
+            backoff(*restarts);
+        }
+    }
+
+    /// Returns the effective options of the tree.
+    pub(crate) fn options(&self) -> Options {
+        self.runtime_options.apply(&self.options)
+    }
+
+    /// Returns the LSN below which obsolete versions may be dropped.
+    ///
+    /// This is [`Options::gc_keep_versions_below_lsn`] unless the published
+    /// LSN or an older snapshot is smaller.
+    pub(crate) fn gc_watermark(&self) -> u64 {
+        let watermark = self.runtime_options.gc_keep_versions_below_lsn();
+        self.snapshots.watermark(watermark)
+    }
+
+    /// Keeps the versions visible at `lsn` until it is unpinned.
+    ///
+    /// Returns false without pinning if `lsn` is below the GC watermark,
+    /// since some versions visible at it may have been dropped.
+    pub(crate) fn pin_snapshot(&self, lsn: u64) -> bool {
+        let watermark = self.runtime_options.gc_keep_versions_below_lsn();
+        self.snapshots.pin(lsn, watermark)
+    }
+
+    /// Pins the LSN published by [`Tree::publish_lsn`] and returns it.
+    ///
+    /// Returns `None` if no LSN has been published.
+    pub(crate) fn pin_visible_snapshot(&self) -> Option<u64> {
+        self.snapshots.pin_visible()
+    }
+
+    /// Publishes the LSN that new reads are served at.
+    ///
+    /// The versions visible at the published LSN are kept by consolidations,
+    /// so it can be pinned at any time.
+    pub(crate) fn publish_lsn(&self, lsn: u64) {
+        self.snapshots.publish(lsn);
+    }
+
+    pub(crate) fn unpin_snapshot(&self, lsn: u64) {
+        self.snapshots.unpin(lsn);
+    }
+
+    /// Changes an option at runtime.
+    pub(crate) fn set_option(&self, name: &str, value: &str) -> crate::Result<()> {
+        self.runtime_options.set(name, value)
+    }
+
     /// Returns the statistics of the tree.
     pub(crate) fn stats(&self) -> Stats {
-        self.stats.snapshot()
+        Stats {
+            smos_in_flight: self.smo_permits.acquired() as u64,
+            ..self.stats.snapshot()
+        }
     }
 
     pub(crate) fn store(&self) -> &PageStore<E> {
         &self.store
     }
 }
+
+/// Backs off exponentially with the number of restarts, so that operations
+/// contending on a hot page give each other a chance to make progress.
+fn backoff(restarts: u32) {
+    const SPIN_LIMIT: u32 = 6;
+    if restarts <= SPIN_LIMIT {
+        for _ in 0..1 << restarts {
+            std::hint::spin_loop();
+        }
+    } else {
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        env::Photon,
+        page::ValuePageRef,
+        page_store::{FileInfo, GcPickStrategy, JobHandle},
+    };
+
+    /// A strategy that never picks files for GC.
+    struct NoGc;
+
+    impl GcPickStrategy for NoGc {
+        fn threshold(&self) -> f64 {
+            f64::INFINITY
+        }
+
+        fn score(&self, _: &FileInfo) -> f64 {
+            0.0
+        }
+    }
+
+    /// Writes the same keys with explicit LSNs and returns the files written,
+    /// sorted by name.
+    async fn write_files(path: &Path) -> Vec<(String, Vec<u8>)> {
+        let _ = std::fs::remove_dir_all(path);
+        let options = Options {
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        let tree = Arc::new(Tree::open(Photon, path, options).await.unwrap());
+        let rewriter = Arc::new(PageRewriter::new(tree.clone()));
+        let jobs = JobHandle::new(&Photon, tree.store(), rewriter, Box::new(NoGc));
+        for i in 0..64u64 {
+            let key = format!("key{:02}", i % 16);
+            let value = format!("value{i}");
+            let key = Key::new(key.as_bytes(), i + 1);
+            tree.write(key, Value::Put(value.as_bytes())).await.unwrap();
+            if i % 16 == 15 {
+                tree.store().seal_active_buffer();
+                tree.consolidate_pending().await.unwrap();
+                jobs.tick().await;
+            }
+        }
+        drop(jobs);
+        drop(tree);
+
+        let mut files = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let name = entry.file_name().into_string().unwrap();
+                (name, std::fs::read(entry.path()).unwrap())
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        std::fs::remove_dir_all(path).unwrap();
+        files
+    }
+
+    #[photonio::test]
+    fn bloom_skips_leaf_loads() {
+        let path = std::env::temp_dir().join("test_bloom_skips_leaf_loads");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            initial_split_keys: vec![b"m".to_vec()],
+            leaf_bloom_bits_per_key: 10,
+            ..Default::default()
+        };
+        {
+            let tree = Arc::new(Tree::open(Photon, &path, options.clone()).await.unwrap());
+            let rewriter = Arc::new(PageRewriter::new(tree.clone()));
+            let jobs = JobHandle::new(&Photon, tree.store(), rewriter, Box::new(NoGc));
+            for (i, key) in [b"a", b"b", b"n"].into_iter().enumerate() {
+                let key = Key::new(key, i as u64 + 1);
+                tree.write(key, Value::Put(b"v")).await.unwrap();
+            }
+            tree.store().seal_active_buffer();
+            jobs.tick().await;
+        }
+
+        // All pages are in page files after reopening.
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let get = |key: &'static [u8]| {
+            let tree = &tree;
+            async move {
+                let txn = tree.begin();
+                let (value, _) = txn.get(Key::new(key, u64::MAX)).await.unwrap();
+                (value.map(<[u8]>::to_vec), txn.guard.num_file_reads())
+            }
+        };
+        // The first read loads the root and the leaf, and builds the bloom.
+        let (value, reads) = get(b"a").await;
+        assert_eq!(value, Some(b"v".to_vec()));
+        assert!(reads >= 2);
+        // A miss only loads the root.
+        assert_eq!(get(b"c").await, (None, 1));
+        assert_eq!(tree.stats().bloom_skipped_loads, 1);
+        // A hit still loads the leaf.
+        let (value, reads) = get(b"b").await;
+        assert_eq!(value, Some(b"v".to_vec()));
+        assert!(reads >= 2);
+        assert_eq!(tree.stats().bloom_skipped_loads, 1);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Returns the number of versions in the leaf pages of the tree.
+    async fn num_versions<E: Env>(tree: &Tree<E>) -> usize {
+        let txn = tree.begin();
+        let mut count = 0;
+        for id in txn.live_page_ids().await.unwrap() {
+            let view = txn.page_view(id, Range::full()).await.unwrap();
+            if view.page.tier().is_leaf() {
+                txn.walk_page(view.page, |page| {
+                    if page.kind().is_data() {
+                        count += ValuePageRef::from(page).len();
+                    }
+                    false
+                })
+                .await
+                .unwrap();
+            }
+        }
+        count
+    }
+
+    #[photonio::test]
+    fn consolidation_drops_old_versions() {
+        let path = std::env::temp_dir().join("test_consolidation_drops_old_versions");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            gc_keep_versions_below_lsn: 5,
+            ..Default::default()
+        };
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let write = |lsn: u64| {
+            let tree = &tree;
+            async move {
+                let value = format!("v{lsn}");
+                let key = Key::new(b"k", lsn);
+                tree.write(key, Value::Put(value.as_bytes())).await.unwrap();
+            }
+        };
+        let get = |lsn: u64| {
+            let tree = &tree;
+            async move {
+                let txn = tree.begin();
+                let (value, _) = txn.get(Key::new(b"k", lsn)).await.unwrap();
+                value.map(|value| String::from_utf8(value.to_vec()).unwrap())
+            }
+        };
+        for lsn in 1..=10 {
+            write(lsn).await;
+        }
+        // Versions visible at and after the watermark are kept.
+        tree.consolidate_all().await.unwrap();
+        assert_eq!(num_versions(&tree).await, 6);
+        assert_eq!(get(5).await.as_deref(), Some("v5"));
+        assert_eq!(get(4).await, None);
+
+        // Snapshots can only be pinned at or above the watermark, and hold it
+        // when the option is raised.
+        assert!(!tree.pin_snapshot(4));
+        assert!(tree.pin_snapshot(7));
+        tree.set_option("gc_keep_versions_below_lsn", &u64::MAX.to_string())
+            .unwrap();
+        write(11).await;
+        tree.consolidate_all().await.unwrap();
+        assert_eq!(num_versions(&tree).await, 5);
+        assert_eq!(get(7).await.as_deref(), Some("v7"));
+
+        // Only the latest version is kept once the snapshot is released.
+        tree.unpin_snapshot(7);
+        write(12).await;
+        tree.consolidate_all().await.unwrap();
+        assert_eq!(num_versions(&tree).await, 1);
+        assert_eq!(get(u64::MAX).await.as_deref(), Some("v12"));
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn get_empty_value() {
+        let path = std::env::temp_dir().join("test_get_empty_value");
+        let _ = std::fs::remove_dir_all(&path);
+        let tree = Tree::open(Photon, &path, Options::default()).await.unwrap();
+        tree.write(Key::new(b"a", 1), Value::Put(b""))
+            .await
+            .unwrap();
+        tree.write(Key::new(b"b", 1), Value::Put(b"v"))
+            .await
+            .unwrap();
+        tree.write(Key::new(b"b", 2), Value::Delete).await.unwrap();
+        let get = |key: &'static [u8], lsn: u64| {
+            let tree = &tree;
+            async move {
+                let txn = tree.begin();
+                let (value, _) = txn.get(Key::new(key, lsn)).await.unwrap();
+                value.map(<[u8]>::to_vec)
+            }
+        };
+        // An empty value is present, unlike a deleted key.
+        assert_eq!(get(b"a", u64::MAX).await, Some(Vec::new()));
+        assert_eq!(get(b"b", u64::MAX).await, None);
+        assert_eq!(get(b"b", 1).await, Some(b"v".to_vec()));
+        // Still the same after the chain is consolidated.
+        tree.consolidate_all().await.unwrap();
+        assert_eq!(get(b"a", u64::MAX).await, Some(Vec::new()));
+        assert_eq!(get(b"b", u64::MAX).await, None);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn hinted_gets_skip_traversal() {
+        let path = std::env::temp_dir().join("test_hinted_gets_skip_traversal");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            initial_split_keys: vec![b"m".to_vec()],
+            ..Default::default()
+        };
+        // The keys are in ascending order, half of them in each leaf page.
+        let keys = ["a", "n"]
+            .into_iter()
+            .flat_map(|prefix| (0..8).map(move |i| format!("{prefix}{i}")))
+            .collect::<Vec<_>>();
+        {
+            let tree = Arc::new(Tree::open(Photon, &path, options.clone()).await.unwrap());
+            let rewriter = Arc::new(PageRewriter::new(tree.clone()));
+            let jobs = JobHandle::new(&Photon, tree.store(), rewriter, Box::new(NoGc));
+            for (i, key) in keys.iter().enumerate() {
+                let key = Key::new(key.as_bytes(), i as u64 + 1);
+                tree.write(key, Value::Put(b"v")).await.unwrap();
+            }
+            tree.store().seal_active_buffer();
+            jobs.tick().await;
+        }
+
+        // All pages are loaded from page files after reopening, so every
+        // visited page is a file read.
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let mut plain_reads = 0;
+        for key in &keys {
+            let txn = tree.begin();
+            let (value, _) = txn.get(Key::new(key.as_bytes(), u64::MAX)).await.unwrap();
+            assert_eq!(value, Some(&b"v"[..]));
+            plain_reads += txn.guard.num_file_reads();
+        }
+        let mut hint = AccessHint::new();
+        let mut hinted_reads = 0;
+        for key in &keys {
+            let txn = tree.begin();
+            let key = Key::new(key.as_bytes(), u64::MAX);
+            let value = txn.get_with_hint(key, &mut hint).await.unwrap();
+            assert_eq!(value, Some(&b"v"[..]));
+            hinted_reads += txn.guard.num_file_reads();
+        }
+        // Only the first get in each leaf page starts from the root.
+        assert!(
+            hinted_reads + keys.len() - 2 <= plain_reads,
+            "{hinted_reads} hinted reads, {plain_reads} plain reads"
+        );
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Returns the chain lengths of the leaf pages under a root with a single
+    /// split key.
+    async fn leaf_chain_lens(tree: &Tree<Photon>) -> Vec<u8> {
+        let txn = tree.begin();
+        let mut lens = Vec::new();
+        for id in [MIN_ID + 1, MIN_ID + 2] {
+            let view = txn.page_view(id, Range::full()).await.unwrap();
+            lens.push(view.page.chain_len());
+        }
+        lens
+    }
+
+    #[photonio::test]
+    fn reads_consolidate_long_chains() {
+        use std::time::Duration;
+
+        use crate::util::sync::unblock;
+
+        let path = std::env::temp_dir().join("test_reads_consolidate_long_chains");
+        let _ = std::fs::remove_dir_all(&path);
+        // Writes do not consolidate the chain by themselves.
+        let options = Options {
+            page_chain_length: 64,
+            max_delta_chain_bytes: usize::MAX,
+            hard_max_delta_chain: 64,
+            read_triggered_consolidation: true,
+            ..Default::default()
+        };
+        let tree = Arc::new(Tree::open(Photon, &path, options).await.unwrap());
+        let chain_len = || async {
+            let txn = tree.begin();
+            let view = txn.page_view(MIN_ID, Range::full()).await.unwrap();
+            view.page.chain_len()
+        };
+        for i in 0..16u64 {
+            let value = format!("v{i}");
+            tree.write(Key::new(b"k", i + 1), Value::Put(value.as_bytes()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(chain_len().await, 17);
+
+        tree.set_option("page_chain_length", "4").unwrap();
+        let mut reads = 0;
+        while chain_len().await > 4 {
+            let value = tree
+                .get(Key::new(b"k", u64::MAX), |value| value.map(<[u8]>::to_vec))
+                .await
+                .unwrap();
+            assert_eq!(value, Some(b"v15".to_vec()));
+            reads += 1;
+            assert!(reads < 1000, "the chain is not consolidated");
+            // Let the background consolidation run.
+            unblock(|| std::thread::sleep(Duration::from_millis(1))).await;
+        }
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn consolidate_all_leaves() {
+        let path = std::env::temp_dir().join("test_consolidate_all_leaves");
+        let _ = std::fs::remove_dir_all(&path);
+        // Writes do not consolidate the chains by themselves.
+        let options = Options {
+            initial_split_keys: vec![b"m".to_vec()],
+            page_chain_length: 64,
+            max_delta_chain_bytes: usize::MAX,
+            hard_max_delta_chain: 64,
+            ..Default::default()
+        };
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        for i in 0..16u64 {
+            for prefix in ["a", "n"] {
+                let key = format!("{prefix}{:02}", i % 4);
+                let key = Key::new(key.as_bytes(), i + 1);
+                tree.write(key, Value::Put(b"v")).await.unwrap();
+            }
+        }
+        assert_eq!(leaf_chain_lens(&tree).await, [17, 17]);
+
+        assert_eq!(tree.consolidate_all().await.unwrap(), 2);
+        assert_eq!(leaf_chain_lens(&tree).await, [1, 1]);
+        let txn = tree.begin();
+        for i in 0..4 {
+            let key = format!("n{i:02}");
+            let (value, _) = txn.get(Key::new(key.as_bytes(), u64::MAX)).await.unwrap();
+            assert_eq!(value, Some(&b"v"[..]));
+        }
+        drop(txn);
+        // Pages without delta pages are left alone.
+        assert_eq!(tree.consolidate_all().await.unwrap(), 0);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn pressure_tracks_page_changes() {
+        let path = std::env::temp_dir().join("test_pressure_tracks_page_changes");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            initial_split_keys: vec![b"m".to_vec()],
+            page_size: 1024,
+            page_chain_length: 64,
+            max_delta_chain_bytes: usize::MAX,
+            hard_max_delta_chain: 64,
+            ..Default::default()
+        };
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let value = [0; 64];
+        for i in 0..16u64 {
+            for prefix in ["a", "n"] {
+                let key = format!("{prefix}{i:02}");
+                let key = Key::new(key.as_bytes(), i + 1);
+                tree.write(key, Value::Put(&value)).await.unwrap();
+            }
+        }
+        // Both leaves are over the page size with their delta pages.
+        let pressure = tree.pressure();
+        assert_eq!(pressure.leaves_over_threshold, 2);
+        assert_eq!(pressure.chains_over_threshold, 0);
+
+        // Purging most keys of a leaf shrinks it below the page size.
+        for i in 0..12 {
+            let key = format!("a{i:02}");
+            tree.purge(key.as_bytes()).await.unwrap();
+        }
+        assert_eq!(tree.pressure().leaves_over_threshold, 1);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn scan_during_splits() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let path = std::env::temp_dir().join("test_scan_during_splits");
+        let _ = std::fs::remove_dir_all(&path);
+        // Small pages split all the time.
+        let options = Options {
+            page_size: 256,
+            page_chain_length: 2,
+            ..Default::default()
+        };
+        let tree = Arc::new(Tree::open(Photon, &path, options).await.unwrap());
+        let key = |i: u64| format!("key{i:04}").into_bytes();
+        // The even keys are present throughout the scans.
+        for i in (0..400).step_by(2) {
+            let key = key(i);
+            tree.write(Key::new(&key, i + 1), Value::Put(b"v"))
+                .await
+                .unwrap();
+        }
+        let writer_done = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let tree = tree.clone();
+            let writer_done = writer_done.clone();
+            std::thread::spawn(move || {
+                // Fill in the odd keys, which splits the pages under the scans.
+                futures::executor::block_on(async {
+                    for i in (1..400).step_by(2) {
+                        let key = key(i);
+                        tree.write(Key::new(&key, i + 1000), Value::Put(b"v"))
+                            .await
+                            .unwrap();
+                    }
+                });
+                writer_done.store(true, Ordering::Release);
+            })
+        };
+
+        let mut scans = 0;
+        while scans == 0 || !writer_done.load(Ordering::Acquire) {
+            let mut cursor = tree.scan(b"", u64::MAX);
+            let mut keys = Vec::new();
+            while let Some((key, _)) = cursor.next().await.unwrap() {
+                keys.push(key);
+            }
+            assert!(
+                keys.windows(2).all(|w| w[0] < w[1]),
+                "keys are duplicated or out of order"
+            );
+            for i in (0..400).step_by(2) {
+                assert!(keys.binary_search(&key(i)).is_ok(), "key {i} is missed");
+            }
+            scans += 1;
+        }
+        writer.join().unwrap();
+        assert!(tree.stats().success.split_page > 0);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn fair_scheduling_bounds_latency() {
+        use std::{
+            sync::atomic::{AtomicU64, Ordering},
+            time::Duration,
+        };
+
+        let path = std::env::temp_dir().join("test_fair_scheduling_bounds_latency");
+        let _ = std::fs::remove_dir_all(&path);
+        // Short chains make writers replace the hot page all the time.
+        let options = Options {
+            scheduling: Scheduling::Fair,
+            page_chain_length: 2,
+            ..Default::default()
+        };
+        let tree = Arc::new(Tree::open(Photon, &path, options).await.unwrap());
+        let next_lsn = Arc::new(AtomicU64::new(1));
+        // Readers and writers contend on the same key.
+        let threads = (0..8)
+            .map(|i| {
+                let tree = tree.clone();
+                let next_lsn = next_lsn.clone();
+                std::thread::spawn(move || {
+                    futures::executor::block_on(async {
+                        let mut slowest = Duration::ZERO;
+                        for _ in 0..200 {
+                            let start = Instant::now();
+                            if i % 2 == 0 {
+                                let lsn = next_lsn.fetch_add(1, Ordering::Relaxed);
+                                let key = Key::new(b"hot", lsn);
+                                tree.write(key, Value::Put(b"v")).await.unwrap();
+                            } else {
+                                let key = Key::new(b"hot", u64::MAX);
+                                tree.get(key, |_| ()).await.unwrap();
+                            }
+                            slowest = slowest.max(start.elapsed());
+                        }
+                        slowest
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let slowest = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .max()
+            .unwrap();
+        // No operation is starved.
+        assert!(
+            slowest < Duration::from_secs(1),
+            "the slowest operation took {slowest:?} after {} restarts",
+            tree.stats().max_restarts
+        );
+        let value = tree
+            .get(Key::new(b"hot", u64::MAX), |value| {
+                value.map(<[u8]>::to_vec)
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, Some(b"v".to_vec()));
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn stale_writes_policies() {
+        use crate::StaleWrites;
+
+        let base = std::env::temp_dir().join("test_stale_writes_policies");
+        let _ = std::fs::remove_dir_all(&base);
+        for policy in [StaleWrites::Allow, StaleWrites::Reject, StaleWrites::Ignore] {
+            let options = Options {
+                stale_writes: policy,
+                ..Default::default()
+            };
+            let path = base.join(format!("{policy:?}"));
+            let tree = Tree::open(Photon, &path, options).await.unwrap();
+            tree.write(Key::new(b"k", 5), Value::Put(b"1"))
+                .await
+                .unwrap();
+            // Replays the write with the same LSN and another value.
+            let replay = tree.write(Key::new(b"k", 5), Value::Put(b"2")).await;
+            let older = tree.write(Key::new(b"k", 3), Value::Put(b"3")).await;
+            match policy {
+                StaleWrites::Reject => {
+                    assert!(matches!(replay, Err(Error::InvalidArgument)));
+                    assert!(matches!(older, Err(Error::InvalidArgument)));
+                }
+                _ => {
+                    assert!(replay.is_ok());
+                    assert!(older.is_ok());
+                }
+            }
+            let txn = tree.begin();
+            let (value, _) = txn.get(Key::new(b"k", 5)).await.unwrap();
+            let (older, _) = txn.get(Key::new(b"k", 4)).await.unwrap();
+            if policy == StaleWrites::Allow {
+                assert_eq!(value, Some(&b"2"[..]));
+                assert_eq!(older, Some(&b"3"[..]));
+            } else {
+                // The stale writes are no-ops.
+                assert_eq!(value, Some(&b"1"[..]));
+                assert_eq!(older, None);
+            }
+            // Newer writes are always applied.
+            drop(txn);
+            tree.write(Key::new(b"k", 6), Value::Put(b"4"))
+                .await
+                .unwrap();
+            let txn = tree.begin();
+            let (value, _) = txn.get(Key::new(b"k", u64::MAX)).await.unwrap();
+            assert_eq!(value, Some(&b"4"[..]));
+        }
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[photonio::test]
+    fn single_leaf_reads_skip_index_pages() {
+        let path = std::env::temp_dir().join("test_single_leaf_reads_skip_index_pages");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            page_size: 512,
+            ..Default::default()
+        };
+        let key = |i: u64| format!("key{i:03}").into_bytes();
+        // Writes the keys and flushes all pages to page files.
+        let write = |keys: std::ops::Range<u64>| {
+            let (path, options) = (&path, options.clone());
+            async move {
+                let tree = Arc::new(Tree::open(Photon, path, options).await.unwrap());
+                let rewriter = Arc::new(PageRewriter::new(tree.clone()));
+                let jobs = JobHandle::new(&Photon, tree.store(), rewriter, Box::new(NoGc));
+                for i in keys {
+                    let key = key(i);
+                    tree.write(Key::new(&key, i + 1), Value::Put(&key))
+                        .await
+                        .unwrap();
+                }
+                tree.consolidate_all().await.unwrap();
+                tree.store().seal_active_buffer();
+                jobs.tick().await;
+            }
+        };
+        // Returns whether the root is a leaf, and the number of pages loaded
+        // by a get after reopening.
+        let read = |i: u64| {
+            let (path, options) = (&path, options.clone());
+            async move {
+                let tree = Tree::open(Photon, path, options).await.unwrap();
+                let txn = tree.begin();
+                let (value, _) = txn.get(Key::new(&key(i), u64::MAX)).await.unwrap();
+                assert_eq!(value, Some(key(i).as_slice()));
+                let reads = txn.guard.num_file_reads();
+                let root = txn.page_view(MIN_ID, Range::full()).await.unwrap();
+                (root.page.tier().is_leaf(), reads)
+            }
+        };
+
+        // A small tree reads its only leaf without any index page.
+        write(0..5).await;
+        assert_eq!(read(3).await, (true, 1));
+        // The root turns into an index page once the leaf splits.
+        write(5..100).await;
+        let (root_is_leaf, reads) = read(3).await;
+        assert!(!root_is_leaf);
+        assert!(reads >= 2);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn live_page_ids_of_tree() {
+        let path = std::env::temp_dir().join("test_live_page_ids_of_tree");
+        let _ = std::fs::remove_dir_all(&path);
+        // A root with three leaves.
+        let options = Options {
+            initial_split_keys: vec![b"g".to_vec(), b"m".to_vec()],
+            ..Default::default()
+        };
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let ids = tree.live_page_ids().await.unwrap();
+        assert_eq!(ids, (MIN_ID..MIN_ID + 4).collect::<Vec<_>>());
+
+        // Splitting a leaf adds its right half.
+        #[cfg(feature = "debug")]
+        {
+            for i in 0..20u64 {
+                let key = format!("a{i:02}");
+                tree.write(Key::new(key.as_bytes(), i + 1), Value::Put(b"v"))
+                    .await
+                    .unwrap();
+            }
+            tree.force_split(b"a00").await.unwrap();
+            let split_ids = tree.live_page_ids().await.unwrap();
+            assert_eq!(split_ids.len(), ids.len() + 1);
+            assert!(ids.iter().all(|id| split_ids.contains(id)));
+        }
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(feature = "debug")]
+    #[photonio::test]
+    fn force_split_leaf() {
+        let path = std::env::temp_dir().join("test_force_split_leaf");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            initial_split_keys: vec![b"m".to_vec()],
+            ..Default::default()
+        };
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let key = |i: u64| format!("a{i:02}").into_bytes();
+        for i in 0..20 {
+            let key = key(i);
+            tree.write(Key::new(&key, i + 1), Value::Put(&key))
+                .await
+                .unwrap();
+        }
+        // The leaf is far below the page size.
+        tree.force_split(&key(0)).await.unwrap();
+        assert_eq!(tree.stats().success.split_page, 1);
+
+        // Both halves are readable.
+        for i in 0..20 {
+            let txn = tree.begin();
+            let (value, _) = txn.get(Key::new(&key(i), u64::MAX)).await.unwrap();
+            assert_eq!(value, Some(key(i).as_slice()));
+        }
+        let mut cursor = tree.scan(b"", u64::MAX);
+        let mut keys = Vec::new();
+        while let Some((key, _)) = cursor.next().await.unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(keys, (0..20).map(key).collect::<Vec<_>>());
+        drop(cursor);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(feature = "debug")]
+    #[photonio::test]
+    fn export_mapping_of_live_pages() {
+        let path = std::env::temp_dir().join("test_export_mapping_of_live_pages");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            initial_split_keys: vec![b"m".to_vec()],
+            ..Default::default()
+        };
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        for i in 0..20u64 {
+            let key = format!("a{i:02}");
+            tree.write(Key::new(key.as_bytes(), i + 1), Value::Put(b"v"))
+                .await
+                .unwrap();
+        }
+        tree.force_split(b"a00").await.unwrap();
+
+        let dump = tree.export_mapping().await.unwrap();
+        assert!(dump.is_consistent());
+        // The root, the two initial leaves and the right half of the split.
+        assert_eq!(dump.ids(), (MIN_ID..MIN_ID + 4).collect::<Vec<_>>());
+        assert_eq!(dump.ids(), tree.live_page_ids().await.unwrap());
+        let root = &dump.entries[0];
+        assert!(!root.is_leaf);
+        assert!(dump.entries[1..].iter().all(|entry| entry.is_leaf));
+        // Nothing has been flushed yet.
+        assert!(dump.entries.iter().all(|entry| entry.in_memory));
+        let txn = tree.begin();
+        for entry in &dump.entries {
+            assert_eq!(txn.guard.page_addr(entry.id), entry.addr);
+        }
+        drop(txn);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(feature = "debug")]
+    #[photonio::test]
+    fn dump_leaf_page() {
+        let path = std::env::temp_dir().join("test_dump_leaf_page");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            initial_split_keys: vec![b"m".to_vec()],
+            ..Default::default()
+        };
+        {
+            let tree = Arc::new(Tree::open(Photon, &path, options.clone()).await.unwrap());
+            let rewriter = Arc::new(PageRewriter::new(tree.clone()));
+            let jobs = JobHandle::new(&Photon, tree.store(), rewriter, Box::new(NoGc));
+            for (i, key) in [b"b", b"a", b"n", b"b"].into_iter().enumerate() {
+                let key = Key::new(key, i as u64 + 1);
+                tree.write(key, Value::Put(b"v")).await.unwrap();
+            }
+            tree.store().seal_active_buffer();
+            jobs.tick().await;
+        }
+
+        // The pages are loaded from page files after reopening.
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let root = tree.dump_page(MIN_ID).await.unwrap();
+        assert!(!root.is_leaf);
+        assert_eq!(root.keys.last().unwrap(), b"m");
+        let left = tree.dump_page(MIN_ID + 1).await.unwrap();
+        assert!(left.is_leaf && !left.is_split);
+        // Overwritten versions are merged.
+        assert_eq!(left.keys, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(left.key_range(), Some((&b"a"[..], &b"b"[..])));
+        let right = tree.dump_page(MIN_ID + 2).await.unwrap();
+        assert_eq!(right.keys, vec![b"n".to_vec()]);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn empty_page_files_are_dropped() {
+        let path = std::env::temp_dir().join("test_empty_page_files_are_dropped");
+        let _ = std::fs::remove_dir_all(&path);
+        let options = Options {
+            run_jobs_inline: true,
+            ..Default::default()
+        };
+        let page_files = || {
+            let mut names = std::fs::read_dir(&path)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| name.starts_with("db_"))
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        // Writes or consolidates the keys, and flushes all pages to page files.
+        let session = |consolidate: bool| {
+            let options = options.clone();
+            let path = &path;
+            async move {
+                let tree = Arc::new(Tree::open(Photon, path, options).await.unwrap());
+                let rewriter = Arc::new(PageRewriter::new(tree.clone()));
+                let jobs = JobHandle::new(&Photon, tree.store(), rewriter, Box::new(NoGc));
+                if consolidate {
+                    assert_eq!(tree.consolidate_all().await.unwrap(), 1);
+                } else {
+                    for (i, key) in [b"a", b"b", b"c"].into_iter().enumerate() {
+                        let key = Key::new(key, i as u64 + 1);
+                        tree.write(key, Value::Put(b"v")).await.unwrap();
+                    }
+                }
+                tree.store().seal_active_buffer();
+                jobs.tick().await;
+            }
+        };
+
+        session(false).await;
+        let written = page_files();
+        assert!(!written.is_empty());
+        // The consolidation replaces every page written before, so the files
+        // are removed once the tree is reopened.
+        session(true).await;
+        let tree = Tree::open(Photon, &path, options).await.unwrap();
+        let files = page_files();
+        assert!(!files.is_empty());
+        assert!(written.iter().all(|name| !files.contains(name)));
+        let txn = tree.begin();
+        for key in [b"a", b"b", b"c"] {
+            let (value, _) = txn.get(Key::new(key, u64::MAX)).await.unwrap();
+            assert_eq!(value, Some(&b"v"[..]));
+        }
+        drop(txn);
+        drop(tree);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[photonio::test]
+    fn inline_jobs_write_identical_files() {
+        let base = std::env::temp_dir().join("test_inline_jobs_write_identical_files");
+        let first = write_files(&base.join("first")).await;
+        let second = write_files(&base.join("second")).await;
+        assert!(first.len() > 1);
+        assert_eq!(first, second);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{page::*, page_store::*};
+use crate::{page::*, page_store::*, util::checksum::crc32c};
 
 pub(super) struct PageView<'a> {
     pub(super) id: u64,
@@ -29,19 +29,94 @@ where
     }
 }
 
+/// Returns the value of a visible version, or `None` for a tombstone.
+///
+/// Returns [`Error::Corrupted`] if the value does not match its checksum.
+pub(super) fn visible_value(value: Value<'_>) -> Result<Option<&[u8]>> {
+    match value {
+        Value::Put(v) | Value::Lww(_, v) => Ok(Some(v)),
+        Value::Checked(checksum, v) if crc32c(v) == checksum => Ok(Some(v)),
+        Value::Checked(..) => Err(Error::Corrupted),
+        Value::Delete => Ok(None),
+    }
+}
+
+/// Resolves the versions of a key to the version that a read returns.
+///
+/// The versions must be visible to the read, and may come from different
+/// pages of a chain in any order. Scans resolve versions in the same way in
+/// [`VisibleLeafPageIter`].
+pub(super) fn resolve_versions<'a>(
+    mut versions: Vec<(Key<'a>, Value<'a>)>,
+) -> Option<(Key<'a>, Value<'a>)> {
+    versions.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let mut versions = versions.into_iter();
+    let first = versions.next()?;
+    let (winner, _) = resolve_lww(first, &mut versions);
+    Some(winner)
+}
+
+/// Resolves a run of LWW versions of a key that starts with `first`.
+///
+/// Versions are sorted from the newest to the oldest, so the run ends at the
+/// first version of another key or kind. The version with the highest
+/// timestamp in the run wins, and ties are broken by LSN. Returns the winner
+/// and the item after the run, if any. Items that are not LWW versions are
+/// returned as they are.
+pub(super) fn resolve_lww<'a, I>(
+    first: (Key<'a>, Value<'a>),
+    iter: &mut I,
+) -> ((Key<'a>, Value<'a>), Option<(Key<'a>, Value<'a>)>)
+where
+    I: Iterator<Item = (Key<'a>, Value<'a>)>,
+{
+    let Value::Lww(mut timestamp, _) = first.1 else {
+        return (first, None);
+    };
+    let mut winner = first;
+    for (k, v) in iter {
+        match v {
+            Value::Lww(t, _) if k.raw == first.0.raw => {
+                if t > timestamp {
+                    timestamp = t;
+                    winner = (k, v);
+                }
+            }
+            _ => return (winner, Some((k, v))),
+        }
+    }
+    (winner, None)
+}
+
 /// An iterator that merges multiple leaf pages for consolidation.
+///
+/// It keeps all versions visible at and after the safe LSN, and drops the
+/// older ones. If `drop_deletes` is set, keys deleted at or before the safe
+/// LSN are dropped entirely, which is only correct when the whole page chain
+/// is merged.
 pub(super) struct MergingLeafPageIter<'a> {
     iter: MergingIter<SortedPageIter<'a, Key<'a>, Value<'a>>>,
-    last: Option<&'a [u8]>,
+    /// The item read past the end of a run of LWW versions.
+    pending: Option<(Key<'a>, Value<'a>)>,
+    last: Option<Key<'a>>,
     limit: Option<&'a [u8]>,
+    safe_lsn: u64,
+    drop_deletes: bool,
 }
 
 impl<'a> MergingLeafPageIter<'a> {
-    pub(super) fn new(iter: MergingPageIter<'a, Key<'a>, Value<'a>>) -> Self {
+    pub(super) fn new(
+        iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+        safe_lsn: u64,
+        drop_deletes: bool,
+    ) -> Self {
         Self {
             iter: iter.iter,
+            pending: None,
             last: None,
             limit: iter.limit,
+            safe_lsn,
+            drop_deletes,
         }
     }
 }
@@ -50,26 +125,157 @@ impl<'a> Iterator for MergingLeafPageIter<'a> {
     type Item = (Key<'a>, Value<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // TODO: We should keep all versions visible at and after the safe LSN.
-        for (k, v) in &mut self.iter {
+        while let Some((k, v)) = self.pending.take().or_else(|| self.iter.next()) {
+            // Versions are sorted from the newest to the oldest. A version is
+            // visible at and after the safe LSN only if the newer one is not.
+            if let Some(last) = self.last {
+                if k.raw == last.raw && last.lsn <= self.safe_lsn {
+                    continue;
+                }
+            }
+            // Only the winner of the LWW versions at or before the safe LSN is
+            // visible at and after it.
+            let (k, v) = if k.lsn <= self.safe_lsn {
+                let (winner, next) = resolve_lww((k, v), &mut self.iter);
+                self.pending = next;
+                winner
+            } else {
+                (k, v)
+            };
+            self.last = Some(k);
+            if !is_before_end(k.raw, self.limit) {
+                return None;
+            }
+            // No older version is visible, so the tombstone is not needed.
+            if self.drop_deletes && k.lsn <= self.safe_lsn && matches!(v, Value::Delete) {
+                continue;
+            }
+            return Some((k, v));
+        }
+        None
+    }
+}
+
+impl<'a> RewindableIterator for MergingLeafPageIter<'a> {
+    fn rewind(&mut self) {
+        self.iter.rewind();
+        self.pending = None;
+        self.last = None;
+    }
+}
+
+/// An iterator that yields the latest visible value of each key in a leaf page.
+///
+/// The iterator stops at a value that does not match its checksum, which is
+/// reported by [`VisibleLeafPageIter::is_corrupted`].
+pub(super) struct VisibleLeafPageIter<'a> {
+    iter: MergingIter<SortedPageIter<'a, Key<'a>, Value<'a>>>,
+    /// The item read past the end of a run of LWW versions.
+    pending: Option<(Key<'a>, Value<'a>)>,
+    last: Option<&'a [u8]>,
+    limit: Option<&'a [u8]>,
+    lsn: u64,
+    corrupted: bool,
+}
+
+impl<'a> VisibleLeafPageIter<'a> {
+    pub(super) fn new(iter: MergingPageIter<'a, Key<'a>, Value<'a>>, lsn: u64) -> Self {
+        Self {
+            iter: iter.iter,
+            pending: None,
+            last: None,
+            limit: iter.limit,
+            lsn,
+            corrupted: false,
+        }
+    }
+
+    /// Returns true if the iterator stopped at a corrupted value.
+    pub(super) fn is_corrupted(&self) -> bool {
+        self.corrupted
+    }
+
+    /// Positions the iterator at the first key that is at or after `start`.
+    pub(super) fn seek(&mut self, start: &[u8]) {
+        self.iter.seek(&Key::new(start, u64::MAX));
+        self.pending = None;
+        self.last = None;
+    }
+}
+
+impl<'a> Iterator for VisibleLeafPageIter<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((k, v)) = self.pending.take().or_else(|| self.iter.next()) {
+            // Skip versions that are not visible yet.
+            if k.lsn > self.lsn {
+                continue;
+            }
+            // Skip older versions.
             if let Some(last) = self.last {
                 if k.raw == last {
                     continue;
                 }
             }
+            let ((k, v), next) = resolve_lww((k, v), &mut self.iter);
+            self.pending = next;
             self.last = Some(k.raw);
-            if let Some(limit) = self.limit {
-                if k.raw >= limit {
+            if !is_before_end(k.raw, self.limit) {
+                return None;
+            }
+            match visible_value(v) {
+                Ok(Some(v)) => return Some((k.raw, v)),
+                Ok(None) => {}
+                Err(_) => {
+                    self.corrupted = true;
                     return None;
                 }
             }
-            return Some((k, v));
         }
         None
     }
 }
 
-impl<'a> RewindableIterator for MergingLeafPageIter<'a> {
+/// Returns true if the leaf data page holds some version of the key,
+/// regardless of the range of the page.
+pub(super) fn leaf_holds_key(page: PageRef<'_>, key: &[u8]) -> bool {
+    if !page.tier().is_leaf() || !page.kind().is_data() {
+        return false;
+    }
+    let Ok(page) = ValuePageRef::try_new(page) else {
+        return false;
+    };
+    let index = page.rank(key).unwrap_or_else(|i| i);
+    matches!(page.get(index), Some((k, _)) if k.raw == key)
+}
+
+/// An iterator that drops all versions of a key from a leaf page iterator.
+pub(super) struct PurgingLeafPageIter<'a, 'k> {
+    iter: MergingLeafPageIter<'a>,
+    key: &'k [u8],
+}
+
+impl<'a, 'k> PurgingLeafPageIter<'a, 'k> {
+    pub(super) fn new(iter: MergingLeafPageIter<'a>, key: &'k [u8]) -> Self {
+        Self { iter, key }
+    }
+}
+
+impl<'a, 'k> Iterator for PurgingLeafPageIter<'a, 'k> {
+    type Item = (Key<'a>, Value<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (k, v) in &mut self.iter {
+            if k.raw != self.key {
+                return Some((k, v));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, 'k> RewindableIterator for PurgingLeafPageIter<'a, 'k> {
     fn rewind(&mut self) {
         self.iter.rewind();
     }
@@ -108,10 +314,8 @@ impl<'a> Iterator for MergingInnerPageIter<'a> {
                 }
             }
             self.last = Some(start);
-            if let Some(limit) = self.limit {
-                if start >= limit {
-                    return None;
-                }
+            if !is_before_end(start, self.limit) {
+                return None;
             }
             return Some((start, index));
         }
@@ -122,5 +326,6 @@ impl<'a> Iterator for MergingInnerPageIter<'a> {
 impl<'a> RewindableIterator for MergingInnerPageIter<'a> {
     fn rewind(&mut self) {
         self.iter.rewind();
+        self.last = None;
     }
 }
@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::util::sync::lock;
+
+/// The LSNs pinned by read transactions.
+///
+/// Versions visible at a pinned LSN are kept by consolidations until it is
+/// unpinned. If the LSNs are assigned by a table, the versions visible at its
+/// visible LSN are kept as well, so that a snapshot pinned there is never
+/// missing a version dropped by a consolidation that started before it.
+#[derive(Default)]
+pub(super) struct Snapshots {
+    inner: Mutex<SnapshotsInner>,
+}
+
+#[derive(Default)]
+struct SnapshotsInner {
+    /// The number of pins of each LSN.
+    pins: BTreeMap<u64, usize>,
+    /// The LSN visible to new reads, if the table publishes one.
+    visible: Option<u64>,
+}
+
+impl Snapshots {
+    /// Pins `lsn` if the versions visible at it are still kept under
+    /// `watermark`.
+    ///
+    /// Returns false if `lsn` is below the watermark, since consolidations
+    /// may already have dropped some versions visible at it. The check and
+    /// the pin are atomic, so the watermark never passes a pinned LSN.
+    pub(super) fn pin(&self, lsn: u64, watermark: u64) -> bool {
+        let mut inner = lock(&self.inner);
+        if lsn < inner.watermark(watermark) {
+            return false;
+        }
+        *inner.pins.entry(lsn).or_default() += 1;
+        true
+    }
+
+    /// Pins the visible LSN and returns it.
+    ///
+    /// Returns `None` if no LSN has been published.
+    pub(super) fn pin_visible(&self) -> Option<u64> {
+        let mut inner = lock(&self.inner);
+        let lsn = inner.visible?;
+        *inner.pins.entry(lsn).or_default() += 1;
+        Some(lsn)
+    }
+
+    pub(super) fn unpin(&self, lsn: u64) {
+        let mut inner = lock(&self.inner);
+        let count = inner.pins.get_mut(&lsn).expect("snapshot must be pinned");
+        *count -= 1;
+        if *count == 0 {
+            inner.pins.remove(&lsn);
+        }
+    }
+
+    /// Advances the visible LSN to `lsn`, it never goes backwards.
+    pub(super) fn publish(&self, lsn: u64) {
+        let mut inner = lock(&self.inner);
+        inner.visible = inner.visible.max(Some(lsn));
+    }
+
+    /// Returns the LSN below which obsolete versions may be dropped, which is
+    /// `watermark` unless the visible LSN or an older pinned LSN is smaller.
+    pub(super) fn watermark(&self, watermark: u64) -> u64 {
+        lock(&self.inner).watermark(watermark)
+    }
+}
+
+impl SnapshotsInner {
+    fn watermark(&self, watermark: u64) -> u64 {
+        let oldest = self.pins.keys().next().copied();
+        [Some(watermark), self.visible, oldest]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(watermark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_snapshots_hold_watermark() {
+        let snapshots = Snapshots::default();
+        assert_eq!(snapshots.watermark(u64::MAX), u64::MAX);
+
+        // A long-lived snapshot holds the watermark while writes go on.
+        assert!(snapshots.pin(5, 5));
+        assert_eq!(snapshots.watermark(u64::MAX), 5);
+        assert!(snapshots.pin(9, u64::MAX));
+        assert!(snapshots.pin(5, u64::MAX));
+        assert_eq!(snapshots.watermark(u64::MAX), 5);
+        assert_eq!(snapshots.watermark(3), 3);
+        // Versions below the watermark may be gone already.
+        assert!(!snapshots.pin(4, u64::MAX));
+
+        snapshots.unpin(5);
+        assert_eq!(snapshots.watermark(u64::MAX), 5);
+        snapshots.unpin(5);
+        assert_eq!(snapshots.watermark(u64::MAX), 9);
+        snapshots.unpin(9);
+        assert_eq!(snapshots.watermark(u64::MAX), u64::MAX);
+        assert!(!snapshots.pin(9, u64::MAX));
+    }
+
+    #[test]
+    fn visible_lsn_holds_watermark() {
+        let snapshots = Snapshots::default();
+        assert_eq!(snapshots.pin_visible(), None);
+
+        snapshots.publish(5);
+        assert_eq!(snapshots.watermark(u64::MAX), 5);
+        assert_eq!(snapshots.watermark(3), 3);
+        assert_eq!(snapshots.pin_visible(), Some(5));
+
+        // The pin holds the watermark after the visible LSN advances.
+        snapshots.publish(8);
+        snapshots.publish(7);
+        assert_eq!(snapshots.watermark(u64::MAX), 5);
+        snapshots.unpin(5);
+        assert_eq!(snapshots.watermark(u64::MAX), 8);
+    }
+}
@@ -1,16 +1,152 @@
-use crate::util::atomic::Counter;
+use std::{collections::HashSet, sync::Mutex};
+
+use crate::util::{atomic::Counter, sync::lock};
 
 /// Statistics of a tree.
 #[derive(Clone, Debug, Default)]
 pub struct Stats {
     pub success: TxnStats,
     pub restart: TxnStats,
+    /// The maximum number of restarts observed by a single operation.
+    ///
+    /// A large value indicates that some operations are starved on hot pages.
+    pub max_restarts: u64,
+    /// The number of consolidations triggered by the length of page chains.
+    pub chain_length_consolidations: u64,
+    /// The number of consolidations triggered by the size of delta pages.
+    pub chain_bytes_consolidations: u64,
+    /// The number of consolidations forced by
+    /// [`crate::Options::hard_max_delta_chain`].
+    pub hard_chain_consolidations: u64,
+    /// The number of writes stalled by pending flushes.
+    pub write_stalls: u64,
+    /// The total time writes are stalled by pending flushes, in microseconds.
+    pub write_stall_micros: u64,
+    /// The number of page splits and consolidations in progress.
+    ///
+    /// This is bounded by [`crate::Options::max_concurrent_smos`].
+    pub smos_in_flight: u64,
+    /// The number of point reads answered by the bloom filter of a leaf page
+    /// without loading the page.
+    ///
+    /// See [`crate::Options::leaf_bloom_bits_per_key`].
+    pub bloom_skipped_loads: u64,
+}
+
+impl Stats {
+    /// Returns the statistics accumulated since the `previous` snapshot.
+    ///
+    /// Counters that went backwards, e.g. after a restart, saturate at zero.
+    /// [`Stats::max_restarts`] and [`Stats::smos_in_flight`] are not counters,
+    /// so the current values are kept.
+    pub fn diff(&self, previous: &Stats) -> Stats {
+        Stats {
+            success: self.success.diff(&previous.success),
+            restart: self.restart.diff(&previous.restart),
+            max_restarts: self.max_restarts,
+            chain_length_consolidations: self
+                .chain_length_consolidations
+                .saturating_sub(previous.chain_length_consolidations),
+            chain_bytes_consolidations: self
+                .chain_bytes_consolidations
+                .saturating_sub(previous.chain_bytes_consolidations),
+            hard_chain_consolidations: self
+                .hard_chain_consolidations
+                .saturating_sub(previous.hard_chain_consolidations),
+            write_stalls: self.write_stalls.saturating_sub(previous.write_stalls),
+            write_stall_micros: self
+                .write_stall_micros
+                .saturating_sub(previous.write_stall_micros),
+            smos_in_flight: self.smos_in_flight,
+            bloom_skipped_loads: self
+                .bloom_skipped_loads
+                .saturating_sub(previous.bloom_skipped_loads),
+        }
+    }
+}
+
+/// Pressure of the structure maintenance of a tree.
+///
+/// This can be used to decide when to intervene with background work. Pages
+/// are counted as they change, so the pages that have not changed since the
+/// table was opened are not counted.
+#[derive(Clone, Debug, Default)]
+pub struct Pressure {
+    /// The number of leaf pages larger than the page size, including their
+    /// delta pages.
+    pub leaves_over_threshold: u64,
+    /// The number of page chains that should be consolidated.
+    pub chains_over_threshold: u64,
+    /// The number of page files picked by the GC strategy.
+    pub files_needing_gc: u64,
+}
+
+/// Tracks the pages that make up the [`Pressure`] of a tree, so that it is
+/// read without walking through the tree.
+#[derive(Default)]
+pub(super) struct PressureTracker {
+    large_leaves: Mutex<HashSet<u64>>,
+    long_chains: Mutex<HashSet<u64>>,
+}
+
+impl PressureTracker {
+    /// Updates the state of the page with the id after it changes.
+    pub(super) fn update(&self, id: u64, large_leaf: bool, long_chain: bool) {
+        set_contains(&self.large_leaves, id, large_leaf);
+        set_contains(&self.long_chains, id, long_chain);
+    }
+
+    /// Moves the state of the page to another id.
+    pub(super) fn move_page(&self, from: u64, to: u64) {
+        for ids in [&self.large_leaves, &self.long_chains] {
+            let mut ids = lock(ids);
+            if ids.remove(&from) {
+                ids.insert(to);
+            }
+        }
+    }
+
+    /// Returns the pressure of the tracked pages.
+    pub(super) fn snapshot(&self) -> Pressure {
+        Pressure {
+            leaves_over_threshold: lock(&self.large_leaves).len() as u64,
+            chains_over_threshold: lock(&self.long_chains).len() as u64,
+            files_needing_gc: 0,
+        }
+    }
+}
+
+fn set_contains(ids: &Mutex<HashSet<u64>>, id: u64, contains: bool) {
+    let mut ids = lock(ids);
+    if contains {
+        ids.insert(id);
+    } else {
+        ids.remove(&id);
+    }
+}
+
+/// The result of [`crate::Table::compact_and_verify`].
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    /// The number of pages verified.
+    pub pages_verified: u64,
+    /// The ids of the malformed pages. Nothing is compacted if there are any.
+    pub corrupted_pages: Vec<u64>,
+    /// The number of page chains consolidated.
+    pub pages_compacted: u64,
 }
 
 #[derive(Default)]
 pub(super) struct AtomicStats {
     pub(super) success: AtomicTxnStats,
     pub(super) restart: AtomicTxnStats,
+    pub(super) max_restarts: Counter,
+    pub(super) chain_length_consolidations: Counter,
+    pub(super) chain_bytes_consolidations: Counter,
+    pub(super) hard_chain_consolidations: Counter,
+    pub(super) write_stalls: Counter,
+    pub(super) write_stall_micros: Counter,
+    pub(super) bloom_skipped_loads: Counter,
 }
 
 impl AtomicStats {
@@ -18,6 +154,14 @@ impl AtomicStats {
         Stats {
             success: self.success.snapshot(),
             restart: self.restart.snapshot(),
+            max_restarts: self.max_restarts.get(),
+            chain_length_consolidations: self.chain_length_consolidations.get(),
+            chain_bytes_consolidations: self.chain_bytes_consolidations.get(),
+            hard_chain_consolidations: self.hard_chain_consolidations.get(),
+            write_stalls: self.write_stalls.get(),
+            write_stall_micros: self.write_stall_micros.get(),
+            smos_in_flight: 0,
+            bloom_skipped_loads: self.bloom_skipped_loads.get(),
         }
     }
 }
@@ -27,14 +171,31 @@ impl AtomicStats {
 pub struct TxnStats {
     pub get: u64,
     pub write: u64,
+    pub scan: u64,
     pub split_page: u64,
     pub consolidate_page: u64,
 }
 
+impl TxnStats {
+    /// Returns the statistics accumulated since the `previous` snapshot.
+    pub fn diff(&self, previous: &TxnStats) -> TxnStats {
+        TxnStats {
+            get: self.get.saturating_sub(previous.get),
+            write: self.write.saturating_sub(previous.write),
+            scan: self.scan.saturating_sub(previous.scan),
+            split_page: self.split_page.saturating_sub(previous.split_page),
+            consolidate_page: self
+                .consolidate_page
+                .saturating_sub(previous.consolidate_page),
+        }
+    }
+}
+
 #[derive(Default)]
 pub(super) struct AtomicTxnStats {
     pub(super) get: Counter,
     pub(super) write: Counter,
+    pub(super) scan: Counter,
     pub(super) split_page: Counter,
     pub(super) consolidate_page: Counter,
 }
@@ -44,8 +205,38 @@ impl AtomicTxnStats {
         TxnStats {
             get: self.get.get(),
             write: self.write.get(),
+            scan: self.scan.get(),
             split_page: self.split_page.get(),
             consolidate_page: self.consolidate_page.get(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_diff() {
+        let stats = AtomicStats::default();
+        stats.success.get.inc();
+        stats.success.write.inc();
+        let previous = stats.snapshot();
+
+        for _ in 0..3 {
+            stats.success.get.inc();
+        }
+        stats.success.scan.inc();
+        stats.restart.write.inc();
+        let diff = stats.snapshot().diff(&previous);
+        assert_eq!(diff.success.get, 3);
+        assert_eq!(diff.success.write, 0);
+        assert_eq!(diff.success.scan, 1);
+        assert_eq!(diff.restart.write, 1);
+
+        // Counters that were reset saturate at zero.
+        let diff = AtomicStats::default().snapshot().diff(&previous);
+        assert_eq!(diff.success.get, 0);
+        assert_eq!(diff.success.write, 0);
+    }
+}
@@ -1,9 +1,13 @@
-use super::{page::*, Tree};
-use crate::{env::Env, page::*, page_store::*};
+use std::{collections::HashSet, fmt::Debug};
+
+use futures::future::BoxFuture;
+
+use super::{bloom::BloomFilter, cursor::LeafPosition, page::*, AccessHint, Tree, VerifyReport};
+use crate::{env::Env, page::*, page_store::*, util::checksum::crc32c, StaleWrites};
 
 pub(super) struct TreeTxn<'a, E: Env> {
     tree: &'a Tree<E>,
-    guard: Guard<'a>,
+    pub(super) guard: Guard<'a>,
 }
 
 impl<'a, E: Env> TreeTxn<'a, E> {
@@ -16,14 +20,90 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     }
 
     /// Gets the value corresponding to the key.
-    pub(super) async fn get(&self, key: Key<'_>) -> Result<Option<&[u8]>> {
+    ///
+    /// Also returns the id of the leaf page if it should be consolidated
+    /// after the read.
+    pub(super) async fn get(&self, key: Key<'_>) -> Result<(Option<&[u8]>, Option<u64>)> {
+        let use_blooms = self.tree.options.leaf_bloom_bits_per_key > 0;
+        let Some((view, _)) = self.find_leaf_unless_excluded(&key, use_blooms).await? else {
+            self.tree.stats.bloom_skipped_loads.inc();
+            return Ok((None, None));
+        };
+        if use_blooms {
+            self.cache_leaf_bloom(&view).await?;
+        }
+        let value = self.find_value(&key, &view).await?;
+        let limits = ChainLimits::new(self.tree, PageTier::Leaf);
+        // Only the chain length is checked to keep the read path cheap.
+        let hot_leaf = (self.tree.options.read_triggered_consolidation
+            && limits.trigger(view.page.chain_len() as usize, 0).is_some())
+        .then_some(view.id);
+        Ok((value, hot_leaf))
+    }
+
+    /// Gets the LSN of the newest version of the key.
+    ///
+    /// Returns `None` if the key does not exist or has been deleted.
+    pub(super) async fn version_of(&self, key: Key<'_>) -> Result<Option<u64>> {
+        let (view, _) = self.find_leaf(&key).await?;
+        let item = self.find_visible_item(&key, &view).await?;
+        match item {
+            Some((lsn, Value::Put(_) | Value::Lww(..) | Value::Checked(..))) => Ok(Some(lsn)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Gets the value corresponding to the key with an access hint.
+    ///
+    /// If the leaf page cached in the hint may contain the key and it has not
+    /// changed since, the traversal from the root is skipped.
+    pub(super) async fn get_with_hint(
+        &self,
+        key: Key<'_>,
+        hint: &mut AccessHint,
+    ) -> Result<Option<&[u8]>> {
+        if let Some((id, epoch)) = hint.lookup(key.raw) {
+            let view = self.page_view(id, Range::full()).await?;
+            if view.page.epoch() == epoch && view.page.tier().is_leaf() {
+                return self.find_value(&key, &view).await;
+            }
+            hint.clear();
+        }
         let (view, _) = self.find_leaf(&key).await?;
+        hint.update(view.id, view.page.epoch(), view.range);
         self.find_value(&key, &view).await
     }
 
+    /// Collects the visible key-value pairs from the leaf page that contains
+    /// `start`, beginning at `start` and skipping `after` if it is given.
+    ///
+    /// Returns the position of the leaf page to continue the scan.
+    pub(super) async fn scan_leaf<T>(
+        &self,
+        start: &[u8],
+        after: Option<&[u8]>,
+        lsn: u64,
+        f: fn(&[u8]) -> T,
+        out: &mut Vec<(Vec<u8>, T)>,
+    ) -> Result<LeafPosition> {
+        let (view, _) = self.find_leaf(&Key::new(start, u64::MAX)).await?;
+        let iter = self.iter_page::<Key, Value>(&view).await?;
+        let mut iter = VisibleLeafPageIter::new(iter, lsn);
+        iter.seek(start);
+        collect_visible(iter, view.range.end, after, f, out)?;
+        Ok(LeafPosition {
+            id: view.id,
+            epoch: view.page.epoch(),
+            end: view.range.end.map(|end| end.to_vec()),
+        })
+    }
+
     /// Writes the key-value pair to the tree.
     pub(super) async fn write(&self, key: Key<'_>, value: Value<'_>) -> Result<()> {
         let (mut view, parent) = self.find_leaf(&key).await?;
+        if self.is_stale_write(&key, &view).await? {
+            return Ok(());
+        }
         // Build a delta page with the given key-value pair.
         let iter = ItemIter::new((key, value));
         let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(iter);
@@ -50,6 +130,10 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     if page.epoch() == view.page.epoch() {
                         view.addr = addr;
                         view.page = page;
+                        // The key may have been written by other transactions.
+                        if self.is_stale_write(&key, &view).await? {
+                            return Ok(());
+                        }
                         continue;
                     }
                     return Err(Error::Again);
@@ -58,7 +142,8 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
 
         // Try to consolidate the page if it is too long.
-        if self.should_consolidate_page(view.page) {
+        if let Ok(Some(trigger)) = self.should_consolidate_page(&view).await {
+            self.record_consolidation(trigger);
             let _ = self.consolidate_page(view, parent).await;
         }
         Ok(())
@@ -68,11 +153,327 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     pub(super) async fn rewrite(&self, page_id: u64) -> Result<()> {
         let range = Range::full();
         let view = self.page_view(page_id, range).await?;
-        // FIXME: disable split since parent is `None`.
+        // The page is not split, since the parent is unknown.
         self.consolidate_page(view, None).await?;
         Ok(())
     }
 
+    /// Consolidates the page chain of the leaf page with some delta pages.
+    ///
+    /// Returns false if the page is not a leaf page or has no delta pages.
+    pub(super) async fn flatten_leaf(&self, id: u64) -> Result<bool> {
+        let view = self.page_view(id, Range::full()).await?;
+        if !view.page.tier().is_leaf() || view.page.chain_len() <= 1 {
+            return Ok(false);
+        }
+        self.consolidate_page(view, None).await?;
+        Ok(true)
+    }
+
+    /// Purges all versions of the key from the leaf page that contains
+    /// `start`.
+    ///
+    /// Returns the start of the next leaf page if the key is beyond this one.
+    ///
+    /// The pages under a split delta page still hold the keys moved to the
+    /// right page, so the key may be on the chain of any leaf page up to the
+    /// one that contains it. If the chain holds the key, it is consolidated
+    /// into a new base page without the key, and the old pages are
+    /// deallocated. The chain may start with a split delta page, since
+    /// [`Self::find_leaf`] only returns pages whose splits are reconciled. The
+    /// keys moved to the right page are beyond the range of the iterator, so
+    /// the new base page only holds the keys of this page.
+    pub(super) async fn purge_leaf(&self, start: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (view, _) = self.find_leaf(&Key::new(start, u64::MAX)).await?;
+        let next = view
+            .range
+            .end
+            .filter(|end| *end <= key)
+            .map(|end| end.to_vec());
+        // Collect the addresses of all pages on the chain.
+        let mut page_addrs = Vec::with_capacity(view.page.chain_len() as usize);
+        let mut next_addr = view.addr;
+        let mut holds_key = false;
+        self.walk_page(view.page, |page| {
+            page_addrs.push(next_addr);
+            next_addr = page.chain_next();
+            holds_key |= leaf_holds_key(page, key);
+            false
+        })
+        .await?;
+        if !holds_key {
+            return Ok(next);
+        }
+        let safe_lsn = self.tree.gc_watermark();
+        let iter = self.iter_page::<Key, Value>(&view).await?;
+        let iter = PurgingLeafPageIter::new(MergingLeafPageIter::new(iter, safe_lsn, false), key);
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(iter);
+        let mut txn = self.guard.begin();
+        let (new_addr, mut new_page) = txn.alloc_page(builder.size())?;
+        builder.build(&mut new_page);
+        new_page.set_epoch(view.page.epoch());
+        new_page.set_chain_len(1);
+        new_page.set_chain_next(0);
+        txn.replace_page(view.id, view.addr, new_addr, &page_addrs)?;
+        let view = PageView {
+            addr: new_addr,
+            page: new_page.into(),
+            ..view
+        };
+        self.track_pressure(&view).await;
+        Ok(next)
+    }
+
+    /// Builds the root page of an empty tree.
+    ///
+    /// Without split keys, the root is an empty leaf page. Otherwise, the root
+    /// is an index page over an empty leaf page for each range between the
+    /// keys.
+    pub(super) fn init_root(&self, split_keys: &[Vec<u8>]) -> Result<()> {
+        let starts = leaf_starts(split_keys);
+        let mut txn = self.guard.begin();
+        if starts.len() > 1 {
+            // A new page table allocates ids in order, so the leaf pages take
+            // the ids right after the root.
+            let children = starts
+                .iter()
+                .enumerate()
+                .map(|(i, &start)| (start, Index::new(MIN_ID + 1 + i as u64, 0)))
+                .collect::<Vec<_>>();
+            let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data)
+                .with_iter(SliceIter::new(&children));
+            let (addr, mut page) = txn.alloc_page(builder.size())?;
+            builder.build(&mut page);
+            let root_id = txn.insert_page(addr);
+            debug_assert_eq!(root_id, MIN_ID);
+            for (_, index) in &children {
+                let id = insert_empty_leaf(&mut txn)?;
+                debug_assert_eq!(id, index.id);
+            }
+        } else {
+            let root_id = insert_empty_leaf(&mut txn)?;
+            debug_assert_eq!(root_id, MIN_ID);
+        }
+        txn.commit();
+        Ok(())
+    }
+
+    /// Returns the raw page with the id, encoded by [`encode_raw_page`].
+    ///
+    /// Only base pages are shipped, since delta pages refer to other pages by
+    /// their local addresses. A page with a chain is consolidated first, and
+    /// `Error::Again` is returned to retry.
+    pub(super) async fn read_raw_page(&self, id: u64) -> Result<Vec<u8>> {
+        let Some(addr) = self.guard.try_page_addr(id) else {
+            return Err(Error::InvalidArgument);
+        };
+        let page = self.guard.read_page(addr).await?;
+        if page.chain_next() != 0 || page.kind().is_split() {
+            let view = PageView {
+                id,
+                addr,
+                page,
+                range: Range::full(),
+            };
+            self.consolidate_page(view, None).await?;
+            return Err(Error::Again);
+        }
+        Ok(encode_raw_page(page))
+    }
+
+    /// Replaces the page with the id by a raw page read from another tree.
+    ///
+    /// The page keeps the id and epoch it has on the other tree, so the
+    /// index pages shipped along with it still refer to it. The id must be
+    /// mapped in this tree, since ids can not be allocated on demand.
+    pub(super) async fn apply_raw_page(&self, id: u64, raw: &[u8]) -> Result<()> {
+        let data = decode_raw_page(raw)?;
+        let Some(addr) = self.guard.try_page_addr(id) else {
+            return Err(Error::InvalidArgument);
+        };
+        let page = self.guard.read_page(addr).await?;
+        // Collect the addresses of all pages on the chain.
+        let mut page_addrs = Vec::with_capacity(page.chain_len() as usize);
+        let mut next_addr = addr;
+        self.walk_page(page, |page| {
+            page_addrs.push(next_addr);
+            next_addr = page.chain_next();
+            false
+        })
+        .await?;
+        let mut txn = self.guard.begin();
+        let (new_addr, mut new_page) = txn.alloc_page(data.len())?;
+        new_page.data_mut().copy_from_slice(data);
+        let new_page: PageRef<'_> = new_page.into();
+        if new_page.tier() != page.tier() || !verify_page(new_page, &Range::full()) {
+            return Err(Error::Corrupted);
+        }
+        txn.replace_page(id, addr, new_addr, &page_addrs)?;
+        let view = PageView {
+            id,
+            addr: new_addr,
+            page: new_page,
+            range: Range::full(),
+        };
+        self.track_pressure(&view).await;
+        Ok(())
+    }
+
+    /// Collects the ids of all pages reachable from the root.
+    ///
+    /// Pages that are split but not yet linked to their parents are reached
+    /// through the split deltas of their left siblings.
+    pub(super) async fn live_page_ids(&self) -> Result<Vec<u64>> {
+        let mut ids = HashSet::new();
+        let mut pending = vec![MIN_ID];
+        while let Some(id) = pending.pop() {
+            // Pages may be reached by both a split delta and their parent.
+            if !ids.insert(id) {
+                continue;
+            }
+            let view = self.page_view(id, Range::full()).await?;
+            self.walk_page(view.page, |page| {
+                if page.kind().is_split() {
+                    let (_, index) = split_delta_from_page(page);
+                    pending.push(index.id);
+                }
+                false
+            })
+            .await?;
+            if view.page.tier().is_inner() {
+                let iter = self.iter_page::<&[u8], Index>(&view).await?;
+                for (_, index) in MergingInnerPageIter::new(iter) {
+                    pending.push(index.id);
+                }
+            }
+        }
+        let mut ids = ids.into_iter().collect::<Vec<_>>();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Verifies all pages reachable from the root.
+    ///
+    /// Returns the report and the ids of the pages with delta pages on their
+    /// chains.
+    pub(super) async fn verify_pages(&self) -> Result<(VerifyReport, Vec<u64>)> {
+        let mut report = VerifyReport::default();
+        let mut chains = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending: Vec<(u64, Vec<u8>, Option<Vec<u8>>)> = vec![(MIN_ID, Vec::new(), None)];
+        while let Some((id, start, end)) = pending.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            report.pages_verified += 1;
+            // Pages that fail their checksums are read as corrupted.
+            let view = match self.page_view(id, Range::full()).await {
+                Ok(view) => view,
+                Err(Error::Corrupted) => {
+                    report.corrupted_pages.push(id);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let mut range = Range {
+                start: &start,
+                end: end.as_deref(),
+            };
+            let mut valid = true;
+            let walked = self
+                .walk_page(view.page, |page| {
+                    valid = verify_page(page, &range);
+                    if valid && page.kind().is_split() {
+                        // Pages reached by a split delta may also be reached by the
+                        // parent with a narrower range, so the end is not checked.
+                        let (split_key, index) = split_delta_from_page(page);
+                        pending.push((index.id, split_key.to_vec(), None));
+                        // Older pages on the chain may still hold the moved keys.
+                        range.end = None;
+                    }
+                    !valid
+                })
+                .await;
+            match walked {
+                Ok(()) => {}
+                Err(Error::Corrupted) => valid = false,
+                Err(err) => return Err(err),
+            }
+            if !valid {
+                report.corrupted_pages.push(id);
+                continue;
+            }
+            if view.page.chain_len() > 1 {
+                chains.push(id);
+            }
+            if view.page.tier().is_inner() {
+                let iter = self.iter_page::<&[u8], Index>(&view).await?;
+                let children = MergingInnerPageIter::new(iter).collect::<Vec<_>>();
+                let mut child_end = end.clone();
+                for (child_start, index) in children.into_iter().rev() {
+                    pending.push((index.id, child_start.to_vec(), child_end));
+                    child_end = Some(child_start.to_vec());
+                }
+            }
+        }
+        report.corrupted_pages.sort_unstable();
+        Ok((report, chains))
+    }
+
+    /// Collects the start key and the chain size of every leaf page in key
+    /// order.
+    pub(super) async fn leaf_sizes(&self) -> Result<Vec<(Vec<u8>, usize)>> {
+        let mut leaves = Vec::new();
+        let mut pending = vec![(MIN_ID, Vec::new())];
+        while let Some((id, start)) = pending.pop() {
+            let view = self.page_view(id, Range::full()).await?;
+            if view.page.tier().is_leaf() {
+                let mut size = 0;
+                self.walk_page(view.page, |page| {
+                    size += page.size();
+                    false
+                })
+                .await?;
+                leaves.push((start, size));
+                continue;
+            }
+            let iter = self.iter_page::<&[u8], Index>(&view).await?;
+            let children = MergingInnerPageIter::new(iter).collect::<Vec<_>>();
+            // Push in reverse so that children are visited in key order.
+            for (child_start, index) in children.into_iter().rev() {
+                pending.push((index.id, child_start.to_vec()));
+            }
+        }
+        Ok(leaves)
+    }
+
+    /// Returns the largest LSN of the versions in all leaf pages, or `None`
+    /// if there is none.
+    ///
+    /// Keys moved out by a split are still in the chain of the left page,
+    /// which does not change the result.
+    pub(super) async fn max_lsn(&self) -> Result<Option<u64>> {
+        let mut max_lsn = None;
+        for id in self.live_page_ids().await? {
+            let view = self.page_view(id, Range::full()).await?;
+            if !view.page.tier().is_leaf() {
+                continue;
+            }
+            self.try_walk_page(view.page, |page| {
+                if page.kind().is_data() {
+                    let page = ValuePageRef::try_new(page)?;
+                    let lsns = (0..page.len())
+                        .filter_map(|i| page.get(i))
+                        .map(|(k, _)| k.lsn);
+                    max_lsn = max_lsn.max(lsns.max());
+                }
+                Ok(false)
+            })
+            .await?;
+        }
+        Ok(max_lsn)
+    }
+
     pub(super) async fn page_view<'g>(&'g self, id: u64, range: Range<'g>) -> Result<PageView<'g>> {
         let addr = self.guard.page_addr(id);
         let page = self.guard.read_page(addr).await?;
@@ -91,6 +492,19 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         &self,
         key: &Key<'_>,
     ) -> Result<(PageView<'_>, Option<PageView<'_>>)> {
+        let leaf = self.find_leaf_unless_excluded(key, false).await?;
+        Ok(leaf.expect("leaf pages are only excluded by blooms"))
+    }
+
+    /// Finds the leaf page that may contain the key like [`Self::find_leaf`].
+    ///
+    /// If `use_blooms` is true, returns `None` without loading the leaf page
+    /// if its bloom filter excludes the key.
+    async fn find_leaf_unless_excluded(
+        &self,
+        key: &Key<'_>,
+        use_blooms: bool,
+    ) -> Result<Option<(PageView<'_>, Option<PageView<'_>>)>> {
         // The index, range, and parent of the current page, starting from the root.
         let mut index = Index::new(MIN_ID, 0);
         let mut range = Range::full();
@@ -103,8 +517,20 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 let _ = self.reconcile_page(view, parent).await;
                 return Err(Error::Again);
             }
+            // Consolidate an overly long page chain before walking through it, and restart
+            // the operation on the consolidated page.
+            if exceeds_hard_chain_limit(
+                view.page.chain_len(),
+                self.tree.options.hard_max_delta_chain,
+            ) {
+                self.tree.stats.hard_chain_consolidations.inc();
+                return match self.consolidate_page(view, parent).await {
+                    Ok(()) | Err(Error::Again) => Err(Error::Again),
+                    Err(err) => Err(err),
+                };
+            }
             if view.page.tier().is_leaf() {
-                return Ok((view, parent));
+                return Ok(Some((view, parent)));
             }
             // Find the child page that may contain the key and update the current page.
             let (child_index, child_range) = self
@@ -112,6 +538,13 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 .await?
                 .expect("child page must exist");
             index = child_index;
+            if use_blooms {
+                let addr = self.guard.page_addr(index.id);
+                let blooms = &self.tree.leaf_blooms;
+                if blooms.excludes(index.id, addr, index.epoch, key.raw) {
+                    return Ok(None);
+                }
+            }
             range.start = child_range.start;
             // If the child has no range end, use the current one instead.
             if let Some(end) = child_range.end {
@@ -121,23 +554,63 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
     }
 
+    /// Builds the bloom filter of a leaf page loaded from a page file, if it
+    /// has none yet.
+    async fn cache_leaf_bloom(&self, view: &PageView<'_>) -> Result<()> {
+        let blooms = &self.tree.leaf_blooms;
+        if self.guard.is_in_memory(view.addr) || blooms.contains(view.id, view.addr) {
+            return Ok(());
+        }
+        // Keys moved out by a split are still in the chain, which only adds
+        // false positives.
+        let mut keys = Vec::new();
+        self.try_walk_page(view.page, |page| {
+            if page.kind().is_data() {
+                let page = ValuePageRef::try_new(page)?;
+                keys.extend(
+                    (0..page.len())
+                        .filter_map(|i| page.get(i))
+                        .map(|(k, _)| k.raw),
+                );
+            }
+            Ok(false)
+        })
+        .await?;
+        // The versions of a key are adjacent in each page.
+        keys.dedup();
+        let filter = BloomFilter::build(&keys, self.tree.options.leaf_bloom_bits_per_key);
+        blooms.insert(view.id, view.addr, view.page.epoch(), filter);
+        Ok(())
+    }
+
     /// Walks through the page chain and applies the function to each page.
     ///
     /// This function returns when it reaches the end of the chain or the
     /// applied function returns true.
-    async fn walk_page<'g, F>(&'g self, mut page: PageRef<'g>, mut f: F) -> Result<()>
+    pub(super) async fn walk_page<'g, F>(&'g self, page: PageRef<'g>, mut f: F) -> Result<()>
     where
         F: FnMut(PageRef<'g>) -> bool,
+    {
+        self.try_walk_page(page, |page| Ok(f(page))).await
+    }
+
+    /// Like [`Self::walk_page`], but stops at the first error returned by `f`.
+    pub(super) async fn try_walk_page<'g, F>(
+        &'g self,
+        mut page: PageRef<'g>,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(PageRef<'g>) -> Result<bool>,
     {
         loop {
-            if f(page) || page.chain_next() == 0 {
+            if f(page)? || page.chain_next() == 0 {
                 return Ok(());
             }
             page = self.guard.read_page(page.chain_next()).await?;
         }
     }
 
-    #[allow(dead_code)]
     /// Creates an iterator over the key-value pairs in the page.
     pub(super) async fn iter_page<'g, K, V>(
         &'g self,
@@ -149,10 +622,10 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     {
         let mut builder = MergingIterBuilder::with_capacity(view.page.chain_len() as usize);
         let mut range_limit = None;
-        self.walk_page(view.page, |page| {
+        self.try_walk_page(view.page, |page| {
             match page.kind() {
                 PageKind::Data => {
-                    builder.add(SortedPageIter::from(page));
+                    builder.add(SortedPageIter::new(SortedPageRef::try_new(page)?));
                 }
                 PageKind::Split => {
                     // The split key we first encountered must be the smallest.
@@ -167,42 +640,107 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     }
                 }
             }
-            false
+            Ok(false)
         })
         .await?;
         Ok(MergingPageIter::new(builder.build(), range_limit))
     }
 
+    /// Returns true if the write should be dropped because it is not newer
+    /// than the latest version of the key in the page.
+    ///
+    /// Returns [`Error::InvalidArgument`] if such writes are rejected.
+    async fn is_stale_write(&self, key: &Key<'_>, view: &PageView<'_>) -> Result<bool> {
+        let policy = self.tree.options.stale_writes;
+        if policy == StaleWrites::Allow {
+            return Ok(false);
+        }
+        let latest = Key::new(key.raw, u64::MAX);
+        match self.find_item(&latest, view).await? {
+            Some((lsn, _)) if lsn >= key.lsn => match policy {
+                StaleWrites::Reject => Err(Error::InvalidArgument),
+                _ => Ok(true),
+            },
+            _ => Ok(false),
+        }
+    }
+
     /// Finds the value corresponding to the key from the page.
     async fn find_value<'g>(
         &'g self,
         key: &Key<'_>,
         view: &PageView<'g>,
     ) -> Result<Option<&'g [u8]>> {
-        let mut value = None;
-        self.walk_page(view.page, |page| {
+        let item = self.find_visible_item(key, view).await?;
+        // An empty value is still a value, only tombstones hide the key.
+        match item {
+            Some((_, value)) => visible_value(value),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds the version of the key visible at the key LSN from the page.
+    ///
+    /// Unlike [`Self::find_item`], this resolves a run of LWW versions to the
+    /// one that wins. Returns the LSN and value of the version.
+    async fn find_visible_item<'g>(
+        &'g self,
+        key: &Key<'_>,
+        view: &PageView<'g>,
+    ) -> Result<Option<(u64, Value<'g>)>> {
+        let item = self.find_item(key, view).await?;
+        if !matches!(item, Some((_, Value::Lww(..)))) {
+            return Ok(item);
+        }
+        // The versions of the key may be spread across the page chain.
+        let mut versions = Vec::new();
+        self.try_walk_page(view.page, |page| {
+            if page.kind().is_data() {
+                let page = ValuePageRef::try_new(page)?;
+                let index = match page.rank_by(key, self.tree.options.seek_strategy) {
+                    Ok(i) => i,
+                    Err(i) => i,
+                };
+                let items = (index..).map_while(|i| page.get(i));
+                versions.extend(items.take_while(|(k, _)| k.raw == key.raw));
+            }
+            Ok(false)
+        })
+        .await?;
+        Ok(resolve_versions(versions).map(|(k, v)| (k.lsn, v)))
+    }
+
+    /// Finds the newest version of the key visible at the key LSN from the
+    /// page.
+    ///
+    /// Returns the LSN and value of the version.
+    async fn find_item<'g>(
+        &'g self,
+        key: &Key<'_>,
+        view: &PageView<'g>,
+    ) -> Result<Option<(u64, Value<'g>)>> {
+        let mut item = None;
+        self.try_walk_page(view.page, |page| {
             debug_assert!(page.tier().is_leaf());
             // We only care about data pages here.
             if page.kind().is_data() {
-                let page = ValuePageRef::from(page);
-                let index = match page.rank(key) {
+                let page = ValuePageRef::try_new(page)?;
+                let index = match page.rank_by(key, self.tree.options.seek_strategy) {
                     Ok(i) => i,
                     Err(i) => i,
                 };
                 if let Some((k, v)) = page.get(index) {
                     if k.raw == key.raw {
                         debug_assert!(k.lsn <= key.lsn);
-                        if let Value::Put(v) = v {
-                            value = Some(v);
-                        }
-                        return true;
+                        item = Some((k.lsn, v));
+                        return Ok(true);
                     }
                 }
             }
-            false
+            Ok(false)
         })
         .await?;
-        Ok(value)
+        Ok(item)
     }
 
     /// Finds the child page that may contain the key from the page.
@@ -214,11 +752,11 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         view: &PageView<'g>,
     ) -> Result<Option<(Index, Range<'g>)>> {
         let mut child = None;
-        self.walk_page(view.page, |page| {
+        self.try_walk_page(view.page, |page| {
             debug_assert!(page.tier().is_inner());
             // We only care about data pages here.
             if page.kind().is_data() {
-                let page = IndexPageRef::from(page);
+                let page = IndexPageRef::try_new(page)?;
                 // Find the two items that enclose the key.
                 let (left, right) = match page.rank(&key) {
                     // The `i` item is equal to the key, so the range is [i, i + 1).
@@ -233,18 +771,22 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                             end: right.map(|(end, _)| end),
                         };
                         child = Some((index, range));
-                        return true;
+                        return Ok(true);
                     }
                 }
             }
-            false
+            Ok(false)
         })
         .await?;
         Ok(child)
     }
 
     // Splits the page into two halfs.
-    async fn split_page(&self, view: PageView<'_>, parent: Option<PageView<'_>>) -> Result<()> {
+    pub(super) async fn split_page(
+        &self,
+        view: PageView<'_>,
+        parent: Option<PageView<'_>>,
+    ) -> Result<()> {
         match view.page.tier() {
             PageTier::Leaf => self.split_page_impl::<Key, Value>(view, parent).await,
             PageTier::Inner => self.split_page_impl::<&[u8], Index>(view, parent).await,
@@ -257,7 +799,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         parent: Option<PageView<'_>>,
     ) -> Result<()>
     where
-        K: EncodeTo + DecodeFrom + Ord + Clone,
+        K: EncodeTo + DecodeFrom + Ord + Clone + Debug + SplitKey,
         V: EncodeTo + DecodeFrom,
     {
         // We can only split base data pages.
@@ -265,19 +807,27 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             return Err(Error::InvalidArgument);
         }
 
+        let permit = self.tree.smo_permits.acquire().await;
         let page = SortedPageRef::<K, V>::from(view.page);
         if let Some((split_key, right_iter)) = page.split() {
             let mut txn = self.guard.begin();
             // Build and insert the right page.
-            let right_id = {
+            let right = {
                 let builder = SortedPageBuilder::new(view.page.tier(), view.page.kind())
                     .with_iter(right_iter);
                 let (new_addr, mut new_page) = txn.alloc_page(builder.size())?;
                 builder.build(&mut new_page);
-                txn.insert_page(new_addr)
+                PageView {
+                    id: txn.insert_page(new_addr),
+                    addr: new_addr,
+                    page: new_page.into(),
+                    range: Range::full(),
+                }
             };
-            // Build a delta page with the right index.
-            let iter = ItemIter::new((split_key, Index::new(right_id, 0)));
+            let right_id = right.id;
+            // Build a delta page with the right index. The right page starts at
+            // the raw key, which is what index pages are searched with.
+            let iter = ItemIter::new((split_key.as_raw(), Index::new(right_id, 0)));
             let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Split).with_iter(iter);
             let (new_addr, mut new_page) = txn.alloc_page(builder.size())?;
             builder.build(&mut new_page);
@@ -296,15 +846,24 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     self.tree.stats.restart.split_page.inc();
                     Error::Again
                 })?;
+            self.track_pressure(&view).await;
+            self.track_pressure(&right).await;
         }
 
+        // The reconciliation may consolidate the parent, which takes another
+        // permit.
+        drop(permit);
         // Try to reconcile the page after a split.
         let _ = self.reconcile_page(view, parent).await;
         Ok(())
     }
 
     /// Reconciles any conflicts on the page.
-    async fn reconcile_page(&self, view: PageView<'_>, parent: Option<PageView<'_>>) -> Result<()> {
+    pub(super) async fn reconcile_page(
+        &self,
+        view: PageView<'_>,
+        parent: Option<PageView<'_>>,
+    ) -> Result<()> {
         match view.page.kind() {
             PageKind::Data => {}
             PageKind::Split => {
@@ -358,7 +917,8 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .map_err(|_| Error::Again)?;
 
         // Try to consolidate the parent page if it is too long.
-        if self.should_consolidate_page(parent.page) {
+        if let Ok(Some(trigger)) = self.should_consolidate_page(&parent).await {
+            self.record_consolidation(trigger);
             let _ = self.consolidate_page(parent, None).await;
         }
         Ok(())
@@ -382,22 +942,27 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         builder.build(&mut new_page);
         // Update the original root with the new root.
         txn.update_page(view.id, view.addr, new_addr)
-            .map_err(|_| Error::Again)
+            .map_err(|_| Error::Again)?;
+        self.tree.pressure.move_page(view.id, left_id);
+        Ok(())
     }
 
     /// Consolidates delta pages on the page chain.
-    async fn consolidate_page(
+    pub(super) async fn consolidate_page(
         &self,
         view: PageView<'_>,
         parent: Option<PageView<'_>>,
     ) -> Result<()> {
         match view.page.tier() {
             PageTier::Leaf => {
-                self.consolidate_page_impl(view, parent, MergingLeafPageIter::new)
-                    .await
+                let safe_lsn = self.tree.gc_watermark();
+                self.consolidate_page_impl(view, parent, |iter, whole_chain| {
+                    MergingLeafPageIter::new(iter, safe_lsn, whole_chain)
+                })
+                .await
             }
             PageTier::Inner => {
-                self.consolidate_page_impl(view, parent, MergingInnerPageIter::new)
+                self.consolidate_page_impl(view, parent, |iter, _| MergingInnerPageIter::new(iter))
                     .await
             }
         }
@@ -410,15 +975,21 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         f: F,
     ) -> Result<()>
     where
-        F: Fn(MergingPageIter<'g, K, V>) -> I,
+        F: Fn(MergingPageIter<'g, K, V>, bool) -> I,
         I: RewindableIterator<Item = (K, V)>,
-        K: EncodeTo + DecodeFrom + Ord,
+        K: EncodeTo + DecodeFrom + Ord + Clone + Debug,
         V: EncodeTo + DecodeFrom,
     {
+        let permit = self.tree.smo_permits.acquire().await;
         // Consolidate some delta pages on the chain.
         let cons = self.build_consolidation(&view).await?;
-        let iter = f(cons.iter);
-        let builder = SortedPageBuilder::new(view.page.tier(), view.page.kind()).with_iter(iter);
+        // If every page on the chain is consolidated, the new page becomes the
+        // base page. It may be empty if all keys are deleted, which is still a
+        // valid page since its range comes from the parent.
+        let whole_chain = cons.last_page.chain_next() == 0;
+        let iter = f(cons.iter, whole_chain);
+        // Split delta pages are folded into the range of the new page.
+        let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(iter);
         let mut txn = self.guard.begin();
         let (new_addr, mut new_page) = txn.alloc_page(builder.size())?;
         builder.build(&mut new_page);
@@ -436,10 +1007,18 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 self.tree.stats.restart.consolidate_page.inc();
                 Error::Again
             })?;
+        drop(permit);
 
-        // Try to split the page if it is too large.
-        if self.should_split_page(view.page) {
-            let _ = self.split_page(view, parent);
+        // Try to split the page if it is too large. Only splits that can be
+        // reconciled right away are made, since a consolidation without the
+        // parent would fold a pending split into the page. The split is boxed,
+        // since reconciling it may consolidate the parent in turn.
+        let can_reconcile = parent.is_some() || view.id == MIN_ID;
+        if can_reconcile && self.should_split_page(view.page) {
+            let split: BoxFuture<'_, Result<()>> = Box::pin(self.split_page(view, parent));
+            let _ = split.await;
+        } else {
+            self.track_pressure(&view).await;
         }
         Ok(())
     }
@@ -491,6 +1070,9 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     }
 
     // Returns true if the page should be split.
+    //
+    // A base page with a single entry larger than the page size is an overflow
+    // page. It is stored and read like any other page, but it can not be split.
     fn should_split_page(&self, page: PageRef<'_>) -> bool {
         let mut max_size = self.tree.options.page_size;
         if page.tier().is_inner() {
@@ -498,18 +1080,99 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             // TODO: do some benchmarks to evaluate this.
             max_size /= 2;
         }
-        page.size() > max_size
+        page.size() > max_size && !is_overflow_page(page)
     }
 
-    // Returns true if the page should be consolidated.
-    fn should_consolidate_page(&self, page: PageRef<'_>) -> bool {
-        let mut max_chain_len = self.tree.options.page_chain_length;
-        if page.tier().is_inner() {
-            // Adjust the chain length for inner pages.
+    // Returns the trigger if the page chain should be consolidated.
+    //
+    // This walks through the whole chain, and tracks the pressure of the page
+    // along the way.
+    async fn should_consolidate_page(
+        &self,
+        view: &PageView<'_>,
+    ) -> Result<Option<ConsolidationTrigger>> {
+        let limits = ChainLimits::new(self.tree, view.page.tier());
+        let chain_len = view.page.chain_len() as usize;
+        // Sum up the delta pages, the last page on the chain is the base page.
+        // The pages under a split delta page also hold the keys moved to the
+        // right page, so they are not counted in the size of the page.
+        let mut delta_bytes = 0;
+        let mut page_bytes = 0;
+        let mut split = false;
+        self.walk_page(view.page, |page| {
+            if page.chain_next() != 0 {
+                delta_bytes += page.size();
+            }
+            if !split {
+                page_bytes += page.size();
+            }
+            split |= page.kind().is_split();
+            false
+        })
+        .await?;
+        let trigger = limits.trigger(chain_len, delta_bytes);
+        let large_leaf = view.page.tier().is_leaf()
+            && page_bytes > self.tree.options.page_size
+            && !is_overflow_page(view.page);
+        self.tree
+            .pressure
+            .update(view.id, large_leaf, trigger.is_some());
+        Ok(trigger)
+    }
+
+    /// Tracks the pressure of the page after it changes.
+    async fn track_pressure(&self, view: &PageView<'_>) {
+        // The page is tracked again when it changes next time if this fails.
+        let _ = self.should_consolidate_page(view).await;
+    }
+
+    fn record_consolidation(&self, trigger: ConsolidationTrigger) {
+        match trigger {
+            ConsolidationTrigger::ChainLength => self.tree.stats.chain_length_consolidations.inc(),
+            ConsolidationTrigger::ChainBytes => self.tree.stats.chain_bytes_consolidations.inc(),
+        };
+    }
+}
+
+/// The reason to consolidate a page chain.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ConsolidationTrigger {
+    /// The page chain is too long.
+    ChainLength,
+    /// The delta pages on the page chain are too large.
+    ChainBytes,
+}
+
+/// The limits of a page chain before it is consolidated.
+struct ChainLimits {
+    max_chain_len: usize,
+    max_delta_bytes: usize,
+}
+
+impl ChainLimits {
+    fn new<E: Env>(tree: &Tree<E>, tier: PageTier) -> Self {
+        let mut max_chain_len = tree.runtime_options.page_chain_length();
+        let mut max_delta_bytes = tree.options.max_delta_chain_bytes;
+        if tier.is_inner() {
+            // Adjust the limits for inner pages.
             // TODO: do some benchmarks to evaluate this.
             max_chain_len /= 2;
+            max_delta_bytes /= 2;
+        }
+        Self {
+            max_chain_len: max_chain_len.max(1),
+            max_delta_bytes,
+        }
+    }
+
+    fn trigger(&self, chain_len: usize, delta_bytes: usize) -> Option<ConsolidationTrigger> {
+        if chain_len > self.max_chain_len {
+            Some(ConsolidationTrigger::ChainLength)
+        } else if delta_bytes > self.max_delta_bytes {
+            Some(ConsolidationTrigger::ChainBytes)
+        } else {
+            None
         }
-        page.chain_len() as usize > max_chain_len.max(1)
     }
 }
 
@@ -523,9 +1186,616 @@ where
     page_addrs: Vec<u64>,
 }
 
-fn split_delta_from_page(page: PageRef<'_>) -> (&[u8], Index) {
+pub(super) fn split_delta_from_page(page: PageRef<'_>) -> (&[u8], Index) {
     debug_assert!(page.kind().is_split());
     IndexPageRef::from(page)
         .get(0)
         .expect("split page delta must exist")
 }
+
+/// Returns true if a page chain of the length must be consolidated before it
+/// is read.
+fn exceeds_hard_chain_limit(chain_len: u8, limit: usize) -> bool {
+    limit > 0 && chain_len as usize > limit
+}
+
+// Returns true if the page is a base page with a single oversized entry.
+fn is_overflow_page(page: PageRef<'_>) -> bool {
+    page.tier().is_leaf()
+        && page.kind().is_data()
+        && page.chain_next() == 0
+        && ValuePageRef::from(page).len() == 1
+}
+
+/// Returns the start keys of the leaf pages of a tree split at the keys.
+///
+/// The first leaf page starts at the empty key, and the rest start at the
+/// distinct split keys in order.
+fn leaf_starts(split_keys: &[Vec<u8>]) -> Vec<&[u8]> {
+    let mut starts = split_keys
+        .iter()
+        .map(|key| key.as_slice())
+        .filter(|key| !key.is_empty())
+        .collect::<Vec<_>>();
+    starts.sort_unstable();
+    starts.dedup();
+    starts.insert(0, [].as_slice());
+    starts
+}
+
+/// Inserts an empty leaf page, and returns its id.
+fn insert_empty_leaf(txn: &mut PageTxn<'_>) -> Result<u64> {
+    let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+        .with_iter(SliceIter::<(Key<'_>, Value<'_>)>::new(&[]));
+    let (addr, mut page) = txn.alloc_page(builder.size())?;
+    builder.build(&mut page);
+    Ok(txn.insert_page(addr))
+}
+
+/// The length of the checksum appended to raw pages.
+const RAW_PAGE_CHECKSUM_LEN: usize = 4;
+
+/// Encodes a base page to ship to another tree.
+///
+/// The format is the page data as stored in page files, followed by the
+/// CRC-32C (u32, little-endian) of the data.
+fn encode_raw_page(page: PageRef<'_>) -> Vec<u8> {
+    let data = page.data();
+    let mut buf = Vec::with_capacity(data.len() + RAW_PAGE_CHECKSUM_LEN);
+    buf.extend_from_slice(data);
+    buf.extend_from_slice(&crc32c(data).to_le_bytes());
+    buf
+}
+
+/// Returns the page data of a raw page produced by [`encode_raw_page`].
+///
+/// Returns `Error::Corrupted` if the bytes fail the checksum or do not hold a
+/// base page. The content of the page is not checked here.
+fn decode_raw_page(raw: &[u8]) -> Result<&[u8]> {
+    if raw.len() < RAW_PAGE_CHECKSUM_LEN {
+        return Err(Error::Corrupted);
+    }
+    let (data, checksum) = raw.split_at(raw.len() - RAW_PAGE_CHECKSUM_LEN);
+    let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+    if crc32c(data) != checksum || !is_base_page_header(data) {
+        return Err(Error::Corrupted);
+    }
+    Ok(data)
+}
+
+/// Returns true if the page content is consistent and its keys are sorted
+/// and within the range.
+fn verify_page(page: PageRef<'_>, range: &Range<'_>) -> bool {
+    if page.tier().is_leaf() && page.kind().is_data() {
+        match ValuePageRef::try_new(page) {
+            Ok(page) if page.validate().is_ok() => {
+                is_sorted_in_range(SortedPageIter::new(page).map(|(k, _)| k), |k| k.raw, range)
+            }
+            _ => false,
+        }
+    } else {
+        match IndexPageRef::try_new(page) {
+            Ok(page) if page.validate().is_ok() => {
+                is_sorted_in_range(SortedPageIter::new(page).map(|(k, _)| k), |k| *k, range)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn is_sorted_in_range<K: Ord>(
+    keys: impl Iterator<Item = K>,
+    raw: impl Fn(&K) -> &[u8],
+    range: &Range<'_>,
+) -> bool {
+    let mut last = None;
+    for key in keys {
+        if !range.contains(raw(&key)) || last.as_ref().map_or(false, |last| last > &key) {
+            return false;
+        }
+        last = Some(key);
+    }
+    true
+}
+
+/// Picks up to `n - 1` leaf start keys that divide the leaves into `n` shards
+/// of roughly equal size.
+pub(super) fn pick_split_points(leaves: &[(Vec<u8>, usize)], n: usize) -> Vec<Vec<u8>> {
+    let total = leaves.iter().map(|(_, size)| size).sum::<usize>();
+    let mut points = Vec::new();
+    let mut size_before = 0;
+    for (start, size) in leaves {
+        let shard = points.len() + 1;
+        if shard >= n {
+            break;
+        }
+        // Split before the first leaf that starts at or past the shard boundary.
+        if size_before > 0 && size_before * n >= total * shard {
+            points.push(start.clone());
+        }
+        size_before += size;
+    }
+    points
+}
+
+/// Collects the visible keys before `end` and skips `after`, with the values
+/// mapped by `f`.
+///
+/// Returns [`Error::Corrupted`] if a value does not match its checksum.
+fn collect_visible<T>(
+    mut iter: VisibleLeafPageIter<'_>,
+    end: Option<&[u8]>,
+    after: Option<&[u8]>,
+    f: fn(&[u8]) -> T,
+    out: &mut Vec<(Vec<u8>, T)>,
+) -> Result<()> {
+    for (k, v) in &mut iter {
+        if !is_before_end(k, end) {
+            break;
+        }
+        if after == Some(k) {
+            continue;
+        }
+        out.push((k.to_vec(), f(v)));
+    }
+    if iter.is_corrupted() {
+        return Err(Error::Corrupted);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_points() {
+        let leaves = (0..100u32)
+            .map(|i| (i.to_be_bytes().to_vec(), 1000))
+            .collect::<Vec<_>>();
+        let points = pick_split_points(&leaves, 4);
+        assert_eq!(points.len(), 3);
+        // Each shard holds the same number of bytes.
+        let mut bounds = vec![Vec::new()];
+        bounds.extend(points);
+        for (i, start) in bounds.iter().enumerate() {
+            let bytes = leaves
+                .iter()
+                .filter(|(key, _)| key >= start && bounds.get(i + 1).map_or(true, |end| key < end))
+                .map(|(_, size)| size)
+                .sum::<usize>();
+            assert_eq!(bytes, 25000);
+        }
+
+        assert!(pick_split_points(&leaves, 1).is_empty());
+        assert!(pick_split_points(&[], 4).is_empty());
+        // There can be no more shards than leaves.
+        assert_eq!(
+            pick_split_points(&leaves[..2], 4),
+            vec![leaves[1].0.clone()]
+        );
+    }
+
+    fn with_leaf_page(data: &[(Key<'_>, Value<'_>)], f: impl FnOnce(PageRef<'_>)) {
+        let builder =
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(SliceIter::new(data));
+        // Pages must be aligned to 8 bytes.
+        let mut buf = vec![0u64; (builder.size() + 7) / 8];
+        let buf =
+            unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, builder.size()) };
+        let mut page = PageBuf::new(buf);
+        builder.build(&mut page);
+        f(page.into());
+    }
+
+    #[test]
+    fn hard_chain_limit() {
+        let data = [(Key::new(b"a", 1), Value::Put(b"a1"))];
+        with_leaf_page(&data, |page| {
+            assert!(!exceeds_hard_chain_limit(page.chain_len(), 32));
+            // A chain built up while consolidations keep failing.
+            for _ in 0..300 {
+                page.set_chain_len(page.chain_len().saturating_add(1));
+            }
+            assert!(exceeds_hard_chain_limit(page.chain_len(), 32));
+            assert!(exceeds_hard_chain_limit(page.chain_len(), 254));
+            // Lengths saturate, so limits from 255 never trigger.
+            assert!(!exceeds_hard_chain_limit(page.chain_len(), 255));
+            assert!(!exceeds_hard_chain_limit(page.chain_len(), 0));
+        });
+        assert!(exceeds_hard_chain_limit(33, 32));
+        assert!(!exceeds_hard_chain_limit(32, 32));
+    }
+
+    #[test]
+    fn visible_value_lengths() {
+        let large = vec![7; 10000];
+        let data = [
+            (Key::new(b"a", 3), Value::Put(&large[..])),
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+            (Key::new(b"b", 2), Value::Put(b"")),
+            (Key::new(b"c", 4), Value::Delete),
+            (Key::new(b"c", 2), Value::Put(b"c2")),
+            (Key::new(b"d", 5), Value::Put(&large[..4097])),
+            (Key::new(b"e", 1), Value::Put(b"e")),
+        ];
+        fn collect<T>(
+            data: &[(Key<'_>, Value<'_>)],
+            lsn: u64,
+            f: fn(&[u8]) -> T,
+        ) -> Vec<(Vec<u8>, T)> {
+            let mut out = Vec::new();
+            with_leaf_page(data, |page| {
+                let mut builder = MergingIterBuilder::with_capacity(1);
+                builder.add(SortedPageIter::from(page));
+                let iter = MergingPageIter::new(builder.build(), None);
+                let iter = VisibleLeafPageIter::new(iter, lsn);
+                collect_visible(
+                    iter,
+                    Some(b"e".as_slice()),
+                    Some(b"b".as_slice()),
+                    f,
+                    &mut out,
+                )
+                .unwrap();
+            });
+            out
+        }
+
+        let sizes = collect(&data, u64::MAX, <[u8]>::len);
+        let values = collect(&data, u64::MAX, <[u8]>::to_vec);
+        assert_eq!(sizes, vec![(b"a".to_vec(), 10000), (b"d".to_vec(), 4097)]);
+        assert_eq!(
+            sizes,
+            values
+                .into_iter()
+                .map(|(k, v)| (k, v.len()))
+                .collect::<Vec<_>>()
+        );
+        // The lengths are of the versions visible at the LSN.
+        assert_eq!(
+            collect(&data, 2, <[u8]>::len),
+            vec![(b"a".to_vec(), 2), (b"c".to_vec(), 2)]
+        );
+    }
+
+    #[test]
+    fn checked_values_are_verified() {
+        let large = vec![7; 4096];
+        let mut damaged = large.clone();
+        damaged[100] ^= 1;
+        let checksum = crc32c(&large);
+        assert_eq!(visible_value(Value::Put(b"a")).unwrap(), Some(&b"a"[..]));
+        assert_eq!(
+            visible_value(Value::Checked(checksum, &large)).unwrap(),
+            Some(&large[..])
+        );
+        assert!(matches!(
+            visible_value(Value::Checked(checksum, &damaged)),
+            Err(Error::Corrupted)
+        ));
+
+        let scan = |data: &[(Key<'_>, Value<'_>)]| {
+            let mut out = Vec::new();
+            let mut result = Ok(());
+            with_leaf_page(data, |page| {
+                let mut builder = MergingIterBuilder::with_capacity(1);
+                builder.add(SortedPageIter::from(page));
+                let iter = MergingPageIter::new(builder.build(), None);
+                let iter = VisibleLeafPageIter::new(iter, u64::MAX);
+                result = collect_visible(iter, None, None, <[u8]>::len, &mut out);
+            });
+            result.map(|_| out)
+        };
+        // Checked values survive the page encoding.
+        let data = [
+            (Key::new(b"a", 1), Value::Put(b"a")),
+            (Key::new(b"b", 1), Value::Checked(checksum, &large)),
+        ];
+        assert_eq!(
+            scan(&data).unwrap(),
+            vec![(b"a".to_vec(), 1), (b"b".to_vec(), 4096)]
+        );
+        let data = [
+            (Key::new(b"a", 1), Value::Put(b"a")),
+            (Key::new(b"b", 1), Value::Checked(checksum, &damaged)),
+        ];
+        assert!(matches!(scan(&data), Err(Error::Corrupted)));
+    }
+
+    #[test]
+    fn drop_versions_below_safe_lsn() {
+        let data = [
+            (Key::new(b"a", 9), Value::Put(b"a9")),
+            (Key::new(b"a", 7), Value::Put(b"a7")),
+            (Key::new(b"a", 5), Value::Delete),
+            (Key::new(b"a", 3), Value::Put(b"a3")),
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+            (Key::new(b"b", 2), Value::Put(b"b2")),
+        ];
+        let consolidate = |safe_lsn| {
+            let mut lsns = Vec::new();
+            with_leaf_page(&data, |page| {
+                let mut builder = MergingIterBuilder::with_capacity(1);
+                builder.add(SortedPageIter::from(page));
+                let iter = MergingPageIter::new(builder.build(), None);
+                lsns = MergingLeafPageIter::new(iter, safe_lsn, false)
+                    .map(|(k, _)| (k.raw.to_vec(), k.lsn))
+                    .collect();
+            });
+            lsns
+        };
+        let a = |lsn| (b"a".to_vec(), lsn);
+        let b = (b"b".to_vec(), 2);
+        assert_eq!(consolidate(u64::MAX), vec![a(9), b.clone()]);
+        // Versions visible at LSN 6 and later are kept.
+        assert_eq!(consolidate(6), vec![a(9), a(7), a(5), b.clone()]);
+        assert_eq!(consolidate(5), vec![a(9), a(7), a(5), b.clone()]);
+        // Advancing the watermark reduces the number of versions.
+        assert_eq!(consolidate(8), vec![a(9), a(7), b.clone()]);
+        assert_eq!(consolidate(0), vec![a(9), a(7), a(5), a(3), a(1), b]);
+    }
+
+    #[test]
+    fn lww_values() {
+        let data = [
+            // The write with the older timestamp arrives later.
+            (Key::new(b"a", 2), Value::Lww(5, b"old")),
+            (Key::new(b"a", 1), Value::Lww(9, b"new")),
+            // Ties are broken by LSN.
+            (Key::new(b"b", 4), Value::Lww(7, b"b4")),
+            (Key::new(b"b", 3), Value::Lww(7, b"b3")),
+            // A put overrides older versions by LSN.
+            (Key::new(b"c", 6), Value::Put(b"put")),
+            (Key::new(b"c", 5), Value::Lww(9, b"lww")),
+        ];
+        fn merging_iter(page: PageRef<'_>) -> MergingPageIter<'_, Key<'_>, Value<'_>> {
+            let mut builder = MergingIterBuilder::with_capacity(1);
+            builder.add(SortedPageIter::from(page));
+            MergingPageIter::new(builder.build(), None)
+        }
+        fn visible(page: PageRef<'_>) -> Vec<(Vec<u8>, Vec<u8>)> {
+            VisibleLeafPageIter::new(merging_iter(page), u64::MAX)
+                .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                .collect()
+        }
+        let expected = vec![
+            (b"a".to_vec(), b"new".to_vec()),
+            (b"b".to_vec(), b"b4".to_vec()),
+            (b"c".to_vec(), b"put".to_vec()),
+        ];
+        with_leaf_page(&data, |page| {
+            assert_eq!(visible(page), expected);
+            // Only the winners are kept after consolidation.
+            let merged =
+                MergingLeafPageIter::new(merging_iter(page), u64::MAX, true).collect::<Vec<_>>();
+            assert_eq!(merged, vec![data[1], data[2], data[4]]);
+            with_leaf_page(&merged, |new_page| assert_eq!(visible(new_page), expected));
+        });
+    }
+
+    #[test]
+    fn scan_matches_point_reads() {
+        let base = [
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+            (Key::new(b"b", 1), Value::Put(b"b1")),
+            (Key::new(b"c", 2), Value::Lww(9, b"c2")),
+            (Key::new(b"d", 2), Value::Lww(3, b"d2")),
+        ];
+        let delta = [
+            (Key::new(b"a", 3), Value::Lww(2, b"a3")),
+            (Key::new(b"a", 2), Value::Lww(4, b"a2")),
+            (Key::new(b"b", 4), Value::Delete),
+            (Key::new(b"c", 5), Value::Lww(1, b"c5")),
+            (Key::new(b"d", 6), Value::Lww(8, b"d6")),
+        ];
+        // What a point read of the key at the LSN returns.
+        let get = |key: &[u8], lsn| {
+            let versions = base
+                .iter()
+                .chain(&delta)
+                .filter(|(k, _)| k.raw == key && k.lsn <= lsn)
+                .copied()
+                .collect();
+            match resolve_versions(versions) {
+                Some((_, Value::Put(v) | Value::Lww(_, v))) => Some(v.to_vec()),
+                _ => None,
+            }
+        };
+        with_leaf_page(&base, |base_page| {
+            with_leaf_page(&delta, |delta_page| {
+                for lsn in 0..=7 {
+                    let mut builder = MergingIterBuilder::with_capacity(2);
+                    builder.add(SortedPageIter::from(delta_page));
+                    builder.add(SortedPageIter::from(base_page));
+                    let iter = MergingPageIter::new(builder.build(), None);
+                    let scanned = VisibleLeafPageIter::new(iter, lsn)
+                        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                        .collect::<Vec<_>>();
+                    let read = [b"a", b"b", b"c", b"d"]
+                        .into_iter()
+                        .filter_map(|key| get(key, lsn).map(|v| (key.to_vec(), v)))
+                        .collect::<Vec<_>>();
+                    assert_eq!(scanned, read, "lsn {lsn}");
+                }
+            });
+        });
+        assert_eq!(get(b"a", 7), Some(b"a2".to_vec()));
+        assert_eq!(get(b"b", 7), None);
+        assert_eq!(get(b"c", 7), Some(b"c2".to_vec()));
+        assert_eq!(get(b"d", 7), Some(b"d6".to_vec()));
+    }
+
+    #[test]
+    fn drop_deleted_keys() {
+        let data = [
+            (Key::new(b"a", 4), Value::Delete),
+            (Key::new(b"a", 1), Value::Put(b"a1")),
+            (Key::new(b"b", 5), Value::Delete),
+            (Key::new(b"c", 6), Value::Delete),
+            (Key::new(b"c", 2), Value::Put(b"c2")),
+        ];
+        let consolidate = |safe_lsn, drop_deletes| {
+            let mut items = Vec::new();
+            with_leaf_page(&data, |page| {
+                let mut builder = MergingIterBuilder::with_capacity(1);
+                builder.add(SortedPageIter::from(page));
+                let iter = MergingPageIter::new(builder.build(), None);
+                let merged =
+                    MergingLeafPageIter::new(iter, safe_lsn, drop_deletes).collect::<Vec<_>>();
+                // The consolidated page must be readable even if it is empty.
+                with_leaf_page(&merged, |new_page| {
+                    items = SortedPageIter::<Key, Value>::from(new_page)
+                        .map(|(k, _)| (k.raw.to_vec(), k.lsn))
+                        .collect();
+                });
+            });
+            items
+        };
+        // Tombstones are kept unless the whole chain is consolidated.
+        assert_eq!(consolidate(u64::MAX, false).len(), 3);
+        // All keys are deleted, so the consolidated page is empty.
+        assert!(consolidate(u64::MAX, true).is_empty());
+        // Tombstones after the safe LSN still hide older versions.
+        assert_eq!(
+            consolidate(5, true),
+            vec![(b"c".to_vec(), 6), (b"c".to_vec(), 2)]
+        );
+    }
+
+    #[test]
+    fn verify_leaf_page() {
+        let value = [1u8; 16];
+        let data = [
+            (Key::new(b"b", 2), Value::Put(&value)),
+            (Key::new(b"b", 1), Value::Put(&value)),
+            (Key::new(b"c", 3), Value::Put(&value)),
+        ];
+        with_leaf_page(&data, |page| {
+            assert!(verify_page(page, &Range::full()));
+            let range = |start: &'static [u8], end: Option<&'static [u8]>| Range { start, end };
+            assert!(verify_page(page, &range(b"b", Some(b"d".as_slice()))));
+            // Keys must be within the range.
+            assert!(!verify_page(page, &range(b"c", None)));
+            assert!(!verify_page(page, &range(b"a", Some(b"c".as_slice()))));
+            // The offsets of a truncated page point past its content.
+            let truncated = PageRef::new(&page.data()[..page.size() / 2]);
+            assert!(!verify_page(truncated, &Range::full()));
+        });
+        assert!(!is_sorted_in_range(
+            [b"b".as_slice(), b"a"].into_iter(),
+            |k| *k,
+            &Range::full()
+        ));
+    }
+
+    #[test]
+    fn overflow_page() {
+        let large = vec![7u8; 64 << 10];
+        with_leaf_page(&[(Key::new(b"a", 1), Value::Put(&large))], |page| {
+            assert!(page.size() > 8 << 10);
+            assert!(is_overflow_page(page));
+            // The large value is read back like any other value.
+            let (key, value) = ValuePageRef::from(page).get(0).unwrap();
+            assert_eq!(key, Key::new(b"a", 1));
+            assert_eq!(value, Value::Put(&large));
+        });
+        with_leaf_page(
+            &[
+                (Key::new(b"a", 1), Value::Put(&large)),
+                (Key::new(b"b", 1), Value::Put(b"b")),
+            ],
+            |page| assert!(!is_overflow_page(page)),
+        );
+    }
+
+    #[test]
+    fn consolidation_trigger() {
+        let limits = ChainLimits {
+            max_chain_len: 4,
+            max_delta_bytes: 64 << 10,
+        };
+        assert_eq!(limits.trigger(2, 1 << 10), None);
+        assert_eq!(
+            limits.trigger(5, 1 << 10),
+            Some(ConsolidationTrigger::ChainLength)
+        );
+        // A short chain with a few large deltas.
+        assert_eq!(
+            limits.trigger(2, 100 << 10),
+            Some(ConsolidationTrigger::ChainBytes)
+        );
+        assert_eq!(
+            limits.trigger(5, 100 << 10),
+            Some(ConsolidationTrigger::ChainLength)
+        );
+    }
+
+    #[test]
+    fn ship_raw_page() {
+        let data = [
+            (Key::new(b"a", 2), Value::Put(b"a2")),
+            (Key::new(b"b", 1), Value::Put(b"b1")),
+        ];
+        with_leaf_page(&data, |page| {
+            let mut buf = PageBuf::from(*page);
+            buf.set_epoch(3);
+            let raw = encode_raw_page(page);
+            assert_eq!(raw.len(), page.size() + RAW_PAGE_CHECKSUM_LEN);
+
+            // Install the bytes in another aligned buffer, as a replica does.
+            let shipped = decode_raw_page(&raw).unwrap();
+            let mut replica_buf = vec![0u64; (shipped.len() + 7) / 8];
+            let replica_data = unsafe {
+                std::slice::from_raw_parts_mut(replica_buf.as_mut_ptr() as *mut u8, shipped.len())
+            };
+            let mut replica = PageBuf::new(replica_data);
+            replica.data_mut().copy_from_slice(shipped);
+            assert!(verify_page(PageRef::from(*replica), &Range::full()));
+            assert_eq!(replica.epoch(), 3);
+            let (key, value) = ValuePageRef::from(PageRef::from(*replica)).get(1).unwrap();
+            assert_eq!(key, Key::new(b"b", 1));
+            assert_eq!(value, Value::Put(b"b1"));
+
+            // Pages with an unknown value kind are rejected. The last item
+            // ends with the value kind and two bytes of the value.
+            let data = replica.data_mut();
+            let kind = data.len() - 3;
+            data[kind] = 7;
+            assert!(!verify_page(PageRef::from(*replica), &Range::full()));
+
+            // Damaged and truncated bytes are rejected.
+            let mut damaged = raw.clone();
+            damaged[20] ^= 1;
+            assert!(matches!(decode_raw_page(&damaged), Err(Error::Corrupted)));
+            assert!(matches!(decode_raw_page(&raw[..3]), Err(Error::Corrupted)));
+            assert!(matches!(decode_raw_page(&raw[1..]), Err(Error::Corrupted)));
+
+            // Pages with chains are not shipped.
+            buf.set_chain_next(8);
+            let raw = encode_raw_page(page);
+            assert!(matches!(decode_raw_page(&raw), Err(Error::Corrupted)));
+        });
+    }
+
+    #[test]
+    fn presplit_leaves() {
+        assert_eq!(leaf_starts(&[]), vec![b"".as_slice()]);
+        let keys = [
+            b"m".to_vec(),
+            b"c".to_vec(),
+            Vec::new(),
+            b"x".to_vec(),
+            b"c".to_vec(),
+        ];
+        assert_eq!(
+            leaf_starts(&keys),
+            vec![
+                b"".as_slice(),
+                b"c".as_slice(),
+                b"m".as_slice(),
+                b"x".as_slice()
+            ]
+        );
+    }
+}
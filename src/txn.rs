@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use crate::{env::Photon, Cursor, Error, Result, Table};
+
+/// An optimistic transaction on a [`Table`].
+///
+/// Reads are served from a snapshot of the table taken when the transaction
+/// begins, which is kept by consolidations until the transaction is dropped.
+/// Writes are buffered until [`Transaction::commit`]. The commit fails with
+/// [`Error::Conflict`] if any key read by the transaction has changed since,
+/// which makes committed transactions serializable.
+pub struct Transaction<'a> {
+    table: &'a Table,
+    lsn: u64,
+    /// The LSNs of the versions observed by reads, `None` for missing keys.
+    reads: BTreeMap<Vec<u8>, Option<u64>>,
+    /// The buffered writes, `None` for deletes.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(table: &'a Table, lsn: u64) -> Self {
+        Self {
+            table,
+            lsn,
+            reads: BTreeMap::new(),
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the value corresponding to the key.
+    ///
+    /// Keys written by the transaction return the buffered value.
+    pub async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        let version = self.table.version_of(key, self.lsn).await?;
+        let value = self.table.get_at(key, self.lsn).await?;
+        self.reads.entry(key.to_vec()).or_insert(version);
+        Ok(value)
+    }
+
+    /// Buffers a put of the key-value pair.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    /// Buffers a delete of the key.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Applies the buffered writes to the table at a new LSN.
+    ///
+    /// Returns [`Error::Conflict`] without applying anything if a key read by
+    /// the transaction has been written since. Reads observe all the writes
+    /// once the commit succeeds, and none of them before. If a write fails,
+    /// the writes applied before it are undone before the error is returned,
+    /// though they can still be read at the LSN of the commit with
+    /// [`Table::get_at`].
+    pub async fn commit(self) -> Result<()> {
+        self.table.commit(&self.reads, &self.writes).await
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        self.table.release_snapshot(self.lsn);
+    }
+}
+
+/// A read-only transaction on a [`Table`].
+///
+/// All reads observe the state of the table when the transaction begins,
+/// regardless of later writes. The versions visible to the transaction are
+/// kept by consolidations until it is dropped.
+pub struct ReadTxn<'a> {
+    table: &'a Table,
+    lsn: u64,
+}
+
+impl<'a> ReadTxn<'a> {
+    pub(crate) fn new(table: &'a Table, lsn: u64) -> Self {
+        Self { table, lsn }
+    }
+
+    /// Returns the LSN of the snapshot read by the transaction.
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    /// Gets the value corresponding to the key.
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.table.get_at(key, self.lsn).await
+    }
+
+    /// Returns a cursor over the key-value pairs at or after `start`.
+    pub fn scan(&self, start: &[u8]) -> Result<Cursor<'a, Photon>> {
+        self.table.scan_at(start, self.lsn)
+    }
+}
+
+impl Drop for ReadTxn<'_> {
+    fn drop(&mut self) {
+        self.table.release_snapshot(self.lsn);
+    }
+}
+
+/// Checks that the versions observed by reads are still the current ones.
+///
+/// `current` holds the current versions of the read keys in key order.
+pub(crate) fn validate_reads(
+    reads: &BTreeMap<Vec<u8>, Option<u64>>,
+    current: &[Option<u64>],
+) -> Result<()> {
+    debug_assert_eq!(reads.len(), current.len());
+    if reads.values().eq(current) {
+        Ok(())
+    } else {
+        Err(Error::Conflict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting_transactions() {
+        // The LSN of the newest version of each key.
+        let mut versions = BTreeMap::from([(b"x".to_vec(), 1), (b"y".to_vec(), 2)]);
+        let current = |versions: &BTreeMap<Vec<u8>, u64>, reads: &BTreeMap<Vec<u8>, _>| {
+            reads
+                .keys()
+                .map(|key| versions.get(key).copied())
+                .collect::<Vec<_>>()
+        };
+
+        // Both transactions read `x` and `y`, and then write `x`.
+        let reads = BTreeMap::from([(b"x".to_vec(), Some(1)), (b"y".to_vec(), Some(2))]);
+
+        // The first commit succeeds and writes a new version of `x`.
+        assert!(validate_reads(&reads, &current(&versions, &reads)).is_ok());
+        versions.insert(b"x".to_vec(), 3);
+
+        // The second commit read the old `x`, so it conflicts.
+        assert!(matches!(
+            validate_reads(&reads, &current(&versions, &reads)),
+            Err(Error::Conflict)
+        ));
+
+        // A key created after it was read as missing conflicts too.
+        let reads = BTreeMap::from([(b"x".to_vec(), Some(3)), (b"z".to_vec(), None)]);
+        assert!(validate_reads(&reads, &current(&versions, &reads)).is_ok());
+        versions.insert(b"z".to_vec(), 4);
+        assert!(matches!(
+            validate_reads(&reads, &current(&versions, &reads)),
+            Err(Error::Conflict)
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+//! Helpers to align I/O to device blocks.
+
+/// Returns the range of whole blocks that covers `len` bytes at `offset`.
+///
+/// Returns the offset and length of the aligned range, and the number of
+/// bytes to trim from its front to get to `offset`. The block size must be a
+/// power of two.
+pub(crate) fn aligned_range(offset: u64, len: usize, block_size: usize) -> (u64, usize, usize) {
+    debug_assert!(block_size.is_power_of_two());
+    let mask = block_size as u64 - 1;
+    let aligned_offset = offset & !mask;
+    let aligned_end = (offset + len as u64 + mask) & !mask;
+    let aligned_len = (aligned_end - aligned_offset) as usize;
+    let trim_front = (offset - aligned_offset) as usize;
+    (aligned_offset, aligned_len, trim_front)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned_ranges() {
+        const B: usize = 4096;
+        // Exactly aligned.
+        assert_eq!(aligned_range(0, 0, B), (0, 0, 0));
+        assert_eq!(aligned_range(0, B, B), (0, B, 0));
+        assert_eq!(aligned_range(B as u64, 2 * B, B), (B as u64, 2 * B, 0));
+        // Within a block.
+        assert_eq!(aligned_range(0, 1, B), (0, B, 0));
+        assert_eq!(aligned_range(100, 200, B), (0, B, 100));
+        assert_eq!(aligned_range(B as u64 - 1, 1, B), (0, B, B - 1));
+        assert_eq!(aligned_range(B as u64 + 10, 0, B), (B as u64, B, 10));
+        // Across blocks.
+        assert_eq!(aligned_range(B as u64 - 1, 2, B), (0, 2 * B, B - 1));
+        assert_eq!(aligned_range(100, 2 * B, B), (0, 3 * B, 100));
+        assert_eq!(aligned_range(0, B + 1, B), (0, 2 * B, 0));
+    }
+
+    #[test]
+    fn aligned_ranges_cover_requests() {
+        for block_size in [1, 2, 8, 16] {
+            for offset in 0..(block_size * 4) as u64 {
+                for len in 0..block_size * 4 {
+                    let (aligned_offset, aligned_len, trim_front) =
+                        aligned_range(offset, len, block_size);
+                    assert_eq!(aligned_offset % block_size as u64, 0);
+                    assert_eq!(aligned_len % block_size, 0);
+                    assert_eq!(aligned_offset + trim_front as u64, offset);
+                    assert!(trim_front < block_size);
+                    // The range covers the request, and has no block beyond it.
+                    let end = offset + len as u64;
+                    let aligned_end = aligned_offset + aligned_len as u64;
+                    assert!(aligned_end >= end);
+                    assert!(aligned_end - end < block_size as u64);
+                }
+            }
+        }
+    }
+}
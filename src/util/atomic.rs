@@ -19,6 +19,11 @@ impl Counter {
     pub(crate) fn add(&self, n: u64) -> u64 {
         self.0.fetch_add(n, Ordering::Relaxed)
     }
+
+    /// Updates the counter to `n` if it is larger.
+    pub(crate) fn max(&self, n: u64) -> u64 {
+        self.0.fetch_max(n, Ordering::Relaxed)
+    }
 }
 
 impl Default for Counter {
@@ -42,6 +47,18 @@ impl Sequencer {
     pub(crate) fn inc(&self) -> u64 {
         self.0.fetch_add(1, Ordering::Release)
     }
+
+    /// Advances the sequence to `value`.
+    ///
+    /// Returns false and leaves the sequence unchanged if `value` is smaller
+    /// than the current one, so that the sequence never goes backwards.
+    pub(crate) fn advance_to(&self, value: u64) -> bool {
+        self.0
+            .fetch_update(Ordering::Release, Ordering::Acquire, |current| {
+                (value >= current).then_some(value)
+            })
+            .is_ok()
+    }
 }
 
 impl Default for Sequencer {
@@ -49,3 +66,19 @@ impl Default for Sequencer {
         Self::new(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequencer_advance_to() {
+        let seq = Sequencer::new(0);
+        seq.inc();
+        assert!(seq.advance_to(10));
+        assert_eq!(seq.inc(), 10);
+        assert!(seq.advance_to(11));
+        assert!(!seq.advance_to(5));
+        assert_eq!(seq.get(), 11);
+    }
+}
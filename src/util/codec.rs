@@ -0,0 +1,56 @@
+//! A checked little-endian codec for the formats exchanged outside of page
+//! files, such as write batches and snapshot files.
+
+use crate::{Error, Result};
+
+/// Appends the length of the data as a `u32` and then the data.
+pub(crate) fn put_slice(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Decodes values from a buffer, returning [`Error::Corrupted`] if the buffer
+/// runs out.
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Returns the number of bytes left to decode.
+    pub(crate) fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn get_bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(Error::Corrupted);
+        }
+        let (data, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(data)
+    }
+
+    pub(crate) fn get_u8(&mut self) -> Result<u8> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    pub(crate) fn get_u32(&mut self) -> Result<u32> {
+        let data = self.get_bytes(4)?;
+        Ok(u32::from_le_bytes(data.try_into().unwrap()))
+    }
+
+    pub(crate) fn get_u64(&mut self) -> Result<u64> {
+        let data = self.get_bytes(8)?;
+        Ok(u64::from_le_bytes(data.try_into().unwrap()))
+    }
+
+    /// Decodes data encoded by [`put_slice`].
+    pub(crate) fn get_slice(&mut self) -> Result<&'a [u8]> {
+        let len = self.get_u32()? as usize;
+        self.get_bytes(len)
+    }
+}
@@ -0,0 +1,71 @@
+//! Helpers to render keys in diagnostics.
+
+use std::fmt::Write;
+
+/// The default maximum number of key bytes rendered by [`format_key`].
+pub(crate) const DEFAULT_MAX_KEY_LEN: usize = 32;
+
+/// Renders the key as a human-readable string.
+///
+/// Printable ASCII keys are rendered as quoted strings, other keys are
+/// rendered in hex. Keys longer than `max_len` bytes are truncated with an
+/// ellipsis.
+pub(crate) fn format_key(key: &[u8], max_len: usize) -> String {
+    let (key, truncated) = if key.len() > max_len {
+        (&key[..max_len], true)
+    } else {
+        (key, false)
+    };
+    let mut s = String::with_capacity(key.len() * 2 + 5);
+    if key.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        s.push('"');
+        for &b in key {
+            if b == b'"' || b == b'\\' {
+                s.push('\\');
+            }
+            s.push(b as char);
+        }
+        s.push('"');
+    } else {
+        s.push_str("0x");
+        for b in key {
+            write!(s, "{b:02x}").unwrap();
+        }
+    }
+    if truncated {
+        s.push_str("...");
+    }
+    s
+}
+
+/// Renders the key range `[start, end)` as a human-readable string.
+///
+/// A range without an end is unbounded.
+pub(crate) fn format_range(start: &[u8], end: Option<&[u8]>, max_len: usize) -> String {
+    let start = format_key(start, max_len);
+    match end {
+        Some(end) => format!("[{start}, {})", format_key(end, max_len)),
+        None => format!("[{start}, +inf)"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_keys() {
+        assert_eq!(format_key(b"", 8), "\"\"");
+        assert_eq!(format_key(b"user 42", 8), "\"user 42\"");
+        assert_eq!(format_key(br#"a"b\c"#, 8), r#""a\"b\\c""#);
+        assert_eq!(format_key(&[0x00, 0xff, b'a'], 8), "0x00ff61");
+        assert_eq!(format_key(b"0123456789", 4), "\"0123\"...");
+        assert_eq!(format_key(&[0xab; 10], 2), "0xabab...");
+    }
+
+    #[test]
+    fn format_ranges() {
+        assert_eq!(format_range(b"", None, 8), "[\"\", +inf)");
+        assert_eq!(format_range(&[0x00], Some(&[0xff]), 8), "[0x00, 0xff)");
+    }
+}
@@ -1,2 +1,7 @@
+pub(crate) mod align;
 pub(crate) mod atomic;
+pub(crate) mod checksum;
+pub(crate) mod codec;
+pub(crate) mod fmt;
 pub(crate) mod notify;
+pub(crate) mod sync;
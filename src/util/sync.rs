@@ -0,0 +1,157 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex, MutexGuard, PoisonError,
+};
+
+use super::notify::Notify;
+
+/// Acquires the mutex, ignoring poisoning.
+///
+/// The data protected by the mutexes in this crate is always replaced as a
+/// whole, so a panic in another thread holding the lock can't leave it in an
+/// inconsistent state. Ignoring poisoning prevents a single panic from
+/// wedging the whole engine.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs the blocking function on another thread, so that it does not block
+/// the runtime of the calling task.
+///
+/// This is for the few system calls that the runtime can not issue
+/// asynchronously, such as `lseek` and `fallocate`.
+pub(crate) async fn unblock<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.await.expect("the blocking function panicked")
+}
+
+/// An async semaphore that bounds the number of tasks holding a permit.
+pub(crate) struct Semaphore {
+    permits: usize,
+    acquired: AtomicUsize,
+    released: Notify,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with the number of permits, zero for unlimited.
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            permits: if permits == 0 { usize::MAX } else { permits },
+            acquired: AtomicUsize::new(0),
+            released: Notify::new(),
+        }
+    }
+
+    /// Waits until a permit is available, and takes it.
+    pub(crate) async fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            let acquired = self.acquired.load(Ordering::Acquire);
+            if acquired < self.permits {
+                if self
+                    .acquired
+                    .compare_exchange_weak(
+                        acquired,
+                        acquired + 1,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_ok()
+                {
+                    return SemaphorePermit { semaphore: self };
+                }
+                continue;
+            }
+            self.released.notified().await;
+        }
+    }
+
+    /// Returns the number of permits taken.
+    pub(crate) fn acquired(&self) -> usize {
+        self.acquired.load(Ordering::Relaxed)
+    }
+}
+
+/// A permit of a [`Semaphore`], which is given back when dropped.
+pub(crate) struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.acquired.fetch_sub(1, Ordering::AcqRel);
+        self.semaphore.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{panic, sync::Arc, thread, time::Duration};
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn lock_poisoned() {
+        let mutex = Arc::new(Mutex::new(1));
+        let cloned = mutex.clone();
+        let result = thread::spawn(move || {
+            let _guard = cloned.lock().unwrap();
+            panic::panic_any("poison the mutex");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+        *lock(&mutex) += 1;
+        assert_eq!(*lock(&mutex), 2);
+    }
+
+    #[test]
+    fn semaphore_bounds_holders() {
+        let semaphore = Arc::new(Semaphore::new(3));
+        let max_holders = Arc::new(AtomicUsize::new(0));
+        let handles = (0..16)
+            .map(|_| {
+                let semaphore = semaphore.clone();
+                let max_holders = max_holders.clone();
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        let _permit = block_on(semaphore.acquire());
+                        max_holders.fetch_max(semaphore.acquired(), Ordering::Relaxed);
+                        thread::sleep(Duration::from_micros(10));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        // All holders complete.
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let max_holders = max_holders.load(Ordering::Relaxed);
+        assert!(max_holders > 0 && max_holders <= 3);
+        assert_eq!(semaphore.acquired(), 0);
+
+        // Zero permits means unlimited.
+        let semaphore = Semaphore::new(0);
+        let permits = (0..100)
+            .map(|_| block_on(semaphore.acquire()))
+            .collect::<Vec<_>>();
+        assert_eq!(semaphore.acquired(), 100);
+        drop(permits);
+        assert_eq!(semaphore.acquired(), 0);
+    }
+
+    #[test]
+    fn unblock_on_another_thread() {
+        let caller = thread::current().id();
+        let callee = block_on(unblock(|| thread::current().id()));
+        assert_ne!(caller, callee);
+    }
+}